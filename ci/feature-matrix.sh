@@ -0,0 +1,39 @@
+#!/usr/bin/env bash
+# Checks that mini-prompt builds, lints, and passes its tests (doctests included)
+# with each feature on its own, as well as with none and all of them.
+set -euo pipefail
+cd "$(dirname "$0")/.."
+
+features=(
+    ""
+    openrouter
+    anthropic
+    openai
+    markdown
+    schemars
+    yaml
+    toml
+    chrono
+    base64
+    mcp
+    builtin-http
+    builtin-shell
+    tiktoken
+    keyring
+    raw-response
+    simd-json
+    cli
+)
+
+run() {
+    echo "+ $*" >&2
+    "$@"
+}
+
+for f in "${features[@]}"; do
+    args=(-p mini-prompt --no-default-features --features "$f")
+    run cargo clippy "${args[@]}" --all-targets -- -D warnings
+    run cargo test "${args[@]}"
+done
+run cargo clippy -p mini-prompt --all-features --all-targets -- -D warnings
+run cargo test -p mini-prompt --all-features
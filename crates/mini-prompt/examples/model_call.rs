@@ -13,7 +13,7 @@ type Model = models::Gemma27B3;
 
 #[tokio::main]
 async fn main() -> Result<(), CallErr> {
-    let mut backend = callers::Openrouter::<Model>::default();
+    let backend = callers::Openrouter::<Model>::default();
 
     let resp =
         backend.simple_call(indoc! {
@@ -0,0 +1,324 @@
+//! A ReAct-style agent, which reasons and uses tools step by step towards a goal.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "anthropic")] {
+//! # use mini_prompt::*;
+//! use mini_prompt::agent::{Agent, Finish};
+//!
+//! let session = ToolsSession::new(
+//!     callers::Anthropic::<models::ClaudeHaiku35>::default(),
+//!     vec![(
+//!         ToolInfo::new("weather", "Returns the weather in a city.", None),
+//!         Box::new(|_args| "sunny, 24C".to_string()),
+//!     )],
+//! );
+//! let mut agent = Agent::new(session, "Should I take an umbrella in Sydney today?")
+//!     .with_max_steps(5);
+//!
+//! # tokio::task::spawn(async move {
+//! if let Ok(Finish::Answered(answer)) = agent.run().await {
+//!     for step in agent.trace() {
+//!         println!("thought: {}", step.thought);
+//!         for action in step.actions.iter() {
+//!             println!("{}({}) => {}", action.tool, action.arguments, action.observation);
+//!         }
+//!     }
+//!     println!("{}", answer);
+//! }
+//! # });
+//! # }
+//! ```
+
+use crate::tools::{Handoff, ToolsSession};
+use crate::{CallBase, CallErr, ModelCaller, Turn};
+
+const DEFAULT_MAX_STEPS: usize = 10;
+
+const REACT_INSTRUCTIONS: &str = "Work towards the goal one step at a time. Before using \
+    any tools, briefly explain what you know so far and why you are using them. Once the \
+    goal is achieved, respond with your final answer without using any tools.";
+
+/// A tool invoked by the agent, and the result it observed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Action {
+    /// The name of the tool.
+    pub tool: String,
+    /// The arguments the tool was invoked with, typically JSON.
+    pub arguments: String,
+    /// The result returned by the tool.
+    pub observation: String,
+}
+
+/// A single step taken by an [Agent]: one response from the model, and the tools it used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentStep {
+    /// The text of the model's response, which explains its reasoning. For the final
+    /// step, this is the answer.
+    pub thought: String,
+    /// The tools invoked during this step, in order. Empty for the final step.
+    pub actions: Vec<Action>,
+}
+
+/// Why an [Agent] stopped running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finish {
+    /// The model responded without using any tools, with the given answer.
+    Answered(String),
+    /// The model handed the conversation off to another agent, see
+    /// [ToolsSession::with_handoff].
+    HandedOff(Handoff),
+    /// The stop condition was met after the last step in the trace.
+    Stopped,
+    /// The step limit was reached before the model answered.
+    OutOfSteps,
+}
+
+/// The type of a stop condition, see [Agent::with_stop].
+pub type StopFunc<C> = Box<dyn FnMut(&AgentStep, &C) -> bool + Send>;
+
+/// Pursues a goal using the tools of a [ToolsSession], one step at a time.
+///
+/// Each step is a single request to the model, followed by invoking any tools it called.
+/// The model is asked to explain its reasoning alongside its tool calls, so the trace
+/// reads as a series of thoughts, actions, and observations.
+pub struct Agent<B: ModelCaller, C: Send = ()> {
+    session: ToolsSession<B, C>,
+    goal: String,
+    params: CallBase,
+    max_steps: usize,
+    stop: Option<StopFunc<C>>,
+    /// Every turn of the current run, which the model sees on each step.
+    scratchpad: Vec<Turn>,
+    trace: Vec<AgentStep>,
+}
+
+impl<B: ModelCaller, C: Send> Agent<B, C> {
+    /// Constructs an agent which pursues the given goal with the session's tools.
+    pub fn new<S: Into<String>>(session: ToolsSession<B, C>, goal: S) -> Self {
+        Self {
+            session,
+            goal: goal.into(),
+            params: CallBase::default(),
+            max_steps: DEFAULT_MAX_STEPS,
+            stop: None,
+            scratchpad: vec![],
+            trace: vec![],
+        }
+    }
+
+    /// Sets the parameters of each request, such as the system prompt or temperature.
+    /// The goal and guidance on reasoning are appended to the instructions.
+    pub fn with_params(mut self, params: CallBase) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Sets the maximum number of steps in a run, which defaults to 10.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Sets a condition which is checked after each step which used tools, and stops
+    /// the run if it returns true. It is handed the step and the session's context,
+    /// which tools can use to signal they are done.
+    ///
+    /// ```
+    /// # #[cfg(feature = "openrouter")] {
+    /// # use mini_prompt::*;
+    /// # use mini_prompt::agent::Agent;
+    /// let session = ToolsSession::new(
+    ///     callers::Openrouter::<models::Gemma27B3>::default(),
+    ///     vec![],
+    /// );
+    /// let agent = Agent::new(session, "Deploy the website.")
+    ///     .with_stop(|step, _| step.actions.iter().any(|a| a.tool == "deploy"));
+    /// # }
+    /// ```
+    pub fn with_stop<F>(mut self, stop: F) -> Self
+    where
+        F: FnMut(&AgentStep, &C) -> bool + Send + 'static,
+    {
+        self.stop = Some(Box::new(stop));
+        self
+    }
+
+    /// Returns the goal the agent is pursuing.
+    pub fn goal(&self) -> &str {
+        &self.goal
+    }
+
+    /// Returns the steps taken in the most recent run.
+    pub fn trace(&self) -> &[AgentStep] {
+        &self.trace
+    }
+
+    /// Returns every turn of the most recent run: each model response, followed by
+    /// the results of the tools it used.
+    pub fn scratchpad(&self) -> &[Turn] {
+        &self.scratchpad
+    }
+
+    /// Returns the underlying session.
+    pub fn session(&self) -> &ToolsSession<B, C> {
+        &self.session
+    }
+
+    /// Returns the underlying session, mutably, such as to enable or disable tools.
+    pub fn session_mut(&mut self) -> &mut ToolsSession<B, C> {
+        &mut self.session
+    }
+
+    /// Consumes the agent, returning the underlying session.
+    pub fn into_session(self) -> ToolsSession<B, C> {
+        self.session
+    }
+
+    /// Runs the agent from the start until the model answers, hands off the
+    /// conversation, the stop condition is met, or the step limit is reached. The
+    /// previous trace and scratchpad are cleared.
+    ///
+    /// The session's observers are notified of each model request and tool call, and
+    /// its transcript is set to the scratchpad once the run completes.
+    pub async fn run(&mut self) -> Result<Finish, CallErr> {
+        self.trace.clear();
+        let mut instructions = self.params.instructions.clone();
+        if !instructions.is_empty() {
+            instructions += "\n\n";
+        }
+        instructions += &format!("## Goal\n\n{}\n\n{}", self.goal, REACT_INSTRUCTIONS);
+        let params = self.session.prepare(CallBase {
+            instructions,
+            ..self.params.clone()
+        });
+
+        let mut turns = vec![];
+        let mut res = Err(CallErr::NoCompletions);
+        let mut finish = Finish::OutOfSteps;
+        for i in 0..self.max_steps {
+            let (resp, actions) = match self.session.step(&params, &mut turns, i).await {
+                Ok(stepped) => stepped,
+                Err(e) => {
+                    res = Err(e);
+                    break;
+                }
+            };
+            let step = AgentStep {
+                thought: resp.content.text(),
+                actions,
+            };
+            res = Ok(resp);
+
+            if step.actions.is_empty() {
+                finish = Finish::Answered(step.thought.clone());
+                self.trace.push(step);
+                break;
+            }
+            let stop = match self.stop.as_mut() {
+                Some(stop) => stop(&step, &self.session.context()),
+                None => false,
+            };
+            self.trace.push(step);
+            if self.session.has_handoff() {
+                break;
+            }
+            if stop {
+                finish = Finish::Stopped;
+                break;
+            }
+        }
+
+        self.session.finish(turns.clone(), &res);
+        self.scratchpad = turns;
+        if let Some(handoff) = self.session.take_handoff() {
+            finish = Finish::HandedOff(handoff);
+        }
+        match res {
+            Err(e) => Err(e),
+            Ok(_) => Ok(finish),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scripted;
+    use crate::ToolInfo;
+
+    fn session(responses: Vec<crate::CallResp>) -> ToolsSession<Scripted, Vec<String>> {
+        let mut session = ToolsSession::new(Scripted::new(responses), vec![]).with_context(vec![]);
+        session.add_context_tool(
+            ToolInfo::new("search", "Searches the web.", None),
+            Box::new(|log: &mut Vec<String>, args| {
+                log.push(args);
+                "found it".to_string()
+            }),
+        );
+        session
+    }
+
+    #[tokio::test]
+    async fn run_to_answer() {
+        let mut searching = Scripted::tool_calls(&[("search", "{\"q\": \"rust\"}")]);
+        searching
+            .content
+            .content
+            .insert(0, crate::Message::text("I should search."));
+        let mut agent = Agent::new(
+            session(vec![searching, Scripted::text("Rust is a language.")]),
+            "What is Rust?",
+        );
+
+        let finish = agent.run().await.unwrap();
+        assert_eq!(finish, Finish::Answered("Rust is a language.".into()));
+        assert_eq!(
+            agent.trace(),
+            &[
+                AgentStep {
+                    thought: "I should search.".into(),
+                    actions: vec![Action {
+                        tool: "search".into(),
+                        arguments: "{\"q\": \"rust\"}".into(),
+                        observation: "found it".into(),
+                    }],
+                },
+                AgentStep {
+                    thought: "Rust is a language.".into(),
+                    actions: vec![],
+                },
+            ]
+        );
+        assert_eq!(agent.scratchpad().len(), 3);
+        assert_eq!(agent.session().transcript().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn stop_and_step_limit() {
+        let search = || Scripted::tool_calls(&[("search", "{}")]);
+        let mut agent = Agent::new(session(vec![search(), search(), search()]), "Search.")
+            .with_stop(|_, log: &Vec<String>| log.len() >= 2);
+        assert_eq!(agent.run().await.unwrap(), Finish::Stopped);
+        assert_eq!(agent.trace().len(), 2);
+
+        let mut agent =
+            Agent::new(session(vec![search(), search(), search()]), "Search.").with_max_steps(2);
+        assert_eq!(agent.run().await.unwrap(), Finish::OutOfSteps);
+        assert_eq!(agent.session().context().len(), 2);
+
+        let session = session(vec![
+            search(),
+            Scripted::tool_calls(&[("transfer_to_expert", "{}")]),
+        ])
+        .with_handoff("expert", "Knows everything.");
+        let mut agent = Agent::new(session, "Search.");
+        match agent.run().await.unwrap() {
+            Finish::HandedOff(handoff) => assert_eq!(handoff.target, "expert"),
+            f => panic!("unexpected finish: {:?}", f),
+        }
+        assert_eq!(agent.trace().len(), 2);
+
+        // Errors from the model end the run.
+        assert!(matches!(agent.run().await, Err(CallErr::NoCompletions)));
+    }
+}
@@ -0,0 +1,297 @@
+//! Assertions for model output, for use in tests.
+//!
+//! Each `assert_*` function panics with a description of the mismatch, including the
+//! text being checked. The functions without the prefix return the description as an
+//! error instead, for checks which shouldn't fail the test outright.
+//!
+//! ```
+//! use mini_prompt::assertions::*;
+//!
+//! let resp = "Sure! <answer> Paris </answer>\n```json\n{\"city\": \"Paris\", \"rank\": 1}\n```";
+//! assert_contains_ci(resp, "PARIS");
+//! assert_tagged_eq(resp, "answer", "Paris");
+//! assert_json_matches(
+//!     resp,
+//!     &serde_json::json!({
+//!         "type": "object",
+//!         "properties": {"city": {"type": "string"}, "rank": {"type": "integer"}},
+//!         "required": ["city"],
+//!     }),
+//! );
+//! assert!(contains_ci(resp, "London").is_err());
+//! ```
+
+use crate::parse::{tagged, TagOptions};
+use serde_json::Value;
+
+/// Checks that the text contains the needle, ignoring case.
+pub fn contains_ci(text: &str, needle: &str) -> Result<(), String> {
+    match text.to_lowercase().contains(&needle.to_lowercase()) {
+        true => Ok(()),
+        false => Err(format!("expected text to contain '{}': {:?}", needle, text)),
+    }
+}
+
+/// Checks that the text equals the expected text, ignoring case and surrounding
+/// whitespace.
+pub fn eq_ci(text: &str, expected: &str) -> Result<(), String> {
+    match text.trim().to_lowercase() == expected.trim().to_lowercase() {
+        true => Ok(()),
+        false => Err(format!("expected '{}', got {:?}", expected, text)),
+    }
+}
+
+/// Checks that the contents of the first `<tag>` in the text equal the expected text,
+/// ignoring surrounding whitespace.
+pub fn tagged_eq(text: &str, tag: &str, expected: &str) -> Result<(), String> {
+    match tagged(text, &TagOptions::new(tag)) {
+        Some((answer, _)) if answer.trim() == expected.trim() => Ok(()),
+        Some((answer, _)) => Err(format!(
+            "expected <{}> to contain '{}', got {:?}",
+            tag, expected, answer
+        )),
+        None => Err(format!("expected a <{}> tag: {:?}", tag, text)),
+    }
+}
+
+/// Checks that the text contains JSON conforming to the schema, returning the JSON.
+/// The JSON is found as described in [crate::parse::json].
+///
+/// The keywords checked are `type`, `properties`, `required`, `additionalProperties`,
+/// `items`, `enum`, `const`, `minimum`, `maximum`, `anyOf`, `oneOf`, and local `$ref`s,
+/// which covers the schemas generated by schemars. Other keywords are ignored.
+pub fn json_matches(text: &str, schema: &Value) -> Result<Value, String> {
+    let value: Value =
+        crate::parse::json(text).map_err(|e| format!("expected JSON ({}): {:?}", e, text))?;
+    let mut errors = vec![];
+    validate(&value, schema, schema, "$", &mut errors);
+    match errors.is_empty() {
+        true => Ok(value),
+        false => Err(format!(
+            "JSON does not match the schema: {}: {}",
+            errors.join("; "),
+            value
+        )),
+    }
+}
+
+/// Validates the value against the schema, recording a description of each mismatch.
+fn validate(value: &Value, schema: &Value, root: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(r) = schema.get("$ref").and_then(Value::as_str) {
+        match r.strip_prefix('#').map(|p| root.pointer(p)) {
+            Some(Some(schema)) => validate(value, schema, root, path, errors),
+            _ => errors.push(format!("{}: unresolved $ref '{}'", path, r)),
+        }
+        return;
+    }
+
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !types.iter().any(|t| is_type(value, t)) {
+            errors.push(format!(
+                "{}: expected {}, got {}",
+                path,
+                types.join(" or "),
+                value
+            ));
+            return;
+        }
+    }
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            errors.push(format!("{}: {} is not one of {:?}", path, value, options));
+        }
+    }
+    if let Some(c) = schema.get("const") {
+        if c != value {
+            errors.push(format!("{}: expected {}, got {}", path, c, value));
+        }
+    }
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            if n < min {
+                errors.push(format!("{}: {} is less than {}", path, value, min));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            if n > max {
+                errors.push(format!("{}: {} is greater than {}", path, value, max));
+            }
+        }
+    }
+    for key in ["anyOf", "oneOf"] {
+        if let Some(options) = schema.get(key).and_then(Value::as_array) {
+            let matches = options.iter().any(|s| {
+                let mut errs = vec![];
+                validate(value, s, root, path, &mut errs);
+                errs.is_empty()
+            });
+            if !matches {
+                errors.push(format!(
+                    "{}: {} matches none of the {} schemas",
+                    path, value, key
+                ));
+            }
+        }
+    }
+
+    if let Value::Object(obj) = value {
+        let props = schema.get("properties").and_then(Value::as_object);
+        for key in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !obj.contains_key(key) {
+                errors.push(format!("{}: missing required key '{}'", path, key));
+            }
+        }
+        for (k, v) in obj {
+            match props.and_then(|p| p.get(k)) {
+                Some(s) => validate(v, s, root, &format!("{}.{}", path, k), errors),
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    errors.push(format!("{}: unexpected key '{}'", path, k))
+                }
+                None => {}
+            }
+        }
+    }
+    if let (Value::Array(items), Some(s)) = (value, schema.get("items")) {
+        for (i, v) in items.iter().enumerate() {
+            validate(v, s, root, &format!("{}[{}]", path, i), errors);
+        }
+    }
+}
+
+fn is_type(value: &Value, t: &str) -> bool {
+    match t {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|f| f.fract() == 0.0)
+        }
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+/// Asserts that the text contains the needle, ignoring case.
+#[track_caller]
+pub fn assert_contains_ci(text: &str, needle: &str) {
+    if let Err(e) = contains_ci(text, needle) {
+        panic!("{}", e);
+    }
+}
+
+/// Asserts that the text equals the expected text, ignoring case and surrounding
+/// whitespace.
+#[track_caller]
+pub fn assert_eq_ci(text: &str, expected: &str) {
+    if let Err(e) = eq_ci(text, expected) {
+        panic!("{}", e);
+    }
+}
+
+/// Asserts that the contents of the first `<tag>` in the text equal the expected
+/// text, ignoring surrounding whitespace.
+#[track_caller]
+pub fn assert_tagged_eq(text: &str, tag: &str, expected: &str) {
+    if let Err(e) = tagged_eq(text, tag, expected) {
+        panic!("{}", e);
+    }
+}
+
+/// Asserts that the text contains JSON conforming to the schema, as described in
+/// [json_matches], returning the JSON.
+#[track_caller]
+pub fn assert_json_matches(text: &str, schema: &Value) -> Value {
+    json_matches(text, schema).unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn text_assertions() {
+        assert!(contains_ci("The Sausage Party", "sausage party").is_ok());
+        assert!(eq_ci(" 2025-02-11\n", "2025-02-11").is_ok());
+        assert_eq!(
+            eq_ci("no", "yes"),
+            Err("expected 'yes', got \"no\"".to_string())
+        );
+        assert!(tagged_eq("<id> 412 </id>", "id", "412").is_ok());
+        assert_eq!(
+            tagged_eq("<id>3</id>", "id", "412"),
+            Err("expected <id> to contain '412', got \"3\"".to_string())
+        );
+        assert_eq!(
+            tagged_eq("nothing", "id", "412"),
+            Err("expected a <id> tag: \"nothing\"".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "expected text to contain 'london'")]
+    fn assert_panics() {
+        assert_contains_ci("Paris", "london");
+    }
+
+    #[test]
+    fn json_schema() {
+        #[cfg(feature = "schemars")]
+        {
+            #[allow(dead_code)]
+            #[derive(schemars::JsonSchema)]
+            struct Mem {
+                id: u32,
+                date: Option<String>,
+                kind: Kind,
+            }
+            #[allow(dead_code)]
+            #[derive(schemars::JsonSchema)]
+            enum Kind {
+                Note,
+                Event,
+            }
+            let schema = crate::json_schema::<Mem>();
+
+            let v = json_matches(
+                "```json\n{\"id\": 412, \"date\": null, \"kind\": \"Event\"}\n```",
+                &schema,
+            )
+            .unwrap();
+            assert_eq!(v["id"], 412);
+
+            let err = json_matches("{\"id\": -1, \"kind\": \"Party\"}", &schema).unwrap_err();
+            assert!(err.contains("$.id: -1 is less than"), "{}", err);
+            assert!(err.contains("$.kind: \"Party\" is not one of"), "{}", err);
+        }
+
+        let schema = json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {"n": {"type": "integer"}},
+                "required": ["n"],
+                "additionalProperties": false,
+            },
+        });
+        assert_eq!(
+            json_matches("[{\"n\": 1.5}, {\"m\": 2}]", &schema).unwrap_err(),
+            "JSON does not match the schema: $[0].n: expected integer, got 1.5; \
+            $[1]: missing required key 'n'; $[1]: unexpected key 'm': [{\"n\":1.5},{\"m\":2}]"
+        );
+        assert!(json_matches("not json", &schema).is_err());
+    }
+}
@@ -0,0 +1,741 @@
+//! Submitting many calls at once to a provider's batch API, which costs less than
+//! making each call individually, but may take up to a day to finish.
+//!
+//! Batches can be submitted to OpenAI with [Openai](crate::callers::Openai) and to
+//! Anthropic with [Anthropic](crate::callers::Anthropic), which both implement
+//! [BatchCaller].
+//!
+//! Calls which should finish sooner can instead be made concurrently with
+//! [concurrent], which reports progress as calls finish and can be stopped early.
+
+use crate::tokens;
+#[cfg(feature = "anthropic")]
+use crate::{
+    callers::{anthropic_response, Anthropic},
+    data_model::{
+        AnthropicBatch, AnthropicBatchOutcome, AnthropicBatchRequest, AnthropicBatchResult,
+    },
+    models::AnthropicModel,
+};
+#[cfg(feature = "openai")]
+use crate::{
+    callers::{oai_response, Openai},
+    data_model::{OAIBatch, OAIBatchRequest, OAIBatchResult, OAICompletionsResponse, OAIFile},
+    models::OpenAIModel,
+};
+use crate::{CallBase, CallErr, CallResp, ModelCaller, Turn};
+use futures::StreamExt;
+#[cfg(feature = "openai")]
+use reqwest::StatusCode;
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+use reqwest::{Client, RequestBuilder, Response};
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "openai")]
+use serde::Deserialize;
+use std::ops::ControlFlow;
+use std::time::Duration;
+#[cfg(feature = "openai")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A type which is able to submit calls to a provider's batch API.
+pub trait BatchCaller: Send + Sync {
+    /// Submits the calls as a batch, returning the ID of the batch.
+    fn submit(
+        &self,
+        calls: Vec<(CallBase, Vec<Turn>)>,
+    ) -> impl std::future::Future<Output = Result<String, CallErr>> + Send;
+
+    /// Checks on a batch, returning the result of each call once the batch has finished.
+    fn poll(
+        &self,
+        id: &str,
+    ) -> impl std::future::Future<Output = Result<BatchStatus, CallErr>> + Send;
+}
+
+/// The state of a batch, see [BatchCaller::poll].
+#[derive(Debug)]
+pub enum BatchStatus {
+    /// The batch is still running, and has finished `completed` of its `total` calls.
+    Pending { completed: usize, total: usize },
+    /// The batch has finished, with the result of each call in the order submitted.
+    /// Calls which the provider did not get to before the batch expired or was
+    /// cancelled have failed.
+    Done(Vec<Result<CallResp, CallErr>>),
+}
+
+/// Submits the calls as a batch and waits for it to finish, checking on it every
+/// `poll_interval`. The result of each call is returned in the order given.
+///
+/// This must be run within a tokio runtime.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "openai")] {
+/// # use mini_prompt::*;
+/// # use std::time::Duration;
+/// let backend = callers::Openai::<models::GPT41Mini>::default();
+/// # tokio::task::spawn(async move {
+/// let calls = ["cat", "dog", "fish"]
+///     .iter()
+///     .map(|animal| {
+///         let params = CallBase {
+///             instructions: format!("Write a haiku about a {}.", animal),
+///             ..Default::default()
+///         };
+///         (params, vec![])
+///     })
+///     .collect();
+///
+/// let results = batch::run(&backend, calls, Duration::from_secs(60))
+///     .await
+///     .unwrap();
+/// for haiku in results.into_iter().flatten() {
+///     println!("{}\n", haiku.content.text());
+/// }
+/// # });
+/// # }
+/// ```
+pub async fn run<B: BatchCaller>(
+    backend: &B,
+    calls: Vec<(CallBase, Vec<Turn>)>,
+    poll_interval: Duration,
+) -> Result<Vec<Result<CallResp, CallErr>>, CallErr> {
+    let prompts: Vec<_> = calls.iter().map(|(p, _)| p.prompt.clone()).collect();
+    let id = backend.submit(calls).await?;
+    loop {
+        match backend.poll(&id).await? {
+            BatchStatus::Pending { .. } => tokio::time::sleep(poll_interval).await,
+            BatchStatus::Done(mut results) => {
+                results.resize_with(prompts.len(), || Err("no result: batch ended".into()));
+                for (result, prompt) in results.iter_mut().zip(prompts) {
+                    if let Ok(resp) = result {
+                        resp.prompt = prompt;
+                    }
+                }
+                return Ok(results);
+            }
+        }
+    }
+}
+
+/// How far [concurrent] has got through its calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of calls which have finished, including those which failed.
+    pub completed: usize,
+    pub total: usize,
+    /// The number of finished calls which failed.
+    pub failures: usize,
+    /// The tokens generated by the successful calls so far, estimated with
+    /// [tokens::estimate].
+    pub tokens: usize,
+}
+
+/// Makes the calls concurrently, at most `limit` at a time, returning the result of
+/// each call in the order given.
+///
+/// `progress` is called each time a call finishes. If it returns
+/// [ControlFlow::Break], no more calls are started and calls in flight are dropped,
+/// failing along with those which hadn't started.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "openrouter")] {
+/// # use mini_prompt::*;
+/// # use std::ops::ControlFlow;
+/// let backend = callers::Openrouter::<models::Gemma27B3>::default();
+/// # tokio::task::spawn(async move {
+/// let calls = (1..=100)
+///     .map(|n| {
+///         let params = CallBase {
+///             instructions: format!("Write a haiku about the number {}.", n),
+///             ..Default::default()
+///         };
+///         (params, vec![])
+///     })
+///     .collect();
+///
+/// let results = batch::concurrent(&backend, calls, 8, |p| {
+///     println!("{}/{} done, {} failed", p.completed, p.total, p.failures);
+///     if p.failures > 10 {
+///         ControlFlow::Break(())
+///     } else {
+///         ControlFlow::Continue(())
+///     }
+/// })
+/// .await;
+/// # });
+/// # }
+/// ```
+pub async fn concurrent<B, F>(
+    backend: &B,
+    calls: Vec<(CallBase, Vec<Turn>)>,
+    limit: usize,
+    mut progress: F,
+) -> Vec<Result<CallResp, CallErr>>
+where
+    B: ModelCaller,
+    F: FnMut(&Progress) -> ControlFlow<()>,
+{
+    let mut state = Progress {
+        total: calls.len(),
+        ..Default::default()
+    };
+    let mut out: Vec<Option<Result<CallResp, CallErr>>> = (0..calls.len()).map(|_| None).collect();
+    let mut pending = futures::stream::iter(calls.into_iter().enumerate())
+        .map(|(i, (params, turns))| async move { (i, backend.call(params, turns).await) })
+        .buffer_unordered(limit.max(1));
+
+    while let Some((i, result)) = pending.next().await {
+        state.completed += 1;
+        match &result {
+            Ok(resp) => state.tokens += tokens::count_turn(&resp.content, tokens::estimate),
+            Err(_) => state.failures += 1,
+        }
+        out[i] = Some(result);
+        if progress(&state).is_break() {
+            break;
+        }
+    }
+    out.into_iter()
+        .map(|r| r.unwrap_or_else(|| Err("no result: aborted".into())))
+        .collect()
+}
+
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+/// Sends a request, deserializing the JSON response.
+async fn send<T: DeserializeOwned>(req: RequestBuilder) -> Result<T, CallErr> {
+    let resp = req.send().await?;
+    if !resp.status().is_success() {
+        return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+    }
+    Ok(resp.json().await?)
+}
+
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+/// The result of a call in a batch, with the custom ID of the call.
+type Keyed = (String, Result<CallResp, CallErr>);
+
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+/// Orders results by their custom ID, which is the index of the call. Calls without a
+/// result fail, mentioning the status of the batch.
+///
+/// If the provider did not report the number of calls, there is one for each custom ID
+/// up to the largest.
+fn in_order(
+    total: Option<usize>,
+    results: Vec<Keyed>,
+    status: &str,
+) -> Vec<Result<CallResp, CallErr>> {
+    let total = total.unwrap_or_else(|| {
+        results
+            .iter()
+            .filter_map(|(id, _)| id.parse::<usize>().ok())
+            .max()
+            .map_or(0, |i| i + 1)
+    });
+    let mut out: Vec<Option<Result<CallResp, CallErr>>> = (0..total).map(|_| None).collect();
+    for (id, result) in results {
+        if let Some(slot) = id.parse::<usize>().ok().and_then(|i| out.get_mut(i)) {
+            *slot = Some(result);
+        }
+    }
+    out.into_iter()
+        .map(|r| r.unwrap_or_else(|| Err(format!("no result: batch {}", status).into())))
+        .collect()
+}
+
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+/// Splits a body into lines as it is received, so large result files can be decoded
+/// without holding them in memory whole.
+#[derive(Default)]
+struct Lines {
+    partial: Vec<u8>,
+}
+
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+impl Lines {
+    /// Calls `f` with each non-blank line completed by the chunk.
+    fn push<F>(&mut self, chunk: &[u8], f: &mut F) -> Result<(), CallErr>
+    where
+        F: FnMut(&str) -> Result<(), CallErr>,
+    {
+        self.partial.extend_from_slice(chunk);
+        let Some(end) = self.partial.iter().rposition(|b| *b == b'\n') else {
+            return Ok(());
+        };
+        Self::each(&self.partial[..end], f)?;
+        self.partial.drain(..=end);
+        Ok(())
+    }
+
+    /// Calls `f` with the last line, if it wasn't terminated.
+    fn finish<F>(self, f: &mut F) -> Result<(), CallErr>
+    where
+        F: FnMut(&str) -> Result<(), CallErr>,
+    {
+        Self::each(&self.partial, f)
+    }
+
+    fn each<F>(bytes: &[u8], f: &mut F) -> Result<(), CallErr>
+    where
+        F: FnMut(&str) -> Result<(), CallErr>,
+    {
+        let text = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+        text.lines()
+            .filter(|l| !l.trim().is_empty())
+            .try_for_each(f)
+    }
+}
+
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+/// Reads the body of a response, calling `f` with each non-blank line as it arrives.
+async fn for_each_line<F>(mut resp: Response, mut f: F) -> Result<(), CallErr>
+where
+    F: FnMut(&str) -> Result<(), CallErr>,
+{
+    let mut lines = Lines::default();
+    while let Some(chunk) = resp.chunk().await? {
+        lines.push(&chunk, &mut f)?;
+    }
+    lines.finish(&mut f)
+}
+
+#[cfg(feature = "openai")]
+/// Converts a line of the output or error file of an OpenAI batch into a result.
+fn oai_result(model: &str, line: &str) -> Result<Keyed, CallErr> {
+    let r: OAIBatchResult = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let result = match (r.response, r.error) {
+        (Some(resp), _) if resp.status_code == 200 => {
+            let res = OAICompletionsResponse::deserialize(&resp.body);
+            let raw = cfg!(feature = "raw-response").then_some(resp.body);
+            res.map_err(|e| e.to_string().into())
+                .and_then(|res| oai_response(res, model, None))
+                .map(|res| CallResp { raw, ..res })
+        }
+        (Some(resp), _) => Err(CallErr::RequestFailed(
+            StatusCode::from_u16(resp.status_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            resp.body.to_string(),
+        )),
+        (None, Some(err)) => Err(err.message.into()),
+        (None, None) => Err("batch result has no response".into()),
+    };
+    Ok((r.custom_id, result))
+}
+
+#[cfg(feature = "openai")]
+/// Batches are submitted to the OpenAI Batch API, so results may take up to 24 hours.
+impl<M: OpenAIModel> BatchCaller for Openai<M> {
+    async fn submit(&self, calls: Vec<(CallBase, Vec<Turn>)>) -> Result<String, CallErr> {
+        let mut jsonl = String::new();
+        for (i, (params, turns)) in calls.into_iter().enumerate() {
+            let line = OAIBatchRequest {
+                custom_id: i.to_string(),
+                method: "POST".into(),
+                url: "/v1/chat/completions".into(),
+                body: self.completions_request(params, turns)?,
+            };
+            jsonl += &serde_json::to_string(&line).map_err(|e| e.to_string())?;
+            jsonl.push('\n');
+        }
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let boundary = format!("mini-prompt-{:x}", nanos);
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\nbatch\r\n\
+            --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"batch.jsonl\"\r\n\
+            Content-Type: application/jsonl\r\n\r\n{jsonl}\r\n--{b}--\r\n",
+            b = boundary,
+            jsonl = jsonl,
+        );
+
+        let client = Client::new();
+        let file: OAIFile = send(
+            client
+                .post(format!("{}/files", self.base_url()?))
+                .headers(self.headers().await?)
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={}", boundary),
+                )
+                .body(body),
+        )
+        .await?;
+
+        let batch: OAIBatch = send(
+            client
+                .post(format!("{}/batches", self.base_url()?))
+                .headers(self.headers().await?)
+                .json(&serde_json::json!({
+                    "input_file_id": file.id,
+                    "endpoint": "/v1/chat/completions",
+                    "completion_window": "24h",
+                })),
+        )
+        .await?;
+        Ok(batch.id)
+    }
+
+    async fn poll(&self, id: &str) -> Result<BatchStatus, CallErr> {
+        let client = Client::new();
+        let batch: OAIBatch = send(
+            client
+                .get(format!("{}/batches/{}", self.base_url()?, id))
+                .headers(self.headers().await?),
+        )
+        .await?;
+
+        let total = batch.request_counts.as_ref().map(|c| c.total);
+        match batch.status.as_str() {
+            "completed" | "expired" | "cancelled" => {}
+            "failed" => {
+                let errors = batch.errors.map(|e| e.data).unwrap_or_default();
+                let errors: Vec<_> = errors.into_iter().map(|e| e.message).collect();
+                return Err(format!("batch failed: {}", errors.join("; ")).into());
+            }
+            _ => {
+                let counts = batch.request_counts.unwrap_or_default();
+                return Ok(BatchStatus::Pending {
+                    completed: counts.completed + counts.failed,
+                    total: counts.total,
+                });
+            }
+        }
+
+        let mut results = Vec::new();
+        for file in [batch.output_file_id, batch.error_file_id]
+            .into_iter()
+            .flatten()
+        {
+            let resp = client
+                .get(format!("{}/files/{}/content", self.base_url()?, file))
+                .headers(self.headers().await?)
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+            }
+            for_each_line(resp, |line| {
+                results.push(oai_result(M::MODEL_STR, line)?);
+                Ok(())
+            })
+            .await?;
+        }
+
+        Ok(BatchStatus::Done(in_order(total, results, &batch.status)))
+    }
+}
+
+#[cfg(feature = "anthropic")]
+/// Converts a line of the results of an Anthropic message batch into a result.
+fn anthropic_result(model: &str, line: &str) -> Result<Keyed, CallErr> {
+    let r: AnthropicBatchResult = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let result = match r.result {
+        AnthropicBatchOutcome::Succeeded { message } => {
+            let raw = match cfg!(feature = "raw-response") {
+                true => serde_json::from_str::<serde_json::Value>(line)
+                    .ok()
+                    .and_then(|v| v.pointer("/result/message").cloned()),
+                false => None,
+            };
+            anthropic_response(message, model, None).map(|res| CallResp { raw, ..res })
+        }
+        AnthropicBatchOutcome::Errored { error } => Err(error
+            .pointer("/error/message")
+            .and_then(|m| m.as_str())
+            .map_or_else(|| error.to_string(), String::from)
+            .into()),
+        AnthropicBatchOutcome::Canceled => Err("call was canceled".into()),
+        AnthropicBatchOutcome::Expired => Err("call expired".into()),
+    };
+    Ok((r.custom_id, result))
+}
+
+#[cfg(feature = "anthropic")]
+/// Batches are submitted to the Anthropic message batches API, so results may take up
+/// to 24 hours.
+impl<M: AnthropicModel> BatchCaller for Anthropic<M> {
+    async fn submit(&self, calls: Vec<(CallBase, Vec<Turn>)>) -> Result<String, CallErr> {
+        let requests = calls
+            .into_iter()
+            .enumerate()
+            .map(|(i, (params, turns))| {
+                Ok(AnthropicBatchRequest {
+                    custom_id: i.to_string(),
+                    params: self.msg_request(params, turns)?,
+                })
+            })
+            .collect::<Result<Vec<_>, CallErr>>()?;
+
+        let batch: AnthropicBatch = send(
+            Client::new()
+                .post(format!("{}/messages/batches", self.base_url()?))
+                .headers(self.headers().await?)
+                .json(&serde_json::json!({ "requests": requests })),
+        )
+        .await?;
+        Ok(batch.id)
+    }
+
+    async fn poll(&self, id: &str) -> Result<BatchStatus, CallErr> {
+        let client = Client::new();
+        let batch: AnthropicBatch = send(
+            client
+                .get(format!("{}/messages/batches/{}", self.base_url()?, id))
+                .headers(self.headers().await?),
+        )
+        .await?;
+
+        let counts = &batch.request_counts;
+        let completed = counts.succeeded + counts.errored + counts.canceled + counts.expired;
+        let total = completed + counts.processing;
+        let results_url = match (batch.processing_status.as_str(), batch.results_url) {
+            ("ended", Some(url)) => url,
+            ("ended", None) => return Err("batch ended without results".into()),
+            _ => return Ok(BatchStatus::Pending { completed, total }),
+        };
+
+        let resp = client
+            .get(results_url)
+            .headers(self.headers().await?)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+        }
+
+        let mut results = Vec::new();
+        for_each_line(resp, |line| {
+            results.push(anthropic_result(M::MODEL_STR, line)?);
+            Ok(())
+        })
+        .await?;
+        Ok(BatchStatus::Done(in_order(Some(total), results, "ended")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scripted;
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    use {
+        crate::prompt::PromptRef,
+        std::sync::atomic::{AtomicUsize, Ordering},
+        std::sync::Mutex,
+    };
+
+    /// Finishes a batch on the second poll, answering each call with its instructions.
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    #[derive(Default)]
+    struct Echo {
+        calls: Mutex<Vec<(CallBase, Vec<Turn>)>>,
+        polls: AtomicUsize,
+    }
+
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    impl BatchCaller for Echo {
+        async fn submit(&self, calls: Vec<(CallBase, Vec<Turn>)>) -> Result<String, CallErr> {
+            *self.calls.lock().unwrap() = calls;
+            Ok("batch_1".into())
+        }
+
+        async fn poll(&self, id: &str) -> Result<BatchStatus, CallErr> {
+            assert_eq!(id, "batch_1");
+            let calls = self.calls.lock().unwrap();
+            if self.polls.fetch_add(1, Ordering::Relaxed) < 1 {
+                return Ok(BatchStatus::Pending {
+                    completed: 0,
+                    total: calls.len(),
+                });
+            }
+            let results = calls
+                .iter()
+                .enumerate()
+                .map(|(i, (p, _))| (i.to_string(), Ok(Scripted::text(&p.instructions))))
+                .rev()
+                .take(calls.len() - 1)
+                .collect();
+            Ok(BatchStatus::Done(in_order(
+                Some(calls.len()),
+                results,
+                "expired",
+            )))
+        }
+    }
+
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    #[tokio::test]
+    async fn run_until_done() {
+        let prompt = PromptRef {
+            name: "echo".into(),
+            version: 1,
+            hash: "abc".into(),
+        };
+        let calls = ["a", "b", "c"]
+            .iter()
+            .map(|s| {
+                let params = CallBase {
+                    instructions: s.to_string(),
+                    prompt: Some(prompt.clone()),
+                    ..Default::default()
+                };
+                (params, vec![])
+            })
+            .collect();
+
+        let backend = Echo::default();
+        let results = run(&backend, calls, Duration::ZERO).await.unwrap();
+        assert_eq!(backend.polls.into_inner(), 2);
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_err());
+        let resp = results[2].as_ref().unwrap();
+        assert_eq!(resp.content.text(), "c");
+        assert_eq!(resp.prompt, Some(prompt));
+    }
+
+    /// Finishes a batch immediately, without any results.
+    struct Empty;
+
+    impl BatchCaller for Empty {
+        async fn submit(&self, _calls: Vec<(CallBase, Vec<Turn>)>) -> Result<String, CallErr> {
+            Ok("batch_1".into())
+        }
+
+        async fn poll(&self, _id: &str) -> Result<BatchStatus, CallErr> {
+            Ok(BatchStatus::Done(vec![]))
+        }
+    }
+
+    #[tokio::test]
+    async fn run_fails_calls_without_results() {
+        let calls = vec![(CallBase::default(), vec![]); 2];
+        let results = run(&Empty, calls, Duration::ZERO).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(
+            matches!(&results[1], Err(CallErr::Other(e)) if e.to_string() == "no result: batch ended")
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_with_progress() {
+        let backend = Scripted::new(vec![Scripted::text("one"), Scripted::text("two")]);
+        let calls = vec![(CallBase::default(), vec![]); 4];
+        let mut seen = vec![];
+        let results = concurrent(&backend, calls, 1, |p| {
+            seen.push(*p);
+            if p.completed == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .await;
+
+        // The third call fails as the script has run out, then the fourth isn't made.
+        assert_eq!(backend.seen().len(), 3);
+        assert_eq!(results[1].as_ref().unwrap().content.text(), "two");
+        assert!(matches!(results[2], Err(CallErr::NoCompletions)));
+        assert!(
+            matches!(&results[3], Err(CallErr::Other(e)) if e.to_string() == "no result: aborted")
+        );
+        let tokens = tokens::count_turn(&Scripted::text("one").content, tokens::estimate);
+        assert_eq!(
+            seen.last(),
+            Some(&Progress {
+                completed: 3,
+                total: 4,
+                failures: 1,
+                tokens: tokens * 2,
+            })
+        );
+    }
+
+    /// Parses the lines of a results file in chunks of 7 bytes, as if streamed.
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    fn parse(lines: &str, f: fn(&str, &str) -> Result<Keyed, CallErr>) -> Vec<Keyed> {
+        let (mut out, mut split) = (Vec::new(), Lines::default());
+        let mut each = |line: &str| {
+            out.push(f("model", line)?);
+            Ok(())
+        };
+        for chunk in lines.as_bytes().chunks(7) {
+            split.push(chunk, &mut each).unwrap();
+        }
+        split.finish(&mut each).unwrap();
+        out
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn parse_oai_results() {
+        let lines = r#"
+{"id": "r1", "custom_id": "1", "response": {"status_code": 200, "body": {"id": "c1", "object": "chat.completion", "model": "gpt", "choices": [{"index": 0, "message": {"role": "assistant", "content": "hi"}, "finish_reason": "stop"}]}}, "error": null}
+{"id": "r2", "custom_id": "0", "response": {"status_code": 400, "body": {"error": "bad"}}, "error": null}
+
+{"id": "r3", "custom_id": "2", "response": null, "error": {"code": "batch_expired", "message": "expired"}}
+"#;
+        let results = in_order(Some(4), parse(lines, oai_result), "expired");
+        assert!(matches!(
+            results[0],
+            Err(CallErr::RequestFailed(StatusCode::BAD_REQUEST, _))
+        ));
+        assert_eq!(results[1].as_ref().unwrap().content.text(), "hi");
+        #[cfg(feature = "raw-response")]
+        assert!(results[1].as_ref().unwrap().raw.as_ref().unwrap()["choices"].is_array());
+        assert!(matches!(&results[2], Err(CallErr::Other(e)) if e.to_string() == "expired"));
+        assert!(
+            matches!(&results[3], Err(CallErr::Other(e)) if e.to_string() == "no result: batch expired")
+        );
+
+        assert!(oai_result("gpt", "not json").is_err());
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn oai_results_without_counts() {
+        let lines = r#"
+{"id": "r1", "custom_id": "2", "response": {"status_code": 200, "body": {"id": "c1", "object": "chat.completion", "model": "gpt", "choices": [{"index": 0, "message": {"role": "assistant", "content": "hi"}, "finish_reason": "stop"}]}}, "error": null}
+{"id": "r2", "custom_id": "0", "response": null, "error": {"code": "batch_expired", "message": "expired"}}
+"#;
+        let results = in_order(None, parse(lines, oai_result), "completed");
+        assert_eq!(results.len(), 3);
+        assert!(matches!(&results[0], Err(CallErr::Other(e)) if e.to_string() == "expired"));
+        assert!(
+            matches!(&results[1], Err(CallErr::Other(e)) if e.to_string() == "no result: batch completed")
+        );
+        assert_eq!(results[2].as_ref().unwrap().content.text(), "hi");
+    }
+
+    #[cfg(feature = "anthropic")]
+    #[test]
+    fn parse_anthropic_results() {
+        let lines = r#"
+{"custom_id": "2", "result": {"type": "succeeded", "message": {"id": "msg_1", "type": "message", "role": "assistant", "model": "claude", "content": [{"type": "text", "text": "hi"}], "stop_reason": "end_turn"}}}
+{"custom_id": "0", "result": {"type": "errored", "error": {"type": "error", "error": {"type": "invalid_request_error", "message": "bad"}}}}
+{"custom_id": "1", "result": {"type": "expired"}}
+"#;
+        let results = in_order(Some(3), parse(lines, anthropic_result), "ended");
+        assert!(matches!(&results[0], Err(CallErr::Other(e)) if e.to_string() == "bad"));
+        assert!(matches!(&results[1], Err(CallErr::Other(e)) if e.to_string() == "call expired"));
+        let resp = results[2].as_ref().unwrap();
+        assert_eq!(resp.content.text(), "hi");
+        assert_eq!(resp.model, "claude");
+    }
+
+    #[cfg(feature = "anthropic")]
+    #[tokio::test]
+    async fn anthropic_rejects_constraints() {
+        let params = CallBase {
+            constraint: Some(crate::Constraint::Regex("[0-9]+".into())),
+            ..Default::default()
+        };
+        let backend = Anthropic::<crate::models::ClaudeHaiku35>::default();
+        assert!(matches!(
+            backend.submit(vec![(params, vec![])]).await,
+            Err(CallErr::Other(_))
+        ));
+    }
+}
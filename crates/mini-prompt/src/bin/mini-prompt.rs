@@ -0,0 +1,134 @@
+//! Calls a model from the command line, for shell pipelines and trying out prompts.
+//!
+//! ```sh
+//! git diff | mini-prompt -p anthropic "Write a commit message for this diff."
+//! mini-prompt --json-schema answer.json "What's 2+2?"
+//! mini-prompt --stream "Tell me a story about a crab."
+//! ```
+
+use clap::Parser;
+use mini_prompt::config::Config;
+use mini_prompt::{CallBase, CallErr, Message, ModelCaller, Role, Turn};
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Calls a model with a prompt, printing the response.
+///
+/// The provider, model, and keys which aren't given as options are read from the
+/// environment and the mini-prompt config file.
+#[derive(Parser)]
+#[command(name = "mini-prompt", version)]
+struct Args {
+    /// The prompt. If input is piped to stdin, it is appended to the prompt.
+    prompt: Vec<String>,
+    /// The provider to call: openrouter, anthropic, or openai.
+    #[arg(short, long)]
+    provider: Option<String>,
+    /// The model to call, as named by the provider.
+    #[arg(short, long)]
+    model: Option<String>,
+    /// The system prompt.
+    #[arg(short, long)]
+    system: Option<String>,
+    /// The sampling temperature, typically from 0 to 1.
+    #[arg(short, long)]
+    temperature: Option<f32>,
+    /// A file containing a JSON schema the response must match. The response is
+    /// printed as JSON.
+    #[arg(long, value_name = "FILE")]
+    json_schema: Option<PathBuf>,
+    /// How many times a response which doesn't match the schema is retried.
+    #[arg(long, default_value_t = 2)]
+    retries: usize,
+    /// Prints the response as it is generated.
+    #[arg(long, conflicts_with = "json_schema")]
+    stream: bool,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(out) => {
+            println!("{}", out);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            match e {
+                CallErr::Other(e) => eprintln!("mini-prompt: {}", e),
+                e => eprintln!("mini-prompt: {:?}", e),
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<String, CallErr> {
+    let mut prompt = args.prompt.join(" ");
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        let mut input = String::new();
+        stdin
+            .lock()
+            .read_to_string(&mut input)
+            .map_err(|e| format!("reading stdin: {}", e))?;
+        if !input.trim().is_empty() {
+            prompt = match prompt.is_empty() {
+                true => input,
+                false => prompt + "\n\n" + &input,
+            };
+        }
+    }
+    if prompt.trim().is_empty() {
+        return Err("no prompt: pass it as arguments or on stdin".into());
+    }
+
+    // The configured model is only used with the configured provider.
+    let config = Config::load()?;
+    let backend = match args.provider {
+        Some(p) if Some(&p) != config.provider.as_ref() => Config {
+            provider: Some(p),
+            model: args.model,
+            ..config
+        },
+        _ => Config {
+            model: args.model.or(config.model.clone()),
+            ..config
+        },
+    }
+    .caller()?;
+
+    let params = CallBase {
+        system: args.system.unwrap_or_default(),
+        temperature: args.temperature,
+        ..Default::default()
+    };
+    let turns = vec![Turn {
+        role: Role::User,
+        content: vec![Message::text(prompt)],
+    }];
+
+    match args.json_schema {
+        Some(path) => {
+            let schema = std::fs::read_to_string(&path)
+                .map_err(|e| format!("reading {}: {}", path.display(), e))?;
+            let schema = serde_json::from_str(&schema)
+                .map_err(|e| format!("invalid schema {}: {}", path.display(), e))?;
+            let resp = backend
+                .call_with_schema(params, turns, schema, args.retries)
+                .await?;
+            Ok(serde_json::to_string_pretty(&resp).map_err(|e| e.to_string())?)
+        }
+        None if args.stream => {
+            backend
+                .call_streaming(params, turns, |text| {
+                    print!("{}", text);
+                    let _ = std::io::stdout().flush();
+                })
+                .await?;
+            // The response was printed as it arrived, so only the newline is left.
+            Ok(String::new())
+        }
+        None => Ok(backend.call(params, turns).await?.content.text()),
+    }
+}
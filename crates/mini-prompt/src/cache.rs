@@ -0,0 +1,517 @@
+//! Caching model responses, so repeated calls don't cost another request.
+//!
+//! [Cached] answers calls identical to an earlier one, such as when rerunning a
+//! deterministic pipeline, while [SemanticCache] also answers calls whose prompts are
+//! merely similar.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "openrouter")] {
+//! # use mini_prompt::*;
+//! use mini_prompt::cache::{Cached, FileStore};
+//!
+//! let backend = Cached::new(
+//!     callers::Openrouter::<models::Gemma27B3>::default(),
+//!     FileStore::new(".cache/responses"),
+//! );
+//! # tokio::task::spawn(async move {
+//! // Only the first run of the program makes a request.
+//! backend.simple_call("Name a color.").await.unwrap();
+//! # });
+//! # }
+//! ```
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "openrouter")] {
+//! # use mini_prompt::*;
+//! use mini_prompt::cache::SemanticCache;
+//! use std::time::Duration;
+//!
+//! let backend = SemanticCache::new(
+//!     callers::Openrouter::<models::Gemma27B3>::default(),
+//!     embed::Openai::<models::TextEmbedding3Small>::default(),
+//! )
+//! .threshold(0.92)
+//! .ttl(Duration::from_secs(60 * 60));
+//! # tokio::task::spawn(async move {
+//! backend.simple_call("How do I reset my password?").await.unwrap();
+//! // Likely answered from the cache.
+//! backend.simple_call("how can I reset my password").await.unwrap();
+//! println!("{:?}", backend.stats());
+//! # });
+//! # }
+//! ```
+
+use crate::embed::{cosine_similarity, Embedder};
+use crate::models::Model;
+use crate::{CallBase, CallErr, CallResp, Message, ModelCaller, Turn};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often a cache was able to answer calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Calls answered from the cache.
+    pub hits: usize,
+    /// Calls passed on to the backend.
+    pub misses: usize,
+}
+
+/// Somewhere responses can be kept by [Cached], keyed by a hash of the call.
+///
+/// Stores are shared by concurrent calls, so stores which keep state use interior
+/// mutability.
+pub trait CacheStore: Send + Sync {
+    /// Returns the response stored under the key, if any.
+    fn get(&self, key: &str) -> Option<CallResp>;
+    /// Stores the response under the key, replacing any existing response.
+    fn put(&self, key: &str, resp: &CallResp);
+}
+
+/// A [CacheStore] which keeps responses in memory, evicting the least recently used
+/// once it is full.
+#[derive(Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    /// Responses, along with the tick they were last used.
+    entries: Mutex<HashMap<String, (CallResp, u64)>>,
+    tick: AtomicU64,
+}
+
+impl MemoryStore {
+    /// Constructs a store which keeps at most `capacity` responses.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+            tick: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the number of stored responses.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Returns true if no responses are stored.
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<CallResp> {
+        let tick = self.tick.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut entries = self.entries.lock().unwrap();
+        let (resp, used) = entries.get_mut(key)?;
+        *used = tick;
+        Some(resp.clone())
+    }
+
+    fn put(&self, key: &str, resp: &CallResp) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.tick.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(key) {
+            let lru = entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| k.clone());
+            if let Some(k) = lru {
+                entries.remove(&k);
+            }
+        }
+        entries.insert(key.to_string(), (resp.clone(), tick));
+    }
+}
+
+/// A [CacheStore] which keeps each response as a JSON file in a directory, so
+/// responses are kept between runs.
+///
+/// The directory is created when the first response is stored. Files which can't be
+/// read or written are treated as missing, so a broken cache never fails a call.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// Constructs a store which keeps responses in the given directory.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+impl CacheStore for FileStore {
+    fn get(&self, key: &str) -> Option<CallResp> {
+        let data = std::fs::read(self.path(key)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    fn put(&self, key: &str, resp: &CallResp) {
+        if let Ok(data) = serde_json::to_vec(resp) {
+            let _ = std::fs::create_dir_all(&self.dir);
+            let _ = std::fs::write(self.path(key), data);
+        }
+    }
+}
+
+/// Returns a hash of the call to the given [endpoint](ModelCaller::endpoint) as a hex
+/// string, which is the same between runs as long as the serialized form of the call
+/// doesn't change.
+fn call_key(endpoint: &str, params: &CallBase, turns: &[Turn]) -> String {
+    let data = serde_json::to_vec(&(endpoint, params, turns)).unwrap_or_default();
+    // 64-bit FNV-1a.
+    let hash = data.iter().fold(0xcbf29ce484222325u64, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Wraps a [ModelCaller], answering calls identical to an earlier one from a
+/// [CacheStore].
+///
+/// Calls are keyed by a hash of the [endpoint](ModelCaller::endpoint) of the backend,
+/// which includes the model, along with the parameters and turns of the call. Only
+/// successful responses are stored.
+pub struct Cached<B: ModelCaller, S: CacheStore = MemoryStore> {
+    backend: B,
+    store: S,
+    stats: Mutex<Stats>,
+}
+
+impl<B: ModelCaller, S: CacheStore> Cached<B, S> {
+    /// Constructs a cache in front of the given backend, keeping responses in the store.
+    pub fn new(backend: B, store: S) -> Self {
+        Self {
+            backend,
+            store,
+            stats: Mutex::default(),
+        }
+    }
+
+    /// Returns how often calls were answered from the cache.
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Returns the store responses are kept in.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Consumes the wrapper, returning the underlying backend.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+}
+
+impl<B: ModelCaller, S: CacheStore> ModelCaller for Cached<B, S> {
+    fn get_model(&self) -> impl Model {
+        self.backend.get_model()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.backend.supports_response_schema()
+    }
+
+    fn endpoint(&self) -> String {
+        self.backend.endpoint()
+    }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        let key = call_key(&self.backend.endpoint(), &params, &turns);
+        if let Some(resp) = self.store.get(&key) {
+            self.stats.lock().unwrap().hits += 1;
+            return Ok(resp);
+        }
+
+        self.stats.lock().unwrap().misses += 1;
+        let resp = self.backend.call(params, turns).await?;
+        self.store.put(&key, &resp);
+        Ok(resp)
+    }
+}
+
+/// A cached response, and what it was a response to.
+struct Entry {
+    /// The parameters of the call other than the instructions, which must match exactly.
+    scope: String,
+    embedding: Vec<f32>,
+    resp: CallResp,
+    created: Instant,
+    used: Instant,
+}
+
+/// Wraps a [ModelCaller], answering calls from a cache of earlier responses when the
+/// prompt is similar enough to that of an earlier call.
+///
+/// The instructions and the text of each turn are embedded and compared by cosine
+/// similarity, while all other parameters, such as the system prompt and tools, must
+/// match exactly. Calls whose turns contain tool calls or results bypass the cache.
+///
+/// Similarity defaults to at least 0.95, entries never expire, and at most 1000 are
+/// kept, evicting the least recently used. Failed calls aren't cached, and if the
+/// prompt can't be embedded the call is passed on to the backend without caching.
+pub struct SemanticCache<B: ModelCaller, E: Embedder> {
+    backend: B,
+    embedder: E,
+    threshold: f32,
+    ttl: Option<Duration>,
+    max_entries: usize,
+    entries: Mutex<Vec<Entry>>,
+    stats: Mutex<Stats>,
+}
+
+impl<B: ModelCaller, E: Embedder> SemanticCache<B, E> {
+    /// Constructs a cache in front of the given backend, using the embedder to compare
+    /// prompts.
+    pub fn new(backend: B, embedder: E) -> Self {
+        Self {
+            backend,
+            embedder,
+            threshold: 0.95,
+            ttl: None,
+            max_entries: 1000,
+            entries: Mutex::default(),
+            stats: Mutex::default(),
+        }
+    }
+
+    /// Sets how similar a prompt must be to that of a cached response to be answered
+    /// with it, from -1 to 1.
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets how long a response is cached for.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the maximum number of responses to cache.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Returns how often calls were answered from the cache.
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Returns the number of cached responses, including any which have expired.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Returns true if no responses are cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+
+    /// Removes all cached responses.
+    pub fn clear(&mut self) {
+        self.entries.get_mut().unwrap().clear();
+    }
+
+    /// Consumes the wrapper, returning the underlying backend.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+
+    /// Returns the response of the most similar unexpired entry in scope, if it is
+    /// similar enough, marking the entry as used.
+    fn lookup(&self, scope: &str, embedding: &[f32]) -> Option<CallResp> {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(ttl) = self.ttl {
+            entries.retain(|e| e.created.elapsed() < ttl);
+        }
+        let entry = entries
+            .iter_mut()
+            .filter(|e| e.scope == scope)
+            .map(|e| (cosine_similarity(&e.embedding, embedding), e))
+            .filter(|(sim, _)| *sim >= self.threshold)
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, e)| e)?;
+        entry.used = Instant::now();
+        Some(entry.resp.clone())
+    }
+
+    fn insert(&self, scope: String, embedding: Vec<f32>, resp: CallResp) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries {
+            let lru = (0..entries.len()).min_by_key(|i| entries[*i].used);
+            entries.swap_remove(lru.unwrap());
+        }
+        let now = Instant::now();
+        entries.push(Entry {
+            scope,
+            embedding,
+            resp,
+            created: now,
+            used: now,
+        });
+    }
+}
+
+/// Returns the text compared between calls: the instructions and the text of each
+/// turn, along with its role.
+fn query_text(params: &CallBase, turns: &[Turn]) -> String {
+    let mut out = params.instructions.clone();
+    for t in turns {
+        out += &format!("\n\n{:?}: {}", t.role, t.text());
+    }
+    out
+}
+
+/// Returns the parameters of the call which must match exactly, other than the
+/// instructions and the prompt reference.
+fn scope(params: &CallBase) -> String {
+    serde_json::to_string(&CallBase {
+        instructions: String::new(),
+        prompt: None,
+        ..params.clone()
+    })
+    .unwrap_or_default()
+}
+
+impl<B: ModelCaller, E: Embedder> ModelCaller for SemanticCache<B, E> {
+    fn get_model(&self) -> impl Model {
+        self.backend.get_model()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.backend.supports_response_schema()
+    }
+
+    fn endpoint(&self) -> String {
+        self.backend.endpoint()
+    }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        let uses_tools = turns
+            .iter()
+            .flat_map(|t| t.content.iter())
+            .any(|m| !matches!(m, Message::Text { .. }));
+        if uses_tools {
+            return self.backend.call(params, turns).await;
+        }
+
+        let embedding = match self.embedder.embed(vec![query_text(&params, &turns)]).await {
+            Ok(mut v) if v.len() == 1 => v.remove(0),
+            _ => return self.backend.call(params, turns).await,
+        };
+        let scope = scope(&params);
+        if let Some(resp) = self.lookup(&scope, &embedding) {
+            self.stats.lock().unwrap().hits += 1;
+            return Ok(CallResp {
+                prompt: params.prompt,
+                ..resp
+            });
+        }
+
+        self.stats.lock().unwrap().misses += 1;
+        let resp = self.backend.call(params, turns).await?;
+        self.insert(scope, embedding, resp.clone());
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Scripted, Words};
+
+    #[tokio::test]
+    async fn exact_hits() {
+        let backend = Scripted::new(vec![
+            Scripted::text("red"),
+            Scripted::text("blue"),
+            Scripted::text("green"),
+        ]);
+        let cache = Cached::new(backend, MemoryStore::new(1));
+        assert_eq!(cache.simple_call("Name a color.").await.unwrap(), "red");
+        assert_eq!(cache.simple_call("Name a color.").await.unwrap(), "red");
+        assert_eq!(cache.simple_call("Name a colour.").await.unwrap(), "blue");
+        // Evicted to make room for the previous call.
+        assert_eq!(cache.simple_call("Name a color.").await.unwrap(), "green");
+        assert_eq!(cache.stats(), Stats { hits: 1, misses: 3 });
+        assert_eq!(cache.store().len(), 1);
+
+        let dir = std::env::temp_dir().join(format!("mini-prompt-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = Cached::new(
+            Scripted::new(vec![Scripted::text("red")]),
+            FileStore::new(&dir),
+        );
+        assert_eq!(cache.simple_call("Name a color.").await.unwrap(), "red");
+        // A new backend and store find the response on disk.
+        let cache = Cached::new(Scripted::new(vec![]), FileStore::new(&dir));
+        assert_eq!(cache.simple_call("Name a color.").await.unwrap(), "red");
+        assert!(cache.simple_call("Name a colour.").await.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn semantic_hits() {
+        let backend = Scripted::new(vec![
+            Scripted::text("Use the reset link."),
+            Scripted::text("Bonjour."),
+            Scripted::text("Paris."),
+            Scripted::text("Reset again."),
+        ]);
+        let cache = SemanticCache::new(backend, Words).threshold(0.8);
+
+        let ask = |instructions: &str| CallBase {
+            instructions: instructions.into(),
+            ..Default::default()
+        };
+        let resp = cache.call(ask("How do I reset my password?"), vec![]).await;
+        assert_eq!(resp.unwrap().content.text(), "Use the reset link.");
+        let resp = cache.call(ask("how do i RESET my password"), vec![]).await;
+        assert_eq!(resp.unwrap().content.text(), "Use the reset link.");
+
+        // The same prompt with a different system prompt is a miss.
+        let resp = cache
+            .call(
+                CallBase {
+                    system: "Reply in French.".into(),
+                    ..ask("How do I reset my password?")
+                },
+                vec![],
+            )
+            .await;
+        assert_eq!(resp.unwrap().content.text(), "Bonjour.");
+        assert_eq!(cache.stats(), Stats { hits: 1, misses: 2 });
+
+        // The least recently used entry is evicted.
+        let cache = cache.max_entries(2);
+        cache.simple_call("capital of France").await.unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.simple_call("reset my password").await.unwrap(),
+            "Reset again."
+        );
+
+        let cache = cache.ttl(Duration::ZERO);
+        assert!(matches!(
+            cache.simple_call("capital of France").await,
+            Err(CallErr::NoCompletions)
+        ));
+        assert!(cache.is_empty());
+    }
+}
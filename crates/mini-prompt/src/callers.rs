@@ -1,16 +1,50 @@
 //! Types that drive a model providers API.
 
-use crate::data_model::{
-    AnthropicMessage, AnthropicMsgRequest, AnthropicMsgResponse, AnthropicToolChoice,
-    OAICompletionsRequest, OAICompletionsResponse, OAIToolChoice,
+#[cfg(any(feature = "openai", feature = "openrouter"))]
+use crate::data_model::{OAIChatMessage, OAICompletionsRequest, OAICompletionsResponse};
+use crate::models::Model;
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+use crate::stream::read_events;
+#[cfg(any(feature = "openai", feature = "openrouter"))]
+use crate::stream::OAIStream;
+use crate::{before_deadline, CallBase, CallErr, CallResp, Message, ParseAttempt, Role, Turn};
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+use crate::{
+    config,
+    credentials::{self, CredentialProvider},
+    prompt::PromptRef,
+    FinishReason,
 };
-use crate::models::{AnthropicModel, Model, OpenAIModel, OpenrouterModel};
-use crate::{CallBase, CallErr, CallResp, FinishReason, Message, Turn};
-use reqwest::Client;
-use std::env;
+#[cfg(feature = "openrouter")]
+use crate::{data_model::OpenrouterReasoning, models::OpenrouterModel};
+#[cfg(feature = "anthropic")]
+use crate::{
+    data_model::{
+        AnthropicCompletion, AnthropicCountTokensRequest, AnthropicCountTokensResponse,
+        AnthropicMessage, AnthropicMsgRequest, AnthropicMsgResponse, AnthropicThinking,
+    },
+    models::AnthropicModel,
+    stream::AnthropicStream,
+};
+#[cfg(feature = "openai")]
+use crate::{models::OpenAIModel, Constraint};
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Client,
+};
+#[cfg(feature = "schemars")]
+use schemars::JsonSchema;
+use std::sync::Arc;
+
+pub use crate::cache::Cached;
 
 /// A type which is able to make model calls.
-pub trait ModelCaller: Send {
+///
+/// Calls take `&self`, so one caller can be shared between concurrent tasks, such as
+/// by reference or in an [Arc](std::sync::Arc). Callers which keep state between
+/// calls use interior mutability.
+pub trait ModelCaller: Send + Sync {
     /// Returns information about the model this caller is wired to.
     fn get_model(&self) -> impl Model;
 
@@ -18,8 +52,9 @@ pub trait ModelCaller: Send {
     /// is the core driver of this crate.
     ///
     /// ```rust,no_run
+    /// # #[cfg(feature = "openrouter")] {
     /// # use mini_prompt::*;
-    /// # let mut caller = callers::Openrouter::<models::Gemma27B3>::default();
+    /// # let caller = callers::Openrouter::<models::Gemma27B3>::default();
     /// # tokio::task::spawn(async move {
     /// caller.call(
     ///     CallBase {
@@ -35,16 +70,255 @@ pub trait ModelCaller: Send {
     ///     ],
     /// );
     /// # });
+    /// # }
     /// ```
+    ///
+    /// To force the response to begin with some text, such as `{`, set
+    /// [CallBase::prefill].
     fn call(
-        &mut self,
+        &self,
         params: CallBase,
         turns: Vec<Turn>,
     ) -> impl std::future::Future<Output = Result<CallResp, CallErr>> + Send;
 
+    /// Performs a model call like [call](ModelCaller::call), passing the text of the
+    /// response to `on_text` as it is generated, such as to print it.
+    ///
+    /// The text passed to `on_text` adds up to the text of the response. Callers which
+    /// don't stream responses pass all of the text once the call completes, which is
+    /// what this does by default. [CallResp::raw] isn't kept for streamed responses.
+    ///
+    /// ```rust,no_run
+    /// # #[cfg(feature = "openrouter")] {
+    /// # use mini_prompt::*;
+    /// # let caller = callers::Openrouter::<models::Gemma27B3>::default();
+    /// # tokio::task::spawn(async move {
+    /// let resp = caller
+    ///     .call_streaming(
+    ///         CallBase {
+    ///             instructions: "Write a haiku about rust.".into(),
+    ///             ..Default::default()
+    ///         },
+    ///         vec![],
+    ///         |text| print!("{}", text),
+    ///     )
+    ///     .await;
+    /// # });
+    /// # }
+    /// ```
+    fn call_streaming<F: FnMut(&str) + Send>(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        mut on_text: F,
+    ) -> impl std::future::Future<Output = Result<CallResp, CallErr>> + Send {
+        async move {
+            let resp = self.call(params, turns).await?;
+            let text = resp.content.text();
+            if !text.is_empty() {
+                on_text(&text);
+            }
+            Ok(resp)
+        }
+    }
+
+    /// Returns true if the caller enforces [CallBase::response_schema] using the
+    /// provider's structured outputs API.
+    fn supports_response_schema(&self) -> bool {
+        false
+    }
+
+    /// Identifies the model and API endpoint the caller sends calls to, such as
+    /// `gpt-4o-mini https://api.openai.com/v1`. Responses are cached under this, so it
+    /// should be the same between runs. Defaults to the [name](Model::name) of the model.
+    fn endpoint(&self) -> String {
+        self.get_model().name().to_string()
+    }
+
+    /// Makes a call where the model must respond with JSON matching the schema of `T`,
+    /// returning the parsed response.
+    ///
+    /// The schema is enforced by the provider if [supports_response_schema](ModelCaller::supports_response_schema)
+    /// is true. Otherwise, it is appended to the instructions, and the response is expected
+    /// in a json markdown code block. Responses which don't parse are retried as described
+    /// in [call_parsed](ModelCaller::call_parsed).
+    ///
+    /// ```rust,no_run
+    /// # #[cfg(feature = "openrouter")] {
+    /// # use mini_prompt::*;
+    /// #[derive(serde::Deserialize, schemars::JsonSchema)]
+    /// struct Answer {
+    ///     answer: u32,
+    /// }
+    ///
+    /// # let caller = callers::Openrouter::<models::Gemma27B3>::default();
+    /// # tokio::task::spawn(async move {
+    /// let resp: Answer = caller
+    ///     .call_structured(
+    ///         CallBase {
+    ///             instructions: "What's 2+2?".into(),
+    ///             ..Default::default()
+    ///         },
+    ///         vec![],
+    ///         2,
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// # }
+    /// ```
+    #[cfg(feature = "schemars")]
+    fn call_structured<T: serde::de::DeserializeOwned + JsonSchema>(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        retries: usize,
+    ) -> impl std::future::Future<Output = Result<T, CallErr>> {
+        let params = with_schema(
+            self.supports_response_schema(),
+            params,
+            crate::json_schema::<T>(),
+        );
+        self.call_parsed(params, turns, retries, crate::parse::json)
+    }
+
+    /// Makes a call where the model must respond with JSON matching the given schema,
+    /// such as one read from a file, returning the JSON.
+    ///
+    /// The schema is given to the model as described in
+    /// [call_structured](ModelCaller::call_structured), and responses are checked with
+    /// [json_matches](crate::assertions::json_matches), so responses which don't match
+    /// are retried even if the provider doesn't enforce the schema.
+    fn call_with_schema(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        schema: serde_json::Value,
+        retries: usize,
+    ) -> impl std::future::Future<Output = Result<serde_json::Value, CallErr>> {
+        let params = with_schema(self.supports_response_schema(), params, schema.clone());
+        async move {
+            self.call_parsed(params, turns, retries, |text| {
+                crate::assertions::json_matches(text, &schema)
+            })
+            .await
+        }
+    }
+
+    /// Makes a call, parsing the text of the response with the given function.
+    ///
+    /// If parsing fails, the model is shown its response along with the error and asked
+    /// to try again, up to `retries` times. If no attempt parses, [CallErr::ParseFailed]
+    /// is returned with every attempt.
+    ///
+    /// ```rust,no_run
+    /// # #[cfg(feature = "openrouter")] {
+    /// # use mini_prompt::*;
+    /// # let caller = callers::Openrouter::<models::Gemma27B3>::default();
+    /// # tokio::task::spawn(async move {
+    /// let n: u32 = caller
+    ///     .call_parsed(
+    ///         CallBase {
+    ///             instructions: "Pick a number from 1 to 10. Respond with just the number.".into(),
+    ///             ..Default::default()
+    ///         },
+    ///         vec![],
+    ///         2,
+    ///         |text| text.trim().parse().map_err(|e| format!("{}", e)),
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// # }
+    /// ```
+    fn call_parsed<T, F: Fn(&str) -> Result<T, String>>(
+        &self,
+        params: CallBase,
+        mut turns: Vec<Turn>,
+        retries: usize,
+        parse: F,
+    ) -> impl std::future::Future<Output = Result<T, CallErr>> {
+        async move {
+            let mut attempts = Vec::new();
+            loop {
+                let call = self.call(params.clone(), turns.clone());
+                let res = before_deadline(params.deadline, call).await?;
+                let output = res.content.text();
+                let err = match parse(&output) {
+                    Ok(v) => return Ok(v),
+                    Err(err) => err,
+                };
+
+                let retry = format!(
+                    "Your response could not be parsed: {}\n\nPlease try again.",
+                    err
+                );
+                attempts.push(ParseAttempt { output, err });
+                if attempts.len() > retries {
+                    return Err(CallErr::ParseFailed(attempts));
+                }
+                turns.push(res.content);
+                turns.push(Turn {
+                    role: Role::User,
+                    content: vec![Message::text(retry)],
+                });
+            }
+        }
+    }
+
+    /// Makes a call where the model answers within `<key>` tags, returning the text
+    /// between them with surrounding whitespace trimmed.
+    ///
+    /// Generation is stopped at the closing tag, so the model can't ramble after its
+    /// answer, and the closing tag is appended before the response is parsed. Responses
+    /// without the opening tag are retried as described in
+    /// [call_parsed](ModelCaller::call_parsed). This works with models which lack a
+    /// JSON mode, and pairs well with prefilling the opening tag, see [CallBase::prefill].
+    ///
+    /// ```rust,no_run
+    /// # #[cfg(feature = "openrouter")] {
+    /// # use mini_prompt::*;
+    /// # let caller = callers::Openrouter::<models::Gemma27B3>::default();
+    /// # tokio::task::spawn(async move {
+    /// let answer = caller
+    ///     .call_tagged(
+    ///         CallBase {
+    ///             instructions: "What's the capital of France? Think it through, then \
+    ///                 give the city within <answer> tags."
+    ///                 .into(),
+    ///             ..Default::default()
+    ///         },
+    ///         vec![],
+    ///         "answer",
+    ///         1,
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// # }
+    /// ```
+    fn call_tagged(
+        &self,
+        mut params: CallBase,
+        turns: Vec<Turn>,
+        key: &str,
+        retries: usize,
+    ) -> impl std::future::Future<Output = Result<String, CallErr>> {
+        let close = format!("</{}>", key);
+        params.stop.push(close.clone());
+        self.call_parsed(params, turns, retries, move |text| {
+            crate::parse::tagged(
+                &(text.to_string() + &close),
+                &crate::parse::TagOptions::new(key),
+            )
+            .map(|(answer, _)| answer.trim().to_string())
+            .ok_or_else(|| format!("the response has no <{}> tag", key))
+        })
+    }
+
     /// Convenience method to prompt a model and get the response as a string.
     fn simple_call<S: Into<String> + Send>(
-        &mut self,
+        &self,
         prompt: S,
     ) -> impl std::future::Future<Output = Result<String, CallErr>> {
         let base_params = CallBase {
@@ -53,156 +327,743 @@ pub trait ModelCaller: Send {
         };
         async {
             let res = self.call(base_params, vec![]).await?;
+            first_text(res)
+        }
+    }
 
-            match &res.content.content.get(0) {
-                Some(Message::Text { text }) => Ok(text.clone()),
-                _ => Err("unexpected: no message content".into()),
-            }
+    /// Convenience method to prompt a model following some prior conversation,
+    /// getting the response as a string.
+    ///
+    /// The history is given as (role, text) pairs, where the role is one of
+    /// [Role::User], [Role::Assistant], or [Role::System].
+    ///
+    /// ```rust,no_run
+    /// # #[cfg(feature = "openrouter")] {
+    /// # use mini_prompt::*;
+    /// # let caller = callers::Openrouter::<models::Gemma27B3>::default();
+    /// # tokio::task::spawn(async move {
+    /// let resp = caller
+    ///     .chat(
+    ///         &[
+    ///             (Role::User, "My name is Ferris."),
+    ///             (Role::Assistant, "Nice to meet you, Ferris!"),
+    ///         ],
+    ///         "What's my name?",
+    ///     )
+    ///     .await;
+    /// # });
+    /// # }
+    /// ```
+    fn chat<S: Into<String> + Send>(
+        &self,
+        history: &[(Role, &str)],
+        prompt: S,
+    ) -> impl std::future::Future<Output = Result<String, CallErr>> {
+        let mut turns: Vec<Turn> = history
+            .iter()
+            .map(|(role, text)| Turn {
+                role: role.clone(),
+                content: vec![Message::text(*text)],
+            })
+            .collect();
+        turns.push(Turn {
+            role: Role::User,
+            content: vec![Message::text(prompt.into())],
+        });
+
+        async {
+            let res = self.call(CallBase::default(), turns).await?;
+            first_text(res)
+        }
+    }
+}
+
+/// Gives the schema to the provider if it enforces schemas, and otherwise appends it
+/// to the instructions.
+fn with_schema(enforced: bool, params: CallBase, schema: serde_json::Value) -> CallBase {
+    if enforced {
+        return CallBase {
+            response_schema: Some(schema),
+            ..params
+        };
+    }
+    let format = format!(
+        "Respond with JSON conforming to the following schema, within a markdown \
+        code block with json as the language.\n```json\n{}\n```",
+        schema
+    );
+    CallBase {
+        instructions: if params.instructions.is_empty() {
+            format
+        } else {
+            params.instructions + "\n\n" + &format
+        },
+        ..params
+    }
+}
+
+/// Returns the first text message of a response.
+fn first_text(res: CallResp) -> Result<String, CallErr> {
+    match res.content.content.into_iter().next() {
+        Some(Message::Text { text }) => Ok(text.to_string()),
+        _ => Err("unexpected: no message content".into()),
+    }
+}
+
+/// The future returned by [DynModelCaller::call_boxed] and
+/// [DynModelCaller::call_streaming_boxed].
+pub type BoxedCall<'a> =
+    std::pin::Pin<Box<dyn std::future::Future<Output = Result<CallResp, CallErr>> + Send + 'a>>;
+
+/// An object-safe version of [ModelCaller], so callers of different types can be
+/// stored together, such as in a `Vec<Box<dyn DynModelCaller>>`.
+///
+/// Every [ModelCaller] implements this trait, and `Box<dyn DynModelCaller>`
+/// implements [ModelCaller] in turn, so boxed callers can be used anywhere a caller
+/// can. The [get_model](ModelCaller::get_model) of a boxed caller is an
+/// [ErasedModel](crate::models::ErasedModel) with the name of the original model.
+///
+/// ```rust,no_run
+/// # #[cfg(all(feature = "openrouter", feature = "anthropic"))] {
+/// # use mini_prompt::*;
+/// use mini_prompt::callers::DynModelCaller;
+///
+/// let backend: Box<dyn DynModelCaller> = match std::env::var("PROVIDER").as_deref() {
+///     Ok("anthropic") => Box::new(callers::Anthropic::<models::ClaudeHaiku35>::default()),
+///     _ => Box::new(callers::Openrouter::<models::Gemma27B3>::default()),
+/// };
+/// println!("using {}", backend.model_name());
+/// # tokio::task::spawn(async move {
+/// let resp = backend.simple_call("Hi!").await;
+/// # });
+/// # }
+/// ```
+pub trait DynModelCaller: Send + Sync {
+    /// Returns the [name](Model::name) of the model this caller is wired to.
+    fn model_name(&self) -> &'static str;
+
+    /// See [ModelCaller::supports_response_schema].
+    fn supports_response_schema(&self) -> bool;
+
+    /// See [ModelCaller::endpoint].
+    fn endpoint(&self) -> String;
+
+    /// See [ModelCaller::call].
+    fn call_boxed(&self, params: CallBase, turns: Vec<Turn>) -> BoxedCall<'_>;
+
+    /// See [ModelCaller::call_streaming].
+    fn call_streaming_boxed<'a>(
+        &'a self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        on_text: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxedCall<'a>;
+}
+
+impl<C: ModelCaller> DynModelCaller for C {
+    fn model_name(&self) -> &'static str {
+        self.get_model().name()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        ModelCaller::supports_response_schema(self)
+    }
+
+    fn endpoint(&self) -> String {
+        ModelCaller::endpoint(self)
+    }
+
+    fn call_boxed(&self, params: CallBase, turns: Vec<Turn>) -> BoxedCall<'_> {
+        Box::pin(self.call(params, turns))
+    }
+
+    fn call_streaming_boxed<'a>(
+        &'a self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        on_text: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxedCall<'a> {
+        Box::pin(self.call_streaming(params, turns, on_text))
+    }
+}
+
+impl ModelCaller for Box<dyn DynModelCaller + '_> {
+    fn get_model(&self) -> impl Model {
+        crate::models::ErasedModel {
+            name: (**self).model_name(),
+        }
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        DynModelCaller::supports_response_schema(&**self)
+    }
+
+    fn endpoint(&self) -> String {
+        DynModelCaller::endpoint(&**self)
+    }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        (**self).call_boxed(params, turns).await
+    }
+
+    async fn call_streaming<F: FnMut(&str) + Send>(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        mut on_text: F,
+    ) -> Result<CallResp, CallErr> {
+        (**self)
+            .call_streaming_boxed(params, turns, &mut on_text)
+            .await
+    }
+}
+
+/// Forwards calls to the referenced caller, so a caller can be lent to helpers
+/// which take ownership of one.
+impl<B: ModelCaller> ModelCaller for &B {
+    fn get_model(&self) -> impl Model {
+        (**self).get_model()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        (**self).supports_response_schema()
+    }
+
+    fn endpoint(&self) -> String {
+        (**self).endpoint()
+    }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        (**self).call(params, turns).await
+    }
+
+    async fn call_streaming<F: FnMut(&str) + Send>(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        on_text: F,
+    ) -> Result<CallResp, CallErr> {
+        (**self).call_streaming(params, turns, on_text).await
+    }
+}
+
+/// Forwards calls to the referenced caller, so a caller can be lent to helpers
+/// which take ownership of one.
+impl<B: ModelCaller> ModelCaller for &mut B {
+    fn get_model(&self) -> impl Model {
+        (**self).get_model()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        (**self).supports_response_schema()
+    }
+
+    fn endpoint(&self) -> String {
+        (**self).endpoint()
+    }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        (**self).call(params, turns).await
+    }
+
+    async fn call_streaming<F: FnMut(&str) + Send>(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        on_text: F,
+    ) -> Result<CallResp, CallErr> {
+        (**self).call_streaming(params, turns, on_text).await
+    }
+}
+
+/// Shares a caller between tasks, such as those started with `tokio::task::spawn`.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "openrouter")] {
+/// # use mini_prompt::*;
+/// use std::sync::Arc;
+///
+/// let backend = Arc::new(callers::Openrouter::<models::Gemma27B3>::default());
+/// for topic in ["cats", "dogs"] {
+///     let backend = backend.clone();
+///     tokio::task::spawn(async move {
+///         backend.simple_call(format!("Write a haiku about {}.", topic)).await
+///     });
+/// }
+/// # }
+/// ```
+impl<B: ModelCaller> ModelCaller for Arc<B> {
+    fn get_model(&self) -> impl Model {
+        (**self).get_model()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        (**self).supports_response_schema()
+    }
+
+    fn endpoint(&self) -> String {
+        (**self).endpoint()
+    }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        (**self).call(params, turns).await
+    }
+
+    async fn call_streaming<F: FnMut(&str) + Send>(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        on_text: F,
+    ) -> Result<CallResp, CallErr> {
+        (**self).call_streaming(params, turns, on_text).await
+    }
+}
+
+/// Shares a caller between tasks, holding the lock for the duration of each call, so
+/// calls through clones of the [Arc] are made one at a time. Callers can be shared
+/// without a lock through an [Arc](std::sync::Arc) alone, so this is only needed where
+/// calls must not overlap.
+///
+/// The model is an [ErasedModel](crate::models::ErasedModel) with the name of the
+/// original model, or the type name of the caller if the lock is held. If the lock is
+/// held when [supports_response_schema](ModelCaller::supports_response_schema) is
+/// checked, it returns false, so structured calls fall back to describing the schema in
+/// the instructions, and the [endpoint](ModelCaller::endpoint) is the model name.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "openrouter")] {
+/// # use mini_prompt::*;
+/// use std::sync::Arc;
+/// use tokio::sync::Mutex;
+///
+/// let backend = Arc::new(Mutex::new(callers::Openrouter::<models::Gemma27B3>::default()));
+/// for topic in ["cats", "dogs"] {
+///     let backend = backend.clone();
+///     tokio::task::spawn(async move {
+///         backend.simple_call(format!("Write a haiku about {}.", topic)).await
+///     });
+/// }
+/// # }
+/// ```
+impl<B: ModelCaller> ModelCaller for Arc<tokio::sync::Mutex<B>> {
+    fn get_model(&self) -> impl Model {
+        crate::models::ErasedModel {
+            name: match self.try_lock() {
+                Ok(backend) => backend.get_model().name(),
+                Err(_) => std::any::type_name::<B>(),
+            },
+        }
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.try_lock()
+            .map(|b| b.supports_response_schema())
+            .unwrap_or(false)
+    }
+
+    fn endpoint(&self) -> String {
+        match self.try_lock() {
+            Ok(backend) => backend.endpoint(),
+            Err(_) => self.get_model().name().to_string(),
         }
     }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        self.lock().await.call(params, turns).await
+    }
+
+    async fn call_streaming<F: FnMut(&str) + Send>(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        on_text: F,
+    ) -> Result<CallResp, CallErr> {
+        self.lock()
+            .await
+            .call_streaming(params, turns, on_text)
+            .await
+    }
 }
 
+#[cfg(feature = "openrouter")]
 /// A [ModelCaller] that talks to a model accessible via Openrouter.
 ///
 /// If an API key is not provided, it will be read from the environment variable
-/// `OPENROUTER_API_KEY` or `OR_KEY`.
+/// `OPENROUTER_API_KEY` or `OR_KEY`, or the [config file](crate::config).
 #[derive(Debug, Clone, Default)]
 pub struct Openrouter<M: OpenrouterModel> {
     pub model: M,
     pub api_key: Option<String>,
+    /// Where the key comes from if `api_key` isn't set, see [crate::credentials].
+    pub credentials: Option<Arc<dyn CredentialProvider>>,
+    /// Defaults to `https://openrouter.ai/api/v1`.
+    pub base_url: Option<String>,
+    /// The URL of your app, sent as the `HTTP-Referer` header so requests are
+    /// attributed to it in Openrouter's rankings and logs.
+    pub app_url: Option<String>,
+    /// The name of your app, sent as the `X-Title` header and shown alongside
+    /// `app_url`.
+    pub app_name: Option<String>,
+    /// Sent as the `User-Agent` header. Defaults to `mini-prompt/<version>`.
+    pub user_agent: Option<String>,
+    /// Enables reasoning on models which support it, such as Gemini 2.5, Deepseek,
+    /// and Claude. Unless excluded, the reasoning is returned as [Message::Thinking].
+    pub reasoning: Option<Reasoning>,
 }
 
-impl<M: OpenrouterModel> ModelCaller for Openrouter<M> {
-    fn get_model(&self) -> impl Model {
-        M::default()
-    }
+#[cfg(feature = "openrouter")]
+/// Controls how much a model reasons before it responds, see [Openrouter::reasoning].
+///
+/// If neither `effort` nor `max_tokens` is set, the model's default is used.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reasoning {
+    /// Used by models which take an effort, and mapped to a budget for the rest.
+    pub effort: Option<ReasoningEffort>,
+    /// The maximum number of tokens to reason with, for models which take a budget.
+    /// Can't be set along with `effort`.
+    pub max_tokens: Option<usize>,
+    /// Reason without returning the reasoning in the response.
+    pub exclude: bool,
+}
 
-    async fn call(&mut self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
-        // Map `system` and `instructions` into one text stanza, as expected by
-        // this API.
-        let system_prompt = match (params.system != "", params.instructions != "") {
-            (true, true) => Some((params.system + "\n\n" + &params.instructions).into()),
-            (false, true) => Some(params.instructions.clone()),
-            (true, false) => Some(params.system.clone()),
-            (false, false) => None,
-        }
-        .map(|p| self.get_model().make_prompt(p));
+#[cfg(feature = "openrouter")]
+/// How hard a model reasons, see [Reasoning].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
 
-        let mut messages = Vec::with_capacity(1 + turns.len());
-        if let Some(system_prompt) = system_prompt {
-            messages.push(system_prompt);
+#[cfg(feature = "openrouter")]
+impl ReasoningEffort {
+    /// Returns the name of the effort, as sent in requests.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
         }
-        messages.extend(turns.into_iter().map(|t| t.into_oai_msgs()).flatten());
+    }
+}
 
-        let client = Client::new();
-        let resp = client
-            .post("https://openrouter.ai/api/v1/chat/completions")
-            .bearer_auth(self.api_key.clone().unwrap_or_else(|| {
-                env::var("OPENROUTER_API_KEY").unwrap_or_else(|_| env::var("OR_KEY").unwrap())
-            }))
-            .json(&OAICompletionsRequest {
-                model: M::MODEL_STR.into(),
-                temperature: params.temperature,
-                provider: Some(crate::data_model::OpenrouterProvider {
-                    // These providers kept returning other ppl's completions :O
-                    ignore: vec!["Nebius".into(), "Kluster".into(), "DeepInfra".into()],
-                }),
-                messages,
-                tool_choice: if params.tools.is_empty() {
-                    None
-                } else {
-                    Some(OAIToolChoice::Auto)
-                },
-                tools: params.tools.into_iter().map(|td| td.into()).collect(),
-                ..Default::default()
-            })
-            .send()
-            .await?;
+#[cfg(feature = "openrouter")]
+impl Reasoning {
+    fn wire(&self) -> Result<OpenrouterReasoning, CallErr> {
+        if self.effort.is_some() && self.max_tokens.is_some() {
+            return Err("reasoning effort and max_tokens can't both be set".into());
+        }
+        Ok(OpenrouterReasoning {
+            enabled: true,
+            effort: self.effort.map(|e| e.name()),
+            max_tokens: self.max_tokens,
+            exclude: self.exclude,
+        })
+    }
+}
 
-        if !resp.status().is_success() {
-            return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+#[cfg(feature = "openrouter")]
+impl<M: OpenrouterModel> Openrouter<M> {
+    fn base_url(&self) -> Result<&str, CallErr> {
+        match &self.base_url {
+            Some(url) => Ok(url),
+            None => {
+                Ok(config::base_url(|c| &c.openrouter)?.unwrap_or("https://openrouter.ai/api/v1"))
+            }
         }
+    }
 
-        let mut res = resp.json::<OAICompletionsResponse>().await?;
+    async fn api_key(&self) -> Result<String, CallErr> {
+        credentials::api_key(
+            &self.api_key,
+            &self.credentials,
+            &["OPENROUTER_API_KEY", "OR_KEY"],
+            |c| &c.openrouter,
+        )
+        .await
+    }
 
-        if res.model == "" {
-            res.model = M::MODEL_STR.into();
+    /// Returns the headers sent with every request, including the key.
+    async fn headers(&self) -> Result<HeaderMap, CallErr> {
+        let mut headers = HeaderMap::new();
+        let key = format!("Bearer {}", self.api_key().await?);
+        insert_header(&mut headers, "authorization", &key)?;
+        insert_user_agent(&mut headers, &self.user_agent)?;
+        if let Some(url) = &self.app_url {
+            insert_header(&mut headers, "http-referer", url)?;
         }
-        if let Some("chat.completion") = res.object.as_ref().map(|s| s.as_str()) {
-        } else if res.object.is_some() {
-            return Err(format!("unexpected value for 'object': {}", res.object.unwrap()).into());
-        }
-        if res.choices.len() == 0 {
-            return Err(CallErr::NoCompletions);
+        if let Some(name) = &self.app_name {
+            insert_header(&mut headers, "x-title", name)?;
         }
+        Ok(headers)
+    }
 
-        let finish_reason = res.choices[0].finish_reason.clone();
-        match finish_reason {
-            FinishReason::Stop | FinishReason::ToolCalls => Ok(res.into()),
-            _ => Err(format!(
-                "unexpected finish reason: {:?}",
-                res.choices[0].finish_reason
-            )
-            .into()),
+    /// Builds the body of a chat completions request for the given call.
+    fn completions_request(
+        &self,
+        mut params: CallBase,
+        turns: Vec<Turn>,
+    ) -> Result<OAICompletionsRequest, CallErr> {
+        if params.constraint.is_some() {
+            return Err("output constraints are not supported by this API".into());
+        }
+        let reasoning = self.reasoning.as_ref().map(Reasoning::wire).transpose()?;
+        let mut messages =
+            oai_system_msgs(self.get_model(), params.take_system(), params.instructions);
+        for t in turns {
+            messages.extend(t.into_oai_msgs()?);
         }
+        messages.extend(params.prefill.take().map(OAIChatMessage::assistant));
+        developer_as_system(&mut messages);
+
+        Ok(OAICompletionsRequest {
+            model: M::MODEL_STR.into(),
+            temperature: params.temperature,
+            stop: params.stop,
+            provider: Some(crate::data_model::OpenrouterProvider {
+                // These providers kept returning other ppl's completions :O
+                ignore: vec!["Nebius".into(), "Kluster".into(), "DeepInfra".into()],
+            }),
+            reasoning,
+            messages,
+            tool_choice: if params.tools.is_empty() {
+                None
+            } else {
+                Some(params.tool_choice.into())
+            },
+            tools: params.tools.into_iter().map(|td| td.into()).collect(),
+            response_format: params.response_schema.map(|s| s.into()),
+            logprobs: params.top_logprobs.map(|_| true),
+            top_logprobs: params.top_logprobs,
+            seed: params.seed,
+            ..Default::default()
+        })
     }
 }
 
+#[cfg(feature = "openrouter")]
+impl<M: OpenrouterModel> ModelCaller for Openrouter<M> {
+    fn get_model(&self) -> impl Model {
+        M::default()
+    }
+
+    fn endpoint(&self) -> String {
+        // An unreadable config file fails the call, so nothing is cached under this.
+        format!("{} {}", M::MODEL_STR, self.base_url().unwrap_or_default())
+    }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        before_deadline(params.deadline, async move {
+            let prompt = params.prompt.clone();
+            let prefill = params.prefill.clone();
+            let client = Client::new();
+            let resp = client
+                .post(format!("{}/chat/completions", self.base_url()?))
+                .headers(self.headers().await?)
+                .json(&self.completions_request(params, turns)?)
+                .send()
+                .await?;
+
+            if !resp.status().is_success() {
+                return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+            }
+
+            let (res, raw) = read_json(resp).await?;
+            Ok(CallResp {
+                raw,
+                ..oai_response(res, M::MODEL_STR, prompt)?
+            }
+            .with_prefill(prefill))
+        })
+        .await
+    }
+
+    async fn call_streaming<F: FnMut(&str) + Send>(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        on_text: F,
+    ) -> Result<CallResp, CallErr> {
+        before_deadline(params.deadline, async move {
+            let prompt = params.prompt.clone();
+            let prefill = params.prefill.clone();
+            let client = Client::new();
+            let req = client
+                .post(format!("{}/chat/completions", self.base_url()?))
+                .headers(self.headers().await?)
+                .json(&OAICompletionsRequest {
+                    stream: Some(true),
+                    ..self.completions_request(params, turns)?
+                });
+            let res = stream_completions(req, prefill.as_deref(), on_text).await?;
+            Ok(oai_response(res, M::MODEL_STR, prompt)?.with_prefill(prefill))
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "anthropic")]
 /// A [ModelCaller] that talks to a model via Anthropic's public messages API.
 ///
 /// If an API key is not provided, it will be read from the environment variable
-/// `ANTHROPIC_API_KEY`.
+/// `ANTHROPIC_API_KEY`, or the [config file](crate::config).
 /// If max_tokens is not set, it defaults to 8k.
 #[derive(Debug, Clone, Default)]
 pub struct Anthropic<M: AnthropicModel> {
     pub model: M,
     pub max_tokens: Option<usize>,
     pub api_key: Option<String>,
+    /// Where the key comes from if `api_key` isn't set, see [crate::credentials].
+    pub credentials: Option<Arc<dyn CredentialProvider>>,
+    /// Defaults to `https://api.anthropic.com/v1`.
+    pub base_url: Option<String>,
+    /// Beta features to enable, sent in the `anthropic-beta` header.
+    pub betas: Vec<AnthropicBeta>,
+    /// The version of the API to use, sent in the `anthropic-version` header.
+    /// Defaults to [ANTHROPIC_VERSION].
+    pub api_version: Option<String>,
+    /// Sent as the `User-Agent` header. Defaults to `mini-prompt/<version>`.
+    pub user_agent: Option<String>,
+    /// Enables extended thinking, letting the model use up to this many tokens
+    /// (at least 1024, and less than `max_tokens`) to reason before it responds. The
+    /// thinking is returned as [Message::Thinking], and must be kept in the turns of
+    /// later calls during tool use.
+    pub thinking_budget: Option<usize>,
 }
 
-impl<M: AnthropicModel> ModelCaller for Anthropic<M> {
-    fn get_model(&self) -> impl Model {
-        M::default()
+#[cfg(feature = "anthropic")]
+/// The version of the Anthropic API used unless [Anthropic::api_version] is set.
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+#[cfg(feature = "anthropic")]
+/// A beta feature of the Anthropic API, see [Anthropic::betas].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnthropicBeta {
+    /// Caching of prompt prefixes marked with `cache_control`.
+    PromptCaching,
+    /// Up to 128k output tokens from Claude 3.7 Sonnet.
+    Output128k,
+    /// Tool use with fewer output tokens, for Claude 3.7 Sonnet.
+    TokenEfficientTools,
+    /// Thinking between tool calls, for Claude 4 models.
+    InterleavedThinking,
+    /// Any other beta, by name.
+    Other(String),
+}
+
+#[cfg(feature = "anthropic")]
+impl AnthropicBeta {
+    /// Returns the name of the beta, as sent in the header.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::PromptCaching => "prompt-caching-2024-07-31",
+            Self::Output128k => "output-128k-2025-02-19",
+            Self::TokenEfficientTools => "token-efficient-tools-2025-02-19",
+            Self::InterleavedThinking => "interleaved-thinking-2025-05-14",
+            Self::Other(name) => name,
+        }
     }
+}
 
-    async fn call(&mut self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
-        let mut messages = Vec::new();
-        if !params.instructions.is_empty() {
-            messages.push(AnthropicMessage::user_text(params.instructions));
+#[cfg(feature = "anthropic")]
+impl<M: AnthropicModel> Anthropic<M> {
+    pub(crate) fn base_url(&self) -> Result<&str, CallErr> {
+        match &self.base_url {
+            Some(url) => Ok(url),
+            None => {
+                Ok(config::base_url(|c| &c.anthropic)?.unwrap_or("https://api.anthropic.com/v1"))
+            }
         }
-        messages.extend(turns.into_iter().map(|t| t.into_anthropic_msgs()).flatten());
+    }
+
+    pub(crate) async fn api_key(&self) -> Result<String, CallErr> {
+        credentials::api_key(
+            &self.api_key,
+            &self.credentials,
+            &["ANTHROPIC_API_KEY"],
+            |c| &c.anthropic,
+        )
+        .await
+    }
+
+    /// Returns the headers sent with every request, including the key.
+    pub(crate) async fn headers(&self) -> Result<HeaderMap, CallErr> {
+        let mut headers = HeaderMap::new();
+        insert_header(&mut headers, "x-api-key", &self.api_key().await?)?;
+        let version = self.api_version.as_deref().unwrap_or(ANTHROPIC_VERSION);
+        insert_header(&mut headers, "anthropic-version", version)?;
+        insert_user_agent(&mut headers, &self.user_agent)?;
+        if !self.betas.is_empty() {
+            let betas: Vec<&str> = self.betas.iter().map(|b| b.name()).collect();
+            insert_header(&mut headers, "anthropic-beta", &betas.join(","))?;
+        }
+        Ok(headers)
+    }
+
+    /// Builds the body of a messages API request for the given call.
+    pub(crate) fn msg_request(
+        &self,
+        mut params: CallBase,
+        turns: Vec<Turn>,
+    ) -> Result<AnthropicMsgRequest, CallErr> {
+        if params.constraint.is_some() {
+            return Err("output constraints are not supported by this API".into());
+        }
+        if params.seed.is_some() {
+            return Err("seeds are not supported by this API".into());
+        }
+        if self.thinking_budget.is_some() && params.temperature.is_some() {
+            return Err("temperature is not supported with extended thinking".into());
+        }
+        let (system, mut messages) =
+            system_and_messages(params.take_system(), params.instructions, turns)?;
+        if let Some(prefill) = params.prefill.take() {
+            if prefill.ends_with(char::is_whitespace) {
+                return Err("a prefill can't end with whitespace".into());
+            }
+            messages.push(AnthropicMessage::assistant_text(prefill));
+        }
+
+        Ok(AnthropicMsgRequest {
+            thinking: self
+                .thinking_budget
+                .map(|budget_tokens| AnthropicThinking::Enabled { budget_tokens }),
+            model: M::MODEL_STR.into(),
+            temperature: params.temperature,
+            max_tokens: self.max_tokens.unwrap_or(8192),
+            stop_sequences: params.stop,
+            messages,
+            system,
+            tool_choice: if params.tools.is_empty() {
+                None
+            } else {
+                Some(params.tool_choice.into())
+            },
+            tools: params.tools.into_iter().map(|td| td.into()).collect(),
+            stream: None,
+        })
+    }
+
+    /// Counts the input tokens of a call using Anthropic's token counting API,
+    /// without making the call.
+    pub async fn count_tokens(
+        &self,
+        mut params: CallBase,
+        turns: Vec<Turn>,
+    ) -> Result<usize, CallErr> {
+        let (system, messages) =
+            system_and_messages(params.take_system(), params.instructions, turns)?;
 
         let client = Client::new();
         let resp = client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("anthropic-version", "2023-06-01")
+            .post(format!("{}/messages/count_tokens", self.base_url()?))
             .header("content-type", "application/json")
-            .header(
-                "x-api-key",
-                self.api_key
-                    .clone()
-                    .unwrap_or_else(|| env::var("ANTHROPIC_API_KEY").unwrap()),
-            )
-            .json(&AnthropicMsgRequest {
+            .headers(self.headers().await?)
+            .json(&AnthropicCountTokensRequest {
                 model: M::MODEL_STR.into(),
-                temperature: params.temperature,
-                max_tokens: self.max_tokens.unwrap_or(8192),
                 messages,
-                system: if params.system == "" {
-                    None
-                } else {
-                    Some(params.system)
-                },
-                tool_choice: if params.tools.is_empty() {
-                    None
-                } else {
-                    Some(AnthropicToolChoice {
-                        r#type: OAIToolChoice::Auto,
-                        ..Default::default()
-                    })
-                },
+                system,
                 tools: params.tools.into_iter().map(|td| td.into()).collect(),
-                ..Default::default()
             })
             .send()
             .await?;
@@ -210,113 +1071,1091 @@ impl<M: AnthropicModel> ModelCaller for Anthropic<M> {
         if !resp.status().is_success() {
             return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
         }
+        Ok(resp
+            .json::<AnthropicCountTokensResponse>()
+            .await?
+            .input_tokens)
+    }
+}
 
-        let mut res = resp.json::<AnthropicMsgResponse>().await?;
+#[cfg(feature = "anthropic")]
+impl<M: AnthropicModel> ModelCaller for Anthropic<M> {
+    fn get_model(&self) -> impl Model {
+        M::default()
+    }
 
-        if res.model == "" {
-            res.model = M::MODEL_STR.into();
-        }
+    fn endpoint(&self) -> String {
+        // An unreadable config file fails the call, so nothing is cached under this.
+        format!("{} {}", M::MODEL_STR, self.base_url().unwrap_or_default())
+    }
 
-        if let Some("message") = res.object.as_ref().map(|s| s.as_str()) {
-        } else if res.object.is_some() {
-            return Err(format!("unexpected value for 'object': {}", res.object.unwrap()).into());
-        }
-        if let Some("assistant") = res.role.as_ref().map(|s| s.as_str()) {
-        } else if res.object.is_some() {
-            return Err(format!("unexpected value for 'role': {}", res.role.unwrap()).into());
-        }
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        before_deadline(params.deadline, async move {
+            let prompt = params.prompt.clone();
+            let prefill = params.prefill.clone();
+            let client = Client::new();
+            let resp = client
+                .post(format!("{}/messages", self.base_url()?))
+                .header("content-type", "application/json")
+                .headers(self.headers().await?)
+                .json(&self.msg_request(params, turns)?)
+                .send()
+                .await?;
+
+            if !resp.status().is_success() {
+                return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+            }
+            let (res, raw) = read_json(resp).await?;
+            Ok(CallResp {
+                raw,
+                ..anthropic_response(res, M::MODEL_STR, prompt)?
+            }
+            .with_prefill(prefill))
+        })
+        .await
+    }
+
+    async fn call_streaming<F: FnMut(&str) + Send>(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        mut on_text: F,
+    ) -> Result<CallResp, CallErr> {
+        before_deadline(params.deadline, async move {
+            let prompt = params.prompt.clone();
+            let prefill = params.prefill.clone();
+            let client = Client::new();
+            let resp = client
+                .post(format!("{}/messages", self.base_url()?))
+                .header("content-type", "application/json")
+                .headers(self.headers().await?)
+                .json(&AnthropicMsgRequest {
+                    stream: Some(true),
+                    ..self.msg_request(params, turns)?
+                })
+                .send()
+                .await?;
 
-        match res.stop_reason {
-            FinishReason::Stop | FinishReason::ToolCalls => {
-                // Convert Anthropics format into whats expected by this API
-                Ok(res.into())
+            if !resp.status().is_success() {
+                return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+            }
+            if let Some(prefill) = &prefill {
+                on_text(prefill);
             }
-            _ => Err(format!("unexpected finish reason: {:?}", res.stop_reason).into()),
+            let mut res = AnthropicStream::default();
+            read_events(resp, |data| res.push(data, &mut on_text)).await?;
+            Ok(anthropic_response(res.finish()?, M::MODEL_STR, prompt)?.with_prefill(prefill))
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "anthropic")]
+/// Maps the system prompts, any system or developer turns leading the conversation, and the
+/// instructions and turns into the system blocks and messages of a request to the
+/// Anthropic messages API.
+///
+/// The API has no system messages within a conversation, so later system and
+/// developer turns are sent as user text.
+fn system_and_messages(
+    mut system: Vec<String>,
+    instructions: String,
+    turns: Vec<Turn>,
+) -> Result<(Vec<AnthropicCompletion>, Vec<AnthropicMessage>), CallErr> {
+    let mut turns = turns.into_iter().peekable();
+    while let Some(t) = turns.next_if(|t| matches!(t.role, Role::System | Role::Developer)) {
+        system.push(t.text());
+    }
+    let system = system
+        .into_iter()
+        .map(|text| AnthropicCompletion::Text { text })
+        .collect();
+
+    let mut messages = Vec::new();
+    if !instructions.is_empty() {
+        messages.push(AnthropicMessage::user_text(instructions));
+    }
+    for t in turns {
+        messages.extend(t.into_anthropic_msgs()?);
+    }
+    Ok((system, messages))
+}
+
+#[cfg(any(feature = "openai", feature = "openrouter"))]
+/// Maps the system prompts and instructions into messages, as expected by chat
+/// completions APIs. The instructions are appended to the last system prompt.
+fn oai_system_msgs(
+    model: impl Model,
+    mut system: Vec<String>,
+    instructions: String,
+) -> Vec<OAIChatMessage> {
+    if !instructions.is_empty() {
+        match system.last_mut() {
+            Some(last) => *last = std::mem::take(last) + "\n\n" + &instructions,
+            None => system.push(instructions),
         }
     }
+    system.into_iter().map(|p| model.make_prompt(p)).collect()
+}
+
+#[cfg(any(feature = "openai", feature = "openrouter"))]
+/// Sends developer messages as system messages, for models without the developer role.
+fn developer_as_system(messages: &mut [OAIChatMessage]) {
+    messages
+        .iter_mut()
+        .filter(|m| m.role == Role::Developer)
+        .for_each(|m| m.role = Role::System);
+}
+
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+/// Inserts a header, failing if the value can't be sent in a header. The value isn't
+/// included in the error, as it may be a key.
+fn insert_header(headers: &mut HeaderMap, name: &'static str, value: &str) -> Result<(), CallErr> {
+    let mut value = HeaderValue::from_str(value)
+        .map_err(|_| format!("invalid value for the {} header", name))?;
+    value.set_sensitive(name == "authorization" || name == "x-api-key");
+    headers.insert(name, value);
+    Ok(())
+}
+
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+/// Inserts the `User-Agent` header, defaulting to the name and version of this crate.
+fn insert_user_agent(headers: &mut HeaderMap, user_agent: &Option<String>) -> Result<(), CallErr> {
+    let default = concat!("mini-prompt/", env!("CARGO_PKG_VERSION"));
+    insert_header(
+        headers,
+        "user-agent",
+        user_agent.as_deref().unwrap_or(default),
+    )
+}
+
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+/// Reads the JSON body of a response. If the `raw-response` feature is enabled, the
+/// body is also returned as a [serde_json::Value], for [CallResp::raw].
+///
+/// The whole body is read before it is decoded, with simd-json if the `simd-json`
+/// feature is enabled.
+async fn read_json<T: serde::de::DeserializeOwned>(
+    resp: reqwest::Response,
+) -> Result<(T, Option<serde_json::Value>), CallErr> {
+    let body = Vec::from(resp.bytes().await?);
+    if cfg!(feature = "raw-response") {
+        let raw: serde_json::Value = decode_json(body)?;
+        // Deserializing from a reference avoids holding two copies of the body.
+        let res = T::deserialize(&raw).map_err(|e| e.to_string())?;
+        Ok((res, Some(raw)))
+    } else {
+        Ok((decode_json(body)?, None))
+    }
+}
+
+#[cfg(all(
+    feature = "simd-json",
+    any(feature = "openai", feature = "anthropic", feature = "openrouter")
+))]
+/// Decodes a JSON response body in place with simd-json.
+fn decode_json<T: serde::de::DeserializeOwned>(mut body: Vec<u8>) -> Result<T, CallErr> {
+    Ok(simd_json::serde::from_slice(&mut body).map_err(|e| e.to_string())?)
+}
+
+#[cfg(all(
+    not(feature = "simd-json"),
+    any(feature = "openai", feature = "anthropic", feature = "openrouter")
+))]
+/// Decodes a JSON response body.
+fn decode_json<T: serde::de::DeserializeOwned>(body: Vec<u8>) -> Result<T, CallErr> {
+    Ok(serde_json::from_slice(&body).map_err(|e| e.to_string())?)
 }
 
+#[cfg(any(feature = "openai", feature = "openrouter"))]
+/// Sends a chat completions request which streams the response, passing the text of
+/// the response to `on_text` as it arrives, after the prefill if there is one.
+async fn stream_completions(
+    req: reqwest::RequestBuilder,
+    prefill: Option<&str>,
+    mut on_text: impl FnMut(&str) + Send,
+) -> Result<OAICompletionsResponse, CallErr> {
+    let resp = req.send().await?;
+    if !resp.status().is_success() {
+        return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+    }
+    if let Some(prefill) = prefill {
+        on_text(prefill);
+    }
+    let mut res = OAIStream::default();
+    read_events(resp, |data| res.push(data, &mut on_text)).await?;
+    res.finish()
+}
+
+#[cfg(feature = "anthropic")]
+/// Checks a messages API response describes a finished message, converting it into
+/// a [CallResp].
+pub(crate) fn anthropic_response(
+    mut res: AnthropicMsgResponse,
+    model: &str,
+    prompt: Option<PromptRef>,
+) -> Result<CallResp, CallErr> {
+    if res.model.is_empty() {
+        res.model = model.into();
+    }
+
+    if let Some(object) = res.object.as_deref().filter(|o| *o != "message") {
+        return Err(format!("unexpected value for 'object': {}", object).into());
+    }
+    if let Some(role) = res.role.as_deref().filter(|r| *r != "assistant") {
+        return Err(format!("unexpected value for 'role': {}", role).into());
+    }
+
+    match res.stop_reason {
+        FinishReason::Stop | FinishReason::ToolCalls => {
+            // Convert Anthropics format into whats expected by this API
+            Ok(CallResp {
+                prompt,
+                ..res.into()
+            })
+        }
+        _ => Err(format!("unexpected finish reason: {:?}", res.stop_reason).into()),
+    }
+}
+
+#[cfg(feature = "openai")]
 /// A [ModelCaller] that talks to a model accessible via the OpenAI chat completions API.
 ///
 /// If an API key is not provided, it will be read from the environment variable
-/// `OPENAI_API_KEY`.
+/// `OPENAI_API_KEY`, or the [config file](crate::config).
+///
+/// Any server implementing the same API, such as llama.cpp or vLLM, can be used by
+/// setting `base_url`. This also allows output to be constrained with [CallBase::constraint].
 #[derive(Debug, Clone, Default)]
 pub struct Openai<M: OpenAIModel> {
     pub model: M,
     pub api_key: Option<String>,
+    /// Where the key comes from if `api_key` isn't set, see [crate::credentials].
+    pub credentials: Option<Arc<dyn CredentialProvider>>,
+    /// Defaults to `https://api.openai.com/v1`.
+    pub base_url: Option<String>,
+    /// Sent as the `OpenAI-Organization` header, so usage is billed to that
+    /// organization when the key belongs to several.
+    pub organization: Option<String>,
+    /// Sent as the `OpenAI-Project` header, so usage is attributed to that project.
+    pub project: Option<String>,
+    /// Sent as the `User-Agent` header. Defaults to `mini-prompt/<version>`.
+    pub user_agent: Option<String>,
 }
 
+pub(crate) const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+#[cfg(feature = "openai")]
+impl<M: OpenAIModel> Openai<M> {
+    pub(crate) fn base_url(&self) -> Result<&str, CallErr> {
+        match &self.base_url {
+            Some(url) => Ok(url),
+            None => Ok(config::base_url(|c| &c.openai)?.unwrap_or(OPENAI_BASE_URL)),
+        }
+    }
+
+    pub(crate) async fn api_key(&self) -> Result<String, CallErr> {
+        credentials::api_key(&self.api_key, &self.credentials, &["OPENAI_API_KEY"], |c| {
+            &c.openai
+        })
+        .await
+    }
+
+    /// Returns the headers sent with every request, including the key.
+    pub(crate) async fn headers(&self) -> Result<HeaderMap, CallErr> {
+        let mut headers = HeaderMap::new();
+        let key = format!("Bearer {}", self.api_key().await?);
+        insert_header(&mut headers, "authorization", &key)?;
+        insert_user_agent(&mut headers, &self.user_agent)?;
+        if let Some(org) = &self.organization {
+            insert_header(&mut headers, "openai-organization", org)?;
+        }
+        if let Some(project) = &self.project {
+            insert_header(&mut headers, "openai-project", project)?;
+        }
+        Ok(headers)
+    }
+
+    /// Builds the body of a chat completions request for the given call.
+    pub(crate) fn completions_request(
+        &self,
+        mut params: CallBase,
+        turns: Vec<Turn>,
+    ) -> Result<OAICompletionsRequest, CallErr> {
+        if M::REASONING && params.temperature.is_some() {
+            return Err("temperature is not supported by reasoning models".into());
+        }
+        let mut messages =
+            oai_system_msgs(self.get_model(), params.take_system(), params.instructions);
+        for t in turns {
+            messages.extend(t.into_oai_msgs()?);
+        }
+        messages.extend(params.prefill.take().map(OAIChatMessage::assistant));
+        if !M::REASONING {
+            developer_as_system(&mut messages);
+        }
+
+        let (grammar, guided_regex) = match params.constraint {
+            Some(Constraint::Grammar(g)) => (Some(g), None),
+            Some(Constraint::Regex(r)) => (None, Some(r)),
+            None => (None, None),
+        };
+
+        Ok(OAICompletionsRequest {
+            model: M::MODEL_STR.into(),
+            temperature: params.temperature,
+            max_completion_tokens: M::REASONING.then_some(params.max_tokens),
+            stop: params.stop,
+            provider: None,
+            reasoning: None,
+            messages,
+            tool_choice: if params.tools.is_empty() {
+                None
+            } else {
+                Some(params.tool_choice.into())
+            },
+            tools: params.tools.into_iter().map(|td| td.into()).collect(),
+            response_format: params.response_schema.map(|s| s.into()),
+            logprobs: params.top_logprobs.map(|_| true),
+            top_logprobs: params.top_logprobs,
+            seed: params.seed,
+            grammar,
+            guided_regex,
+            stream: None,
+        })
+    }
+}
+
+#[cfg(feature = "openai")]
 impl<M: OpenAIModel> ModelCaller for Openai<M> {
     fn get_model(&self) -> impl Model {
         M::default()
     }
 
-    async fn call(&mut self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
-        // Map `system` and `instructions` into one text stanza, as expected by
-        // this API.
-        let system_prompt = match (params.system != "", params.instructions != "") {
-            (true, true) => Some((params.system + "\n\n" + &params.instructions).into()),
-            (false, true) => Some(params.instructions.clone()),
-            (true, false) => Some(params.system.clone()),
-            (false, false) => None,
-        }
-        .map(|p| self.get_model().make_prompt(p));
+    fn endpoint(&self) -> String {
+        // An unreadable config file fails the call, so nothing is cached under this.
+        format!("{} {}", M::MODEL_STR, self.base_url().unwrap_or_default())
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        true
+    }
 
-        let mut messages = Vec::with_capacity(1 + turns.len());
-        if let Some(system_prompt) = system_prompt {
-            messages.push(system_prompt);
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        before_deadline(params.deadline, async move {
+            let prompt = params.prompt.clone();
+            let prefill = params.prefill.clone();
+            let client = Client::new();
+            let resp = client
+                .post(format!("{}/chat/completions", self.base_url()?))
+                .headers(self.headers().await?)
+                .json(&self.completions_request(params, turns)?)
+                .send()
+                .await?;
+
+            if !resp.status().is_success() {
+                return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+            }
+            let (res, raw) = read_json(resp).await?;
+            Ok(CallResp {
+                raw,
+                ..oai_response(res, M::MODEL_STR, prompt)?
+            }
+            .with_prefill(prefill))
+        })
+        .await
+    }
+
+    async fn call_streaming<F: FnMut(&str) + Send>(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+        on_text: F,
+    ) -> Result<CallResp, CallErr> {
+        before_deadline(params.deadline, async move {
+            let prompt = params.prompt.clone();
+            let prefill = params.prefill.clone();
+            let client = Client::new();
+            let req = client
+                .post(format!("{}/chat/completions", self.base_url()?))
+                .headers(self.headers().await?)
+                .json(&OAICompletionsRequest {
+                    stream: Some(true),
+                    ..self.completions_request(params, turns)?
+                });
+            let res = stream_completions(req, prefill.as_deref(), on_text).await?;
+            Ok(oai_response(res, M::MODEL_STR, prompt)?.with_prefill(prefill))
+        })
+        .await
+    }
+}
+
+#[cfg(any(feature = "openai", feature = "openrouter"))]
+/// Checks a chat completions response describes a finished completion, converting it
+/// into a [CallResp].
+pub(crate) fn oai_response(
+    mut res: OAICompletionsResponse,
+    model: &str,
+    prompt: Option<PromptRef>,
+) -> Result<CallResp, CallErr> {
+    if res.model.is_empty() {
+        res.model = model.into();
+    }
+    if let Some(object) = res.object.as_deref().filter(|o| *o != "chat.completion") {
+        return Err(format!("unexpected value for 'object': {}", object).into());
+    }
+    if res.choices.is_empty() {
+        return Err(CallErr::NoCompletions);
+    }
+
+    let finish_reason = res.choices[0].finish_reason.clone();
+    match finish_reason {
+        FinishReason::Stop | FinishReason::ToolCalls => Ok(CallResp {
+            prompt,
+            ..res.into()
+        }),
+        _ => Err(format!(
+            "unexpected finish reason: {:?}",
+            res.choices[0].finish_reason
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Scripted, Shared};
+
+    #[tokio::test]
+    async fn dyn_callers() {
+        let mut backends: Vec<Box<dyn DynModelCaller>> = vec![
+            Box::new(Scripted::new(vec![Scripted::text("one")])),
+            Box::new(Shared::new(&["two"])),
+        ];
+        let mut out = vec![];
+        for b in backends.iter_mut() {
+            assert_eq!(b.model_name(), "mini_prompt::models::Gemma27B3");
+            out.push(b.simple_call("Count.").await.unwrap());
         }
-        messages.extend(turns.into_iter().map(|t| t.into_oai_msgs()).flatten());
+        assert_eq!(out, vec!["one", "two"]);
+        assert!(backends[0].simple_call("Count.").await.is_err());
 
-        let client = Client::new();
-        let resp = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .bearer_auth(
-                self.api_key
-                    .clone()
-                    .unwrap_or_else(|| env::var("OPENAI_API_KEY").unwrap()),
+        // Boxing a boxed caller keeps the name of the model.
+        let nested: Box<dyn DynModelCaller> = Box::new(backends.pop().unwrap());
+        assert_eq!(nested.model_name(), "mini_prompt::models::Gemma27B3");
+    }
+
+    #[tokio::test]
+    async fn forwarding_callers() {
+        let backend = Scripted::new(vec![
+            Scripted::text("one"),
+            Scripted::text("two"),
+            Scripted::text("three"),
+        ]);
+        let borrowed: Box<dyn DynModelCaller + '_> = Box::new(&backend);
+        assert_eq!(borrowed.simple_call("Count.").await.unwrap(), "one");
+        drop(borrowed);
+        assert_eq!(backend.seen().len(), 1);
+
+        // Concurrent calls share the backend.
+        let shared = std::sync::Arc::new(backend);
+        let calls = (0..2).map(|_| {
+            let shared = shared.clone();
+            async move { shared.simple_call("Count.").await }
+        });
+        let out = futures::future::join_all(calls).await;
+        assert!(out.iter().all(|r| r.is_ok()));
+        assert_eq!(shared.seen().len(), 3);
+
+        let backend = std::sync::Arc::into_inner(shared).unwrap();
+        let shared = std::sync::Arc::new(tokio::sync::Mutex::new(backend));
+        assert!(shared.simple_call("Count.").await.is_err());
+        assert_eq!(shared.lock().await.seen().len(), 4);
+
+        assert_eq!(shared.model_name(), "mini_prompt::models::Gemma27B3");
+        let _guard = shared.lock().await;
+        assert_eq!(shared.model_name(), "mini_prompt::testing::Scripted");
+    }
+
+    #[tokio::test]
+    async fn call_streaming() {
+        let backend: Box<dyn DynModelCaller> = Box::new(Scripted::new(vec![
+            Scripted::text("one"),
+            Scripted::text(""),
+        ]));
+        let mut out = String::new();
+        let resp = backend
+            .call_streaming(CallBase::default(), vec![], |text| out.push_str(text))
+            .await
+            .unwrap();
+        assert_eq!(resp.content.text(), "one");
+        assert_eq!(out, "one");
+
+        // Empty responses pass no text.
+        let mut chunks = 0;
+        backend
+            .call_streaming(CallBase::default(), vec![], |_| chunks += 1)
+            .await
+            .unwrap();
+        assert_eq!(chunks, 0);
+    }
+
+    #[tokio::test]
+    async fn chat() {
+        let caller = Scripted::new(vec![Scripted::text("Ferris")]);
+        let resp = caller
+            .chat(
+                &[(Role::User, "I'm Ferris."), (Role::Assistant, "Hi!")],
+                "Who am I?",
             )
-            .json(&OAICompletionsRequest {
-                model: M::MODEL_STR.into(),
-                temperature: params.temperature,
-                provider: None,
-                messages,
-                tool_choice: if params.tools.is_empty() {
-                    None
-                } else {
-                    Some(OAIToolChoice::Auto)
-                },
-                tools: params.tools.into_iter().map(|td| td.into()).collect(),
-                ..Default::default()
-            })
-            .send()
-            .await?;
+            .await
+            .unwrap();
+        assert_eq!(resp, "Ferris");
 
-        if !resp.status().is_success() {
-            return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
-        }
+        let turns = &caller.seen()[0].1;
+        assert_eq!(turns.len(), 3);
+        assert_eq!(turns[1].role, Role::Assistant);
+        assert_eq!(turns[2].content, vec![Message::text("Who am I?")]);
+    }
+
+    #[cfg(all(feature = "openrouter", feature = "anthropic"))]
+    #[tokio::test]
+    async fn constraint_unsupported() {
+        let params = CallBase {
+            constraint: Some(Constraint::Regex("[0-9]+".into())),
+            ..Default::default()
+        };
+        let caller = Openrouter::<crate::models::Gemma27B3>::default();
+        assert!(matches!(
+            caller.call(params.clone(), vec![]).await,
+            Err(CallErr::Other(_))
+        ));
+        let caller = Anthropic::<crate::models::ClaudeHaiku35>::default();
+        assert!(matches!(
+            caller.call(params, vec![]).await,
+            Err(CallErr::Other(_))
+        ));
+    }
+
+    #[cfg(all(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+    #[tokio::test]
+    async fn provider_headers() {
+        let caller = Openai::<crate::models::GPT41Mini> {
+            api_key: Some("sk-1".into()),
+            organization: Some("org-1".into()),
+            project: Some("proj_1".into()),
+            ..Default::default()
+        };
+        let headers = caller.headers().await.unwrap();
+        assert_eq!(headers["authorization"], "Bearer sk-1");
+        assert!(headers["authorization"].is_sensitive());
+        assert_eq!(headers["openai-organization"], "org-1");
+        assert_eq!(headers["openai-project"], "proj_1");
+        assert_eq!(headers["user-agent"], "mini-prompt/0.0.1");
+
+        let caller = Openai::<crate::models::GPT41Mini> {
+            api_key: Some("sk-1".into()),
+            project: Some("bad\nproject".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            caller.headers().await,
+            Err(CallErr::Other(e)) if e.to_string() == "invalid value for the openai-project header"
+        ));
+
+        let caller = Anthropic::<crate::models::ClaudeSonnet4> {
+            api_key: Some("sk-ant".into()),
+            betas: vec![
+                AnthropicBeta::InterleavedThinking,
+                AnthropicBeta::Other("new-beta".into()),
+            ],
+            ..Default::default()
+        };
+        let openrouter = Openrouter::<crate::models::Gemma27B3> {
+            api_key: Some("sk-or".into()),
+            app_url: Some("https://example.com".into()),
+            app_name: Some("Example".into()),
+            user_agent: Some("example/1.0".into()),
+            ..Default::default()
+        };
+        let headers = openrouter.headers().await.unwrap();
+        assert_eq!(headers["http-referer"], "https://example.com");
+        assert_eq!(headers["x-title"], "Example");
+        assert_eq!(headers["user-agent"], "example/1.0");
 
-        let mut res = resp.json::<OAICompletionsResponse>().await?;
+        let pinned = Anthropic::<crate::models::ClaudeSonnet4> {
+            api_version: Some("2099-01-01".into()),
+            ..caller.clone()
+        };
+        assert_eq!(
+            pinned.headers().await.unwrap()["anthropic-version"],
+            "2099-01-01"
+        );
+        let headers = caller.headers().await.unwrap();
+        assert!(headers["x-api-key"].is_sensitive());
+        assert_eq!(headers["anthropic-version"], ANTHROPIC_VERSION);
+        assert_eq!(
+            headers["anthropic-beta"],
+            "interleaved-thinking-2025-05-14,new-beta"
+        );
+    }
 
-        if res.model == "" {
-            res.model = M::MODEL_STR.into();
+    #[cfg(feature = "schemars")]
+    #[tokio::test]
+    async fn call_structured() {
+        #[derive(Debug, serde::Deserialize, JsonSchema, PartialEq)]
+        struct Answer {
+            answer: u32,
         }
-        if let Some("chat.completion") = res.object.as_ref().map(|s| s.as_str()) {
-        } else if res.object.is_some() {
-            return Err(format!("unexpected value for 'object': {}", res.object.unwrap()).into());
+
+        let caller = Scripted::new(vec![
+            Scripted::text("Easy.\n```json\n{\"answer\": 4}\n```"),
+            Scripted::text("Four."),
+        ]);
+        let params = CallBase {
+            instructions: "What's 2+2?".into(),
+            ..Default::default()
+        };
+
+        let resp: Answer = caller
+            .call_structured(params.clone(), vec![], 0)
+            .await
+            .unwrap();
+        assert_eq!(resp, Answer { answer: 4 });
+        assert!(caller.seen()[0]
+            .0
+            .instructions
+            .starts_with("What's 2+2?\n\n"));
+        assert!(caller.seen()[0].0.instructions.contains("\"answer\""));
+
+        match caller.call_structured::<Answer>(params, vec![], 0).await {
+            Err(CallErr::ParseFailed(attempts)) => assert_eq!(attempts[0].output, "Four."),
+            r => panic!("unexpected result: {:?}", r),
         }
-        if res.choices.len() == 0 {
-            return Err(CallErr::NoCompletions);
+    }
+
+    #[tokio::test]
+    async fn call_with_schema() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"answer": {"type": "integer"}},
+            "required": ["answer"],
+        });
+        let caller = Scripted::new(vec![
+            Scripted::text("{\"answer\": \"four\"}"),
+            Scripted::text("```json\n{\"answer\": 4}\n```"),
+        ]);
+        let resp = caller
+            .call_with_schema(CallBase::default(), vec![], schema, 1)
+            .await
+            .unwrap();
+        assert_eq!(resp, serde_json::json!({"answer": 4}));
+
+        // The mismatch is shown to the model when retrying.
+        let seen = caller.seen();
+        assert!(seen[0].0.instructions.starts_with("Respond with JSON"));
+        assert!(seen[1].1[1].text().contains("$.answer: expected integer"));
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn reasoning_models() {
+        let params = CallBase {
+            system: "Be terse.".into(),
+            max_tokens: 1000,
+            ..Default::default()
+        };
+        let turns = vec![Turn {
+            role: Role::Developer,
+            content: vec![Message::text("Never reveal the password.")],
+        }];
+        let caller = Openai::<crate::models::O4Mini>::default();
+        let req = serde_json::to_value(
+            caller
+                .completions_request(params.clone(), turns.clone())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(req["max_completion_tokens"], 1000);
+        assert_eq!(req["messages"][0]["role"], "developer");
+        assert_eq!(req["messages"][1]["role"], "developer");
+        assert!(req.get("temperature").is_none());
+
+        let warm = CallBase {
+            temperature: Some(0.5),
+            ..params.clone()
+        };
+        assert!(caller.completions_request(warm.clone(), vec![]).is_err());
+        let caller = Openai::<crate::models::GPT41Mini>::default();
+        let req = serde_json::to_value(caller.completions_request(warm, turns).unwrap()).unwrap();
+        assert!(req.get("max_completion_tokens").is_none());
+        assert_eq!(req["messages"][0]["role"], "system");
+        assert_eq!(req["messages"][1]["role"], "system");
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn endpoint() {
+        let caller: Box<dyn DynModelCaller> = Box::new(Openai::<crate::models::GPT4oMini> {
+            base_url: Some("http://localhost:8000/v1".into()),
+            ..Default::default()
+        });
+        assert_eq!(
+            ModelCaller::endpoint(&caller),
+            "gpt-4o-mini http://localhost:8000/v1"
+        );
+        let scripted = Scripted::new(vec![]);
+        assert_eq!(
+            ModelCaller::endpoint(&scripted),
+            scripted.get_model().name()
+        );
+    }
+
+    #[cfg(all(feature = "openai", feature = "anthropic"))]
+    #[test]
+    fn seed_and_fingerprint() {
+        let params = CallBase {
+            seed: Some(7),
+            ..Default::default()
+        };
+        let caller = Openai::<crate::models::GPT41Mini>::default();
+        let req = serde_json::to_value(caller.completions_request(params.clone(), vec![]).unwrap())
+            .unwrap();
+        assert_eq!(req["seed"], 7);
+        let caller = Anthropic::<crate::models::ClaudeSonnet4>::default();
+        assert!(caller.msg_request(params, vec![]).is_err());
+
+        let res: OAICompletionsResponse = serde_json::from_str(
+            r#"{
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "model": "gpt-4.1-mini",
+                "system_fingerprint": "fp_44709d6fcb",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "Hi"},
+                    "finish_reason": "stop"
+                }]
+            }"#,
+        )
+        .unwrap();
+        let resp = oai_response(res, "", None).unwrap();
+        assert_eq!(resp.system_fingerprint.as_deref(), Some("fp_44709d6fcb"));
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn decode_json() {
+        let res: OAICompletionsResponse = super::decode_json(
+            br#"{"id": "1", "model": "gpt-4.1-mini", "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi \u00e9"},
+                "finish_reason": "stop"
+            }]}"#
+                .to_vec(),
+        )
+        .unwrap();
+        assert_eq!(res.choices[0].message.content.as_deref(), Some("Hi é"));
+        assert!(super::decode_json::<OAICompletionsResponse>(b"{".to_vec()).is_err());
+    }
+
+    #[cfg(all(feature = "openai", feature = "anthropic"))]
+    #[test]
+    fn prefill() {
+        let turns = vec![Turn {
+            role: Role::User,
+            content: vec![Message::text("List three colours as JSON.")],
+        }];
+        let params = CallBase {
+            prefill: Some("{".into()),
+            ..Default::default()
+        };
+        let caller = Openai::<crate::models::GPT41Mini>::default();
+        let req = caller
+            .completions_request(params.clone(), turns.clone())
+            .unwrap();
+        assert_eq!(req.messages.len(), 2);
+        assert_eq!(req.messages[1], OAIChatMessage::assistant("{"));
+        let req = caller
+            .completions_request(CallBase::default(), turns.clone())
+            .unwrap();
+        assert_eq!(req.messages.len(), 1);
+
+        let caller = Anthropic::<crate::models::ClaudeSonnet4>::default();
+        let req = caller.msg_request(params, turns.clone()).unwrap();
+        assert_eq!(
+            req.messages[1],
+            AnthropicMessage::assistant_text("{".into())
+        );
+        let params = CallBase {
+            prefill: Some("{\n".into()),
+            ..Default::default()
+        };
+        assert!(caller.msg_request(params, turns).is_err());
+    }
+
+    #[cfg(all(feature = "openai", feature = "anthropic"))]
+    #[test]
+    fn system_segments() {
+        let params = CallBase {
+            system: "Follow the policy.".into(),
+            system_segments: vec!["".into(), "Be a pirate.".into()],
+            instructions: "Greet the user.".into(),
+            ..Default::default()
+        };
+        let turns = vec![
+            Turn {
+                role: Role::System,
+                content: vec![Message::text("The user is Ferris.")],
+            },
+            Turn {
+                role: Role::User,
+                content: vec![Message::text("Hi!")],
+            },
+        ];
+
+        let caller = Openai::<crate::models::GPT41Mini>::default();
+        let req = caller
+            .completions_request(params.clone(), turns.clone())
+            .unwrap();
+        let msgs: Vec<_> = req
+            .messages
+            .iter()
+            .map(|m| (m.role.clone(), m.content.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            msgs,
+            vec![
+                (Role::System, "Follow the policy."),
+                (Role::System, "Be a pirate.\n\nGreet the user."),
+                (Role::System, "The user is Ferris."),
+                (Role::User, "Hi!"),
+            ]
+        );
+
+        let caller = Anthropic::<crate::models::ClaudeSonnet4>::default();
+        let req = serde_json::to_value(caller.msg_request(params, turns).unwrap()).unwrap();
+        assert_eq!(
+            req["system"],
+            serde_json::json!([
+                {"type": "text", "text": "Follow the policy."},
+                {"type": "text", "text": "Be a pirate."},
+                {"type": "text", "text": "The user is Ferris."},
+            ])
+        );
+        assert_eq!(req["messages"][0]["content"][0]["text"], "Greet the user.");
+        assert_eq!(req["messages"][1]["content"][0]["text"], "Hi!");
+    }
+
+    #[cfg(feature = "anthropic")]
+    #[test]
+    fn extended_thinking() {
+        let res: AnthropicMsgResponse = serde_json::from_str(
+            r#"{
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-sonnet-4-20250514",
+                "content": [
+                    {"type": "thinking", "thinking": "Need the weather.", "signature": "sig"},
+                    {"type": "redacted_thinking", "data": "enc"},
+                    {"type": "tool_use", "id": "t1", "name": "weather", "input": {}}
+                ],
+                "stop_reason": "tool_use",
+                "usage": {"input_tokens": 1, "output_tokens": 1}
+            }"#,
+        )
+        .unwrap();
+        let resp = anthropic_response(res, "", None).unwrap();
+        assert_eq!(
+            resp.content.content[0],
+            Message::Thinking {
+                text: "Need the weather.".into(),
+                signature: Some("sig".into()),
+            }
+        );
+
+        let turns = vec![
+            resp.content,
+            Turn {
+                role: Role::Tool,
+                content: vec![Message::ToolResult {
+                    id: "t1".into(),
+                    result: "Sunny".into(),
+                }],
+            },
+        ];
+        let caller = Anthropic::<crate::models::ClaudeSonnet4> {
+            thinking_budget: Some(2048),
+            ..Default::default()
+        };
+        let req = serde_json::to_value(
+            caller
+                .msg_request(CallBase::default(), turns.clone())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(req["thinking"]["type"], "enabled");
+        assert_eq!(req["thinking"]["budget_tokens"], 2048);
+        let blocks = &req["messages"][0]["content"];
+        assert_eq!(blocks[0]["signature"], "sig");
+        assert_eq!(blocks[1]["data"], "enc");
+        assert_eq!(blocks[2]["type"], "tool_use");
+        assert_eq!(Turn::to_oai_messages(&turns).unwrap()[0].content, None);
+
+        let warm = CallBase {
+            temperature: Some(0.5),
+            ..Default::default()
+        };
+        assert!(caller.msg_request(warm, turns).is_err());
+    }
+
+    #[cfg(feature = "openrouter")]
+    #[test]
+    fn openrouter_reasoning() {
+        let reasoning = Reasoning {
+            effort: Some(ReasoningEffort::High),
+            exclude: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(reasoning.wire().unwrap()).unwrap(),
+            serde_json::json!({"enabled": true, "effort": "high", "exclude": true})
+        );
+        let both = Reasoning {
+            max_tokens: Some(2000),
+            ..reasoning
+        };
+        assert!(both.wire().is_err());
+
+        let res: OAICompletionsResponse = serde_json::from_str(
+            r#"{
+                "id": "gen-1",
+                "object": "chat.completion",
+                "model": "google/gemini-2.5-flash",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "4", "reasoning": "2 + 2 is 4."},
+                    "finish_reason": "stop"
+                }]
+            }"#,
+        )
+        .unwrap();
+        let resp = oai_response(res, "", None).unwrap();
+        assert_eq!(
+            resp.content.content,
+            vec![
+                Message::Thinking {
+                    text: "2 + 2 is 4.".into(),
+                    signature: None,
+                },
+                Message::text("4"),
+            ]
+        );
+        assert_eq!(resp.content.text(), "4");
+    }
+
+    #[tokio::test]
+    async fn deadlines() {
+        use std::time::{Duration, Instant};
+
+        let params = CallBase::default().with_timeout(Duration::from_secs(60));
+        let earlier = params.clone().with_timeout(Duration::from_millis(100));
+        assert!(earlier.deadline < params.deadline);
+        let kept = earlier.clone().with_timeout(Duration::from_secs(60));
+        assert_eq!(kept.deadline, earlier.deadline);
+
+        // A caller which takes 40ms per call.
+        struct Slow(Scripted);
+        impl ModelCaller for Slow {
+            fn get_model(&self) -> impl Model {
+                self.0.get_model()
+            }
+            async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+                tokio::time::sleep(Duration::from_millis(40)).await;
+                self.0.call(params, turns).await
+            }
         }
+        let slow = Slow(Scripted::new(vec![Scripted::text("x"); 5]));
+        let res = slow
+            .call_parsed(earlier.clone(), vec![], 4, |_| Err::<(), _>("no".into()))
+            .await;
+        assert!(matches!(res, Err(CallErr::DeadlineExceeded)));
+        assert!(slow.0.seen().len() < 3);
+        assert_eq!(earlier.remaining(), Some(Duration::ZERO));
 
-        let finish_reason = res.choices[0].finish_reason.clone();
-        match finish_reason {
-            FinishReason::Stop | FinishReason::ToolCalls => Ok(res.into()),
-            _ => Err(format!(
-                "unexpected finish reason: {:?}",
-                res.choices[0].finish_reason
-            )
-            .into()),
+        let backend = Scripted::new(vec![Scripted::text("x")]);
+        let expired = CallBase {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+        let res = backend.call_parsed(expired, vec![], 1, |_| Ok(())).await;
+        assert!(matches!(res, Err(CallErr::DeadlineExceeded)));
+        assert!(backend.seen().is_empty());
+    }
+
+    #[cfg(feature = "anthropic")]
+    #[tokio::test]
+    async fn call_tagged() {
+        let caller = Scripted::new(vec![
+            Scripted::text("It's Paris."),
+            Scripted::text("France's capital is Paris.\n<answer> Paris"),
+        ]);
+        let answer = caller
+            .call_tagged(CallBase::default(), vec![], "answer", 1)
+            .await
+            .unwrap();
+        assert_eq!(answer, "Paris");
+        let (params, turns) = &caller.seen()[1];
+        assert_eq!(params.stop, vec!["</answer>"]);
+        assert!(turns[1].text().contains("the response has no <answer> tag"));
+
+        let res: AnthropicMsgResponse = serde_json::from_str(
+            r#"{"id": "msg_1", "content": [{"type": "text", "text": "<answer>4"}],
+                "stop_reason": "stop_sequence", "usage": {"input_tokens": 1, "output_tokens": 1}}"#,
+        )
+        .unwrap();
+        assert_eq!(res.stop_reason, FinishReason::Stop);
+    }
+
+    #[tokio::test]
+    async fn call_parsed_retries() {
+        let parse = |text: &str| text.parse::<u32>().map_err(|e| e.to_string());
+        let caller = Scripted::new(vec![
+            Scripted::text("four"),
+            Scripted::text("4.0"),
+            Scripted::text("4"),
+        ]);
+
+        let n = caller
+            .call_parsed(CallBase::default(), vec![], 2, parse)
+            .await
+            .unwrap();
+        assert_eq!(n, 4);
+        assert_eq!(caller.seen().len(), 3);
+        let retried = &caller.seen()[2].1;
+        assert_eq!(retried.len(), 4);
+        assert_eq!(retried[2].text(), "4.0");
+        assert!(retried[3]
+            .text()
+            .starts_with("Your response could not be parsed"));
+
+        let caller = Scripted::new(vec![Scripted::text("a"), Scripted::text("b")]);
+        match caller
+            .call_parsed(CallBase::default(), vec![], 1, parse)
+            .await
+        {
+            Err(CallErr::ParseFailed(attempts)) => {
+                assert_eq!(
+                    attempts
+                        .iter()
+                        .map(|a| a.output.as_str())
+                        .collect::<Vec<_>>(),
+                    vec!["a", "b"]
+                );
+            }
+            r => panic!("unexpected result: {:?}", r),
         }
     }
 }
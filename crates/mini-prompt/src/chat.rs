@@ -0,0 +1,552 @@
+//! Multi-turn conversations with a model.
+
+use crate::tokens::{self, TokenCounter};
+use crate::{CallBase, CallErr, Message, ModelCaller, Role, Turn};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Returns the most recent turns whose combined token count fits within `max_tokens`.
+///
+/// The window always begins with a user turn, so it never starts partway through
+/// an exchange (such as with a tool result). If even the most recent user turn does
+/// not fit, the window begins at that turn regardless.
+pub fn sliding_window<F: Fn(&Turn) -> usize>(
+    turns: &[Turn],
+    max_tokens: usize,
+    count: F,
+) -> &[Turn] {
+    let mut total = 0;
+    let mut start = turns.len();
+    for (i, t) in turns.iter().enumerate().rev() {
+        total += count(t);
+        if total > max_tokens {
+            break;
+        }
+        start = i;
+    }
+
+    match turns[start..].iter().position(|t| t.role == Role::User) {
+        Some(offset) => &turns[start + offset..],
+        None => match turns.iter().rposition(|t| t.role == Role::User) {
+            Some(last_user) => &turns[last_user..],
+            None => &turns[start..],
+        },
+    }
+}
+
+/// Decides whether a tool result is kept, given the name of the tool and its result.
+type ToolResultFilter = Arc<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// Configures summarization of the older turns of a [ChatSession], see
+/// [ChatSession::with_compaction].
+#[derive(Clone)]
+pub struct Compaction {
+    /// Older turns are summarized once the history exceeds this many tokens.
+    pub threshold: usize,
+    /// The number of most recent turns which are kept verbatim. More may be kept,
+    /// as the kept turns must begin with a user turn.
+    pub keep_recent: usize,
+    /// The instructions given to the model, which are followed by a transcript of
+    /// the turns to summarize.
+    pub prompt: String,
+    preserve: Option<ToolResultFilter>,
+}
+
+impl Default for Compaction {
+    fn default() -> Self {
+        Self {
+            threshold: 32_000,
+            keep_recent: 4,
+            prompt: "Summarize the following conversation between a user and an AI assistant. \
+                Keep any facts, decisions, and open questions needed to continue the conversation."
+                .into(),
+            preserve: None,
+        }
+    }
+}
+
+impl Compaction {
+    /// Constructs a compaction strategy which triggers once the history exceeds
+    /// the given number of tokens.
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            ..Default::default()
+        }
+    }
+
+    /// Sets a predicate, called with the name and result of each summarized tool call,
+    /// which decides if the result is kept verbatim alongside the summary.
+    pub fn preserve_tool_results<F: Fn(&str, &str) -> bool + Send + Sync + 'static>(
+        mut self,
+        f: F,
+    ) -> Self {
+        self.preserve = Some(Arc::new(f));
+        self
+    }
+}
+
+/// Renders turns as a plain-text transcript, for summarization.
+fn transcript(turns: &[Turn]) -> String {
+    let mut out = String::new();
+    for t in turns {
+        for m in t.content.iter() {
+            out += &match m {
+                Message::Text { text } => format!("{:?}: {}\n", t.role, text),
+                Message::ToolCall {
+                    name, arguments, ..
+                } => format!("{:?}: (called {} with {})\n", t.role, name, arguments),
+                Message::ToolResult { result, .. } => {
+                    format!("{:?}: (tool result) {}\n", t.role, result)
+                }
+                Message::Thinking { .. } | Message::RedactedThinking { .. } => continue,
+            };
+        }
+    }
+    out
+}
+
+/// A snapshot of the history of a [ChatSession], see [ChatSession::checkpoint].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint(Vec<Turn>);
+
+impl Checkpoint {
+    /// Returns the turns captured by the checkpoint.
+    pub fn turns(&self) -> &[Turn] {
+        &self.0
+    }
+}
+
+/// A conversation with a model, which accumulates the history of turns so
+/// each message is sent with the context of the ones before it.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "openrouter")] {
+/// # use mini_prompt::*;
+/// let backend = callers::Openrouter::<models::Gemma27B3>::default();
+/// let mut chat = ChatSession::new(
+///     backend,
+///     CallBase {
+///         system: "You are a helpful assistant".to_string(),
+///         ..Default::default()
+///     },
+/// );
+///
+/// # tokio::task::spawn(async move {
+/// let answer = chat.send("What's the tallest mountain?").await.unwrap();
+/// let answer = chat.send("How tall is it?").await.unwrap();
+/// # });
+/// # }
+/// ```
+pub struct ChatSession<B: ModelCaller, S: ModelCaller = B> {
+    backend: B,
+    /// The model used for compaction, if not the backend.
+    summarizer: Option<S>,
+    params: CallBase,
+    history: Vec<Turn>,
+
+    /// The maximum number of tokens of history and instructions sent with each call.
+    token_budget: Option<usize>,
+    token_counter: TokenCounter,
+    compaction: Option<Compaction>,
+}
+
+impl<B: ModelCaller> ChatSession<B> {
+    /// Constructs a new [ChatSession] with an empty history, which makes
+    /// calls to the given backend using the given parameters.
+    pub fn new(backend: B, params: CallBase) -> Self {
+        Self {
+            backend,
+            summarizer: None,
+            params,
+            history: vec![],
+            token_budget: None,
+            token_counter: Arc::new(tokens::estimate),
+            compaction: None,
+        }
+    }
+
+    /// Sets the model used to summarize older turns, such as a cheaper model than
+    /// the backend. See [with_compaction](ChatSession::with_compaction).
+    pub fn with_summarizer<S: ModelCaller>(self, summarizer: S) -> ChatSession<B, S> {
+        ChatSession {
+            backend: self.backend,
+            summarizer: Some(summarizer),
+            params: self.params,
+            history: self.history,
+            token_budget: self.token_budget,
+            token_counter: self.token_counter,
+            compaction: self.compaction,
+        }
+    }
+}
+
+impl<B: ModelCaller, S: ModelCaller> ChatSession<B, S> {
+    /// Resumes a conversation from a previously saved history.
+    pub fn with_history(mut self, history: Vec<Turn>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Limits each call to the most recent turns which fit within the given number of
+    /// tokens, alongside the system prompt and instructions. See [sliding_window].
+    ///
+    /// Older turns are kept in the history, but not sent to the model. Tokens are
+    /// estimated using [tokens::estimate] unless another counter is set using
+    /// [with_token_counter](ChatSession::with_token_counter).
+    pub fn with_token_budget(mut self, max_tokens: usize) -> Self {
+        self.token_budget = Some(max_tokens);
+        self
+    }
+
+    /// Sets the function used to count tokens when applying the token budget.
+    pub fn with_token_counter<F: Fn(&str) -> usize + Send + Sync + 'static>(
+        mut self,
+        counter: F,
+    ) -> Self {
+        self.token_counter = Arc::new(counter);
+        self
+    }
+
+    /// Enables summarization of older turns, which happens before sending a message
+    /// if the history exceeds the threshold of the given [Compaction].
+    ///
+    /// The summary is prepended to the first kept turn, replacing the turns before it
+    /// in the history. Summaries are made by the backend, unless another model is set
+    /// using [with_summarizer](ChatSession::with_summarizer).
+    ///
+    /// ```rust,no_run
+    /// # #[cfg(feature = "anthropic")] {
+    /// # use mini_prompt::*;
+    /// # use mini_prompt::chat::Compaction;
+    /// let chat = ChatSession::new(
+    ///     callers::Anthropic::<models::ClaudeSonnet4>::default(),
+    ///     CallBase::default(),
+    /// )
+    /// .with_compaction(Compaction::new(50_000).preserve_tool_results(|name, _| name == "plan"))
+    /// .with_summarizer(callers::Anthropic::<models::ClaudeHaiku35>::default());
+    /// # }
+    /// ```
+    pub fn with_compaction(mut self, compaction: Compaction) -> Self {
+        self.compaction = Some(compaction);
+        self
+    }
+
+    /// Summarizes older turns of the history, regardless of its size, returning whether
+    /// anything was summarized. Uses the default [Compaction] if none was configured.
+    pub async fn compact(&mut self) -> Result<bool, CallErr> {
+        let c = self.compaction.clone().unwrap_or_default();
+        let keep_from = self.history.len().saturating_sub(c.keep_recent);
+        let split = match self.history[keep_from..]
+            .iter()
+            .position(|t| t.role == Role::User)
+        {
+            Some(i) if keep_from + i > 0 => keep_from + i,
+            _ => return Ok(false),
+        };
+
+        let params = CallBase {
+            instructions: format!("{}\n\n{}", c.prompt, transcript(&self.history[..split])),
+            ..Default::default()
+        };
+        let resp = match &self.summarizer {
+            Some(s) => s.call(params, vec![]).await?,
+            None => self.backend.call(params, vec![]).await?,
+        };
+        let mut summary = format!(
+            "Summary of the conversation so far:\n{}",
+            resp.content.text()
+        );
+
+        if let Some(preserve) = &c.preserve {
+            let mut names = std::collections::HashMap::new();
+            let mut preserved = String::new();
+            for m in self.history[..split].iter().flat_map(|t| t.content.iter()) {
+                match m {
+                    Message::ToolCall { id, name, .. } => {
+                        names.insert(id, name);
+                    }
+                    Message::ToolResult { id, result } => {
+                        if let Some(name) = names.get(id).filter(|n| preserve(n, result)) {
+                            preserved += &format!("{}: {}\n", name, result);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if !preserved.is_empty() {
+                summary += "\n\nResults of earlier tool calls:\n";
+                summary += &preserved;
+            }
+        }
+
+        self.history.drain(..split);
+        self.history[0].content.insert(0, Message::text(summary));
+        Ok(true)
+    }
+
+    /// Returns the turns which would be sent to the model, after applying the token budget.
+    pub fn window(&self) -> &[Turn] {
+        let Some(max_tokens) = self.token_budget else {
+            return &self.history;
+        };
+        let count = &*self.token_counter;
+        let system: usize = self.params.system_segments.iter().map(|s| count(s)).sum();
+        let max_tokens = max_tokens
+            .saturating_sub(count(&self.params.system) + system + count(&self.params.instructions));
+        sliding_window(&self.history, max_tokens, |t| tokens::count_turn(t, count))
+    }
+
+    /// Returns the turns of the conversation so far.
+    pub fn history(&self) -> &[Turn] {
+        &self.history
+    }
+
+    /// Captures the current history, so the conversation can later be
+    /// returned to this point with [rollback](ChatSession::rollback).
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.history.clone())
+    }
+
+    /// Restores the history captured by a checkpoint, discarding any turns since.
+    pub fn rollback(&mut self, checkpoint: &Checkpoint) {
+        self.history.clone_from(&checkpoint.0);
+    }
+
+    /// Returns an independent copy of the session, so alternate continuations
+    /// of the conversation can be explored.
+    pub fn fork(&self) -> Self
+    where
+        B: Clone,
+        S: Clone,
+    {
+        Self {
+            backend: self.backend.clone(),
+            summarizer: self.summarizer.clone(),
+            params: self.params.clone(),
+            history: self.history.clone(),
+            token_budget: self.token_budget,
+            token_counter: self.token_counter.clone(),
+            compaction: self.compaction.clone(),
+        }
+    }
+
+    /// Discards the history of the conversation.
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Returns the parameters used for each call.
+    pub fn params(&self) -> &CallBase {
+        &self.params
+    }
+
+    /// Returns a mutable reference to the parameters used for each call.
+    pub fn params_mut(&mut self) -> &mut CallBase {
+        &mut self.params
+    }
+
+    /// Returns a reference to the backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Consumes the session, returning the backend and history.
+    pub fn into_parts(self) -> (B, Vec<Turn>) {
+        (self.backend, self.history)
+    }
+
+    /// Sends a user message, returning the text of the model's response.
+    ///
+    /// Both the message and the response are added to the history. If the call
+    /// fails, the history is left unchanged so the message can be retried.
+    pub async fn send<T: Into<String>>(&mut self, user_text: T) -> Result<String, CallErr> {
+        if let Some(threshold) = self.compaction.as_ref().map(|c| c.threshold) {
+            let count = &*self.token_counter;
+            let total: usize = self
+                .history
+                .iter()
+                .map(|t| tokens::count_turn(t, count))
+                .sum();
+            if total > threshold {
+                self.compact().await?;
+            }
+        }
+
+        self.history.push(Turn {
+            role: Role::User,
+            content: vec![Message::text(user_text.into())],
+        });
+
+        let resp = match self
+            .backend
+            .call(self.params.clone(), self.window().to_vec())
+            .await
+        {
+            Ok(resp) => resp,
+            Err(e) => {
+                self.history.pop();
+                return Err(e);
+            }
+        };
+
+        let text = resp.content.text();
+        self.history.push(resp.content);
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scripted;
+
+    #[tokio::test]
+    async fn send_accumulates_history() {
+        let backend = Scripted::new(vec![Scripted::text("Everest"), Scripted::text("8849m")]);
+        let mut chat = ChatSession::new(
+            backend,
+            CallBase {
+                system: "Be brief".into(),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(chat.send("Tallest mountain?").await.unwrap(), "Everest");
+        assert_eq!(chat.send("How tall?").await.unwrap(), "8849m");
+        assert!(chat.send("And the next?").await.is_err());
+
+        let roles: Vec<_> = chat.history().iter().map(|t| t.role.clone()).collect();
+        assert_eq!(
+            roles,
+            vec![Role::User, Role::Assistant, Role::User, Role::Assistant]
+        );
+
+        let (backend, _) = chat.into_parts();
+        assert_eq!(backend.seen()[0].1.len(), 1);
+        assert_eq!(backend.seen()[1].1.len(), 3);
+        assert_eq!(backend.seen()[1].0.system, "Be brief");
+    }
+
+    #[tokio::test]
+    async fn checkpoint_and_rollback() {
+        let backend = Scripted::new(vec![Scripted::text("a"), Scripted::text("b")]);
+        let mut chat = ChatSession::new(backend, CallBase::default());
+
+        chat.send("one").await.unwrap();
+        let cp = chat.checkpoint();
+        chat.send("two").await.unwrap();
+        assert_eq!(chat.history().len(), 4);
+
+        chat.rollback(&cp);
+        assert_eq!(chat.history(), cp.turns());
+        assert_eq!(chat.history().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fork() {
+        let backend = Scripted::new(vec![Scripted::text("a"), Scripted::text("b")]);
+        let mut chat = ChatSession::new(backend, CallBase::default());
+        chat.send("one").await.unwrap();
+
+        let mut branch = chat.fork();
+        assert_eq!(branch.send("two").await.unwrap(), "b");
+        assert_eq!(chat.send("three").await.unwrap(), "b");
+        assert_eq!(branch.history().len(), 4);
+        assert_eq!(branch.history()[..2], chat.history()[..2]);
+        assert_ne!(branch.history()[2], chat.history()[2]);
+    }
+
+    #[test]
+    fn sliding_window_starts_with_user() {
+        let turn = |role| Turn {
+            role,
+            content: vec![],
+        };
+        let turns = vec![
+            turn(Role::User),
+            turn(Role::Assistant),
+            turn(Role::Tool),
+            turn(Role::Assistant),
+            turn(Role::User),
+            turn(Role::Assistant),
+        ];
+        let roles = |w: &[Turn]| w.iter().map(|t| t.role.clone()).collect::<Vec<_>>();
+
+        assert_eq!(sliding_window(&turns, 10, |_| 1).len(), 6);
+        assert_eq!(
+            roles(sliding_window(&turns, 4, |_| 1)),
+            vec![Role::User, Role::Assistant]
+        );
+        assert_eq!(sliding_window(&turns, 0, |_| 1).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn token_budget() {
+        let backend = Scripted::new(vec![Scripted::text("a"), Scripted::text("b")]);
+        let mut chat = ChatSession::new(backend, CallBase::default())
+            .with_token_budget(12)
+            .with_token_counter(|s| s.len());
+
+        chat.send("one").await.unwrap();
+        chat.send("two").await.unwrap();
+        assert_eq!(chat.history().len(), 4);
+
+        let (backend, _) = chat.into_parts();
+        assert_eq!(backend.seen()[1].1.len(), 1);
+        assert_eq!(backend.seen()[1].1[0].content, vec![Message::text("two")]);
+    }
+
+    #[tokio::test]
+    async fn compaction() {
+        let backend = Scripted::new(vec![
+            Scripted::text("a"),
+            Scripted::text("b"),
+            Scripted::text("c"),
+        ]);
+        let summarizer = Scripted::new(vec![Scripted::text("they said hi")]);
+        let mut chat = ChatSession::new(backend, CallBase::default())
+            .with_compaction(Compaction {
+                keep_recent: 2,
+                ..Compaction::new(20).preserve_tool_results(|name, _| name == "plan")
+            })
+            .with_summarizer(summarizer)
+            .with_history(vec![
+                Turn {
+                    role: Role::Assistant,
+                    content: vec![Message::ToolCall {
+                        id: "1".into(),
+                        name: "plan".into(),
+                        arguments: "{}".into(),
+                    }],
+                },
+                Turn {
+                    role: Role::Tool,
+                    content: vec![Message::ToolResult {
+                        id: "1".into(),
+                        result: "the plan".into(),
+                    }],
+                },
+            ]);
+
+        chat.send("hi").await.unwrap();
+        assert_eq!(chat.history().len(), 4);
+        chat.send("hello again").await.unwrap();
+        assert_eq!(chat.history().len(), 4);
+
+        let summarizer = chat.summarizer.as_ref().unwrap();
+        assert_eq!(summarizer.seen().len(), 1);
+        let instructions = &summarizer.seen()[0].0.instructions;
+        assert!(instructions.contains("Tool: (tool result) the plan"));
+        assert!(!instructions.contains("User: hi"));
+        assert_eq!(
+            chat.history()[0].content[0],
+            Message::text(
+                "Summary of the conversation so far:\nthey said hi\n\n\
+                Results of earlier tool calls:\nplan: the plan\n"
+            )
+        );
+        assert_eq!(chat.history()[0].content[1], Message::text("hi"));
+        assert_eq!(chat.history()[2].content[0], Message::text("hello again"));
+    }
+}
@@ -0,0 +1,234 @@
+//! Asking a model to choose between a fixed set of classes.
+
+use crate::parse::{multiclass, EnumOptions};
+use crate::{CallBase, CallErr, ModelCaller};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::Deserialize;
+
+/// Asks the model which of the given classes applies, deserializing the chosen class
+/// into `T`, which is typically a unit-variant enum.
+///
+/// If the caller [supports_response_schema](ModelCaller::supports_response_schema), the
+/// answer is constrained to the classes by a schema. Otherwise, the model is asked to
+/// finish with a line of the form `answer: <class>`, which is extracted with
+/// [multiclass]. Unparseable responses are retried once.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "openrouter")] {
+/// # use mini_prompt::*;
+/// #[derive(serde::Deserialize)]
+/// #[serde(rename_all = "lowercase")]
+/// enum Sentiment {
+///     Positive,
+///     Negative,
+/// }
+///
+/// # let backend = callers::Openrouter::<models::Gemma27B3>::default();
+/// # tokio::task::spawn(async move {
+/// let s: Sentiment = classify(
+///     &backend,
+///     "What is the sentiment of this review? 'Terrible, would not buy again.'",
+///     &["positive", "negative"],
+/// )
+/// .await
+/// .unwrap();
+/// # });
+/// # }
+/// ```
+pub async fn classify<B: ModelCaller, T: DeserializeOwned>(
+    backend: &B,
+    prompt: &str,
+    classes: &[&str],
+) -> Result<T, CallErr> {
+    #[derive(Deserialize)]
+    struct Answer {
+        answer: String,
+    }
+
+    let to_class = |class: &str| {
+        T::deserialize(class.to_string().into_deserializer())
+            .map_err(|e: serde::de::value::Error| e.to_string())
+    };
+
+    if backend.supports_response_schema() {
+        let params = CallBase {
+            instructions: prompt.to_string(),
+            response_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "answer": {"type": "string", "enum": classes},
+                },
+                "required": ["answer"],
+                "additionalProperties": false,
+            })),
+            ..Default::default()
+        };
+        backend
+            .call_parsed(params, vec![], 1, |text| {
+                let a: Answer = crate::parse::json(text)?;
+                match classes.contains(&a.answer.as_str()) {
+                    true => to_class(&a.answer),
+                    false => Err(format!("'{}' is not one of the classes", a.answer)),
+                }
+            })
+            .await
+    } else {
+        let params = CallBase {
+            instructions: format!(
+                "{}\n\nFinish your response with a line of the form 'answer: <class>', \
+                where <class> is one of: {}.",
+                prompt,
+                classes.join(", ")
+            ),
+            ..Default::default()
+        };
+        let opts = EnumOptions::from(classes).fuzzy();
+        backend
+            .call_parsed(params, vec![], 1, |text| match multiclass(text, &opts) {
+                Some(class) => to_class(class),
+                None => Err(format!(
+                    "no line of the form 'answer: <class>' where <class> is one of: {}",
+                    classes.join(", ")
+                )),
+            })
+            .await
+    }
+}
+
+/// Asks the model which of the given classes applies, returning each class with the
+/// probability the model gave it, most likely first.
+///
+/// Probabilities are read from the logprobs of the first token of the response, so the
+/// caller must support [CallBase::top_logprobs], and each class should begin with a
+/// distinct token. They may sum to less than one if the model considered answers other
+/// than the classes, which allows abstaining when no class is likely enough.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "openai")] {
+/// # use mini_prompt::*;
+/// # let backend = callers::Openai::<models::GPT41Mini>::default();
+/// # tokio::task::spawn(async move {
+/// let dist = classify::classify_distribution(
+///     &backend,
+///     "Is this email spam? 'You have won a prize!'",
+///     &["yes", "no"],
+/// )
+/// .await
+/// .unwrap();
+/// if dist[0].1 < 0.9 {
+///     println!("not sure, sending for human review");
+/// }
+/// # });
+/// # }
+/// ```
+pub async fn classify_distribution<'a, B: ModelCaller>(
+    backend: &B,
+    prompt: &str,
+    classes: &[&'a str],
+) -> Result<Vec<(&'a str, f32)>, CallErr> {
+    let params = CallBase {
+        instructions: format!(
+            "{}\n\nRespond with only one of the following, and nothing else: {}.",
+            prompt,
+            classes.join(", ")
+        ),
+        top_logprobs: Some(20),
+        ..Default::default()
+    };
+    let first = backend
+        .call(params, vec![])
+        .await?
+        .logprobs
+        .and_then(|l| l.into_iter().next())
+        .ok_or("response has no logprobs")?;
+    let top = match first.top.is_empty() {
+        true => vec![(first.token, first.logprob)],
+        false => first.top,
+    };
+
+    let mut dist: Vec<(&'a str, f32)> = classes.iter().map(|c| (*c, 0.0)).collect();
+    for (token, logprob) in top {
+        let token = token.trim().to_lowercase();
+        if token.is_empty() {
+            continue;
+        }
+        // Split the probability of tokens shared by several classes between them.
+        let matches: Vec<usize> = (0..classes.len())
+            .filter(|i| classes[*i].to_lowercase().starts_with(&token))
+            .collect();
+        for i in matches.iter() {
+            dist[*i].1 += logprob.exp() / matches.len() as f32;
+        }
+    }
+    dist.sort_by(|a, b| b.1.total_cmp(&a.1));
+    Ok(dist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scripted;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Sentiment {
+        Positive,
+        Negative,
+    }
+
+    #[tokio::test]
+    async fn classify_from_text() {
+        let backend = Scripted::new(vec![
+            Scripted::text("It's hard to say."),
+            Scripted::text("The review complains.\nAnswer: Negative"),
+        ]);
+        let s: Sentiment = classify(&backend, "Is 'meh' positive?", &["positive", "negative"])
+            .await
+            .unwrap();
+        assert_eq!(s, Sentiment::Negative);
+        assert_eq!(backend.seen().len(), 2);
+        assert!(backend.seen()[0]
+            .0
+            .instructions
+            .ends_with("one of: positive, negative."));
+
+        let backend = Scripted::new(vec![Scripted::text("answer: positive")]);
+        let s: String = classify(&backend, "?", &["positive", "negative"])
+            .await
+            .unwrap();
+        assert_eq!(s, "positive");
+    }
+
+    #[tokio::test]
+    async fn distribution() {
+        let top = [
+            (" Pos", 0.6f32),
+            ("neg", 0.3),
+            ("maybe", 0.05),
+            ("pos", 0.05),
+        ];
+        let backend = Scripted::new(vec![crate::CallResp {
+            logprobs: Some(vec![crate::TokenLogprob {
+                token: " Pos".into(),
+                logprob: 0.6f32.ln(),
+                top: top.iter().map(|(t, p)| (t.to_string(), p.ln())).collect(),
+            }]),
+            ..Scripted::text(" Positive")
+        }]);
+        let dist = classify_distribution(&backend, "?", &["negative", "positive"])
+            .await
+            .unwrap();
+        assert_eq!(
+            dist.iter().map(|(c, _)| *c).collect::<Vec<_>>(),
+            ["positive", "negative"]
+        );
+        assert!((dist[0].1 - 0.65).abs() < 1e-4);
+        assert!((dist[1].1 - 0.3).abs() < 1e-4);
+        assert_eq!(backend.seen()[0].0.top_logprobs, Some(20));
+
+        let backend = Scripted::new(vec![Scripted::text("positive")]);
+        assert!(classify_distribution(&backend, "?", &["positive"])
+            .await
+            .is_err());
+    }
+}
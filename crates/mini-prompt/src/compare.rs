@@ -0,0 +1,276 @@
+//! Comparing two backends, or two variants of a prompt, on the same calls.
+//!
+//! ```rust,no_run
+//! # #[cfg(all(feature = "openrouter", feature = "anthropic"))] {
+//! # use mini_prompt::*;
+//! use mini_prompt::compare::{Compare, Preference};
+//!
+//! let mut ab = Compare::new(
+//!     callers::Openrouter::<models::Gemma27B3>::default(),
+//!     callers::Anthropic::<models::ClaudeHaiku35>::default(),
+//! );
+//! # tokio::task::spawn(async move {
+//! let params = CallBase {
+//!     instructions: "Write a haiku about rust.".into(),
+//!     ..Default::default()
+//! };
+//! let (a, b) = ab.call_both(params, vec![]).await;
+//! println!("A: {}\n\nB: {}", a.unwrap().content.text(), b.unwrap().content.text());
+//!
+//! ab.prefer(0, Preference::B);
+//! println!("{:?}", ab.tally());
+//! # });
+//! # }
+//! ```
+
+use crate::models::Model;
+use crate::{CallBase, CallErr, CallResp, ModelCaller, Turn};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// Transforms the parameters of a call into those of the second variant, see
+/// [Compare::with_variant].
+pub type VariantFunc = Box<dyn Fn(CallBase) -> CallBase + Send + Sync>;
+
+/// Which response of a [Pair] was preferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Preference {
+    A,
+    B,
+    Tie,
+}
+
+/// One side of a [Pair].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outcome {
+    /// The instructions this side was called with.
+    pub instructions: String,
+    /// The text of the response, or a description of the error if the call failed.
+    pub output: Result<String, String>,
+}
+
+/// The responses of both sides to a single call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pair {
+    /// The turns both sides were called with.
+    pub turns: Vec<Turn>,
+    pub a: Outcome,
+    pub b: Outcome,
+    /// Which response was preferred, once decided with [Compare::prefer].
+    pub preference: Option<Preference>,
+}
+
+/// The number of pairs preferring each side, see [Compare::tally].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub a: usize,
+    pub b: usize,
+    pub ties: usize,
+    /// Pairs with no preference recorded.
+    pub undecided: usize,
+}
+
+impl Tally {
+    /// Returns the fraction of decided pairs won by A, counting ties as half a win,
+    /// or `None` if no pairs have been decided.
+    pub fn win_rate_a(&self) -> Option<f32> {
+        match self.a + self.b + self.ties {
+            0 => None,
+            n => Some((self.a as f32 + self.ties as f32 / 2.0) / n as f32),
+        }
+    }
+}
+
+/// Sends each call to two backends concurrently, recording both responses as a
+/// [Pair] for later comparison.
+///
+/// To compare two variants of a prompt instead, use the same kind of backend for both
+/// sides along with [Compare::with_variant].
+///
+/// As a [ModelCaller], the response of A is returned, so the wrapper can shadow a
+/// candidate backend or prompt in existing code.
+pub struct Compare<A: ModelCaller, B: ModelCaller> {
+    a: A,
+    b: B,
+    variant: Option<VariantFunc>,
+    pairs: Mutex<Vec<Pair>>,
+}
+
+impl<A: ModelCaller, B: ModelCaller> Compare<A, B> {
+    /// Constructs a caller which compares the two backends.
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            variant: None,
+            pairs: Mutex::new(vec![]),
+        }
+    }
+
+    /// Transforms the parameters of each call before they are sent to B, such as to
+    /// try different instructions.
+    ///
+    /// ```rust,no_run
+    /// # #[cfg(feature = "openrouter")] {
+    /// # use mini_prompt::*;
+    /// # use mini_prompt::compare::Compare;
+    /// let backend = callers::Openrouter::<models::Gemma27B3>::default();
+    /// let ab = Compare::new(backend.clone(), backend).with_variant(|params| CallBase {
+    ///     instructions: format!("{}\n\nBe concise.", params.instructions),
+    ///     ..params
+    /// });
+    /// # }
+    /// ```
+    pub fn with_variant<F>(mut self, variant: F) -> Self
+    where
+        F: Fn(CallBase) -> CallBase + Send + Sync + 'static,
+    {
+        self.variant = Some(Box::new(variant));
+        self
+    }
+
+    /// Makes the call against both sides concurrently, returning the response of A
+    /// then B.
+    pub async fn call_both(
+        &self,
+        params: CallBase,
+        turns: Vec<Turn>,
+    ) -> (Result<CallResp, CallErr>, Result<CallResp, CallErr>) {
+        let params_b = match &self.variant {
+            Some(variant) => variant(params.clone()),
+            None => params.clone(),
+        };
+        let (instructions_a, instructions_b) =
+            (params.instructions.clone(), params_b.instructions.clone());
+
+        let (a, b) = futures::join!(
+            self.a.call(params, turns.clone()),
+            self.b.call(params_b, turns.clone())
+        );
+
+        let output = |r: &Result<CallResp, CallErr>| match r {
+            Ok(resp) => Ok(resp.content.text()),
+            Err(e) => Err(format!("{:?}", e)),
+        };
+        self.pairs.lock().unwrap().push(Pair {
+            turns,
+            a: Outcome {
+                instructions: instructions_a,
+                output: output(&a),
+            },
+            b: Outcome {
+                instructions: instructions_b,
+                output: output(&b),
+            },
+            preference: None,
+        });
+        (a, b)
+    }
+
+    /// Returns the pairs recorded so far, in the order the calls were made.
+    pub fn pairs(&self) -> Vec<Pair> {
+        self.pairs.lock().unwrap().clone()
+    }
+
+    /// Removes and returns the pairs recorded so far, such as to save them.
+    pub fn take_pairs(&mut self) -> Vec<Pair> {
+        std::mem::take(self.pairs.get_mut().unwrap())
+    }
+
+    /// Records which response of the pair at the given index was preferred.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn prefer(&mut self, index: usize, preference: Preference) {
+        self.pairs.get_mut().unwrap()[index].preference = Some(preference);
+    }
+
+    /// Counts the pairs preferring each side.
+    pub fn tally(&self) -> Tally {
+        let mut out = Tally::default();
+        for p in self.pairs.lock().unwrap().iter() {
+            match p.preference {
+                Some(Preference::A) => out.a += 1,
+                Some(Preference::B) => out.b += 1,
+                Some(Preference::Tie) => out.ties += 1,
+                None => out.undecided += 1,
+            }
+        }
+        out
+    }
+
+    /// Consumes the wrapper, returning the two backends.
+    pub fn into_inner(self) -> (A, B) {
+        (self.a, self.b)
+    }
+}
+
+impl<A: ModelCaller, B: ModelCaller> ModelCaller for Compare<A, B> {
+    fn get_model(&self) -> impl Model {
+        self.a.get_model()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.a.supports_response_schema() && self.b.supports_response_schema()
+    }
+
+    fn endpoint(&self) -> String {
+        self.a.endpoint()
+    }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        self.call_both(params, turns).await.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scripted;
+
+    #[tokio::test]
+    async fn compare_variants() {
+        let mut ab = Compare::new(
+            Scripted::new(vec![Scripted::text("one"), Scripted::text("three")]),
+            Scripted::new(vec![Scripted::text("two")]),
+        )
+        .with_variant(|params| CallBase {
+            instructions: params.instructions + " Be brief.",
+            ..params
+        });
+
+        assert_eq!(ab.simple_call("Count.").await.unwrap(), "one");
+        let (a, b) = ab.call_both(CallBase::default(), vec![]).await;
+        assert_eq!(a.unwrap().content.text(), "three");
+        assert!(b.is_err());
+
+        assert_eq!(
+            ab.pairs()[0].a,
+            Outcome {
+                instructions: "Count.".into(),
+                output: Ok("one".into()),
+            }
+        );
+        assert_eq!(
+            ab.pairs()[0].b,
+            Outcome {
+                instructions: "Count. Be brief.".into(),
+                output: Ok("two".into()),
+            }
+        );
+        assert_eq!(ab.pairs()[1].b.output, Err("NoCompletions".into()));
+
+        ab.prefer(0, Preference::B);
+        assert_eq!(
+            ab.tally(),
+            Tally {
+                a: 0,
+                b: 1,
+                ties: 0,
+                undecided: 1,
+            }
+        );
+        assert_eq!(ab.tally().win_rate_a(), Some(0.0));
+        assert_eq!(ab.take_pairs().len(), 2);
+        assert!(ab.pairs().is_empty());
+    }
+}
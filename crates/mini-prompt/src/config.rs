@@ -0,0 +1,236 @@
+//! Defaults read from a config file, so keys and endpoints don't need to be set in
+//! the environment of every program.
+//!
+//! The file is read from `$MINI_PROMPT_CONFIG` if set, otherwise from
+//! `mini-prompt/config.toml` in `$XDG_CONFIG_HOME` or `~/.config`:
+//!
+//! ```toml
+//! # Used by Config::caller.
+//! provider = "anthropic"
+//! model = "claude-3-5-haiku-latest"
+//!
+//! [anthropic]
+//! api_key = "sk-ant-..."
+//!
+//! [openai]
+//! api_key = "sk-..."
+//! base_url = "http://localhost:8080/v1"
+//! ```
+//!
+//! The file is only read with the `toml` feature, which is enabled by default.
+//!
+//! Values given to a caller when it is constructed take precedence, followed by
+//! environment variables such as `OPENAI_API_KEY`, then the config file.
+//!
+//! ```rust,no_run
+//! # #[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))] {
+//! # use mini_prompt::*;
+//! use mini_prompt::config::Config;
+//!
+//! let backend = Config::global().and_then(Config::caller).unwrap();
+//! # tokio::task::spawn(async move {
+//! let resp = backend.simple_call("Hi!").await;
+//! # });
+//! # }
+//! ```
+
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+use crate::callers::{self, DynModelCaller};
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+use crate::models;
+use crate::CallErr;
+use serde::Deserialize;
+use std::env;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// The contents of the config file. Unknown keys are ignored, so a file with settings
+/// for a newer version can still be read.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Config {
+    /// The provider used by [Config::caller]: one of `openrouter`, `anthropic`, or
+    /// `openai`. The cargo feature of the same name must be enabled.
+    pub provider: Option<String>,
+    /// The model used by [Config::caller], as named by the provider.
+    pub model: Option<String>,
+
+    #[serde(default)]
+    pub openrouter: Provider,
+    #[serde(default)]
+    pub anthropic: Provider,
+    #[serde(default)]
+    pub openai: Provider,
+    #[serde(default)]
+    pub cohere: Provider,
+    #[serde(default)]
+    pub voyage: Provider,
+}
+
+/// Settings for a provider.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Provider {
+    pub api_key: Option<String>,
+    /// Where requests are sent. Only used for the chat providers and the OpenAI
+    /// embeddings and moderations APIs.
+    pub base_url: Option<String>,
+}
+
+impl Config {
+    /// Returns the path of the config file, or None if no home directory is known.
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("MINI_PROMPT_CONFIG") {
+            return Some(path.into());
+        }
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+            .map(|dir| dir.join("mini-prompt").join("config.toml"))
+    }
+
+    /// Reads the config file, returning an empty config if it doesn't exist.
+    pub fn load() -> Result<Self, CallErr> {
+        Ok(Self::read()?)
+    }
+
+    #[cfg(feature = "toml")]
+    fn read() -> Result<Self, String> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s)
+                .map_err(|e| format!("invalid config file {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("reading config file {}: {}", path.display(), e)),
+        }
+    }
+
+    #[cfg(not(feature = "toml"))]
+    fn read() -> Result<Self, String> {
+        Ok(Self::default())
+    }
+
+    /// Returns the config file as read by [load](Config::load) the first time this is
+    /// called, which is what callers use for keys and base URLs.
+    ///
+    /// If the file can't be read, the error is returned here, and from every call which
+    /// falls back to the config file.
+    pub fn global() -> Result<&'static Self, CallErr> {
+        static CONFIG: OnceLock<Result<Config, String>> = OnceLock::new();
+        CONFIG
+            .get_or_init(Self::read)
+            .as_ref()
+            .map_err(|e| e.as_str().into())
+    }
+
+    /// Returns a caller for the configured provider and model. If the model isn't set,
+    /// the first supported model of the provider is used, and if the provider isn't
+    /// set, Openrouter is used.
+    #[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+    pub fn caller(&self) -> Result<Box<dyn DynModelCaller>, CallErr> {
+        let model = self.model.as_deref();
+        macro_rules! select {
+            ($caller:ident, $kind:ident, $($m:ident),+) => {{
+                $(if model.map_or(true, |m| m == <models::$m as models::$kind>::MODEL_STR) {
+                    return Ok(Box::new(callers::$caller::<models::$m>::default()));
+                })+
+            }};
+        }
+
+        let provider = self.provider.as_deref().unwrap_or("openrouter");
+        match provider {
+            #[cfg(feature = "openrouter")]
+            "openrouter" => select!(
+                Openrouter,
+                OpenrouterModel,
+                Gemma27B3,
+                Qwen235B3,
+                Phi4,
+                Gemini2Flash,
+                Gemini25Flash,
+                DevstralSmall,
+                GPT4oMini,
+                Deepseek0324v3,
+                ClaudeSonnet4,
+                ClaudeHaiku35
+            ),
+            #[cfg(feature = "anthropic")]
+            "anthropic" => select!(Anthropic, AnthropicModel, ClaudeHaiku35, ClaudeSonnet4),
+            #[cfg(feature = "openai")]
+            "openai" => select!(
+                Openai,
+                OpenAIModel,
+                GPT41Mini,
+                GPT4oMini,
+                O4Mini,
+                O3,
+                O3Mini
+            ),
+            _ => return Err(format!("unknown provider '{}'", provider).into()),
+        }
+        Err(format!(
+            "unknown model '{}' for provider '{}'",
+            model.unwrap_or_default(),
+            provider
+        )
+        .into())
+    }
+}
+
+/// Returns the base URL from the provider's settings in the config file.
+pub(crate) fn base_url(
+    provider: fn(&Config) -> &Provider,
+) -> Result<Option<&'static str>, CallErr> {
+    Ok(provider(Config::global()?).base_url.as_deref())
+}
+
+#[cfg(all(
+    test,
+    feature = "toml",
+    feature = "openai",
+    feature = "anthropic",
+    feature = "openrouter"
+))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_select() {
+        let config: Config = toml::from_str(
+            r#"
+            provider = "anthropic"
+            model = "claude-sonnet-4-20250514"
+
+            [openai]
+            api_key = "sk-1"
+            base_url = "http://localhost:8080/v1"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.openai.api_key.as_deref(), Some("sk-1"));
+        assert_eq!(config.anthropic, Provider::default());
+        assert_eq!(
+            config.caller().unwrap().model_name(),
+            "mini_prompt::models::ClaudeSonnet4"
+        );
+
+        let config = Config {
+            provider: Some("openai".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.caller().unwrap().model_name(),
+            "mini_prompt::models::GPT41Mini"
+        );
+        let config = Config {
+            model: Some("gpt-4.1-mini".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.caller(),
+            Err(CallErr::Other(e)) if e.to_string() == "unknown model 'gpt-4.1-mini' for provider 'openrouter'"
+        ));
+        let config: Config = toml::from_str("timeout = 5\n[openai]\nkey = \"typo\"").unwrap();
+        assert_eq!(config, Config::default());
+    }
+}
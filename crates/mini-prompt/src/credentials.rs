@@ -0,0 +1,204 @@
+//! Where callers get the keys they authenticate requests with.
+//!
+//! Every caller, embedder, and reranker has an `api_key` and a `credentials` field. If
+//! neither is set, the key is read from the provider's environment variables, then
+//! the [config file](crate::config), and the request fails with [CallErr::Other] if
+//! there is no key.
+//!
+//! Setting `credentials` replaces that lookup with a [CredentialProvider], which is
+//! asked for a key before every request, so rotating tokens or keys held by a secret
+//! manager can be used:
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "openai")] {
+//! # use mini_prompt::*;
+//! use mini_prompt::credentials::Callback;
+//! use std::sync::Arc;
+//!
+//! let backend = callers::Openai::<models::GPT41Mini> {
+//!     credentials: Some(Arc::new(Callback::new(|| async {
+//!         // Fetch or refresh a short-lived token.
+//!         Ok("token".to_string())
+//!     }))),
+//!     ..Default::default()
+//! };
+//! # }
+//! ```
+
+use crate::config::{self, Config};
+use crate::CallErr;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The future returned by [CredentialProvider::api_key].
+pub type BoxedKey<'a> = Pin<Box<dyn Future<Output = Result<String, CallErr>> + Send + 'a>>;
+
+/// A source of API keys.
+pub trait CredentialProvider: Send + Sync + std::fmt::Debug {
+    /// Returns the key to authenticate a request with. This is called before every
+    /// request, so providers of short-lived tokens should cache them, refreshing them
+    /// when they are about to expire.
+    fn api_key(&self) -> BoxedKey<'_>;
+}
+
+/// Reads the key from the first of the environment variables which is set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Env(pub Vec<String>);
+
+impl Env {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(vars: I) -> Self {
+        Self(vars.into_iter().map(|v| v.into()).collect())
+    }
+}
+
+impl CredentialProvider for Env {
+    fn api_key(&self) -> BoxedKey<'_> {
+        Box::pin(async move {
+            self.0
+                .iter()
+                .find_map(|v| std::env::var(v).ok())
+                .ok_or_else(|| format!("no API key: set {}", self.0.join(" or ")).into())
+        })
+    }
+}
+
+/// Always returns the same key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Static(pub String);
+
+impl std::fmt::Debug for Static {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Static(..)")
+    }
+}
+
+impl CredentialProvider for Static {
+    fn api_key(&self) -> BoxedKey<'_> {
+        Box::pin(async move { Ok(self.0.clone()) })
+    }
+}
+
+/// Reads the key from the operating system's keyring, such as the macOS Keychain,
+/// the Windows Credential Manager, or the Linux kernel keyring.
+#[cfg(feature = "keyring")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keyring {
+    pub service: String,
+    pub user: String,
+}
+
+#[cfg(feature = "keyring")]
+impl Keyring {
+    pub fn new<S: Into<String>, U: Into<String>>(service: S, user: U) -> Self {
+        Self {
+            service: service.into(),
+            user: user.into(),
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl CredentialProvider for Keyring {
+    fn api_key(&self) -> BoxedKey<'_> {
+        Box::pin(async move {
+            keyring::Entry::new(&self.service, &self.user)
+                .and_then(|e| e.get_password())
+                .map_err(|e| format!("reading {} from the keyring: {}", self.service, e).into())
+        })
+    }
+}
+
+/// Gets the key by calling an async function, such as one which fetches a token
+/// from a secret manager.
+pub struct Callback<F>(pub F);
+
+impl<F, Fut> Callback<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String, CallErr>> + Send + 'static,
+{
+    pub fn new(f: F) -> Self {
+        Self(f)
+    }
+}
+
+impl<F> std::fmt::Debug for Callback<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Callback(..)")
+    }
+}
+
+impl<F, Fut> CredentialProvider for Callback<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String, CallErr>> + Send + 'static,
+{
+    fn api_key(&self) -> BoxedKey<'_> {
+        Box::pin((self.0)())
+    }
+}
+
+/// Returns the key for a request: the explicit key if set, otherwise the key from the
+/// credential provider if set, otherwise the first of the environment variables which
+/// is set, otherwise the key in the config file.
+pub(crate) async fn api_key(
+    key: &Option<String>,
+    credentials: &Option<Arc<dyn CredentialProvider>>,
+    vars: &[&str],
+    provider: fn(&Config) -> &config::Provider,
+) -> Result<String, CallErr> {
+    if let Some(key) = key {
+        return Ok(key.clone());
+    }
+    if let Some(credentials) = credentials {
+        return credentials.api_key().await;
+    }
+    if let Some(key) = vars.iter().find_map(|v| std::env::var(v).ok()) {
+        return Ok(key);
+    }
+    provider(Config::global()?).api_key.clone().ok_or_else(|| {
+        format!(
+            "no API key: set {} or the key in the config file",
+            vars.join(" or ")
+        )
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn lookup_order() {
+        let unset = "MINI_PROMPT_TEST_UNSET_KEY";
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let credentials: Option<Arc<dyn CredentialProvider>> =
+            Some(Arc::new(Callback::new(move || {
+                let n = c.fetch_add(1, Ordering::SeqCst);
+                async move { Ok(format!("token-{}", n)) }
+            })));
+        let key = |k: Option<&str>| {
+            let (k, credentials) = (k.map(String::from), credentials.clone());
+            async move {
+                api_key(&k, &credentials, &[unset], |c| &c.voyage)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(key(None).await, "token-0");
+        assert_eq!(key(None).await, "token-1");
+        assert_eq!(key(Some("explicit")).await, "explicit");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        assert_eq!(Static("k".into()).api_key().await.unwrap(), "k");
+        assert_eq!(format!("{:?}", Static("secret".into())), "Static(..)");
+        assert!(matches!(
+            Env::new([unset]).api_key().await,
+            Err(CallErr::Other(e)) if e.to_string() == "no API key: set MINI_PROMPT_TEST_UNSET_KEY"
+        ));
+    }
+}
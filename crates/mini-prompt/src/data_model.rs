@@ -1,10 +1,17 @@
 //! Wire-format types to use when driving LLM APIs.
 
-use crate::{FinishReason, Role};
+// Requests to providers whose features are disabled are never built.
+#![cfg_attr(
+    not(all(feature = "openai", feature = "anthropic", feature = "openrouter")),
+    allow(dead_code)
+)]
+
+use crate::{FinishReason, Role, ToolChoice};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// The serialized format representing the output of a turn in an LLM conversation.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct OAIChatMessage {
     /// Role: system, user, assistant, tool
     pub role: Role,
@@ -26,6 +33,10 @@ pub struct OAIChatMessage {
     /// Name of the actor, typically set to the responding function if a tool call response
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+
+    /// The reasoning of the model before it responded (Openrouter-specific).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
 }
 
 impl OAIChatMessage {
@@ -36,6 +47,7 @@ impl OAIChatMessage {
             tool_calls: vec![],
             tool_call_id: None,
             name: None,
+            reasoning: None,
         }
     }
     pub fn assistant<S: Into<String>>(s: S) -> Self {
@@ -45,6 +57,7 @@ impl OAIChatMessage {
             tool_calls: vec![],
             tool_call_id: None,
             name: None,
+            reasoning: None,
         }
     }
     pub fn system<S: Into<String>>(s: S) -> Self {
@@ -54,6 +67,17 @@ impl OAIChatMessage {
             tool_calls: vec![],
             tool_call_id: None,
             name: None,
+            reasoning: None,
+        }
+    }
+    pub fn developer<S: Into<String>>(s: S) -> Self {
+        OAIChatMessage {
+            role: Role::Developer,
+            content: Some(s.into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            name: None,
+            reasoning: None,
         }
     }
     pub fn tool<S: Into<String>>(s: S) -> Self {
@@ -63,12 +87,13 @@ impl OAIChatMessage {
             tool_calls: vec![],
             tool_call_id: None,
             name: None,
+            reasoning: None,
         }
     }
 }
 
 /// Describes an invocation of some tool.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct OAIToolCall {
     /// The ID of the tool call.
     pub id: String,
@@ -86,7 +111,7 @@ pub enum ToolCallType {
 }
 
 /// Describes the invocation of some function, typically 1:1 within a [OAIToolCall].
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct FunctionCall {
     pub name: String,
     pub arguments: String,
@@ -98,6 +123,18 @@ pub(crate) struct OpenrouterProvider {
     pub ignore: Vec<String>,
 }
 
+/// Openrouter-specific parameter, controlling the reasoning of models which think.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct OpenrouterReasoning {
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effort: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub exclude: bool,
+}
+
 /// Describes a tool available in a model call.
 #[derive(Clone, Debug, Serialize)]
 pub struct OAITool {
@@ -124,7 +161,7 @@ pub struct FunctionInfo {
     pub name: Option<String>,
     /// The parameters the functions accepts, described as a JSON Schema object. See the guide for examples, and the JSON Schema reference for documentation about the format.
     /// To describe a function that accepts no parameters, provide the value {"type": "object", "properties": {}}.
-    pub parameters: serde_json::Value,
+    pub parameters: Arc<serde_json::Value>,
 }
 
 impl FunctionInfo {
@@ -136,20 +173,45 @@ impl FunctionInfo {
         Self {
             name: Some(name.into()),
             description: description.into(),
-            parameters: match parameters {
+            parameters: Arc::new(match parameters {
                 Some(p) => p,
                 None => serde_json::json!({"type": "object", "properties": {}}),
-            },
+            }),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq, Eq, Default)]
-#[serde(rename_all = "snake_case")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub enum OAIToolChoice {
     #[default]
     None,
     Auto,
+    Required,
+    /// Forces a call to the function with the given name.
+    Function(String),
+}
+
+impl Serialize for OAIToolChoice {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            OAIToolChoice::None => s.serialize_str("none"),
+            OAIToolChoice::Auto => s.serialize_str("auto"),
+            OAIToolChoice::Required => s.serialize_str("required"),
+            OAIToolChoice::Function(name) => {
+                serde_json::json!({"type": "function", "function": {"name": name}}).serialize(s)
+            }
+        }
+    }
+}
+
+impl From<ToolChoice> for OAIToolChoice {
+    fn from(c: ToolChoice) -> Self {
+        match c {
+            ToolChoice::Auto => OAIToolChoice::Auto,
+            ToolChoice::Required => OAIToolChoice::Required,
+            ToolChoice::Tool(name) => OAIToolChoice::Function(name),
+        }
+    }
 }
 
 /// A request to the OpenAI Chat Completions API.
@@ -163,6 +225,10 @@ pub(crate) struct OAICompletionsRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub provider: Option<OpenrouterProvider>,
 
+    /// Openrouter-specific parameter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<OpenrouterReasoning>,
+
     /// Model input and output
     pub messages: Vec<OAIChatMessage>,
 
@@ -174,7 +240,71 @@ pub(crate) struct OAICompletionsRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<OAIToolChoice>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
+
+    /// Sequences which end the response when generated.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+
+    /// The maximum number of tokens to generate, including reasoning tokens. Used
+    /// instead of `max_tokens` by reasoning models.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<usize>,
+
+    /// Constrains the format of the response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<OAIResponseFormat>,
+
+    /// A GBNF grammar the response must match (llama.cpp-specific).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grammar: Option<String>,
+
+    /// A regex the response must match (vLLM-specific).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guided_regex: Option<String>,
+
+    /// Whether to return log probabilities of the output tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<bool>,
+
+    /// The number of most likely tokens to return at each position, with logprobs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u8>,
+
+    /// Requests deterministic sampling.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+
+    /// Sends the response as server-sent events while it is generated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+}
+
+/// Describes the required format of a response to the OpenAI Chat Completions API.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum OAIResponseFormat {
+    JsonSchema { json_schema: OAIJsonSchema },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct OAIJsonSchema {
+    pub name: String,
+    pub schema: serde_json::Value,
+    pub strict: bool,
+}
+
+impl From<serde_json::Value> for OAIResponseFormat {
+    fn from(schema: serde_json::Value) -> Self {
+        OAIResponseFormat::JsonSchema {
+            json_schema: OAIJsonSchema {
+                name: "response".into(),
+                schema,
+                strict: false,
+            },
+        }
+    }
 }
 
 impl Default for OAICompletionsRequest {
@@ -186,7 +316,17 @@ impl Default for OAICompletionsRequest {
             tools: vec![],
             tool_choice: None,
             provider: None,
+            reasoning: None,
             temperature: None,
+            stop: vec![],
+            max_completion_tokens: None,
+            response_format: None,
+            grammar: None,
+            guided_regex: None,
+            logprobs: None,
+            top_logprobs: None,
+            seed: None,
+            stream: None,
         }
     }
 }
@@ -209,6 +349,10 @@ pub struct OAICompletionsResponse {
     /// Array of completion choices
     #[serde(default)]
     pub choices: Vec<ChatChoice>,
+
+    /// Identifies the backend configuration which served the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
 }
 
 /// The serialized description of a response from the model.
@@ -222,15 +366,154 @@ pub struct ChatChoice {
 
     /// Reason why the model stopped generating
     pub finish_reason: FinishReason,
+
+    /// Log probabilities of the generated tokens, if requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<OAILogprobs>,
+}
+
+/// Log probabilities of the tokens in a choice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAILogprobs {
+    #[serde(default)]
+    pub content: Option<Vec<OAITokenLogprob>>,
+}
+
+/// The log probability of a generated token, and of the most likely alternatives.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAITokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+    #[serde(default)]
+    pub top_logprobs: Vec<OAITopLogprob>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAITopLogprob {
+    pub token: String,
+    pub logprob: f32,
+}
+
+/// An event of a streamed response from the OpenAI Completions API.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct OAICompletionsChunk {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub choices: Vec<OAIChunkChoice>,
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+    /// Set if the call failed after the response started (Openrouter-specific).
+    #[serde(default)]
+    pub error: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct OAIChunkChoice {
+    #[serde(default)]
+    pub delta: OAIChunkDelta,
+    #[serde(default)]
+    pub finish_reason: Option<FinishReason>,
+    #[serde(default)]
+    pub logprobs: Option<OAILogprobs>,
+}
+
+/// The part of the message added by a streamed event.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct OAIChunkDelta {
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Vec<OAIToolCallDelta>,
+}
+
+/// Part of a tool call, added to the tool call at the same index.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct OAIToolCallDelta {
+    pub index: usize,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub function: Option<FunctionCallDelta>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct FunctionCallDelta {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub arguments: Option<String>,
+}
+
+impl From<OAITokenLogprob> for crate::TokenLogprob {
+    fn from(t: OAITokenLogprob) -> Self {
+        crate::TokenLogprob {
+            token: t.token,
+            logprob: t.logprob,
+            top: t
+                .top_logprobs
+                .into_iter()
+                .map(|t| (t.token, t.logprob))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AnthropicToolChoiceType {
+    #[default]
+    Auto,
+    Any,
+    Tool,
 }
 
 /// Describes preferences around tool use to Anthropic.
 #[derive(Debug, Default, Clone, Serialize)]
 pub(crate) struct AnthropicToolChoice {
-    pub r#type: OAIToolChoice,
+    pub r#type: AnthropicToolChoiceType,
+    /// The tool which must be called, if the type is `tool`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     pub disable_parallel_tool_use: bool,
 }
 
+impl From<ToolChoice> for AnthropicToolChoice {
+    fn from(c: ToolChoice) -> Self {
+        let (r#type, name) = match c {
+            ToolChoice::Auto => (AnthropicToolChoiceType::Auto, None),
+            ToolChoice::Required => (AnthropicToolChoiceType::Any, None),
+            ToolChoice::Tool(name) => (AnthropicToolChoiceType::Tool, Some(name)),
+        };
+        Self {
+            r#type,
+            name,
+            ..Default::default()
+        }
+    }
+}
+
+/// A request to the Anthropic token counting API.
+#[derive(Debug, Default, Clone, Serialize)]
+pub(crate) struct AnthropicCountTokensRequest {
+    pub model: String,
+    pub messages: Vec<AnthropicMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub system: Vec<AnthropicCompletion>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<AnthropicTool>,
+}
+
+/// A response from the Anthropic token counting API.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct AnthropicCountTokensResponse {
+    pub input_tokens: usize,
+}
+
 /// A request to the Anthropic messages API.
 #[derive(Debug, Default, Clone, Serialize)]
 pub(crate) struct AnthropicMsgRequest {
@@ -240,13 +523,17 @@ pub(crate) struct AnthropicMsgRequest {
     /// Model input and output
     pub messages: Vec<AnthropicMessage>,
 
-    /// The system prompt.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub system: Option<String>,
+    /// The system prompt, as text blocks.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub system: Vec<AnthropicCompletion>,
 
     /// The maximum number of tokens that can be used.
     pub max_tokens: usize,
 
+    /// Sequences which end the response when generated.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+
     /// Explicitly enables or disables function calling.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<AnthropicToolChoice>,
@@ -256,6 +543,21 @@ pub(crate) struct AnthropicMsgRequest {
     pub tools: Vec<AnthropicTool>,
 
     pub temperature: Option<f32>,
+
+    /// Enables extended thinking.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<AnthropicThinking>,
+
+    /// Sends the response as server-sent events while it is generated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+}
+
+/// Configures extended thinking in the Anthropic messages API.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum AnthropicThinking {
+    Enabled { budget_tokens: usize },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -280,7 +582,7 @@ pub(crate) struct AnthropicTool {
     ///   "required": ["ticker"]
     /// }
     /// ```
-    pub input_schema: serde_json::Value,
+    pub input_schema: Arc<serde_json::Value>,
 }
 
 /// A response from the Anthropic Messages API.
@@ -307,9 +609,71 @@ pub(crate) struct AnthropicMsgResponse {
     pub stop_reason: FinishReason,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// An event of a streamed response from the Anthropic Messages API.
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
-pub(crate) enum AnthropicCompletion {
+pub(crate) enum AnthropicStreamEvent {
+    MessageStart {
+        message: AnthropicStreamMessage,
+    },
+    /// Starts a content block, which is kept as JSON as its fields are incomplete.
+    ContentBlockStart {
+        index: usize,
+        content_block: serde_json::Value,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: AnthropicDelta,
+    },
+    MessageDelta {
+        delta: AnthropicMessageDelta,
+    },
+    Error {
+        error: serde_json::Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// The message as sent when a stream starts, before it has content.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct AnthropicStreamMessage {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub model: String,
+}
+
+/// The part of a content block added by a streamed event.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum AnthropicDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct AnthropicMessageDelta {
+    #[serde(default)]
+    pub stop_reason: Option<FinishReason>,
+}
+
+/// A content block within an Anthropic message.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicCompletion {
     Text {
         text: String,
     },
@@ -322,11 +686,18 @@ pub(crate) enum AnthropicCompletion {
         tool_use_id: String,
         content: String,
     },
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
 }
 
-/// The serialized format representing the output of a turn in an LLM conversation.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct AnthropicMessage {
+/// The serialized format representing a turn in a conversation with an Anthropic model.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AnthropicMessage {
     /// Role: user, assistant
     pub role: Role,
     pub content: Vec<AnthropicCompletion>,
@@ -361,3 +732,230 @@ impl AnthropicMessage {
         }
     }
 }
+
+/// A line of the input file of an OpenAI batch.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct OAIBatchRequest {
+    pub custom_id: String,
+    pub method: String,
+    pub url: String,
+    pub body: OAICompletionsRequest,
+}
+
+/// A file uploaded to the OpenAI files API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAIFile {
+    pub id: String,
+}
+
+/// The state of a batch in the OpenAI Batch API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAIBatch {
+    pub id: String,
+    /// One of validating, failed, in_progress, finalizing, completed, expired,
+    /// cancelling, or cancelled.
+    pub status: String,
+    #[serde(default)]
+    pub output_file_id: Option<String>,
+    #[serde(default)]
+    pub error_file_id: Option<String>,
+    #[serde(default)]
+    pub request_counts: Option<OAIBatchCounts>,
+    /// Why the batch failed validation, if it did.
+    #[serde(default)]
+    pub errors: Option<OAIBatchErrors>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OAIBatchCounts {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAIBatchErrors {
+    #[serde(default)]
+    pub data: Vec<OAIBatchError>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAIBatchError {
+    #[serde(default)]
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// A line of the output or error file of an OpenAI batch.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAIBatchResult {
+    pub custom_id: String,
+    #[serde(default)]
+    pub response: Option<OAIBatchResponse>,
+    #[serde(default)]
+    pub error: Option<OAIBatchError>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAIBatchResponse {
+    pub status_code: u16,
+    pub body: serde_json::Value,
+}
+
+/// A request to the OpenAI moderations API.
+#[derive(Debug, Clone, Serialize)]
+pub struct OAIModerationRequest {
+    pub model: String,
+    pub input: Vec<String>,
+}
+
+/// The response of the OpenAI moderations API, with a result for each input.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAIModerationResponse {
+    pub id: String,
+    pub model: String,
+    pub results: Vec<OAIModerationResult>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAIModerationResult {
+    pub flagged: bool,
+    /// Whether the input was flagged for each category, such as `harassment`.
+    pub categories: std::collections::BTreeMap<String, bool>,
+    /// The score of the input for each category, from 0 to 1.
+    pub category_scores: std::collections::BTreeMap<String, f32>,
+}
+
+/// A request to the OpenAI embeddings API.
+#[derive(Debug, Clone, Serialize)]
+pub struct OAIEmbeddingRequest {
+    pub model: String,
+    pub input: Vec<String>,
+}
+
+/// The response of the OpenAI embeddings API, with an embedding for each input.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAIEmbeddingResponse {
+    pub data: Vec<OAIEmbedding>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAIEmbedding {
+    /// The position of the corresponding input in the request.
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
+/// A request to the Cohere v2 embed API.
+#[derive(Debug, Clone, Serialize)]
+pub struct CohereEmbedRequest {
+    pub model: String,
+    pub texts: Vec<String>,
+    /// One of `search_document`, `search_query`, `classification`, or `clustering`.
+    pub input_type: String,
+    pub embedding_types: Vec<String>,
+}
+
+/// The response of the Cohere v2 embed API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CohereEmbedResponse {
+    pub embeddings: CohereEmbeddings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CohereEmbeddings {
+    /// An embedding for each input, in order.
+    #[serde(default)]
+    pub float: Vec<Vec<f32>>,
+}
+
+/// A request to the Voyage AI embeddings API. The response has the same form as
+/// [OAIEmbeddingResponse].
+#[derive(Debug, Clone, Serialize)]
+pub struct VoyageEmbeddingRequest {
+    pub model: String,
+    pub input: Vec<String>,
+    /// Either `query` or `document`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_type: Option<String>,
+}
+
+/// A request to the Cohere v2 rerank API.
+#[derive(Debug, Clone, Serialize)]
+pub struct CohereRerankRequest {
+    pub model: String,
+    pub query: String,
+    pub documents: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_n: Option<usize>,
+}
+
+/// A request to the Voyage AI rerank API.
+#[derive(Debug, Clone, Serialize)]
+pub struct VoyageRerankRequest {
+    pub model: String,
+    pub query: String,
+    pub documents: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<usize>,
+}
+
+/// The response of a rerank API, which Cohere calls `results` and Voyage AI calls
+/// `data`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RerankResponse {
+    #[serde(alias = "data")]
+    pub results: Vec<RerankResult>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RerankResult {
+    /// The position of the document in the request.
+    pub index: usize,
+    pub relevance_score: f32,
+}
+
+/// A request within an Anthropic message batch.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct AnthropicBatchRequest {
+    pub custom_id: String,
+    pub params: AnthropicMsgRequest,
+}
+
+/// The state of a batch in the Anthropic message batches API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnthropicBatch {
+    pub id: String,
+    /// One of in_progress, canceling, or ended.
+    pub processing_status: String,
+    #[serde(default)]
+    pub request_counts: AnthropicBatchCounts,
+    /// Where the results can be downloaded from, once the batch has ended.
+    #[serde(default)]
+    pub results_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AnthropicBatchCounts {
+    pub processing: usize,
+    pub succeeded: usize,
+    pub errored: usize,
+    pub canceled: usize,
+    pub expired: usize,
+}
+
+/// A line of the results of an Anthropic message batch.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct AnthropicBatchResult {
+    pub custom_id: String,
+    pub result: AnthropicBatchOutcome,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum AnthropicBatchOutcome {
+    Succeeded { message: AnthropicMsgResponse },
+    Errored { error: serde_json::Value },
+    Canceled,
+    Expired,
+}
@@ -0,0 +1,513 @@
+//! Embedding text as vectors, so texts can be compared by meaning.
+//!
+//! Embedders are selected the same way as model callers, by provider and model:
+//! [Openai], [Cohere], [Voyage], or [Ollama] for models served locally.
+//!
+//! ```rust,no_run
+//! use mini_prompt::embed::{cosine_similarity, Embedder, Openai};
+//! use mini_prompt::models::TextEmbedding3Small;
+//!
+//! let embedder = Openai::<TextEmbedding3Small>::default();
+//! # tokio::task::spawn(async move {
+//! let v = embedder
+//!     .embed(vec!["How do I reset my password?".into(), "I forgot my login".into()])
+//!     .await
+//!     .unwrap();
+//! println!("similarity: {}", cosine_similarity(&v[0], &v[1]));
+//! # });
+//! ```
+
+use crate::callers::OPENAI_BASE_URL;
+use crate::config;
+use crate::credentials::{self, CredentialProvider};
+use crate::data_model::{
+    CohereEmbedRequest, CohereEmbedResponse, OAIEmbeddingRequest, OAIEmbeddingResponse,
+    VoyageEmbeddingRequest,
+};
+use crate::models::{CohereEmbeddingModel, OpenAIEmbeddingModel, VoyageEmbeddingModel};
+use crate::CallErr;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A type which is able to embed text.
+pub trait Embedder: Send + Sync {
+    /// Embeds each of the texts, returning a vector for each in the same order.
+    fn embed(
+        &self,
+        texts: Vec<String>,
+    ) -> impl std::future::Future<Output = Result<Vec<Vec<f32>>, CallErr>> + Send;
+}
+
+/// Returns the cosine similarity of two vectors, from -1 to 1, or 0 if either has no
+/// magnitude.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b.iter()) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    match norm_a * norm_b {
+        n if n > 0.0 => dot / n.sqrt(),
+        _ => 0.0,
+    }
+}
+
+/// What embedded text will be used for, for providers which embed search queries
+/// differently to the documents being searched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputType {
+    #[default]
+    Document,
+    Query,
+}
+
+/// Posts the request, failing with [CallErr::RequestFailed] if it was unsuccessful.
+pub(crate) async fn post<T: Serialize>(
+    url: String,
+    api_key: Option<String>,
+    body: &T,
+) -> Result<reqwest::Response, CallErr> {
+    let mut req = Client::new().post(url).json(body);
+    if let Some(key) = api_key {
+        req = req.bearer_auth(key);
+    }
+    let resp = req.send().await?;
+    if !resp.status().is_success() {
+        return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+    }
+    Ok(resp)
+}
+
+/// An [Embedder] that uses a model accessible via the OpenAI embeddings API.
+///
+/// If an API key is not provided, it will be read from the environment variable
+/// `OPENAI_API_KEY`, or the [config file](crate::config).
+#[derive(Debug, Clone, Default)]
+pub struct Openai<M: OpenAIEmbeddingModel> {
+    pub model: M,
+    pub api_key: Option<String>,
+    /// Where the key comes from if `api_key` isn't set, see [crate::credentials].
+    pub credentials: Option<Arc<dyn CredentialProvider>>,
+    /// Defaults to `https://api.openai.com/v1`.
+    pub base_url: Option<String>,
+}
+
+impl<M: OpenAIEmbeddingModel> Embedder for Openai<M> {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, CallErr> {
+        let resp = post(
+            format!(
+                "{}/embeddings",
+                match &self.base_url {
+                    Some(url) => url,
+                    None => config::base_url(|c| &c.openai)?.unwrap_or(OPENAI_BASE_URL),
+                }
+            ),
+            Some(
+                credentials::api_key(&self.api_key, &self.credentials, &["OPENAI_API_KEY"], |c| {
+                    &c.openai
+                })
+                .await?,
+            ),
+            &OAIEmbeddingRequest {
+                model: M::MODEL_STR.into(),
+                input: texts,
+            },
+        )
+        .await?;
+        Ok(embeddings(resp.json().await?))
+    }
+}
+
+/// An [Embedder] that uses a model served locally by Ollama, or any other server
+/// implementing the OpenAI embeddings API without authentication.
+#[derive(Debug, Clone, Default)]
+pub struct Ollama<M: OpenAIEmbeddingModel> {
+    pub model: M,
+    /// Defaults to `http://localhost:11434/v1`.
+    pub base_url: Option<String>,
+}
+
+impl<M: OpenAIEmbeddingModel> Embedder for Ollama<M> {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, CallErr> {
+        let resp = post(
+            format!(
+                "{}/embeddings",
+                self.base_url
+                    .as_deref()
+                    .unwrap_or("http://localhost:11434/v1")
+            ),
+            None,
+            &OAIEmbeddingRequest {
+                model: M::MODEL_STR.into(),
+                input: texts,
+            },
+        )
+        .await?;
+        Ok(embeddings(resp.json().await?))
+    }
+}
+
+/// An [Embedder] that uses a model accessible via the Cohere embed API.
+///
+/// If an API key is not provided, it will be read from the environment variable
+/// `COHERE_API_KEY` or `CO_API_KEY`, or the [config file](crate::config).
+#[derive(Debug, Clone, Default)]
+pub struct Cohere<M: CohereEmbeddingModel> {
+    pub model: M,
+    pub api_key: Option<String>,
+    /// Where the key comes from if `api_key` isn't set, see [crate::credentials].
+    pub credentials: Option<Arc<dyn CredentialProvider>>,
+    pub input_type: InputType,
+}
+
+impl<M: CohereEmbeddingModel> Embedder for Cohere<M> {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, CallErr> {
+        let resp = post(
+            "https://api.cohere.com/v2/embed".into(),
+            Some(
+                credentials::api_key(
+                    &self.api_key,
+                    &self.credentials,
+                    &["COHERE_API_KEY", "CO_API_KEY"],
+                    |c| &c.cohere,
+                )
+                .await?,
+            ),
+            &CohereEmbedRequest {
+                model: M::MODEL_STR.into(),
+                texts,
+                input_type: match self.input_type {
+                    InputType::Document => "search_document",
+                    InputType::Query => "search_query",
+                }
+                .into(),
+                embedding_types: vec!["float".into()],
+            },
+        )
+        .await?;
+        let res: CohereEmbedResponse = resp.json().await?;
+        Ok(res.embeddings.float)
+    }
+}
+
+/// An [Embedder] that uses a model accessible via the Voyage AI embeddings API.
+///
+/// If an API key is not provided, it will be read from the environment variable
+/// `VOYAGE_API_KEY`, or the [config file](crate::config).
+#[derive(Debug, Clone, Default)]
+pub struct Voyage<M: VoyageEmbeddingModel> {
+    pub model: M,
+    pub api_key: Option<String>,
+    /// Where the key comes from if `api_key` isn't set, see [crate::credentials].
+    pub credentials: Option<Arc<dyn CredentialProvider>>,
+    pub input_type: InputType,
+}
+
+impl<M: VoyageEmbeddingModel> Embedder for Voyage<M> {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, CallErr> {
+        let resp = post(
+            "https://api.voyageai.com/v1/embeddings".into(),
+            Some(
+                credentials::api_key(&self.api_key, &self.credentials, &["VOYAGE_API_KEY"], |c| {
+                    &c.voyage
+                })
+                .await?,
+            ),
+            &VoyageEmbeddingRequest {
+                model: M::MODEL_STR.into(),
+                input: texts,
+                input_type: Some(
+                    match self.input_type {
+                        InputType::Document => "document",
+                        InputType::Query => "query",
+                    }
+                    .into(),
+                ),
+            },
+        )
+        .await?;
+        Ok(embeddings(resp.json().await?))
+    }
+}
+
+/// Returns the embeddings of the response in the order of the inputs.
+fn embeddings(mut res: OAIEmbeddingResponse) -> Vec<Vec<f32>> {
+    res.data.sort_by_key(|e| e.index);
+    res.data.into_iter().map(|e| e.embedding).collect()
+}
+
+/// Metadata describing a document, such as its source, used to filter queries.
+pub type Metadata = BTreeMap<String, String>;
+
+/// A text stored in a [VectorStore], along with its embedding.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Document {
+    pub text: String,
+    #[serde(default)]
+    pub metadata: Metadata,
+    pub embedding: Vec<f32>,
+}
+
+/// A document matching a query, see [VectorStore::query].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit<'a> {
+    pub document: &'a Document,
+    /// The cosine similarity of the document to the query.
+    pub score: f32,
+}
+
+/// Restricts a query to documents with matching metadata. All conditions must hold.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    conditions: Vec<(String, Vec<String>)>,
+}
+
+impl Filter {
+    /// Constructs a filter which matches every document.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only matches documents where the key has the given value.
+    pub fn eq<K: Into<String>, V: Into<String>>(self, key: K, value: V) -> Self {
+        self.any_of(key, [value])
+    }
+
+    /// Only matches documents where the key has one of the given values.
+    pub fn any_of<K, V, I>(mut self, key: K, values: I) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+        I: IntoIterator<Item = V>,
+    {
+        self.conditions
+            .push((key.into(), values.into_iter().map(|v| v.into()).collect()));
+        self
+    }
+
+    /// Returns true if the metadata satisfies every condition.
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        self.conditions
+            .iter()
+            .all(|(k, values)| metadata.get(k).is_some_and(|v| values.contains(v)))
+    }
+}
+
+/// An in-memory collection of embedded documents, searchable by cosine similarity.
+///
+/// Queries compare against every document, which is fast enough for prototypes and
+/// collections of up to tens of thousands of documents.
+///
+/// ```rust,no_run
+/// use mini_prompt::embed::{Embedder, Filter, InputType, Metadata, Openai, VectorStore};
+/// use mini_prompt::models::TextEmbedding3Small;
+///
+/// let embedder = Openai::<TextEmbedding3Small>::default();
+/// # tokio::task::spawn(async move {
+/// let mut store = VectorStore::new();
+/// let source = |s: &str| Metadata::from([("source".to_string(), s.to_string())]);
+/// store
+///     .add_texts(
+///         &embedder,
+///         vec![
+///             ("Resets are done from the login page.".into(), source("faq")),
+///             ("Our office is in Sydney.".into(), source("about")),
+///         ],
+///     )
+///     .await
+///     .unwrap();
+/// store.save("store.json").unwrap();
+///
+/// let query = embedder.embed(vec!["How do I reset my password?".into()]).await.unwrap();
+/// for hit in store.query(&query[0], 3, &Filter::new().eq("source", "faq")) {
+///     println!("{:.2}: {}", hit.score, hit.document.text);
+/// }
+/// # });
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VectorStore {
+    documents: Vec<Document>,
+}
+
+impl VectorStore {
+    /// Constructs an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a document with the given embedding.
+    pub fn add<S: Into<String>>(&mut self, text: S, embedding: Vec<f32>, metadata: Metadata) {
+        self.documents.push(Document {
+            text: text.into(),
+            metadata,
+            embedding,
+        });
+    }
+
+    /// Embeds each of the texts, adding them as documents with the given metadata.
+    pub async fn add_texts<E: Embedder>(
+        &mut self,
+        embedder: &E,
+        texts: Vec<(String, Metadata)>,
+    ) -> Result<(), CallErr> {
+        let (texts, metadata): (Vec<_>, Vec<_>) = texts.into_iter().unzip();
+        let embeddings = embedder.embed(texts.clone()).await?;
+        if embeddings.len() != texts.len() {
+            return Err(format!(
+                "expected {} embeddings, got {}",
+                texts.len(),
+                embeddings.len()
+            )
+            .into());
+        }
+        for ((text, metadata), embedding) in texts.into_iter().zip(metadata).zip(embeddings) {
+            self.add(text, embedding, metadata);
+        }
+        Ok(())
+    }
+
+    /// Returns the documents in the order they were added.
+    pub fn documents(&self) -> &[Document] {
+        &self.documents
+    }
+
+    /// Returns the number of documents.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns true if the store has no documents.
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Removes the documents for which `f` returns false.
+    pub fn retain<F: FnMut(&Document) -> bool>(&mut self, f: F) {
+        self.documents.retain(f);
+    }
+
+    /// Returns the `k` documents most similar to the embedding which match the filter,
+    /// most similar first.
+    pub fn query(&self, embedding: &[f32], k: usize, filter: &Filter) -> Vec<Hit<'_>> {
+        let mut hits: Vec<Hit> = self
+            .documents
+            .iter()
+            .filter(|d| filter.matches(&d.metadata))
+            .map(|d| Hit {
+                document: d,
+                score: cosine_similarity(&d.embedding, embedding),
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(k);
+        hits
+    }
+
+    /// Writes the store to a JSON file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, serde_json::to_vec(self)?)
+    }
+
+    /// Reads a store written by [save](VectorStore::save).
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Words;
+
+    #[test]
+    fn similarity_and_ordering() {
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]), 1.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0, 1.0], &[-1.0, -1.0]), -1.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+
+        let res: OAIEmbeddingResponse = serde_json::from_str(
+            r#"{
+                "object": "list",
+                "data": [
+                    {"object": "embedding", "index": 1, "embedding": [0.5, 0.5]},
+                    {"object": "embedding", "index": 0, "embedding": [1.0, 0.0]}
+                ],
+                "model": "text-embedding-3-small"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(embeddings(res), vec![vec![1.0, 0.0], vec![0.5, 0.5]]);
+
+        let res: CohereEmbedResponse = serde_json::from_str(
+            r#"{
+                "id": "1",
+                "embeddings": {"float": [[0.25, 0.75]]},
+                "texts": ["hi"],
+                "meta": {"api_version": {"version": "2"}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(res.embeddings.float, vec![vec![0.25, 0.75]]);
+    }
+
+    #[tokio::test]
+    async fn vector_store() {
+        let mut store = VectorStore::new();
+        let source = |s: &str| Metadata::from([("source".to_string(), s.to_string())]);
+        store
+            .add_texts(
+                &Words,
+                vec![
+                    (
+                        "Reset your password from the login page.".into(),
+                        source("faq"),
+                    ),
+                    (
+                        "Passwords must be twelve characters.".into(),
+                        source("policy"),
+                    ),
+                    ("The office is in Sydney.".into(), source("about")),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let query = &Words.embed(vec!["reset password".into()]).await.unwrap()[0];
+        let texts = |hits: Vec<Hit>| -> Vec<String> {
+            hits.iter().map(|h| h.document.text.clone()).collect()
+        };
+        let hits = store.query(query, 2, &Filter::new());
+        assert!(hits[0].score > hits[1].score);
+        assert_eq!(
+            texts(hits),
+            vec![
+                "Reset your password from the login page.",
+                "Passwords must be twelve characters.",
+            ]
+        );
+        assert_eq!(
+            texts(store.query(
+                query,
+                5,
+                &Filter::new().any_of("source", ["policy", "about"])
+            )),
+            vec![
+                "Passwords must be twelve characters.",
+                "The office is in Sydney."
+            ]
+        );
+        assert!(store
+            .query(query, 5, &Filter::new().eq("author", "jo"))
+            .is_empty());
+
+        let path = std::env::temp_dir().join(format!("mini-prompt-vs-{}.json", std::process::id()));
+        store.save(&path).unwrap();
+        assert_eq!(VectorStore::load(&path).unwrap(), store);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,474 @@
+//! Evaluating prompts against a suite of test cases.
+//!
+//! Each [Case] is a call along with the checks its response should pass. A [Suite] of
+//! cases is run against a backend to produce a [Report], and reports for several
+//! backends can be compared with [summary].
+//!
+//! ```rust,no_run
+//! # #[cfg(all(feature = "openrouter", feature = "anthropic"))] {
+//! # use mini_prompt::*;
+//! use mini_prompt::eval::{summary, Case, Check, Suite};
+//!
+//! let suite = Suite::new(vec![
+//!     Case::new("arithmetic", "What's 2+2? Respond with just the number.")
+//!         .check(Check::exact("4")),
+//!     Case::new("capital", "What's the capital of France?")
+//!         .check(Check::contains("paris"))
+//!         .check(Check::judge("Answers in a single sentence.")),
+//! ]);
+//!
+//! let gemma = callers::Openrouter::<models::Gemma27B3>::default();
+//! let haiku = callers::Anthropic::<models::ClaudeHaiku35>::default();
+//! # tokio::task::spawn(async move {
+//! let reports = vec![
+//!     suite.run("gemma", &gemma).await,
+//!     suite.run("haiku", &haiku).await,
+//! ];
+//! println!("{}", summary(&reports));
+//! # });
+//! # }
+//! ```
+
+use crate::parse::keyed_values;
+use crate::{CallBase, ModelCaller, Turn};
+use regex::Regex;
+
+/// Checks a property of a response, see [Case::check].
+pub struct Check {
+    /// A description of the check, used in reports.
+    name: String,
+    kind: CheckKind,
+}
+
+type ParseFunc = Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+enum CheckKind {
+    Exact(String),
+    Contains(String),
+    Regex(Regex),
+    Parse(ParseFunc),
+    Judge(String),
+}
+
+impl Check {
+    /// Passes if the response is the given text, ignoring surrounding whitespace.
+    pub fn exact<S: Into<String>>(text: S) -> Self {
+        let text = text.into();
+        Self {
+            name: format!("is '{}'", text),
+            kind: CheckKind::Exact(text),
+        }
+    }
+
+    /// Passes if the response contains the given text, ignoring case.
+    pub fn contains<S: Into<String>>(text: S) -> Self {
+        let text = text.into();
+        Self {
+            name: format!("contains '{}'", text),
+            kind: CheckKind::Contains(text.to_lowercase()),
+        }
+    }
+
+    /// Passes if the response matches the pattern.
+    pub fn matches(pattern: Regex) -> Self {
+        Self {
+            name: format!("matches '{}'", pattern.as_str()),
+            kind: CheckKind::Regex(pattern),
+        }
+    }
+
+    /// Passes if the given function, such as one built on [crate::parse], accepts the
+    /// response. The name describes the check in reports.
+    ///
+    /// ```
+    /// # use mini_prompt::eval::Check;
+    /// use mini_prompt::parse::{markdown_codeblock, MarkdownOptions};
+    ///
+    /// let check = Check::parses("has a JSON code block", |text| {
+    ///     markdown_codeblock(text, &MarkdownOptions::json())
+    ///         .map(|_| ())
+    ///         .ok_or_else(|| "no code block".to_string())
+    /// });
+    /// ```
+    pub fn parses<S, F>(name: S, parse: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(&str) -> Result<(), String> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            kind: CheckKind::Parse(Box::new(parse)),
+        }
+    }
+
+    /// Passes if a judge model decides the response satisfies the rubric. The judge is
+    /// given the task along with the response.
+    pub fn judge<S: Into<String>>(rubric: S) -> Self {
+        let rubric = rubric.into();
+        Self {
+            name: format!("judged '{}'", rubric),
+            kind: CheckKind::Judge(rubric),
+        }
+    }
+
+    /// Returns the description of the check.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Scores the response, consulting the judge for [Check::judge] checks.
+    async fn score<J: ModelCaller>(&self, task: &str, text: &str, judge: &J) -> Score {
+        let (passed, detail) = match &self.kind {
+            CheckKind::Exact(want) => (text.trim() == want.trim(), String::new()),
+            CheckKind::Contains(want) => (text.to_lowercase().contains(want), String::new()),
+            CheckKind::Regex(pattern) => (pattern.is_match(text), String::new()),
+            CheckKind::Parse(parse) => match parse(text) {
+                Ok(()) => (true, String::new()),
+                Err(e) => (false, e),
+            },
+            CheckKind::Judge(rubric) => match judge_response(judge, task, text, rubric).await {
+                Ok(judged) => judged,
+                Err(e) => (false, format!("judge failed: {:?}", e)),
+            },
+        };
+        Score {
+            check: self.name.clone(),
+            passed,
+            detail,
+        }
+    }
+}
+
+/// Asks the judge whether the response satisfies the rubric, returning the verdict
+/// and the judge's reasoning.
+async fn judge_response<J: ModelCaller>(
+    judge: &J,
+    task: &str,
+    text: &str,
+    rubric: &str,
+) -> Result<(bool, String), crate::CallErr> {
+    let params = CallBase {
+        instructions: format!(
+            "A response was written for the following task.\n\n\
+            <task>\n{}\n</task>\n\n<response>\n{}\n</response>\n\n\
+            Decide whether the response satisfies this rubric:\n\n<rubric>\n{}\n</rubric>\n\n\
+            Briefly explain your reasoning, then finish your response with a line of the \
+            form 'verdict: pass' or 'verdict: fail'.",
+            task, text, rubric
+        ),
+        temperature: Some(0.0),
+        ..Default::default()
+    };
+    judge
+        .call_parsed(params, vec![], 1, |text| {
+            let verdict = keyed_values(text, &["verdict"])
+                .get("verdict")
+                .map(|v| v.to_lowercase());
+            let passed = match verdict.as_deref() {
+                Some(v) if v.contains("pass") => true,
+                Some(v) if v.contains("fail") => false,
+                _ => return Err("no line of the form 'verdict: pass' or 'verdict: fail'".into()),
+            };
+            let reasoning = text
+                .lines()
+                .filter(|l| keyed_values(l, &["verdict"]).is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+            Ok((passed, reasoning.trim().to_string()))
+        })
+        .await
+}
+
+/// A call, along with the checks its response should pass.
+pub struct Case {
+    name: String,
+    params: CallBase,
+    turns: Vec<Turn>,
+    checks: Vec<Check>,
+}
+
+impl Case {
+    /// Constructs a case which calls the model with the given instructions.
+    pub fn new<S: Into<String>>(name: S, instructions: S) -> Self {
+        Self {
+            name: name.into(),
+            params: CallBase {
+                instructions: instructions.into(),
+                ..Default::default()
+            },
+            turns: vec![],
+            checks: vec![],
+        }
+    }
+
+    /// Sets the parameters of the call, replacing the instructions given to [Case::new].
+    pub fn with_params(mut self, params: CallBase) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Sets the turns of the call.
+    pub fn with_turns(mut self, turns: Vec<Turn>) -> Self {
+        self.turns = turns;
+        self
+    }
+
+    /// Adds a check the response must pass.
+    pub fn check(mut self, check: Check) -> Self {
+        self.checks.push(check);
+        self
+    }
+
+    /// Describes the task, for the judge.
+    fn task(&self) -> String {
+        let mut task = self.params.instructions.clone();
+        for t in self.turns.iter() {
+            task += "\n\n";
+            task += &t.text();
+        }
+        task.trim().to_string()
+    }
+}
+
+/// The outcome of a single check, see [CaseResult].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Score {
+    /// The description of the check.
+    pub check: String,
+    pub passed: bool,
+    /// Why the check failed, or the judge's reasoning, if available.
+    pub detail: String,
+}
+
+/// The outcome of running a [Case].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    /// The name of the case.
+    pub case: String,
+    /// The text of the response, or a description of the error if the call failed.
+    pub output: Result<String, String>,
+    /// The outcome of each check, in the order they were added. Empty if the call failed.
+    pub scores: Vec<Score>,
+}
+
+impl CaseResult {
+    /// Returns true if the call succeeded and passed every check.
+    pub fn passed(&self) -> bool {
+        self.output.is_ok() && self.scores.iter().all(|s| s.passed)
+    }
+}
+
+/// The outcome of running a [Suite] against a backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The label of the backend the suite was run against.
+    pub backend: String,
+    /// The outcome of each case, in order.
+    pub results: Vec<CaseResult>,
+}
+
+impl Report {
+    /// Returns the number of cases which passed.
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed()).count()
+    }
+
+    /// Returns the fraction of cases which passed, from 0 to 1.
+    pub fn pass_rate(&self) -> f32 {
+        match self.results.len() {
+            0 => 0.0,
+            n => self.passed() as f32 / n as f32,
+        }
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}: {}/{} passed",
+            self.backend,
+            self.passed(),
+            self.results.len()
+        )?;
+        for r in self.results.iter() {
+            match (&r.output, r.passed()) {
+                (Err(e), _) => writeln!(f, "  FAIL {}: call failed: {}", r.case, e)?,
+                (Ok(_), true) => writeln!(f, "  PASS {}", r.case)?,
+                (Ok(_), false) => {
+                    writeln!(f, "  FAIL {}", r.case)?;
+                    for s in r.scores.iter().filter(|s| !s.passed) {
+                        match s.detail.is_empty() {
+                            true => writeln!(f, "    - {}", s.check)?,
+                            false => writeln!(f, "    - {}: {}", s.check, s.detail)?,
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Summarizes reports for several backends as a table, with a row for each case and a
+/// column for each backend. Cases are taken from the first report.
+pub fn summary(reports: &[Report]) -> String {
+    let Some(first) = reports.first() else {
+        return String::new();
+    };
+    let mut out = String::from("| case |");
+    for r in reports {
+        out += &format!(" {} |", r.backend);
+    }
+    out += "\n|---|";
+    out += &"---|".repeat(reports.len());
+    for (i, case) in first.results.iter().enumerate() {
+        out += &format!("\n| {} |", case.case);
+        for r in reports {
+            out += match r.results.get(i).map(|r| r.passed()) {
+                Some(true) => " pass |",
+                Some(false) => " FAIL |",
+                None => " - |",
+            };
+        }
+    }
+    out += "\n| **passed** |";
+    for r in reports {
+        out += &format!(" {}/{} |", r.passed(), r.results.len());
+    }
+    out
+}
+
+/// A set of cases to run against backends.
+pub struct Suite {
+    cases: Vec<Case>,
+}
+
+impl Suite {
+    /// Constructs a suite of the given cases.
+    pub fn new(cases: Vec<Case>) -> Self {
+        Self { cases }
+    }
+
+    /// Runs each case against the backend in turn, labelling the report with the given
+    /// name. The backend also acts as the judge for [Check::judge] checks.
+    pub async fn run<B: ModelCaller>(&self, name: &str, backend: &B) -> Report {
+        self.run_inner::<B, B>(name, backend, None).await
+    }
+
+    /// Like [run](Suite::run), but uses a separate model as the judge, such as a more
+    /// capable one.
+    pub async fn run_with_judge<B: ModelCaller, J: ModelCaller>(
+        &self,
+        name: &str,
+        backend: &B,
+        judge: &J,
+    ) -> Report {
+        self.run_inner(name, backend, Some(judge)).await
+    }
+
+    /// Runs each case, using the backend as the judge if one isn't given.
+    async fn run_inner<B: ModelCaller, J: ModelCaller>(
+        &self,
+        name: &str,
+        backend: &B,
+        judge: Option<&J>,
+    ) -> Report {
+        let mut results = Vec::with_capacity(self.cases.len());
+        for case in self.cases.iter() {
+            let output = Self::call(case, backend).await;
+            let mut scores = vec![];
+            if let Ok(text) = &output {
+                for check in case.checks.iter() {
+                    let score = match judge {
+                        Some(judge) => check.score(&case.task(), text, judge).await,
+                        None => check.score(&case.task(), text, backend).await,
+                    };
+                    scores.push(score);
+                }
+            }
+            results.push(CaseResult {
+                case: case.name.clone(),
+                output,
+                scores,
+            });
+        }
+        Report {
+            backend: name.to_string(),
+            results,
+        }
+    }
+
+    async fn call<B: ModelCaller>(case: &Case, backend: &B) -> Result<String, String> {
+        backend
+            .call(case.params.clone(), case.turns.clone())
+            .await
+            .map(|resp| resp.content.text())
+            .map_err(|e| format!("{:?}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scripted;
+
+    #[tokio::test]
+    async fn run_suite() {
+        let suite = Suite::new(vec![
+            Case::new("sum", "What's 2+2?").check(Check::exact("4")),
+            Case::new("capital", "Capital of France?")
+                .check(Check::contains("PARIS"))
+                .check(Check::matches(Regex::new(r"^\w+$").unwrap()))
+                .check(Check::judge("Is polite.")),
+            Case::new("json", "Some JSON?").check(Check::parses("is JSON", |text| {
+                serde_json::from_str::<serde_json::Value>(text)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            })),
+            Case::new("broken", "?"),
+        ]);
+
+        let backend = Scripted::new(vec![
+            Scripted::text(" 4\n"),
+            Scripted::text("Paris"),
+            Scripted::text("[1,"),
+        ]);
+        let judge = Scripted::new(vec![Scripted::text("Curt.\nverdict: FAIL")]);
+        let report = suite.run_with_judge("scripted", &backend, &judge).await;
+        assert!(judge.seen()[0]
+            .0
+            .instructions
+            .contains("<response>\nParis\n</response>"));
+
+        let passed: Vec<_> = report.results.iter().map(|r| r.passed()).collect();
+        assert_eq!(passed, vec![true, false, false, false]);
+        assert_eq!(report.passed(), 1);
+        assert_eq!(
+            report.results[1].scores[2],
+            Score {
+                check: "judged 'Is polite.'".into(),
+                passed: false,
+                detail: "Curt.".into(),
+            }
+        );
+        assert_eq!(report.results[3].output, Err("NoCompletions".into()));
+        assert_eq!(
+            report.to_string(),
+            "scripted: 1/4 passed\n  PASS sum\n  FAIL capital\n    - judged 'Is polite.': Curt.\n  \
+            FAIL json\n    - is JSON: EOF while parsing a value at line 1 column 3\n  \
+            FAIL broken: call failed: NoCompletions\n"
+        );
+
+        let other = Report {
+            backend: "other".into(),
+            results: report.results[..1].to_vec(),
+        };
+        assert_eq!(
+            summary(&[report, other]),
+            "| case | scripted | other |\n|---|---|---|\n| sum | pass | pass |\n\
+            | capital | FAIL | - |\n| json | FAIL | - |\n| broken | FAIL | - |\n\
+            | **passed** | 1/4 | 1/1 |"
+        );
+    }
+}
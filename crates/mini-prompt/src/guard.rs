@@ -0,0 +1,292 @@
+//! Checking model responses against rules before they are used.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "openrouter")] {
+//! # use mini_prompt::*;
+//! use mini_prompt::guard::{Guarded, Guardrails, OnViolation};
+//!
+//! let rails = Guardrails::new()
+//!     .deny_keyword("password")
+//!     .deny(regex::Regex::new(r"\bsk-[A-Za-z0-9]{20,}").unwrap())
+//!     .max_chars(2_000)
+//!     .on_violation(OnViolation::Regenerate { attempts: 2 });
+//! let backend = Guarded::new(callers::Openrouter::<models::Gemma27B3>::default(), rails);
+//! # tokio::task::spawn(async move {
+//! match backend.simple_call("How do I reset my account?").await {
+//!     Err(CallErr::Blocked(violations)) => println!("blocked: {:?}", violations),
+//!     r => println!("{:?}", r),
+//! }
+//! # });
+//! # }
+//! ```
+
+use crate::models::Model;
+use crate::{before_deadline, CallBase, CallErr, CallResp, Message, ModelCaller, Role, Turn};
+use regex::Regex;
+
+/// What to do when a response breaks a rule, see [Guardrails::on_violation].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnViolation {
+    /// Replaces denied text with a placeholder, and truncates responses which are too long.
+    Redact,
+    /// Fails the call with [CallErr::Blocked].
+    #[default]
+    Block,
+    /// Tells the model which rules were broken and asks it to try again, up to the given
+    /// number of times, before failing the call with [CallErr::Blocked].
+    Regenerate { attempts: usize },
+}
+
+/// A rule broken by a response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// A description of the rule.
+    pub rule: String,
+    /// The text which broke the rule, if it was a denied pattern.
+    pub matched: Option<String>,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.matched {
+            Some(m) => write!(f, "{} (matched '{}')", self.rule, m),
+            None => write!(f, "{}", self.rule),
+        }
+    }
+}
+
+/// Rules which responses must follow, and what to do when they don't.
+#[derive(Debug, Clone)]
+pub struct Guardrails {
+    /// Patterns which must not appear, along with a description of each.
+    deny: Vec<(Regex, String)>,
+    max_chars: Option<usize>,
+    on_violation: OnViolation,
+    placeholder: String,
+}
+
+impl Default for Guardrails {
+    fn default() -> Self {
+        Self {
+            deny: vec![],
+            max_chars: None,
+            on_violation: OnViolation::default(),
+            placeholder: "[redacted]".into(),
+        }
+    }
+}
+
+impl Guardrails {
+    /// Constructs guardrails with no rules, which block responses that break them.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Denies text matching the given pattern.
+    pub fn deny(mut self, pattern: Regex) -> Self {
+        let rule = format!("must not match the pattern '{}'", pattern.as_str());
+        self.deny.push((pattern, rule));
+        self
+    }
+
+    /// Denies the given word or phrase, ignoring case.
+    pub fn deny_keyword(mut self, keyword: &str) -> Self {
+        let pattern = Regex::new(&format!(r"(?i)\b{}\b", regex::escape(keyword))).unwrap();
+        self.deny
+            .push((pattern, format!("must not mention '{}'", keyword)));
+        self
+    }
+
+    /// Limits responses to the given number of characters.
+    pub fn max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = Some(max_chars);
+        self
+    }
+
+    /// Sets what to do when a response breaks a rule, which defaults to
+    /// [OnViolation::Block].
+    pub fn on_violation(mut self, on_violation: OnViolation) -> Self {
+        self.on_violation = on_violation;
+        self
+    }
+
+    /// Sets the text which replaces denied text in [OnViolation::Redact] mode, which
+    /// defaults to `[redacted]`.
+    pub fn placeholder<S: Into<String>>(mut self, placeholder: S) -> Self {
+        self.placeholder = placeholder.into();
+        self
+    }
+
+    /// Returns every rule the text breaks.
+    pub fn check(&self, text: &str) -> Vec<Violation> {
+        let mut out: Vec<Violation> = self
+            .deny
+            .iter()
+            .filter_map(|(pattern, rule)| {
+                pattern.find(text).map(|m| Violation {
+                    rule: rule.clone(),
+                    matched: Some(m.as_str().to_string()),
+                })
+            })
+            .collect();
+        if let Some(max) = self.max_chars {
+            if text.chars().count() > max {
+                out.push(Violation {
+                    rule: format!("must be at most {} characters long", max),
+                    matched: None,
+                });
+            }
+        }
+        out
+    }
+
+    /// Replaces denied text with the placeholder, and truncates the text to the
+    /// maximum length.
+    pub fn redact(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for (pattern, _) in self.deny.iter() {
+            out = pattern
+                .replace_all(&out, regex::NoExpand(&self.placeholder))
+                .into_owned();
+        }
+        match self.max_chars {
+            Some(max) => out.chars().take(max).collect(),
+            None => out,
+        }
+    }
+}
+
+/// Wraps a [ModelCaller], checking the text of each response against [Guardrails].
+pub struct Guarded<B: ModelCaller> {
+    backend: B,
+    rails: Guardrails,
+}
+
+impl<B: ModelCaller> Guarded<B> {
+    /// Constructs a caller which checks the responses of the given backend.
+    pub fn new(backend: B, rails: Guardrails) -> Self {
+        Self { backend, rails }
+    }
+
+    /// Consumes the wrapper, returning the underlying backend.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+}
+
+impl<B: ModelCaller> ModelCaller for Guarded<B> {
+    fn get_model(&self) -> impl Model {
+        self.backend.get_model()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.backend.supports_response_schema()
+    }
+
+    fn endpoint(&self) -> String {
+        self.backend.endpoint()
+    }
+
+    async fn call(&self, params: CallBase, mut turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        let mut attempt = 0;
+        loop {
+            let call = self.backend.call(params.clone(), turns.clone());
+            let mut resp = before_deadline(params.deadline, call).await?;
+            let violations = self.rails.check(&resp.content.text());
+            if violations.is_empty() {
+                return Ok(resp);
+            }
+
+            match self.rails.on_violation {
+                OnViolation::Redact => {
+                    for m in resp.content.content.iter_mut() {
+                        if let Message::Text { text } = m {
+                            *text = self.rails.redact(text).into();
+                        }
+                    }
+                    return Ok(resp);
+                }
+                OnViolation::Regenerate { attempts } if attempt < attempts => {
+                    attempt += 1;
+                    let rules = violations
+                        .iter()
+                        .map(|v| format!("- {}", v))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    turns.push(resp.content);
+                    turns.push(Turn {
+                        role: Role::User,
+                        content: vec![Message::text(format!(
+                            "Your response broke these rules:\n{}\n\nRewrite your response \
+                            so it follows them.",
+                            rules
+                        ))],
+                    });
+                }
+                _ => return Err(CallErr::Blocked(violations)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scripted;
+
+    #[test]
+    fn check_and_redact() {
+        let rails = Guardrails::new()
+            .deny_keyword("Secret")
+            .deny(Regex::new(r"\d{4}-\d{4}").unwrap())
+            .max_chars(30);
+        assert!(rails.check("secretive, but fine").is_empty());
+
+        let text = "The SECRET code is 1234-5678, ok?";
+        assert_eq!(
+            rails.check(text),
+            vec![
+                Violation {
+                    rule: "must not mention 'Secret'".into(),
+                    matched: Some("SECRET".into()),
+                },
+                Violation {
+                    rule: r"must not match the pattern '\d{4}-\d{4}'".into(),
+                    matched: Some("1234-5678".into()),
+                },
+                Violation {
+                    rule: "must be at most 30 characters long".into(),
+                    matched: None,
+                },
+            ]
+        );
+        assert_eq!(
+            rails.placeholder("$0").redact(text),
+            "The $0 code is $0, ok?"
+        );
+    }
+
+    #[tokio::test]
+    async fn block_and_regenerate() {
+        let rails = Guardrails::new().deny_keyword("darn");
+        let backend = Guarded::new(
+            Scripted::new(vec![Scripted::text("Darn it.")]),
+            rails.clone(),
+        );
+        match backend.simple_call("hi").await {
+            Err(CallErr::Blocked(v)) => assert_eq!(v[0].matched.as_deref(), Some("Darn")),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let backend = Guarded::new(
+            Scripted::new(vec![Scripted::text("Darn it."), Scripted::text("Oh dear.")]),
+            rails.on_violation(OnViolation::Regenerate { attempts: 1 }),
+        );
+        assert_eq!(backend.simple_call("hi").await.unwrap(), "Oh dear.");
+        let retry = &backend.into_inner().seen()[1].1;
+        assert_eq!(retry[0].text(), "Darn it.");
+        assert!(retry[1]
+            .text()
+            .contains("- must not mention 'darn' (matched 'Darn')"));
+    }
+}
@@ -2,12 +2,14 @@
 //!
 //! Simple calls:
 //! ```rust,no_run
+//! # #[cfg(feature = "openrouter")] {
 //! # use mini_prompt::*;
-//! let mut backend = callers::Openrouter::<models::Gemma27B3>::default();
+//! let backend = callers::Openrouter::<models::Gemma27B3>::default();
 //! # tokio::task::spawn(async move {
 //! let resp =
 //!     backend.simple_call("How much wood could a wood-chuck chop").await;
 //! # });
+//! # }
 //! ```
 //!
 //! If you are looking for more control over the input, you can use [call](ModelCaller::call) instead of [simple_call](ModelCaller::simple_call).
@@ -16,9 +18,10 @@
 //!
 //! With tools:
 //! ```rust,no_run
+//! # #[cfg(feature = "anthropic")] {
 //! # use mini_prompt::*;
 //! let backend = callers::Anthropic::<models::ClaudeHaiku35>::default();
-//! let mut session = ToolsSession::new(
+//! let session = ToolsSession::new(
 //!             backend,
 //!             vec![
 //!                 (
@@ -35,13 +38,15 @@
 //! let resp =
 //!     session.simple_call("Go ahead and flubb for me").await;
 //! # });
+//! # }
 //! ```
 //!
 //! Structured output:
 //! ```rust,no_run
+//! # #[cfg(feature = "openrouter")] {
 //! # use mini_prompt::*;
 //! # use mini_prompt::parse::*;
-//! let mut backend = callers::Openrouter::<models::Gemma27B3>::default();
+//! let backend = callers::Openrouter::<models::Gemma27B3>::default();
 //! # tokio::task::spawn(async move {
 //! let resp =
 //!     backend.simple_call("Whats 2+2? output the final answer as JSON within triple backticks (A markdown code block with json as the language).").await;
@@ -49,24 +54,83 @@
 //! let json = markdown_codeblock(&resp.unwrap(), &MarkdownOptions::json()).unwrap();
 //! let p: serde_json::Value = serde_json_lenient::from_str(&json).expect("json decode");
 //! # });
+//! # }
 //! ```
 
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub mod data_model;
 use crate::data_model::{AnthropicMessage, OAIChatMessage};
 
 pub mod parse;
 
+pub mod prompt;
+
+pub mod sampling;
+
+pub mod batch;
+
+pub mod orchestrate;
+
+pub mod pipeline;
+
+pub mod split;
+
+pub mod eval;
+
+pub mod compare;
+
+pub mod assertions;
+
+pub mod embed;
+
+pub mod rerank;
+
+pub mod rag;
+
+pub mod cache;
+
+pub mod classify;
+pub use classify::classify;
+
 pub mod models;
 
 pub mod callers;
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+mod stream;
+
+pub mod config;
+
+pub mod credentials;
 
 pub use callers::ModelCaller;
 
 pub mod tools;
 pub use tools::ToolsSession;
 
+pub mod agent;
+
+pub mod guard;
+
+pub mod pii;
+
+pub mod moderation;
+
+pub mod injection;
+
+pub mod chat;
+pub use chat::ChatSession;
+
+pub mod tokens;
+
+#[cfg(feature = "mcp")]
+pub mod mcp;
+
+#[cfg(test)]
+mod testing;
+
 /// Describes an error which occurred during a model call.
 pub enum CallErr {
     /// The response lacked any completions, which can be non-erroneous for multi-turn contexts
@@ -77,9 +141,26 @@ pub enum CallErr {
     /// A model call API returned a non-2xx status code.
     RequestFailed(reqwest::StatusCode, String),
     /// Any other error.
-    Other(Box<dyn std::error::Error>),
+    Other(Box<dyn std::error::Error + Send + Sync>),
     /// A tool call reported an error instead of returning a result.
     ToolFailed { name: String, err: String },
+    /// The output of the model could not be parsed into the expected structure,
+    /// with the output and error of each attempt in order.
+    ParseFailed(Vec<ParseAttempt>),
+    /// The response broke the rules of a [guard::Guarded] caller.
+    Blocked(Vec<guard::Violation>),
+    /// The input or response was flagged by [moderation::Screened], in the given
+    /// categories, such as `harassment`.
+    Moderated { categories: Vec<String> },
+    /// The call didn't finish before its [deadline](CallBase::deadline).
+    DeadlineExceeded,
+}
+
+/// A model response which could not be parsed, see [CallErr::ParseFailed].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAttempt {
+    pub output: String,
+    pub err: String,
 }
 
 impl std::fmt::Debug for CallErr {
@@ -98,6 +179,13 @@ impl std::fmt::Debug for CallErr {
                 .field("name", name)
                 .field("error", err)
                 .finish(),
+            CallErr::ParseFailed(attempts) => f.debug_tuple("ParseFailed").field(attempts).finish(),
+            CallErr::Blocked(violations) => f.debug_tuple("Blocked").field(violations).finish(),
+            CallErr::Moderated { categories } => f
+                .debug_struct("Moderated")
+                .field("categories", categories)
+                .finish(),
+            CallErr::DeadlineExceeded => write!(f, "DeadlineExceeded"),
         }
     }
 }
@@ -121,7 +209,7 @@ impl From<reqwest::Error> for CallErr {
 }
 
 /// Describes the parameters and use of a tool made available to an LLM.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ToolInfo {
     /// The name of the function to be called. Must be a-z, A-Z, 0-9, or contain underscores and dashes, with a maximum length of 64.
     name: String,
@@ -129,7 +217,9 @@ pub struct ToolInfo {
     description: String,
     /// The parameters the functions accepts, described as a JSON Schema object. See the guide for examples, and the JSON Schema reference for documentation about the format.
     /// To describe a function that accepts no parameters, provide the value {"type": "object", "properties": {}}.
-    pub parameters: serde_json::Value,
+    ///
+    /// The schema is shared between clones, so it is not copied for each request.
+    pub parameters: Arc<serde_json::Value>,
 }
 
 impl ToolInfo {
@@ -141,14 +231,50 @@ impl ToolInfo {
         Self {
             name: name.into(),
             description: description.into(),
-            parameters: match parameters {
+            parameters: Arc::new(match parameters {
                 Some(p) => p,
                 None => serde_json::json!({"type": "object", "properties": {}}),
-            },
+            }),
+        }
+    }
+
+    /// Constructs a tool whose parameters are described by the JSON schema of `Args`,
+    /// so the schema stays in sync with the type the arguments are deserialized into.
+    ///
+    /// ```
+    /// # use mini_prompt::*;
+    /// #[derive(serde::Deserialize, schemars::JsonSchema)]
+    /// struct FlubbArgs {
+    ///     /// How many times to flubb.
+    ///     count: u32,
+    /// }
+    ///
+    /// let tool = ToolInfo::from_schema::<FlubbArgs>("flubb", "Performs a flubb");
+    /// assert_eq!(tool.parameters["required"], serde_json::json!(["count"]));
+    /// ```
+    #[cfg(feature = "schemars")]
+    pub fn from_schema<Args: schemars::JsonSchema>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters: Arc::new(json_schema::<Args>()),
         }
     }
 }
 
+#[cfg(feature = "schemars")]
+/// Returns the JSON schema of a type, without the `$schema` keyword.
+pub(crate) fn json_schema<T: schemars::JsonSchema>() -> serde_json::Value {
+    let mut schema = schemars::schema_for!(T).to_value();
+    if let Some(o) = schema.as_object_mut() {
+        o.remove("$schema");
+    }
+    schema
+}
+
 impl From<ToolInfo> for data_model::OAITool {
     fn from(ti: ToolInfo) -> data_model::OAITool {
         data_model::OAITool {
@@ -172,37 +298,157 @@ impl From<ToolInfo> for data_model::AnthropicTool {
     }
 }
 
+/// Constrains model output during decoding, for servers which support it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Constraint {
+    /// A grammar in GBNF format, sent as `grammar` as expected by llama.cpp.
+    Grammar(String),
+    /// A regular expression, sent as `guided_regex` as expected by vLLM.
+    Regex(String),
+}
+
+/// Controls whether the model must call a tool.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides whether to call tools.
+    #[default]
+    Auto,
+    /// The model must call at least one tool.
+    Required,
+    /// The model must call the tool with the given name.
+    Tool(String),
+}
+
 /// The basic parameters for a (possibly multi-turn) model call.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Missing fields take their default values when deserialized.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CallBase {
     /// Short-form information describing the persona of the LLM.
     ///
     /// EG: "You are an expert software developer"
     pub system: String,
+    /// Further system prompts, sent after `system` in order, for prompts built from
+    /// layers such as an organization's policy, the task, and a persona. Each is sent
+    /// as its own system message, or its own block of the Anthropic system prompt.
+    pub system_segments: Vec<String>,
     /// Task-specific instructions for the LLM.
     pub instructions: String,
     /// Descriptions of tools that may be used.
     pub tools: Vec<ToolInfo>,
+    /// Whether the model must call a tool. Ignored if no tools are provided.
+    pub tool_choice: ToolChoice,
 
     pub temperature: Option<f32>,
     pub max_tokens: usize,
+    /// Sequences which end the response when the model generates them. The sequence
+    /// itself isn't included in the response. OpenAI accepts at most 4.
+    pub stop: Vec<String>,
+    /// Text the response must begin with, such as `{` to force JSON. It is sent as a
+    /// partial assistant turn which the model continues, and prepended to the first
+    /// text message of the response, so the content is the whole turn. Anthropic
+    /// rejects a prefill ending in whitespace, and OpenAI models may respond to it
+    /// rather than continue it.
+    pub prefill: Option<String>,
+
+    /// A JSON schema the response must conform to. Only honored by callers where
+    /// [supports_response_schema](ModelCaller::supports_response_schema) is true.
+    pub response_schema: Option<serde_json::Value>,
+
+    /// Requests the log probability of each generated token, along with the given
+    /// number of most likely alternatives (at most 20). Only supported by OpenAI-compatible
+    /// callers, see [CallResp::logprobs].
+    pub top_logprobs: Option<u8>,
+
+    /// Requests that sampling is deterministic, so repeated calls with the same seed
+    /// and parameters return the same response. Providers only make a best effort, see
+    /// [CallResp::system_fingerprint]. Only supported by OpenAI-compatible callers.
+    pub seed: Option<u64>,
+
+    /// A grammar or regex the response must match. Only supported by [callers::Openai]
+    /// pointed at a compatible server; other callers fail if this is set.
+    pub constraint: Option<Constraint>,
+
+    /// The registered prompt these parameters were built from, if any. This is
+    /// copied to the response, see [prompt::PromptStore].
+    pub prompt: Option<prompt::PromptRef>,
+
+    /// When the call must be done by, including any further calls made to complete
+    /// it, such as retries or the rounds of a tools loop. Calls still running at the
+    /// deadline fail with [CallErr::DeadlineExceeded]. Not serialized.
+    #[serde(skip)]
+    pub deadline: Option<Instant>,
+}
+
+impl CallBase {
+    /// Sets the deadline to `timeout` from now, unless there is an earlier deadline
+    /// already, such as one set by the caller of a wrapper.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        let deadline = Instant::now() + timeout;
+        self.deadline = Some(self.deadline.map_or(deadline, |d| d.min(deadline)));
+        self
+    }
+
+    /// Takes `system` and `system_segments`, returning the system prompts which aren't
+    /// empty, in order.
+    #[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+    pub(crate) fn take_system(&mut self) -> Vec<String> {
+        std::iter::once(std::mem::take(&mut self.system))
+            .chain(std::mem::take(&mut self.system_segments))
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    /// Returns the time left before the deadline, or None if there is no deadline.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|d| d.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// Awaits the future unless the deadline passes first, failing with
+/// [CallErr::DeadlineExceeded] if it does, or if it has already passed.
+pub(crate) async fn before_deadline<T, F>(deadline: Option<Instant>, f: F) -> Result<T, CallErr>
+where
+    F: std::future::Future<Output = Result<T, CallErr>>,
+{
+    match deadline {
+        None => f.await,
+        Some(d) if Instant::now() >= d => Err(CallErr::DeadlineExceeded),
+        Some(d) => tokio::time::timeout_at(d.into(), f)
+            .await
+            .unwrap_or(Err(CallErr::DeadlineExceeded)),
+    }
 }
 
 impl Default for CallBase {
     fn default() -> Self {
         Self {
             system: "".to_string(),
+            system_segments: vec![],
             instructions: "".to_string(),
             tools: vec![],
+            tool_choice: ToolChoice::Auto,
 
             temperature: None,
             max_tokens: 8192,
+            stop: vec![],
+            prefill: None,
+            response_schema: None,
+            top_logprobs: None,
+            seed: None,
+            constraint: None,
+            prompt: None,
+            deadline: None,
         }
     }
 }
 
 /// Describes a round of model input or output.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Turn {
     /// The source of the content: i.e. the user, the model (assistant), a tool.
     pub role: Role,
@@ -212,9 +458,20 @@ pub struct Turn {
 
 impl From<data_model::OAIChatMessage> for Turn {
     fn from(resp: data_model::OAIChatMessage) -> Self {
-        let mut msgs = Vec::with_capacity(1 + resp.tool_calls.len());
-        if let Some(text) = resp.content {
-            msgs.push(Message::Text { text });
+        let mut msgs = Vec::with_capacity(2 + resp.tool_calls.len());
+        if let Some(text) = resp.reasoning.filter(|r| !r.is_empty()) {
+            msgs.push(Message::Thinking {
+                text,
+                signature: None,
+            });
+        }
+        if resp.role == Role::Tool {
+            msgs.push(Message::ToolResult {
+                id: resp.tool_call_id.unwrap_or_default(),
+                result: resp.content.unwrap_or_default().into(),
+            });
+        } else if let Some(text) = resp.content {
+            msgs.push(Message::text(text));
         }
         resp.tool_calls
             .into_iter()
@@ -228,48 +485,128 @@ impl From<data_model::OAIChatMessage> for Turn {
 }
 
 impl Turn {
+    /// Returns the text messages of the turn, joined by newlines.
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|m| match m {
+                Message::Text { text } => Some(&**text),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Converts messages in the OpenAI chat completions format into turns, such as
+    /// to load a transcript saved by another SDK.
+    ///
+    /// Consecutive tool messages are combined into a single [Role::Tool] turn.
+    ///
+    /// ```
+    /// # use mini_prompt::*;
+    /// let msgs: Vec<data_model::OAIChatMessage> = serde_json::from_str(r#"[
+    ///     {"role": "user", "content": "What's the weather?"},
+    ///     {"role": "assistant", "tool_calls": [
+    ///         {"id": "1", "type": "function", "function": {"name": "weather", "arguments": "{}"}}
+    ///     ]},
+    ///     {"role": "tool", "tool_call_id": "1", "content": "sunny"}
+    /// ]"#).unwrap();
+    ///
+    /// let turns = Turn::from_oai_messages(&msgs);
+    /// assert_eq!(turns.len(), 3);
+    /// assert_eq!(Turn::to_oai_messages(&turns).unwrap(), msgs);
+    /// ```
+    pub fn from_oai_messages(msgs: &[OAIChatMessage]) -> Vec<Turn> {
+        use itertools::Itertools;
+        msgs.iter()
+            .cloned()
+            .map(Turn::from)
+            .coalesce(|mut prev, next| {
+                if prev.role == Role::Tool && next.role == Role::Tool {
+                    prev.content.extend(next.content);
+                    Ok(prev)
+                } else {
+                    Err((prev, next))
+                }
+            })
+            .collect()
+    }
+
+    /// Converts turns into messages in the OpenAI chat completions format.
+    ///
+    /// Fails if a turn contains messages which are invalid for its role, such as
+    /// a tool call within a user turn.
+    pub fn to_oai_messages(turns: &[Turn]) -> Result<Vec<OAIChatMessage>, CallErr> {
+        let mut msgs = Vec::with_capacity(turns.len());
+        for t in turns {
+            msgs.extend(t.clone().into_oai_msgs()?);
+        }
+        Ok(msgs)
+    }
+
     /// Converts our broad `Turn` type into the wire format expected by chat completions APIs.
-    pub(crate) fn into_oai_msgs(self) -> Vec<OAIChatMessage> {
+    // coalesce() merges through a Result<T, (T, T)>, which clippy flags as a large Err.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn into_oai_msgs(self) -> Result<Vec<OAIChatMessage>, CallErr> {
         use itertools::Itertools;
-        self.content
+        let msgs = self
+            .content
             .into_iter()
-            .map(|m| match self.role {
-                Role::User => OAIChatMessage::user(match m {
-                    Message::Text { text } => text,
-                    _ => unreachable!(),
-                }),
-                Role::System => OAIChatMessage::system(match m {
-                    Message::Text { text } => text,
-                    _ => unreachable!(),
-                }),
-                Role::Assistant => match m {
-                    Message::Text { text } => OAIChatMessage::assistant(text),
-                    // These will be combined to one msg during coalesce()
-                    Message::ToolCall {
-                        id,
-                        name,
-                        arguments,
-                    } => OAIChatMessage {
-                        role: Role::Assistant,
-                        content: None,
-                        tool_calls: vec![crate::data_model::OAIToolCall {
+            // Thinking can't be sent back to these APIs.
+            .filter(|m| {
+                !matches!(
+                    m,
+                    Message::Thinking { .. } | Message::RedactedThinking { .. }
+                )
+            })
+            .map(|m| {
+                Ok(match self.role {
+                    Role::User => OAIChatMessage::user(match m {
+                        Message::Text { text } => text.to_string(),
+                        m => return Err(misplaced(&self.role, &m)),
+                    }),
+                    Role::System => OAIChatMessage::system(match m {
+                        Message::Text { text } => text.to_string(),
+                        m => return Err(misplaced(&self.role, &m)),
+                    }),
+                    Role::Developer => OAIChatMessage::developer(match m {
+                        Message::Text { text } => text.to_string(),
+                        m => return Err(misplaced(&self.role, &m)),
+                    }),
+                    Role::Assistant => match m {
+                        Message::Text { text } => OAIChatMessage::assistant(text.to_string()),
+                        // These will be combined to one msg during coalesce()
+                        Message::ToolCall {
                             id,
-                            r#type: crate::data_model::ToolCallType::Function,
-                            function: crate::data_model::FunctionCall { name, arguments },
-                        }],
-                        tool_call_id: None,
-                        name: None,
+                            name,
+                            arguments,
+                        } => OAIChatMessage {
+                            role: Role::Assistant,
+                            content: None,
+                            tool_calls: vec![crate::data_model::OAIToolCall {
+                                id,
+                                r#type: crate::data_model::ToolCallType::Function,
+                                function: crate::data_model::FunctionCall { name, arguments },
+                            }],
+                            tool_call_id: None,
+                            name: None,
+                            reasoning: None,
+                        },
+                        m => return Err(misplaced(&self.role, &m)),
                     },
-                    _ => unreachable!(),
-                },
-                Role::Tool => match m {
-                    Message::ToolResult { id, result } => OAIChatMessage {
-                        tool_call_id: Some(id),
-                        ..OAIChatMessage::tool(result)
+                    Role::Tool => match m {
+                        Message::ToolResult { id, result } => OAIChatMessage {
+                            tool_call_id: Some(id),
+                            ..OAIChatMessage::tool(result.to_string())
+                        },
+                        m => return Err(misplaced(&self.role, &m)),
                     },
-                    _ => unreachable!(),
-                },
+                })
             })
+            .collect::<Result<Vec<_>, CallErr>>()?;
+
+        Ok(msgs
+            .into_iter()
             // Combine tool call msgs with earlier Assistant msgs
             // if there was one, as they are expected together.
             .coalesce(|mut prev, next| {
@@ -283,38 +620,127 @@ impl Turn {
                     Err((prev, next))
                 }
             })
-            .collect()
+            .collect())
+    }
+
+    /// Converts messages in the Anthropic messages API format into turns, such as
+    /// to load a saved Anthropic transcript.
+    ///
+    /// Tool results, which Anthropic sends in user messages, are split out into
+    /// [Role::Tool] turns.
+    ///
+    /// ```
+    /// # use mini_prompt::*;
+    /// let msgs: Vec<data_model::AnthropicMessage> = serde_json::from_str(r#"[
+    ///     {"role": "user", "content": [{"type": "text", "text": "What's the weather?"}]},
+    ///     {"role": "assistant", "content": [
+    ///         {"type": "tool_use", "id": "1", "name": "weather", "input": {}}
+    ///     ]},
+    ///     {"role": "user", "content": [
+    ///         {"type": "tool_result", "tool_use_id": "1", "content": "sunny"}
+    ///     ]}
+    /// ]"#).unwrap();
+    ///
+    /// let turns = Turn::from_anthropic_messages(&msgs);
+    /// assert_eq!(turns[2].role, Role::Tool);
+    /// assert_eq!(Turn::to_anthropic_messages(&turns).unwrap(), msgs);
+    /// ```
+    pub fn from_anthropic_messages(msgs: &[AnthropicMessage]) -> Vec<Turn> {
+        let mut turns: Vec<Turn> = Vec::with_capacity(msgs.len());
+        for msg in msgs {
+            for block in msg.content.iter().cloned() {
+                let role = match block {
+                    data_model::AnthropicCompletion::ToolResult { .. } => Role::Tool,
+                    _ => msg.role.clone(),
+                };
+                match turns.last_mut() {
+                    Some(t) if t.role == role => t.content.push(block.into()),
+                    _ => turns.push(Turn {
+                        role,
+                        content: vec![block.into()],
+                    }),
+                }
+            }
+        }
+        turns
+    }
+
+    /// Converts turns into messages in the Anthropic messages API format.
+    ///
+    /// Fails if a turn contains messages which are invalid for its role, such as
+    /// a tool call within a user turn, or if tool call arguments are not valid JSON.
+    pub fn to_anthropic_messages(turns: &[Turn]) -> Result<Vec<AnthropicMessage>, CallErr> {
+        let mut msgs = Vec::with_capacity(turns.len());
+        for t in turns {
+            msgs.extend(t.clone().into_anthropic_msgs()?);
+        }
+        Ok(msgs)
     }
 
     /// Converts our broad `Turn` type into the wire format expected by Anthropic's messages API.
-    pub(crate) fn into_anthropic_msgs(self) -> Vec<AnthropicMessage> {
+    pub(crate) fn into_anthropic_msgs(self) -> Result<Vec<AnthropicMessage>, CallErr> {
         use itertools::Itertools;
-        self.content
+        let msgs = self
+            .content
             .into_iter()
-            .map(|m| match self.role {
-                Role::User | Role::System => match m {
-                    Message::Text { text } => AnthropicMessage::user_text(text),
-                    _ => unreachable!(),
-                },
-                Role::Assistant => match m {
-                    Message::Text { text } => AnthropicMessage::assistant_text(text),
-                    // These will be combined to one msg during coalesce()
-                    Message::ToolCall {
-                        id,
-                        name,
-                        arguments,
-                    } => AnthropicMessage::tool_use(
-                        id,
-                        name,
-                        serde_json::from_str(&arguments).unwrap(),
-                    ),
-                    _ => unreachable!(),
-                },
-                Role::Tool => match m {
-                    Message::ToolResult { id, result } => AnthropicMessage::tool_result(id, result),
-                    _ => unreachable!(),
-                },
+            // Thinking from other providers has no signature, so can't be sent back.
+            .filter(|m| {
+                !matches!(
+                    m,
+                    Message::Thinking {
+                        signature: None,
+                        ..
+                    }
+                )
             })
+            .map(|m| {
+                Ok(match self.role {
+                    Role::User | Role::System | Role::Developer => match m {
+                        Message::Text { text } => AnthropicMessage::user_text(text.to_string()),
+                        m => return Err(misplaced(&self.role, &m)),
+                    },
+                    Role::Assistant => match m {
+                        Message::Text { text } => {
+                            AnthropicMessage::assistant_text(text.to_string())
+                        }
+                        // These will be combined to one msg during coalesce()
+                        Message::ToolCall {
+                            id,
+                            name,
+                            arguments,
+                        } => {
+                            let input = serde_json::from_str(&arguments).map_err(|e| {
+                                format!("invalid arguments for tool call '{}': {}", name, e)
+                            })?;
+                            AnthropicMessage::tool_use(id, name, input)
+                        }
+                        Message::Thinking { text, signature } => AnthropicMessage {
+                            role: Role::Assistant,
+                            content: vec![data_model::AnthropicCompletion::Thinking {
+                                thinking: text,
+                                signature: signature.unwrap_or_default(),
+                            }],
+                        },
+                        Message::RedactedThinking { data } => AnthropicMessage {
+                            role: Role::Assistant,
+                            content: vec![data_model::AnthropicCompletion::RedactedThinking {
+                                data,
+                            }],
+                        },
+                        m => return Err(misplaced(&self.role, &m)),
+                    },
+                    Role::Tool => match m {
+                        Message::ToolResult { id, result } => {
+                            AnthropicMessage::tool_result(id, result.to_string())
+                        }
+                        m => return Err(misplaced(&self.role, &m)),
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>, CallErr>>()?;
+
+        Ok(msgs
+            .into_iter()
             // Combine tool call msgs with earlier Assistant msgs
             // if there was one, as they are expected together.
             .coalesce(|mut prev, next| {
@@ -325,9 +751,22 @@ impl Turn {
                     Err((prev, next))
                 }
             })
-            .collect()
+            .collect())
     }
 }
+
+/// The error for a message which can't be sent in a turn with the given role.
+fn misplaced(role: &Role, m: &Message) -> CallErr {
+    let kind = match m {
+        Message::Text { .. } => "text",
+        Message::ToolCall { .. } => "a tool call",
+        Message::ToolResult { .. } => "a tool result",
+        Message::Thinking { .. } => "thinking",
+        Message::RedactedThinking { .. } => "redacted thinking",
+    };
+    format!("a {:?} turn can't contain {}", role, kind).into()
+}
+
 /// The context of data in or out of the model.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "snake_case")]
@@ -337,6 +776,10 @@ pub enum Role {
     User,
     /// Data in this turn represents the system prompt.
     System,
+    /// Data in this turn represents instructions from the developer, which take
+    /// precedence over the user's. Sent with OpenAI's developer role to OpenAI
+    /// reasoning models, and as a system prompt to other models.
+    Developer,
     /// Data in this turn was generated by the LLM.
     Assistant,
     /// Data in this turn represents the result of a tool call.
@@ -346,10 +789,13 @@ pub enum Role {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 /// A unit of data written or read from the model.
+///
+/// Text and tool results are reference counted, so the history of a conversation can
+/// be cloned for each call, such as in a tools loop, without copying the text.
 pub enum Message {
     Text {
         /// Text tokens fed into or read from the model.
-        text: String,
+        text: Arc<str>,
     },
     ToolCall {
         /// The identifier the model is using for this tool call.
@@ -365,13 +811,23 @@ pub enum Message {
         id: String,
         /// A (usually JSON-formatted) representation of the result of the
         /// function call.
-        result: String,
+        result: Arc<str>,
+    },
+    /// The reasoning of the model before it responded, from models which think.
+    Thinking {
+        text: String,
+        /// Proves to the provider that it produced the thinking, which must be sent
+        /// back unchanged in later turns, such as during tool use.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
     },
+    /// Thinking which was encrypted by the provider, to be sent back unchanged.
+    RedactedThinking { data: String },
 }
 
 impl Message {
     /// Creates a new `Text` message.
-    pub fn text<T: Into<String>>(text: T) -> Self {
+    pub fn text<T: Into<Arc<str>>>(text: T) -> Self {
         Self::Text { text: text.into() }
     }
 }
@@ -390,7 +846,7 @@ impl From<data_model::AnthropicCompletion> for Message {
     fn from(msg: data_model::AnthropicCompletion) -> Self {
         use data_model::AnthropicCompletion;
         match msg {
-            AnthropicCompletion::Text { text } => Message::Text { text },
+            AnthropicCompletion::Text { text } => Message::text(text),
             AnthropicCompletion::ToolUse { id, name, input } => Message::ToolCall {
                 id,
                 name,
@@ -401,8 +857,16 @@ impl From<data_model::AnthropicCompletion> for Message {
                 content,
             } => Message::ToolResult {
                 id: tool_use_id,
-                result: content,
+                result: content.into(),
             },
+            AnthropicCompletion::Thinking {
+                thinking,
+                signature,
+            } => Message::Thinking {
+                text: thinking,
+                signature: Some(signature),
+            },
+            AnthropicCompletion::RedactedThinking { data } => Message::RedactedThinking { data },
         }
     }
 }
@@ -412,7 +876,7 @@ impl From<data_model::AnthropicCompletion> for Message {
 #[serde(rename_all = "snake_case")]
 pub enum FinishReason {
     #[default]
-    #[serde(alias = "end_turn")]
+    #[serde(alias = "end_turn", alias = "stop_sequence")]
     Stop,
     #[serde(alias = "tool_use")]
     ToolCalls,
@@ -422,8 +886,18 @@ pub enum FinishReason {
     ContentFilter,
 }
 
+/// The log probability of a generated token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+    /// The most likely tokens at this position with their log probabilities, most
+    /// likely first.
+    pub top: Vec<(String, f32)>,
+}
+
 /// The response from the model for generating a single turn.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CallResp {
     /// A provider-specific unique ID for this model call.
     pub id: String,
@@ -434,17 +908,77 @@ pub struct CallResp {
     pub finish_reason: FinishReason,
     /// The tokens the model generated.
     pub content: Turn,
+
+    /// The registered prompt which produced this response, if set on the [CallBase].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<prompt::PromptRef>,
+
+    /// The log probability of each generated token, if requested with
+    /// [CallBase::top_logprobs] and supported by the caller.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Vec<TokenLogprob>>,
+
+    /// Identifies the backend configuration which served the call, if reported by the
+    /// provider. Responses to calls with the same [CallBase::seed] are only expected to
+    /// be the same while this is unchanged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
+
+    /// The response body as sent by the provider, for fields this type doesn't model.
+    /// Only kept if the `raw-response` feature is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+impl CallResp {
+    /// Prepends the [prefill](CallBase::prefill) the model continued to its first text
+    /// message, so the content is the whole turn.
+    #[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+    pub(crate) fn with_prefill(mut self, prefill: Option<String>) -> Self {
+        let Some(prefill) = prefill else {
+            return self;
+        };
+        let content = &mut self.content.content;
+        match content.iter_mut().find_map(|m| match m {
+            Message::Text { text } => Some(text),
+            _ => None,
+        }) {
+            Some(text) => *text = (prefill + text).into(),
+            None => {
+                let at = content
+                    .iter()
+                    .take_while(|m| {
+                        matches!(
+                            m,
+                            Message::Thinking { .. } | Message::RedactedThinking { .. }
+                        )
+                    })
+                    .count();
+                content.insert(at, Message::text(prefill));
+            }
+        }
+        self
+    }
 }
 
 impl From<data_model::OAICompletionsResponse> for CallResp {
-    fn from(resp: data_model::OAICompletionsResponse) -> Self {
+    fn from(mut resp: data_model::OAICompletionsResponse) -> Self {
         let finish_reason = resp.choices[0].finish_reason.clone();
+        let logprobs = resp.choices[0]
+            .logprobs
+            .take()
+            .and_then(|l| l.content)
+            .map(|c| c.into_iter().map(|t| t.into()).collect());
 
         Self {
             id: resp.id,
             model: resp.model,
             finish_reason,
             content: resp.choices[0].message.clone().into(),
+            prompt: None,
+            logprobs,
+            system_fingerprint: resp.system_fingerprint,
+            raw: None,
         }
     }
 }
@@ -459,14 +993,189 @@ impl From<data_model::AnthropicMsgResponse> for CallResp {
                 role: Role::Assistant,
                 content: resp.content.into_iter().map(|m| m.into()).collect(),
             },
+            prompt: None,
+            logprobs: None,
+            system_fingerprint: None,
+            raw: None,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+    use crate::data_model::{AnthropicToolChoice, OAIToolChoice};
 
     #[test]
     fn smoke() {}
+
+    #[test]
+    fn serde_round_trip() {
+        let turns = vec![
+            Turn {
+                role: Role::User,
+                content: vec![Message::text("flubb please")],
+            },
+            Turn {
+                role: Role::Assistant,
+                content: vec![Message::ToolCall {
+                    id: "1".into(),
+                    name: "flubb".into(),
+                    arguments: "{}".into(),
+                }],
+            },
+        ];
+        let json = serde_json::to_string(&turns).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Turn>>(&json).unwrap(), turns);
+
+        let params = CallBase {
+            system: "be nice".into(),
+            tools: vec![ToolInfo::new("flubb", "Flubbs.", None)],
+            tool_choice: ToolChoice::Tool("flubb".into()),
+            temperature: Some(0.5),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&params).unwrap();
+        assert_eq!(serde_json::from_str::<CallBase>(&json).unwrap(), params);
+        assert_eq!(
+            serde_json::from_str::<CallBase>(r#"{"system": "hi"}"#).unwrap(),
+            CallBase {
+                system: "hi".into(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn shared_text() {
+        let turns = vec![Turn {
+            role: Role::Tool,
+            content: vec![Message::ToolResult {
+                id: "1".into(),
+                result: "x".repeat(1 << 20).into(),
+            }],
+        }];
+        let copy = turns.clone();
+        match (&turns[0].content[0], &copy[0].content[0]) {
+            (Message::ToolResult { result: a, .. }, Message::ToolResult { result: b, .. }) => {
+                assert!(Arc::ptr_eq(a, b))
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            serde_json::to_value(Message::text("hi")).unwrap(),
+            serde_json::json!({"type": "text", "text": "hi"})
+        );
+    }
+
+    #[cfg(any(feature = "openai", feature = "anthropic", feature = "openrouter"))]
+    #[test]
+    fn prefill() {
+        let prefill = Some("{\"colours\":".to_string());
+        let resp = crate::testing::Scripted::text(" [\"red\"]}").with_prefill(prefill);
+        assert_eq!(resp.content.text(), "{\"colours\": [\"red\"]}");
+        let resp = crate::testing::Scripted::text("unchanged").with_prefill(None);
+        assert_eq!(resp.content.text(), "unchanged");
+    }
+
+    #[test]
+    fn oai_messages_round_trip() {
+        let turns = vec![
+            Turn {
+                role: Role::Assistant,
+                content: vec![
+                    Message::text("Checking both."),
+                    Message::ToolCall {
+                        id: "1".into(),
+                        name: "a".into(),
+                        arguments: "{}".into(),
+                    },
+                    Message::ToolCall {
+                        id: "2".into(),
+                        name: "b".into(),
+                        arguments: "{}".into(),
+                    },
+                ],
+            },
+            Turn {
+                role: Role::Tool,
+                content: vec![
+                    Message::ToolResult {
+                        id: "1".into(),
+                        result: "x".into(),
+                    },
+                    Message::ToolResult {
+                        id: "2".into(),
+                        result: "y".into(),
+                    },
+                ],
+            },
+        ];
+        let msgs = Turn::to_oai_messages(&turns).unwrap();
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(Turn::from_oai_messages(&msgs), turns);
+
+        let misplaced = Turn {
+            role: Role::User,
+            ..turns[1].clone()
+        };
+        assert!(Turn::to_oai_messages(&[misplaced]).is_err());
+    }
+
+    #[test]
+    fn anthropic_messages_invalid() {
+        let call = Message::ToolCall {
+            id: "1".into(),
+            name: "a".into(),
+            arguments: "{not json".into(),
+        };
+        for role in [Role::Assistant, Role::User] {
+            let turns = [Turn {
+                role,
+                content: vec![call.clone()],
+            }];
+            assert!(Turn::to_anthropic_messages(&turns).is_err());
+        }
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn tool_info_from_schema() {
+        #[allow(dead_code)]
+        #[derive(schemars::JsonSchema)]
+        struct Args {
+            /// The path to read.
+            path: String,
+            limit: Option<u32>,
+        }
+
+        let t = ToolInfo::from_schema::<Args>("read", "Reads a file");
+        assert_eq!(t.parameters["type"], "object");
+        assert_eq!(t.parameters["required"], serde_json::json!(["path"]));
+        assert_eq!(
+            t.parameters["properties"]["path"]["description"],
+            "The path to read."
+        );
+        assert!(t.parameters.get("$schema").is_none());
+    }
+
+    #[test]
+    fn tool_choice_wire_format() {
+        let oai = |c| serde_json::to_value(OAIToolChoice::from(c)).unwrap();
+        assert_eq!(oai(ToolChoice::Required), serde_json::json!("required"));
+        assert_eq!(
+            oai(ToolChoice::Tool("plan".into())),
+            serde_json::json!({"type": "function", "function": {"name": "plan"}})
+        );
+
+        let anthropic = |c| serde_json::to_value(AnthropicToolChoice::from(c)).unwrap();
+        assert_eq!(
+            anthropic(ToolChoice::Required),
+            serde_json::json!({"type": "any", "disable_parallel_tool_use": false})
+        );
+        assert_eq!(
+            anthropic(ToolChoice::Tool("plan".into())),
+            serde_json::json!({"type": "tool", "name": "plan", "disable_parallel_tool_use": false})
+        );
+    }
 }
@@ -0,0 +1,629 @@
+//! Support for the Model Context Protocol (MCP).
+//!
+//! [McpClient] connects to an MCP server, and can expose the server's tools
+//! for use in a [ToolsSession](crate::ToolsSession):
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "anthropic")] {
+//! # use mini_prompt::*;
+//! # use mini_prompt::mcp::McpClient;
+//! let client = McpClient::stdio(std::process::Command::new("my-mcp-server")).unwrap();
+//! let session = ToolsSession::new(
+//!     callers::Anthropic::<models::ClaudeHaiku35>::default(),
+//!     client.into_tools().unwrap(),
+//! );
+//! # }
+//! ```
+//!
+//! Conversely, [McpServer] serves tools defined with this crate to other MCP hosts.
+
+use crate::tools::RawToolFunc;
+use crate::{CallErr, ModelCaller, ToolInfo, ToolsSession};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+/// The MCP protocol revision this crate speaks.
+pub const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// A means of exchanging JSON-RPC messages with an MCP server.
+pub trait Transport: Send {
+    /// Sends a request, returning the response with the same ID.
+    fn request(&mut self, msg: Value) -> Result<Value, CallErr>;
+    /// Sends a notification, which has no response.
+    fn notify(&mut self, msg: Value) -> Result<(), CallErr>;
+}
+
+/// Talks to an MCP server running as a child process, over its stdin & stdout.
+pub struct StdioTransport {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl StdioTransport {
+    /// Spawns the given command as an MCP server.
+    pub fn spawn(mut cmd: Command) -> Result<Self, CallErr> {
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| CallErr::Other(Box::new(e)))?;
+        let stdin = child.stdin.take().ok_or("mcp: no stdin on child")?;
+        let stdout = child.stdout.take().ok_or("mcp: no stdout on child")?;
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+
+    fn write(&mut self, msg: &Value) -> Result<(), CallErr> {
+        writeln!(self.stdin, "{}", msg)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| CallErr::Other(Box::new(e)))
+    }
+}
+
+impl Transport for StdioTransport {
+    fn request(&mut self, msg: Value) -> Result<Value, CallErr> {
+        self.write(&msg)?;
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let n = self
+                .stdout
+                .read_line(&mut line)
+                .map_err(|e| CallErr::Other(Box::new(e)))?;
+            if n == 0 {
+                return Err("mcp: server closed stdout".into());
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let resp: Value = serde_json::from_str(&line)
+                .map_err(|e| format!("mcp: invalid message from server: {}", e))?;
+            match (resp.get("id"), resp.get("method")) {
+                // A response to our request.
+                (Some(id), None) if id == &msg["id"] => return Ok(resp),
+                // A request from the server: we only support pings.
+                (Some(id), Some(method)) => {
+                    let reply = if method == "ping" {
+                        json!({"jsonrpc": "2.0", "id": id, "result": {}})
+                    } else {
+                        json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32601, "message": "method not found"}})
+                    };
+                    self.write(&reply)?;
+                }
+                // Notifications, or responses to something else.
+                _ => {}
+            }
+        }
+    }
+
+    fn notify(&mut self, msg: Value) -> Result<(), CallErr> {
+        self.write(&msg)
+    }
+}
+
+impl Drop for StdioTransport {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Talks to an MCP server over the streamable HTTP transport, where responses
+/// may be plain JSON or a stream of server-sent events.
+pub struct HttpTransport {
+    url: String,
+    session_id: Option<String>,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpTransport {
+    /// Creates a transport which talks to the MCP endpoint at the given URL.
+    pub fn new<S: Into<String>>(url: S) -> Self {
+        Self {
+            url: url.into(),
+            session_id: None,
+            headers: vec![],
+        }
+    }
+
+    /// Adds a header to send with every request, such as `Authorization`.
+    pub fn with_header<S: Into<String>>(mut self, name: S, value: S) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    fn post(&mut self, msg: &Value) -> Result<(String, String), CallErr> {
+        // The blocking client cannot be used from within an async runtime, so
+        // requests are made from a thread of their own.
+        let (session_id, content_type, body) = std::thread::scope(|s| {
+            s.spawn(|| -> Result<_, reqwest::Error> {
+                let mut req = reqwest::blocking::Client::new()
+                    .post(&self.url)
+                    .header("accept", "application/json, text/event-stream")
+                    .json(msg);
+                if let Some(id) = &self.session_id {
+                    req = req.header("mcp-session-id", id);
+                }
+                for (k, v) in self.headers.iter() {
+                    req = req.header(k, v);
+                }
+
+                let resp = req.send()?.error_for_status()?;
+                let header = |name| {
+                    resp.headers()
+                        .get(name)
+                        .and_then(|v| v.to_str().ok())
+                        .map(|v| v.to_string())
+                };
+                let session_id = header("mcp-session-id");
+                let content_type = header("content-type").unwrap_or_default();
+                Ok((session_id, content_type, resp.text()?))
+            })
+            .join()
+            .expect("mcp http thread panicked")
+        })?;
+
+        if session_id.is_some() {
+            self.session_id = session_id;
+        }
+        Ok((content_type, body))
+    }
+}
+
+/// Returns the data of each event in a `text/event-stream` body.
+fn sse_data(body: &str) -> Vec<String> {
+    let mut events = vec![];
+    let mut data: Option<String> = None;
+    for line in body.lines() {
+        if line.is_empty() {
+            events.extend(data.take());
+        } else if let Some(d) = line.strip_prefix("data:") {
+            let d = d.strip_prefix(' ').unwrap_or(d);
+            match data.as_mut() {
+                Some(existing) => {
+                    existing.push('\n');
+                    existing.push_str(d);
+                }
+                None => data = Some(d.to_string()),
+            }
+        }
+    }
+    events.extend(data);
+    events
+}
+
+impl Transport for HttpTransport {
+    fn request(&mut self, msg: Value) -> Result<Value, CallErr> {
+        let (content_type, body) = self.post(&msg)?;
+
+        let candidates = if content_type.starts_with("text/event-stream") {
+            sse_data(&body)
+        } else {
+            vec![body]
+        };
+        for c in candidates {
+            let resp: Value = serde_json::from_str(&c)
+                .map_err(|e| format!("mcp: invalid message from server: {}", e))?;
+            if resp.get("method").is_none() && resp.get("id") == msg.get("id") {
+                return Ok(resp);
+            }
+        }
+        Err("mcp: server did not respond to request".into())
+    }
+
+    fn notify(&mut self, msg: Value) -> Result<(), CallErr> {
+        self.post(&msg).map(|_| ())
+    }
+}
+
+/// A connection to an MCP server.
+pub struct McpClient {
+    transport: Box<dyn Transport>,
+    next_id: u64,
+    /// The `serverInfo` reported by the server during initialization.
+    pub server_info: Value,
+}
+
+impl McpClient {
+    /// Initializes a session with an MCP server over the given transport.
+    pub fn new<T: Transport + 'static>(transport: T) -> Result<Self, CallErr> {
+        let mut client = Self {
+            transport: Box::new(transport),
+            next_id: 0,
+            server_info: Value::Null,
+        };
+
+        let init = client.request(
+            "initialize",
+            json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {},
+                "clientInfo": {"name": "mini-prompt", "version": env!("CARGO_PKG_VERSION")},
+            }),
+        )?;
+        client.server_info = init["serverInfo"].clone();
+        client.transport.notify(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized",
+        }))?;
+
+        Ok(client)
+    }
+
+    /// Spawns and connects to an MCP server which communicates over stdio.
+    pub fn stdio(cmd: Command) -> Result<Self, CallErr> {
+        Self::new(StdioTransport::spawn(cmd)?)
+    }
+
+    /// Connects to an MCP server at the given URL, using the streamable HTTP transport.
+    pub fn http<S: Into<String>>(url: S) -> Result<Self, CallErr> {
+        Self::new(HttpTransport::new(url))
+    }
+
+    /// Performs a JSON-RPC request, returning its result.
+    pub fn request(&mut self, method: &str, params: Value) -> Result<Value, CallErr> {
+        self.next_id += 1;
+        let mut resp = self.transport.request(json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+        }))?;
+
+        if let Some(err) = resp.get("error") {
+            return Err(format!("mcp: {} failed: {}", method, err).into());
+        }
+        Ok(resp["result"].take())
+    }
+
+    /// Lists the tools provided by the server.
+    pub fn list_tools(&mut self) -> Result<Vec<ToolInfo>, CallErr> {
+        let mut out = vec![];
+        let mut cursor: Option<Value> = None;
+        loop {
+            let params = match cursor {
+                Some(c) => json!({ "cursor": c }),
+                None => json!({}),
+            };
+            let mut res = self.request("tools/list", params)?;
+
+            if let Value::Array(tools) = res["tools"].take() {
+                for t in tools {
+                    out.push(ToolInfo::new(
+                        t["name"].as_str().unwrap_or_default(),
+                        t["description"].as_str().unwrap_or_default(),
+                        Some(t["inputSchema"].clone()).filter(|s| !s.is_null()),
+                    ));
+                }
+            }
+
+            cursor = Some(res["nextCursor"].take()).filter(|c| !c.is_null());
+            if cursor.is_none() {
+                return Ok(out);
+            }
+        }
+    }
+
+    /// Invokes a tool on the server, returning its result as text.
+    ///
+    /// If the server reports the tool failed, a JSON object describing the
+    /// error is returned instead, so it can be handed to a model.
+    pub fn call_tool(&mut self, name: &str, arguments: Value) -> Result<String, CallErr> {
+        let res = self.request(
+            "tools/call",
+            json!({ "name": name, "arguments": arguments }),
+        )?;
+        Ok(tool_result_text(&res))
+    }
+
+    /// Lists the server's tools, returning them with handlers which invoke them
+    /// on the server, ready for use in a [ToolsSession](crate::ToolsSession).
+    pub fn into_tools(mut self) -> Result<Vec<(ToolInfo, RawToolFunc)>, CallErr> {
+        let tools = self.list_tools()?;
+        let client = Arc::new(Mutex::new(self));
+
+        Ok(tools
+            .into_iter()
+            .map(|ti| {
+                let client = client.clone();
+                let name = ti.name.clone();
+                let f: RawToolFunc = Box::new(move |args: String| {
+                    let args = match serde_json_lenient::from_str(&args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            return json!({"status": "error", "error": format!("invalid arguments: {}", e)})
+                                .to_string()
+                        }
+                    };
+                    match client.lock().unwrap().call_tool(&name, args) {
+                        Ok(s) => s,
+                        Err(e) => json!({"status": "error", "error": format!("{:?}", e)}).to_string(),
+                    }
+                });
+                (ti, f)
+            })
+            .collect())
+    }
+}
+
+/// Flattens the content of a `tools/call` result into text.
+fn tool_result_text(res: &Value) -> String {
+    let text = res["content"]
+        .as_array()
+        .map(|content| {
+            content
+                .iter()
+                .map(|c| match c["text"].as_str() {
+                    Some(t) if c["type"] == "text" => t.to_string(),
+                    _ => c.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default();
+
+    if res["isError"] == true {
+        json!({"status": "error", "error": text}).to_string()
+    } else {
+        text
+    }
+}
+
+/// Serves a set of tools to MCP hosts.
+///
+/// ```rust,no_run
+/// # use mini_prompt::*;
+/// # use mini_prompt::mcp::McpServer;
+/// let mut server = McpServer::new(vec![(
+///     ToolInfo::new("flubb", "Performs the flubb action.", None),
+///     Box::new(move |_args| "flubbed".to_string()),
+/// )]);
+/// server.serve_stdio().unwrap();
+/// ```
+pub struct McpServer {
+    tools: Vec<(ToolInfo, RawToolFunc)>,
+    name: String,
+}
+
+impl McpServer {
+    /// Constructs a server which serves the given tools.
+    pub fn new(tools: Vec<(ToolInfo, RawToolFunc)>) -> Self {
+        Self {
+            tools,
+            name: "mini-prompt".into(),
+        }
+    }
+
+    /// Sets the server name reported to clients.
+    pub fn with_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Serves requests over stdin & stdout until stdin is closed.
+    pub fn serve_stdio(&mut self) -> io::Result<()> {
+        self.serve(io::stdin().lock(), io::stdout().lock())
+    }
+
+    /// Serves newline-delimited JSON-RPC requests read from `r`, writing responses
+    /// to `w`, until `r` is exhausted.
+    pub fn serve<R: BufRead, W: Write>(&mut self, r: R, mut w: W) -> io::Result<()> {
+        for line in r.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let resp = match serde_json::from_str::<Value>(&line) {
+                Ok(req) => match req.get("id") {
+                    Some(id) => {
+                        let method = req["method"].as_str().unwrap_or_default();
+                        match self.handle(method, &req["params"]) {
+                            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                            Err((code, message)) => json!({
+                                "jsonrpc": "2.0",
+                                "id": id,
+                                "error": {"code": code, "message": message},
+                            }),
+                        }
+                    }
+                    // Notifications need no response.
+                    None => continue,
+                },
+                Err(e) => json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": -32700, "message": e.to_string()},
+                }),
+            };
+
+            writeln!(w, "{}", resp)?;
+            w.flush()?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {"tools": {}},
+                "serverInfo": {"name": self.name, "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({
+                "tools": self
+                    .tools
+                    .iter()
+                    .map(|(ti, _)| json!({
+                        "name": ti.name,
+                        "description": ti.description,
+                        "inputSchema": ti.parameters,
+                    }))
+                    .collect::<Vec<_>>(),
+            })),
+            "tools/call" => {
+                let name = params["name"].as_str().unwrap_or_default();
+                let args = match &params["arguments"] {
+                    Value::Null => "{}".to_string(),
+                    v => v.to_string(),
+                };
+
+                match self.tools.iter_mut().find(|(ti, _)| ti.name == name) {
+                    Some((_, f)) => Ok(json!({
+                        "content": [{"type": "text", "text": f(args)}],
+                    })),
+                    None => Ok(json!({
+                        "content": [{"type": "text", "text": format!("no such tool: {}", name)}],
+                        "isError": true,
+                    })),
+                }
+            }
+            _ => Err((-32601, format!("method not found: {}", method))),
+        }
+    }
+}
+
+/// Serves the tools the session currently offers. Calls go through the session, so
+/// its budgets, observers, and injection screen apply.
+impl<B: ModelCaller + 'static, C: Send + 'static> From<ToolsSession<B, C>> for McpServer {
+    fn from(session: ToolsSession<B, C>) -> Self {
+        Self::new(session.into_offered_tools())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sse() {
+        assert_eq!(
+            sse_data("event: message\ndata: {\"a\":\ndata: 1}\n\ndata:2\n"),
+            vec!["{\"a\":\n1}".to_string(), "2".to_string()]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stdio_round_trip() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(
+            r#"
+            read l; echo '{"jsonrpc":"2.0","id":1,"result":{"serverInfo":{"name":"fake"}}}'
+            read l
+            read l; echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"echo","description":"Echoes.","inputSchema":{"type":"object"}}]}}'
+            read l; echo '{"jsonrpc":"2.0","method":"notifications/message","params":{}}'
+            echo '{"jsonrpc":"2.0","id":3,"result":{"content":[{"type":"text","text":"hi"}]}}'
+            "#,
+        );
+
+        let client = McpClient::stdio(cmd).unwrap();
+        assert_eq!(client.server_info["name"], "fake");
+
+        let mut tools = client.into_tools().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(
+            tools[0].0,
+            ToolInfo::new("echo", "Echoes.", Some(json!({"type": "object"})))
+        );
+        assert_eq!((tools[0].1)("{}".into()), "hi");
+    }
+
+    #[test]
+    fn server_round_trip() {
+        let mut server = McpServer::new(vec![(
+            ToolInfo::new("shout", "Shouts.", None),
+            Box::new(|args| args.to_uppercase()),
+        )]);
+
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"shout","arguments":{"a":"b"}}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"nope"}"#,
+        ]
+        .join("\n");
+        let mut out = vec![];
+        server.serve(input.as_bytes(), &mut out).unwrap();
+
+        let out: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(out.len(), 4);
+        assert_eq!(out[0]["result"]["capabilities"], json!({"tools": {}}));
+        assert_eq!(out[1]["result"]["tools"][0]["name"], "shout");
+        assert_eq!(tool_result_text(&out[2]["result"]), r#"{"A":"B"}"#);
+        assert_eq!(out[3]["error"]["code"], -32601);
+    }
+
+    #[test]
+    fn serve_session_tools() {
+        let mut session = ToolsSession::new(
+            crate::testing::Scripted::new(vec![]),
+            vec![
+                (
+                    ToolInfo::new("flubb", "Performs the flubb action.", None),
+                    Box::new(|_args| "flubbed".to_string()),
+                ),
+                (
+                    ToolInfo::new("deploy", "Deploys.", None),
+                    Box::new(|_args| "deployed".to_string()),
+                ),
+            ],
+        )
+        .with_budget("flubb", 1);
+        session.set_enabled("deploy", false);
+        let mut server = McpServer::from(session);
+
+        let call = r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"flubb"}}"#;
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#,
+            call,
+            call,
+        ]
+        .join("\n");
+        let mut out = vec![];
+        server.serve(input.as_bytes(), &mut out).unwrap();
+
+        let out: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let tools = out[0]["result"]["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0]["name"], "flubb");
+        assert_eq!(tool_result_text(&out[1]["result"]), "flubbed");
+        assert!(tool_result_text(&out[2]["result"]).contains("refused"));
+    }
+
+    #[test]
+    fn tool_result_flattening() {
+        assert_eq!(
+            tool_result_text(&json!({"content": [
+                {"type": "text", "text": "one"},
+                {"type": "text", "text": "two"},
+            ]})),
+            "one\ntwo"
+        );
+        assert_eq!(
+            tool_result_text(
+                &json!({"content": [{"type": "text", "text": "boom"}], "isError": true})
+            ),
+            r#"{"status":"error","error":"boom"}"#
+        );
+    }
+}
@@ -3,11 +3,17 @@
 use crate::OAIChatMessage;
 
 /// Some specific LLM.
-pub trait Model: Send + Default {
+pub trait Model: Send + Sync + Default {
     /// Takes a 'system' prompt, formatting it into a message to be used in a model call.
     ///
     /// This is only needed because some models don't understand the system role.
     fn make_prompt(&self, prompt: String) -> OAIChatMessage;
+
+    /// Returns the name of the model, which defaults to its type name, such as
+    /// `mini_prompt::models::Gemma27B3`.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
 
 /// An LLM which can be called via Openrouter.
@@ -24,6 +30,58 @@ pub trait AnthropicModel: Model {
 /// An LLM which can be called via the OpenAI public API.
 pub trait OpenAIModel: Model {
     const MODEL_STR: &'static str;
+    /// Whether this is a reasoning model, such as o3. These take a limit of
+    /// `max_completion_tokens` rather than `max_tokens`, and don't accept a
+    /// temperature.
+    const REASONING: bool = false;
+}
+
+/// An embedding model which can be called via the OpenAI public API, see
+/// [crate::embed::Openai].
+pub trait OpenAIEmbeddingModel: Send + Sync + Default {
+    const MODEL_STR: &'static str;
+}
+
+/// An embedding model which can be called via the Cohere embed API, see
+/// [crate::embed::Cohere].
+pub trait CohereEmbeddingModel: Send + Sync + Default {
+    const MODEL_STR: &'static str;
+}
+
+/// An embedding model which can be called via the Voyage AI embeddings API, see
+/// [crate::embed::Voyage].
+pub trait VoyageEmbeddingModel: Send + Sync + Default {
+    const MODEL_STR: &'static str;
+}
+
+/// A reranking model which can be called via the Cohere rerank API, see
+/// [crate::rerank::Cohere].
+pub trait CohereRerankModel: Send + Sync + Default {
+    const MODEL_STR: &'static str;
+}
+
+/// A reranking model which can be called via the Voyage AI rerank API, see
+/// [crate::rerank::Voyage].
+pub trait VoyageRerankModel: Send + Sync + Default {
+    const MODEL_STR: &'static str;
+}
+
+/// Stands in for the model of a caller whose type has been erased, see
+/// [crate::callers::DynModelCaller]. It has the name of the original model, and
+/// prompts use the system role.
+#[derive(Default, Debug, Clone)]
+pub struct ErasedModel {
+    pub(crate) name: &'static str,
+}
+
+impl Model for ErasedModel {
+    fn make_prompt(&self, prompt: String) -> OAIChatMessage {
+        OAIChatMessage::system(prompt)
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
 }
 
 /// The Gemma3 27b LLM.
@@ -31,7 +89,7 @@ pub trait OpenAIModel: Model {
 pub struct Gemma27B3;
 
 impl OpenrouterModel for Gemma27B3 {
-    const MODEL_STR: &'static str = &"google/gemma-3-27b-it";
+    const MODEL_STR: &'static str = "google/gemma-3-27b-it";
     const NO_SYS_PROMPT: bool = false;
 }
 
@@ -40,7 +98,7 @@ impl OpenrouterModel for Gemma27B3 {
 pub struct Qwen235B3;
 
 impl OpenrouterModel for Qwen235B3 {
-    const MODEL_STR: &'static str = &"qwen/qwen3-235b-a22b";
+    const MODEL_STR: &'static str = "qwen/qwen3-235b-a22b";
     const NO_SYS_PROMPT: bool = false;
 }
 
@@ -49,7 +107,7 @@ impl OpenrouterModel for Qwen235B3 {
 pub struct Phi4;
 
 impl OpenrouterModel for Phi4 {
-    const MODEL_STR: &'static str = &"microsoft/phi-4";
+    const MODEL_STR: &'static str = "microsoft/phi-4";
     const NO_SYS_PROMPT: bool = true;
 }
 
@@ -58,7 +116,7 @@ impl OpenrouterModel for Phi4 {
 pub struct Gemini2Flash;
 
 impl OpenrouterModel for Gemini2Flash {
-    const MODEL_STR: &'static str = &"google/gemini-2.0-flash-001";
+    const MODEL_STR: &'static str = "google/gemini-2.0-flash-001";
     const NO_SYS_PROMPT: bool = false;
 }
 
@@ -67,7 +125,7 @@ impl OpenrouterModel for Gemini2Flash {
 pub struct Gemini25Flash;
 
 impl OpenrouterModel for Gemini25Flash {
-    const MODEL_STR: &'static str = &"google/gemini-2.5-flash-preview-05-20";
+    const MODEL_STR: &'static str = "google/gemini-2.5-flash-preview-05-20";
     const NO_SYS_PROMPT: bool = false;
 }
 
@@ -76,7 +134,7 @@ impl OpenrouterModel for Gemini25Flash {
 pub struct DevstralSmall;
 
 impl OpenrouterModel for DevstralSmall {
-    const MODEL_STR: &'static str = &"mistralai/devstral-small";
+    const MODEL_STR: &'static str = "mistralai/devstral-small";
     const NO_SYS_PROMPT: bool = false;
 }
 
@@ -85,12 +143,12 @@ impl OpenrouterModel for DevstralSmall {
 pub struct GPT4oMini;
 
 impl OpenrouterModel for GPT4oMini {
-    const MODEL_STR: &'static str = &"openai/gpt-4o-mini";
+    const MODEL_STR: &'static str = "openai/gpt-4o-mini";
     const NO_SYS_PROMPT: bool = false;
 }
 
 impl OpenAIModel for GPT4oMini {
-    const MODEL_STR: &'static str = &"gpt-4o-mini";
+    const MODEL_STR: &'static str = "gpt-4o-mini";
 }
 
 /// OpenAI's GPT-4.1 mini model.
@@ -98,7 +156,7 @@ impl OpenAIModel for GPT4oMini {
 pub struct GPT41Mini;
 
 impl OpenAIModel for GPT41Mini {
-    const MODEL_STR: &'static str = &"gpt-4.1-mini";
+    const MODEL_STR: &'static str = "gpt-4.1-mini";
 }
 
 impl Model for GPT41Mini {
@@ -107,12 +165,57 @@ impl Model for GPT41Mini {
     }
 }
 
+/// OpenAI's o3 reasoning model.
+#[derive(Default, Debug, Clone)]
+pub struct O3;
+
+impl OpenAIModel for O3 {
+    const MODEL_STR: &'static str = "o3";
+    const REASONING: bool = true;
+}
+
+impl Model for O3 {
+    fn make_prompt(&self, prompt: String) -> OAIChatMessage {
+        OAIChatMessage::developer(prompt)
+    }
+}
+
+/// OpenAI's o3-mini reasoning model.
+#[derive(Default, Debug, Clone)]
+pub struct O3Mini;
+
+impl OpenAIModel for O3Mini {
+    const MODEL_STR: &'static str = "o3-mini";
+    const REASONING: bool = true;
+}
+
+impl Model for O3Mini {
+    fn make_prompt(&self, prompt: String) -> OAIChatMessage {
+        OAIChatMessage::developer(prompt)
+    }
+}
+
+/// OpenAI's o4-mini reasoning model.
+#[derive(Default, Debug, Clone)]
+pub struct O4Mini;
+
+impl OpenAIModel for O4Mini {
+    const MODEL_STR: &'static str = "o4-mini";
+    const REASONING: bool = true;
+}
+
+impl Model for O4Mini {
+    fn make_prompt(&self, prompt: String) -> OAIChatMessage {
+        OAIChatMessage::developer(prompt)
+    }
+}
+
 /// Deepseek v3 0324
 #[derive(Default, Debug, Clone)]
 pub struct Deepseek0324v3;
 
 impl OpenrouterModel for Deepseek0324v3 {
-    const MODEL_STR: &'static str = &"deepseek/deepseek-chat-v3-0324";
+    const MODEL_STR: &'static str = "deepseek/deepseek-chat-v3-0324";
     const NO_SYS_PROMPT: bool = false;
 }
 
@@ -121,12 +224,12 @@ impl OpenrouterModel for Deepseek0324v3 {
 pub struct ClaudeSonnet4;
 
 impl OpenrouterModel for ClaudeSonnet4 {
-    const MODEL_STR: &'static str = &"anthropic/claude-sonnet-4";
+    const MODEL_STR: &'static str = "anthropic/claude-sonnet-4";
     const NO_SYS_PROMPT: bool = false;
 }
 
 impl AnthropicModel for ClaudeSonnet4 {
-    const MODEL_STR: &'static str = &"claude-sonnet-4-20250514";
+    const MODEL_STR: &'static str = "claude-sonnet-4-20250514";
 }
 
 /// Claude Haiku 3.5
@@ -134,12 +237,12 @@ impl AnthropicModel for ClaudeSonnet4 {
 pub struct ClaudeHaiku35;
 
 impl OpenrouterModel for ClaudeHaiku35 {
-    const MODEL_STR: &'static str = &"anthropic/claude-3.5-haiku";
+    const MODEL_STR: &'static str = "anthropic/claude-3.5-haiku";
     const NO_SYS_PROMPT: bool = false;
 }
 
 impl AnthropicModel for ClaudeHaiku35 {
-    const MODEL_STR: &'static str = &"claude-3-5-haiku-latest";
+    const MODEL_STR: &'static str = "claude-3-5-haiku-latest";
 }
 
 impl<X: OpenrouterModel> Model for X {
@@ -151,3 +254,91 @@ impl<X: OpenrouterModel> Model for X {
         }
     }
 }
+
+/// OpenAI's text-embedding-3-small model.
+#[derive(Default, Debug, Clone)]
+pub struct TextEmbedding3Small;
+
+impl OpenAIEmbeddingModel for TextEmbedding3Small {
+    const MODEL_STR: &'static str = "text-embedding-3-small";
+}
+
+/// OpenAI's text-embedding-3-large model.
+#[derive(Default, Debug, Clone)]
+pub struct TextEmbedding3Large;
+
+impl OpenAIEmbeddingModel for TextEmbedding3Large {
+    const MODEL_STR: &'static str = "text-embedding-3-large";
+}
+
+/// The nomic-embed-text model, typically served locally by Ollama.
+#[derive(Default, Debug, Clone)]
+pub struct NomicEmbedText;
+
+impl OpenAIEmbeddingModel for NomicEmbedText {
+    const MODEL_STR: &'static str = "nomic-embed-text";
+}
+
+/// The mxbai-embed-large model, typically served locally by Ollama.
+#[derive(Default, Debug, Clone)]
+pub struct MxbaiEmbedLarge;
+
+impl OpenAIEmbeddingModel for MxbaiEmbedLarge {
+    const MODEL_STR: &'static str = "mxbai-embed-large";
+}
+
+/// Cohere's embed-english-v3.0 model.
+#[derive(Default, Debug, Clone)]
+pub struct EmbedEnglishV3;
+
+impl CohereEmbeddingModel for EmbedEnglishV3 {
+    const MODEL_STR: &'static str = "embed-english-v3.0";
+}
+
+/// Cohere's embed-multilingual-v3.0 model.
+#[derive(Default, Debug, Clone)]
+pub struct EmbedMultilingualV3;
+
+impl CohereEmbeddingModel for EmbedMultilingualV3 {
+    const MODEL_STR: &'static str = "embed-multilingual-v3.0";
+}
+
+/// Voyage AI's voyage-3 model.
+#[derive(Default, Debug, Clone)]
+pub struct Voyage3;
+
+impl VoyageEmbeddingModel for Voyage3 {
+    const MODEL_STR: &'static str = "voyage-3";
+}
+
+/// Voyage AI's voyage-3-lite model.
+#[derive(Default, Debug, Clone)]
+pub struct Voyage3Lite;
+
+impl VoyageEmbeddingModel for Voyage3Lite {
+    const MODEL_STR: &'static str = "voyage-3-lite";
+}
+
+/// Cohere's rerank-v3.5 model.
+#[derive(Default, Debug, Clone)]
+pub struct RerankV35;
+
+impl CohereRerankModel for RerankV35 {
+    const MODEL_STR: &'static str = "rerank-v3.5";
+}
+
+/// Voyage AI's rerank-2 model.
+#[derive(Default, Debug, Clone)]
+pub struct Rerank2;
+
+impl VoyageRerankModel for Rerank2 {
+    const MODEL_STR: &'static str = "rerank-2";
+}
+
+/// Voyage AI's rerank-2-lite model.
+#[derive(Default, Debug, Clone)]
+pub struct Rerank2Lite;
+
+impl VoyageRerankModel for Rerank2Lite {
+    const MODEL_STR: &'static str = "rerank-2-lite";
+}
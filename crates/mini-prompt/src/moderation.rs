@@ -0,0 +1,266 @@
+//! Screening text with the OpenAI moderations API.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "openrouter")] {
+//! # use mini_prompt::*;
+//! use mini_prompt::moderation::{Moderator, Screened};
+//!
+//! let backend = Screened::new(
+//!     callers::Openrouter::<models::Gemma27B3>::default(),
+//!     Moderator::default(),
+//! );
+//! # tokio::task::spawn(async move {
+//! match backend.simple_call("Tell me a story.").await {
+//!     Err(CallErr::Moderated { categories }) => println!("flagged for {:?}", categories),
+//!     r => println!("{:?}", r),
+//! }
+//! # });
+//! # }
+//! ```
+
+use crate::callers::OPENAI_BASE_URL;
+use crate::config;
+use crate::credentials::{self, CredentialProvider};
+use crate::data_model::{OAIModerationRequest, OAIModerationResponse};
+use crate::models::Model;
+use crate::{CallBase, CallErr, CallResp, ModelCaller, Role, Turn};
+use reqwest::Client;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+const DEFAULT_MODEL: &str = "omni-moderation-latest";
+
+/// The moderation result for a single input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Moderation {
+    /// Whether the input was flagged in any category.
+    pub flagged: bool,
+    /// The categories the input was flagged in, such as `harassment`.
+    pub categories: Vec<String>,
+    /// The score of the input in every category, from 0 to 1.
+    pub scores: BTreeMap<String, f32>,
+}
+
+/// Classifies text as potentially harmful, using the OpenAI moderations API.
+///
+/// If an API key is not provided, it will be read from the environment variable
+/// `OPENAI_API_KEY`, or the [config file](crate::config).
+#[derive(Debug, Clone, Default)]
+pub struct Moderator {
+    pub api_key: Option<String>,
+    /// Where the key comes from if `api_key` isn't set, see [crate::credentials].
+    pub credentials: Option<Arc<dyn CredentialProvider>>,
+    /// Defaults to `https://api.openai.com/v1`.
+    pub base_url: Option<String>,
+    /// Defaults to `omni-moderation-latest`.
+    pub model: Option<String>,
+}
+
+impl Moderator {
+    /// Moderates each of the inputs, returning a result for each in the same order.
+    pub async fn moderate(&self, inputs: Vec<String>) -> Result<Vec<Moderation>, CallErr> {
+        let client = Client::new();
+        let resp = client
+            .post(format!(
+                "{}/moderations",
+                match &self.base_url {
+                    Some(url) => url,
+                    None => config::base_url(|c| &c.openai)?.unwrap_or(OPENAI_BASE_URL),
+                }
+            ))
+            .bearer_auth(
+                credentials::api_key(&self.api_key, &self.credentials, &["OPENAI_API_KEY"], |c| {
+                    &c.openai
+                })
+                .await?,
+            )
+            .json(&OAIModerationRequest {
+                model: self.model.as_deref().unwrap_or(DEFAULT_MODEL).into(),
+                input: inputs,
+            })
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            return Err(CallErr::RequestFailed(resp.status(), resp.text().await?));
+        }
+        Ok(moderations(resp.json().await?))
+    }
+}
+
+fn moderations(res: OAIModerationResponse) -> Vec<Moderation> {
+    res.results
+        .into_iter()
+        .map(|r| Moderation {
+            flagged: r.flagged,
+            categories: r
+                .categories
+                .into_iter()
+                .filter(|(_, flagged)| *flagged)
+                .map(|(c, _)| c)
+                .collect(),
+            scores: r.category_scores,
+        })
+        .collect()
+}
+
+/// Wraps a [ModelCaller], screening its input and output with a [Moderator].
+///
+/// If anything is flagged, the call fails with [CallErr::Moderated]. The input screened
+/// is the instructions, along with the text of any user turns after the last assistant
+/// turn, so earlier turns in a conversation aren't screened again.
+pub struct Screened<B: ModelCaller> {
+    backend: B,
+    moderator: Moderator,
+    inputs: bool,
+    outputs: bool,
+}
+
+impl<B: ModelCaller> Screened<B> {
+    /// Constructs a caller which screens both the input and output of the given backend.
+    pub fn new(backend: B, moderator: Moderator) -> Self {
+        Self {
+            backend,
+            moderator,
+            inputs: true,
+            outputs: true,
+        }
+    }
+
+    /// Sets whether input is screened before calling the model.
+    pub fn screen_inputs(mut self, screen: bool) -> Self {
+        self.inputs = screen;
+        self
+    }
+
+    /// Sets whether the text of responses is screened.
+    pub fn screen_outputs(mut self, screen: bool) -> Self {
+        self.outputs = screen;
+        self
+    }
+
+    /// Consumes the wrapper, returning the underlying backend.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+}
+
+/// Fails with [CallErr::Moderated] if any of the inputs are flagged.
+async fn screen(moderator: &Moderator, inputs: Vec<String>) -> Result<(), CallErr> {
+    if inputs.is_empty() {
+        return Ok(());
+    }
+    let mut categories: Vec<String> = vec![];
+    let mut flagged = false;
+    for m in moderator.moderate(inputs).await? {
+        flagged |= m.flagged;
+        for c in m.categories {
+            if !categories.contains(&c) {
+                categories.push(c);
+            }
+        }
+    }
+    match flagged {
+        true => Err(CallErr::Moderated { categories }),
+        false => Ok(()),
+    }
+}
+
+/// Returns the new input of a call: the instructions, and the text of user turns
+/// following the last assistant turn.
+fn new_inputs(params: &CallBase, turns: &[Turn]) -> Vec<String> {
+    let start = turns
+        .iter()
+        .rposition(|t| t.role == Role::Assistant)
+        .map_or(0, |i| i + 1);
+    std::iter::once(params.instructions.clone())
+        .chain(
+            turns[start..]
+                .iter()
+                .filter(|t| t.role == Role::User)
+                .map(|t| t.text()),
+        )
+        .filter(|t| !t.trim().is_empty())
+        .collect()
+}
+
+impl<B: ModelCaller> ModelCaller for Screened<B> {
+    fn get_model(&self) -> impl Model {
+        self.backend.get_model()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.backend.supports_response_schema()
+    }
+
+    fn endpoint(&self) -> String {
+        self.backend.endpoint()
+    }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        if self.inputs {
+            screen(&self.moderator, new_inputs(&params, &turns)).await?;
+        }
+        let resp = self.backend.call(params, turns).await?;
+        if self.outputs {
+            let text = resp.content.text();
+            if !text.trim().is_empty() {
+                screen(&self.moderator, vec![text]).await?;
+            }
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    #[test]
+    fn parse_moderations() {
+        let res: OAIModerationResponse = serde_json::from_str(
+            r#"{
+                "id": "modr-1",
+                "model": "omni-moderation-latest",
+                "results": [
+                    {
+                        "flagged": true,
+                        "categories": {"harassment": true, "violence": true, "sexual": false},
+                        "category_scores": {"harassment": 0.9, "violence": 0.6, "sexual": 0.01}
+                    },
+                    {
+                        "flagged": false,
+                        "categories": {"harassment": false},
+                        "category_scores": {"harassment": 0.02}
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let results = moderations(res);
+        assert_eq!(results[0].categories, vec!["harassment", "violence"]);
+        assert_eq!(results[0].scores["sexual"], 0.01);
+        assert!(!results[1].flagged);
+        assert!(results[1].categories.is_empty());
+    }
+
+    #[test]
+    fn screens_new_input() {
+        let turn = |role, text: &str| Turn {
+            role,
+            content: vec![Message::text(text)],
+        };
+        let params = CallBase {
+            instructions: "Be kind.".into(),
+            ..Default::default()
+        };
+        let turns = vec![
+            turn(Role::User, "first"),
+            turn(Role::Assistant, "reply"),
+            turn(Role::User, "second"),
+        ];
+        assert_eq!(new_inputs(&params, &turns), vec!["Be kind.", "second"]);
+        assert_eq!(new_inputs(&CallBase::default(), &turns[..1]), vec!["first"]);
+    }
+}
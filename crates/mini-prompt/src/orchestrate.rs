@@ -0,0 +1,509 @@
+//! Combining several model calls to produce a better response than any one call.
+
+use crate::parse::{keyed_values, number};
+use crate::prompt::Template;
+use crate::sampling::{sample_each, sample_n};
+use crate::tokens::estimate;
+use crate::{CallBase, CallErr, CallResp, Message, ModelCaller, Role, Turn};
+
+/// The candidates generated by [best_of_n], and which one the judge preferred.
+#[derive(Debug, Clone)]
+pub struct Judgement {
+    /// The successful responses, in the order they were presented to the judge.
+    pub candidates: Vec<CallResp>,
+    /// The index of the winning candidate.
+    pub winner: usize,
+    /// Why the judge chose the winner.
+    pub rationale: String,
+}
+
+impl Judgement {
+    /// Returns the winning candidate.
+    pub fn best(&self) -> &CallResp {
+        &self.candidates[self.winner]
+    }
+}
+
+/// Generates `n` candidate responses concurrently, then asks a judge model to pick the
+/// one which best satisfies the rubric. The judge can be a different model to the one
+/// generating candidates, such as a cheaper one.
+///
+/// Candidates are judged on their text. Calls which fail are left out, and the judge is
+/// not consulted if only one call succeeds. If every call fails, the first error is
+/// returned.
+///
+/// ```rust,no_run
+/// # #[cfg(all(feature = "openrouter", feature = "openai"))] {
+/// # use mini_prompt::*;
+/// let backend = callers::Openrouter::<models::Gemma27B3>::default();
+/// let judge = callers::Openai::<models::GPT41Mini>::default();
+/// # tokio::task::spawn(async move {
+/// let params = CallBase {
+///     instructions: "Write a tagline for a bakery which only sells sourdough.".into(),
+///     temperature: Some(1.0),
+///     ..Default::default()
+/// };
+/// let judged = orchestrate::best_of_n(
+///     &backend,
+///     &judge,
+///     &params,
+///     &[],
+///     4,
+///     "Short, memorable, and mentions sourdough.",
+/// )
+/// .await
+/// .unwrap();
+/// println!("{}\n\n{}", judged.best().content.text(), judged.rationale);
+/// # });
+/// # }
+/// ```
+pub async fn best_of_n<B: ModelCaller, J: ModelCaller>(
+    backend: &B,
+    judge: &J,
+    params: &CallBase,
+    turns: &[Turn],
+    n: usize,
+    rubric: &str,
+) -> Result<Judgement, CallErr> {
+    let mut candidates = Vec::with_capacity(n);
+    let mut first_err = None;
+    for r in sample_n(backend, params, turns, n).await.results {
+        match r {
+            Ok(resp) => candidates.push(resp),
+            Err(e) => {
+                first_err.get_or_insert(e);
+            }
+        }
+    }
+    match (candidates.len(), first_err) {
+        (0, Some(e)) => return Err(e),
+        (0, None) => return Err(CallErr::NoCompletions),
+        (1, _) => {
+            return Ok(Judgement {
+                candidates,
+                winner: 0,
+                rationale: "Only one candidate was generated.".into(),
+            })
+        }
+        _ => {}
+    }
+
+    let mut instructions = format!(
+        "Several candidate responses were written for the following task.\n\n\
+        <task>\n{}\n</task>\n\n\
+        Judge the candidates against this rubric:\n\n<rubric>\n{}\n</rubric>\n\n",
+        describe_task(params, turns),
+        rubric
+    );
+    for (i, c) in candidates.iter().enumerate() {
+        instructions += &format!(
+            "<candidate number=\"{}\">\n{}\n</candidate>\n\n",
+            i + 1,
+            c.content.text()
+        );
+    }
+    instructions += "Explain which candidate best satisfies the rubric, then finish your \
+        response with a line of the form 'best: <number>'.";
+
+    let count = candidates.len();
+    let judge_params = CallBase {
+        instructions,
+        temperature: Some(0.0),
+        ..Default::default()
+    };
+    let (winner, rationale) = judge
+        .call_parsed(judge_params, vec![], 1, |text| {
+            let best = keyed_values(text, &["best"]).get("best").copied();
+            let winner = best
+                .and_then(number)
+                .map(|(n, _)| n as usize)
+                .filter(|n| (1..=count).contains(n))
+                .ok_or_else(|| {
+                    format!(
+                        "no line of the form 'best: <number>' with a number from 1 to {}",
+                        count
+                    )
+                })?;
+            let rationale = text
+                .lines()
+                .filter(|l| keyed_values(l, &["best"]).is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+            Ok((winner - 1, rationale.trim().to_string()))
+        })
+        .await?;
+
+    Ok(Judgement {
+        candidates,
+        winner,
+        rationale,
+    })
+}
+
+/// A response written by [critique_revise], and the critic's assessment of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Draft {
+    /// The text of the response.
+    pub text: String,
+    /// The critic's feedback on the response.
+    pub critique: String,
+    /// Whether the critic judged the response to satisfy the criteria.
+    pub passed: bool,
+}
+
+/// Generates a response, then has a critic model check it against the criteria, asking
+/// the model to revise its response based on the critic's feedback until the critic is
+/// satisfied or `max_revisions` revisions have been made. The critic can be a different
+/// model to the one writing the response.
+///
+/// Every draft is returned in the order it was written, so the last draft is the final
+/// response. Check [Draft::passed] on the last draft to tell if the critic was satisfied.
+///
+/// ```rust,no_run
+/// # #[cfg(all(feature = "openrouter", feature = "openai"))] {
+/// # use mini_prompt::*;
+/// let backend = callers::Openrouter::<models::Gemma27B3>::default();
+/// let critic = callers::Openai::<models::GPT41Mini>::default();
+/// # tokio::task::spawn(async move {
+/// let params = CallBase {
+///     instructions: "Write a haiku about compilers.".into(),
+///     ..Default::default()
+/// };
+/// let drafts = orchestrate::critique_revise(
+///     &backend,
+///     &critic,
+///     params,
+///     vec![],
+///     "Follows the 5-7-5 syllable pattern, and mentions error messages.",
+///     3,
+/// )
+/// .await
+/// .unwrap();
+/// println!("{}", drafts.last().unwrap().text);
+/// # });
+/// # }
+/// ```
+pub async fn critique_revise<B: ModelCaller, J: ModelCaller>(
+    backend: &B,
+    critic: &J,
+    params: CallBase,
+    mut turns: Vec<Turn>,
+    criteria: &str,
+    max_revisions: usize,
+) -> Result<Vec<Draft>, CallErr> {
+    let task = describe_task(&params, &turns);
+    let mut drafts: Vec<Draft> = vec![];
+    loop {
+        let resp = backend.call(params.clone(), turns.clone()).await?;
+        let text = resp.content.text();
+
+        let critic_params = CallBase {
+            instructions: format!(
+                "A response was written for the following task.\n\n\
+                <task>\n{}\n</task>\n\n\
+                Critique the response against these criteria:\n\n<criteria>\n{}\n</criteria>\n\n\
+                <response>\n{}\n</response>\n\n\
+                Explain any ways the response falls short of the criteria and how to fix them, \
+                then finish your response with a line of the form 'verdict: pass' if the \
+                response satisfies the criteria, or 'verdict: revise' otherwise.",
+                task, criteria, text
+            ),
+            temperature: Some(0.0),
+            ..Default::default()
+        };
+        let (critique, passed) = critic
+            .call_parsed(critic_params, vec![], 1, |text| {
+                let verdict = keyed_values(text, &["verdict"])
+                    .get("verdict")
+                    .map(|v| v.to_lowercase());
+                let passed = match verdict.as_deref() {
+                    Some(v) if v.contains("pass") => true,
+                    Some(v) if v.contains("revise") => false,
+                    _ => {
+                        return Err(
+                            "no line of the form 'verdict: pass' or 'verdict: revise'".into()
+                        )
+                    }
+                };
+                let critique = text
+                    .lines()
+                    .filter(|l| keyed_values(l, &["verdict"]).is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Ok((critique.trim().to_string(), passed))
+            })
+            .await?;
+
+        drafts.push(Draft {
+            text,
+            critique: critique.clone(),
+            passed,
+        });
+        if passed || drafts.len() > max_revisions {
+            return Ok(drafts);
+        }
+
+        turns.push(resp.content);
+        turns.push(Turn {
+            role: Role::User,
+            content: vec![Message::text(format!(
+                "Revise your response based on this feedback. Respond with just the \
+                revised response.\n\n<feedback>\n{}\n</feedback>",
+                critique
+            ))],
+        });
+    }
+}
+
+/// Summarizes each chunk of a long document concurrently, then combines the summaries
+/// into one, for documents too long to fit in a single call.
+///
+/// Each chunk is substituted for `{{text}}` in `map_prompt`. The summaries are then
+/// joined and substituted for `{{text}}` in `reduce_prompt`, whose response is returned.
+/// If the summaries total more than `budget` tokens, as estimated by
+/// [tokens::estimate](crate::tokens::estimate), they are combined in groups which fit
+/// the budget, and the results combined again, until a single response remains.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "openrouter")] {
+/// # use mini_prompt::*;
+/// # use mini_prompt::prompt::Template;
+/// let backend = callers::Openrouter::<models::Gemma27B3>::default();
+/// # tokio::task::spawn(async move {
+/// # let chapters: Vec<String> = vec![];
+/// let summary = orchestrate::map_reduce(
+///     &backend,
+///     &chapters,
+///     &Template::new("Summarize this chapter of a novel:\n\n{{text}}"),
+///     &Template::new("Combine these summaries of a novel into one:\n\n{{text}}"),
+///     8_000,
+/// )
+/// .await
+/// .unwrap();
+/// # });
+/// # }
+/// ```
+pub async fn map_reduce<B: ModelCaller, S: AsRef<str>>(
+    backend: &B,
+    chunks: &[S],
+    map_prompt: &Template,
+    reduce_prompt: &Template,
+    budget: usize,
+) -> Result<String, CallErr> {
+    if chunks.is_empty() {
+        return Err("no chunks to summarize".into());
+    }
+
+    let mut summaries = call_each(
+        backend,
+        chunks
+            .iter()
+            .map(|c| map_prompt.render(&[("text", c.as_ref())])),
+    )
+    .await?;
+    loop {
+        // Greedily group summaries within the budget. Groups have at least two
+        // summaries where possible, so each round makes progress.
+        let mut groups: Vec<Vec<String>> = vec![];
+        let mut tokens = 0;
+        for s in summaries {
+            let n = estimate(&s);
+            match groups.last_mut() {
+                Some(g) if g.len() < 2 || tokens + n <= budget => {
+                    g.push(s);
+                    tokens += n;
+                }
+                _ => {
+                    groups.push(vec![s]);
+                    tokens = n;
+                }
+            }
+        }
+
+        let render = |g: &Vec<String>| reduce_prompt.render(&[("text", &g.join("\n\n"))]);
+        if groups.len() == 1 {
+            return Ok(call_each(backend, groups.iter().map(render))
+                .await?
+                .remove(0));
+        }
+
+        // Lone summaries are carried into the next round as they are.
+        let mut reduced = call_each(backend, groups.iter().filter(|g| g.len() > 1).map(render))
+            .await?
+            .into_iter();
+        summaries = groups
+            .into_iter()
+            .map(|mut g| match g.len() {
+                1 => g.remove(0),
+                _ => reduced.next().unwrap(),
+            })
+            .collect();
+    }
+}
+
+/// Describes the task given by the params and turns, for a model judging the response.
+fn describe_task(params: &CallBase, turns: &[Turn]) -> String {
+    let mut task = params.instructions.clone();
+    for t in turns.iter() {
+        task += "\n\n";
+        task += &t.text();
+    }
+    task.trim().to_string()
+}
+
+/// Calls the model concurrently with each of the rendered instructions, returning the
+/// text of each response in order.
+async fn call_each<B: ModelCaller>(
+    backend: &B,
+    instructions: impl Iterator<Item = Result<String, String>>,
+) -> Result<Vec<String>, CallErr> {
+    let params = instructions
+        .map(|i| {
+            Ok(CallBase {
+                instructions: i?,
+                ..Default::default()
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    sample_each(backend, params, &[])
+        .await
+        .results
+        .into_iter()
+        .map(|r| r.map(|resp| resp.content.text()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Gemma27B3, Model};
+    use crate::testing::{Scripted, Shared};
+
+    #[tokio::test]
+    async fn judge_candidates() {
+        let backend = Shared::new(&["Bread.", "Sourdough, slowly.", "Buns!"]);
+        let judge = Scripted::new(vec![
+            Scripted::text("They're all fine.\nbest: 7"),
+            Scripted::text("The second mentions sourdough.\n\n**Best:** 2"),
+        ]);
+        let params = CallBase {
+            instructions: "Write a bakery tagline.".into(),
+            ..Default::default()
+        };
+        let judged = best_of_n(&backend, &judge, &params, &[], 3, "Mentions sourdough.")
+            .await
+            .unwrap();
+        assert_eq!(judged.candidates.len(), 3);
+        assert_eq!(judged.best().content.text(), "Sourdough, slowly.");
+        assert_eq!(judged.rationale, "The second mentions sourdough.");
+
+        let prompt = &judge.seen()[0].0.instructions;
+        assert!(prompt.contains("<task>\nWrite a bakery tagline.\n</task>"));
+        assert!(prompt.contains("<candidate number=\"3\">\nBuns!\n</candidate>"));
+        assert!(judge.seen()[1].1[1].text().contains("from 1 to 3"));
+
+        // Failed calls are left out, and a lone candidate wins without judging.
+        let backend = Shared::new(&["Bread."]);
+        let judge = Scripted::new(vec![]);
+        let judged = best_of_n(&backend, &judge, &params, &[], 3, "")
+            .await
+            .unwrap();
+        assert_eq!(judged.best().content.text(), "Bread.");
+        assert!(judge.seen().is_empty());
+    }
+
+    #[tokio::test]
+    async fn revise_until_passed() {
+        let backend = Scripted::new(vec![
+            Scripted::text("Code compiles"),
+            Scripted::text("Code compiles fast"),
+            Scripted::text("unused"),
+        ]);
+        let critic = Scripted::new(vec![
+            Scripted::text("Too short.\nverdict: revise"),
+            Scripted::text("Looks good.\n**Verdict:** PASS"),
+        ]);
+        let params = CallBase {
+            instructions: "Write a poem.".into(),
+            ..Default::default()
+        };
+        let drafts = critique_revise(&backend, &critic, params.clone(), vec![], "Long.", 3)
+            .await
+            .unwrap();
+        assert_eq!(
+            drafts,
+            vec![
+                Draft {
+                    text: "Code compiles".into(),
+                    critique: "Too short.".into(),
+                    passed: false,
+                },
+                Draft {
+                    text: "Code compiles fast".into(),
+                    critique: "Looks good.".into(),
+                    passed: true,
+                },
+            ]
+        );
+        let revision = &backend.seen()[1].1;
+        assert_eq!(revision[0].text(), "Code compiles");
+        assert!(revision[1]
+            .text()
+            .contains("<feedback>\nToo short.\n</feedback>"));
+        assert!(critic.seen()[1]
+            .0
+            .instructions
+            .contains("<response>\nCode compiles fast\n</response>"));
+
+        // Drafts stop after the maximum number of revisions.
+        let backend = Scripted::new(vec![Scripted::text("a"), Scripted::text("b")]);
+        let critic = Scripted::new(vec![
+            Scripted::text("verdict: revise"),
+            Scripted::text("verdict: revise"),
+        ]);
+        let drafts = critique_revise(&backend, &critic, params, vec![], "", 1)
+            .await
+            .unwrap();
+        assert_eq!(drafts.len(), 2);
+        assert!(!drafts[1].passed);
+    }
+
+    /// Responds with its instructions in brackets.
+    struct Echo;
+
+    impl ModelCaller for Echo {
+        fn get_model(&self) -> impl Model {
+            Gemma27B3
+        }
+
+        async fn call(&self, params: CallBase, _: Vec<Turn>) -> Result<CallResp, CallErr> {
+            Ok(Scripted::text(&format!("[{}]", params.instructions)))
+        }
+    }
+
+    #[tokio::test]
+    async fn map_reduce_within_budget() {
+        let (map, reduce) = (Template::new("m:{{text}}"), Template::new("r:{{text}}"));
+        let summary = map_reduce(&Echo, &["a", "b", "c"], &map, &reduce, 100)
+            .await
+            .unwrap();
+        assert_eq!(summary, "[r:[m:a]\n\n[m:b]\n\n[m:c]]");
+
+        // Each summary is about two tokens, so at most two fit in the budget.
+        let summary = map_reduce(&Echo, &["a", "b", "c", "d", "e"], &map, &reduce, 4)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            "[r:[r:[r:[m:a]\n\n[m:b]]\n\n[r:[m:c]\n\n[m:d]]]\n\n[m:e]]"
+        );
+
+        let chunks: [&str; 0] = [];
+        assert!(map_reduce(&Echo, &chunks, &map, &reduce, 4).await.is_err());
+        assert!(
+            map_reduce(&Echo, &["a"], &Template::new("{{x}}"), &reduce, 4)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -1,7 +1,15 @@
 //! Helpers and utilities for extracting structured data from LLM output.
 
-use markdown::mdast::{Code, Node};
+#[cfg(feature = "chrono")]
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+#[cfg(feature = "markdown")]
+use markdown::mdast::{Code, List, Node, Table, TableRow};
+#[cfg(feature = "markdown")]
 use markdown::{to_mdast, ParseOptions};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone)]
 /// Describes how to extract a code section from a block of text.
@@ -9,117 +17,1449 @@ pub struct MarkdownOptions<'a> {
     from_back: bool,
     require_lang: bool,
     lang: Option<&'a str>,
+    dedent: bool,
+    tab_width: Option<usize>,
 }
 
 impl<'a> MarkdownOptions<'a> {
-    pub fn json() -> Self {
+    fn with_lang(lang: Option<&'a str>) -> Self {
         MarkdownOptions {
             from_back: true,
             require_lang: false,
-            lang: Some(&"json"),
+            lang,
+            dedent: true,
+            tab_width: None,
         }
     }
 
+    pub fn json() -> Self {
+        Self::with_lang(Some("json"))
+    }
+
     pub fn python() -> Self {
+        Self::with_lang(Some("python"))
+    }
+
+    pub fn yaml() -> Self {
+        Self::with_lang(Some("yaml"))
+    }
+
+    pub fn toml() -> Self {
+        Self::with_lang(Some("toml"))
+    }
+
+    /// Matches code blocks in any language.
+    pub fn any() -> Self {
+        Self::with_lang(None)
+    }
+
+    pub fn leading(self) -> Self {
         MarkdownOptions {
-            from_back: true,
-            require_lang: false,
-            lang: Some(&"python"),
+            from_back: false,
+            ..self
+        }
+    }
+
+    /// Returns code exactly as written, instead of removing indentation common to all
+    /// lines (see [dedent]).
+    pub fn keep_indent(self) -> Self {
+        MarkdownOptions {
+            dedent: false,
+            ..self
+        }
+    }
+
+    /// Replaces leading tabs with the given number of spaces, before any dedenting.
+    pub fn expand_tabs(self, width: usize) -> Self {
+        MarkdownOptions {
+            tab_width: Some(width),
+            ..self
+        }
+    }
+}
+
+/// Removes the leading whitespace common to every non-blank line.
+///
+/// ```
+/// use mini_prompt::parse::dedent;
+/// assert_eq!(dedent("    if x:\n        y()\n"), "if x:\n    y()\n");
+/// ```
+pub fn dedent(text: &str) -> String {
+    let prefix = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .reduce(|a, b| {
+            let common = a
+                .char_indices()
+                .zip(b.chars())
+                .find(|((_, x), y)| x != y)
+                .map_or(a.len().min(b.len()), |((i, _), _)| i);
+            &a[..common]
+        })
+        .unwrap_or("");
+
+    text.split_inclusive('\n')
+        .map(|l| {
+            l.strip_prefix(prefix)
+                .unwrap_or_else(|| l.trim_start_matches([' ', '\t']))
+        })
+        .collect()
+}
+
+/// Replaces tabs in the leading whitespace of each line with the given number of spaces.
+fn expand_tabs(text: &str, width: usize) -> String {
+    text.split_inclusive('\n')
+        .map(|l| {
+            let body = l.trim_start_matches([' ', '\t']);
+            l[..l.len() - body.len()].replace('\t', &" ".repeat(width)) + body
+        })
+        .collect()
+}
+
+/// Extracts a leading or trailing markdown code block using the given opts as configuration.
+pub fn markdown_codeblock(text: &str, opts: &MarkdownOptions) -> Option<String> {
+    markdown_codeblocks(text, opts).next().map(|(_, code)| code)
+}
+
+/// Returns the language and contents of every markdown code block matching the given
+/// opts, in order of appearance, or in reverse if the opts extract a trailing block.
+///
+/// Without the `markdown` feature, only fenced code blocks are found, and the
+/// indentation of blocks nested in lists is kept.
+///
+/// ```
+/// use mini_prompt::parse::{markdown_codeblocks, MarkdownOptions};
+/// let text = "main.rs:\n```rust\nfn main() {}\n```\nbuild.sh:\n```sh\ncargo build\n```";
+/// let blocks: Vec<_> = markdown_codeblocks(text, &MarkdownOptions::any().leading()).collect();
+/// assert_eq!(blocks[1], (Some("sh".to_string()), "cargo build".to_string()));
+/// ```
+pub fn markdown_codeblocks(
+    text: &str,
+    opts: &MarkdownOptions,
+) -> impl Iterator<Item = (Option<String>, String)> {
+    let mut blocks = code_blocks(text);
+    if opts.from_back {
+        blocks.reverse();
+    }
+
+    let (want_lang, require_lang) = (opts.lang.map(String::from), opts.require_lang);
+    let (dedent_code, tab_width) = (opts.dedent, opts.tab_width);
+    blocks
+        .into_iter()
+        .filter(move |(lang, _)| match (lang, &want_lang) {
+            (Some(lang), Some(want_lang)) => lang == want_lang,
+            (Some(_), None) => true,
+            (None, _) => !require_lang,
+        })
+        .map(move |(lang, mut code)| {
+            if let Some(width) = tab_width {
+                code = expand_tabs(&code, width);
+            }
+            if dedent_code {
+                code = dedent(&code);
+            }
+            (lang, code)
+        })
+}
+
+/// Returns the language and contents of every code block, in order of appearance.
+#[cfg(feature = "markdown")]
+fn code_blocks(text: &str) -> Vec<(Option<String>, String)> {
+    fn collect(node: Node, out: &mut Vec<(Option<String>, String)>) {
+        match node {
+            Node::Code(Code { value, lang, .. }) => out.push((lang, value)),
+            mut node => {
+                if let Some(children) = node.children_mut() {
+                    std::mem::take(children)
+                        .into_iter()
+                        .for_each(|c| collect(c, out));
+                }
+            }
+        }
+    }
+
+    let mut blocks = Vec::new();
+    collect(
+        to_mdast(text, &ParseOptions::default()).unwrap(),
+        &mut blocks,
+    );
+    blocks
+}
+
+/// Returns the language and contents of every fenced code block, in order of
+/// appearance.
+#[cfg(not(feature = "markdown"))]
+fn code_blocks(text: &str) -> Vec<(Option<String>, String)> {
+    let mut stream = MarkdownStream::default();
+    let mut events = stream.push(text);
+    events.extend(stream.finish());
+    events
+        .into_iter()
+        .filter_map(|e| match e {
+            StreamEvent::CodeClose { lang, code } => Some((lang, code)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// An event emitted by [MarkdownStream].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// Text outside of any code block.
+    Text(String),
+    /// A code block was opened, with the language given after the fence, if any.
+    CodeOpen(Option<String>),
+    /// Text within the current code block.
+    Code(String),
+    /// The current code block was closed, with its language and contents. The contents
+    /// exclude the line break before the closing fence.
+    CodeClose { lang: Option<String>, code: String },
+}
+
+#[derive(Debug, Clone)]
+struct OpenFence {
+    marker: char,
+    len: usize,
+    lang: Option<String>,
+    code: String,
+}
+
+/// Incrementally splits streamed model output into text and fenced code blocks, so
+/// code can be highlighted or acted on before the response is complete.
+///
+/// Text is emitted as soon as it can't be part of a fence, so events for a line may be
+/// split across several chunks. Fence lines themselves are not emitted.
+///
+/// ```
+/// use mini_prompt::parse::{MarkdownStream, StreamEvent};
+/// let mut stream = MarkdownStream::default();
+/// let mut events = stream.push("Run:\n``");
+/// events.extend(stream.push("`sh\nls\n```"));
+/// events.extend(stream.finish());
+/// assert_eq!(events, vec![
+///     StreamEvent::Text("Run:\n".into()),
+///     StreamEvent::CodeOpen(Some("sh".into())),
+///     StreamEvent::Code("ls\n".into()),
+///     StreamEvent::CodeClose { lang: Some("sh".into()), code: "ls".into() },
+/// ]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownStream {
+    /// The start of the current line, held back while it could be a fence.
+    line: String,
+    /// Whether the start of the current line has already been emitted.
+    committed: bool,
+    fence: Option<OpenFence>,
+}
+
+impl MarkdownStream {
+    /// Consumes the next chunk of text, returning the resulting events.
+    pub fn push(&mut self, chunk: &str) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        for piece in chunk.split_inclusive('\n') {
+            if self.committed {
+                self.emit(piece, &mut events);
+            } else {
+                self.line.push_str(piece);
+                if piece.ends_with('\n') {
+                    let line = std::mem::take(&mut self.line);
+                    self.end_line(&line, &mut events);
+                } else if !self.could_be_fence(&self.line) {
+                    let line = std::mem::take(&mut self.line);
+                    self.emit(&line, &mut events);
+                    self.committed = true;
+                }
+            }
+            if piece.ends_with('\n') {
+                self.committed = false;
+            }
+        }
+        events
+    }
+
+    /// Flushes any held text at the end of the stream, closing an unterminated code
+    /// block if there is one.
+    pub fn finish(mut self) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        let line = std::mem::take(&mut self.line);
+        if !line.is_empty() {
+            self.end_line(&line, &mut events);
+        }
+        if let Some(f) = self.fence.take() {
+            events.push(StreamEvent::CodeClose {
+                lang: f.lang,
+                code: f.code.strip_suffix('\n').unwrap_or(&f.code).to_string(),
+            });
         }
+        events
     }
 
-    pub fn leading(self) -> Self {
-        MarkdownOptions {
-            from_back: false,
-            ..self
+    fn could_be_fence(&self, partial: &str) -> bool {
+        let rest = partial.trim_start_matches(' ');
+        match (fence(partial), &self.fence) {
+            (Some(_), None) => true,
+            // The fence may still grow long enough to close the block.
+            (Some((marker, _, info)), Some(f)) => marker == f.marker && info.is_empty(),
+            (None, _) => {
+                partial.len() - rest.len() <= 3
+                    && (rest.chars().all(|c| c == '`') || rest.chars().all(|c| c == '~'))
+            }
+        }
+    }
+
+    fn end_line(&mut self, line: &str, events: &mut Vec<StreamEvent>) {
+        match (fence(line), &self.fence) {
+            (Some((marker, len, info)), None) if marker == '~' || !info.contains('`') => {
+                let lang = info.split_whitespace().next().map(String::from);
+                events.push(StreamEvent::CodeOpen(lang.clone()));
+                self.fence = Some(OpenFence {
+                    marker,
+                    len,
+                    lang,
+                    code: String::new(),
+                });
+            }
+            (Some((marker, len, "")), Some(f)) if marker == f.marker && len >= f.len => {
+                let f = self.fence.take().unwrap();
+                events.push(StreamEvent::CodeClose {
+                    lang: f.lang,
+                    code: f.code.strip_suffix('\n').unwrap_or(&f.code).to_string(),
+                });
+            }
+            _ => self.emit(line, events),
+        }
+    }
+
+    fn emit(&mut self, text: &str, events: &mut Vec<StreamEvent>) {
+        let event = match &mut self.fence {
+            Some(f) => {
+                f.code.push_str(text);
+                StreamEvent::Code(text.to_string())
+            }
+            None => StreamEvent::Text(text.to_string()),
+        };
+        match (events.last_mut(), event) {
+            (Some(StreamEvent::Text(t)), StreamEvent::Text(s))
+            | (Some(StreamEvent::Code(t)), StreamEvent::Code(s)) => t.push_str(&s),
+            (_, event) => events.push(event),
+        }
+    }
+}
+
+/// Returns the marker, length, and trimmed info string of a line which starts with a
+/// code fence.
+fn fence(line: &str) -> Option<(char, usize, &str)> {
+    let rest = line.trim_start_matches(' ');
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = rest.len() - rest.trim_start_matches(marker).len();
+    (line.len() - rest.len() <= 3 && len >= 3).then(|| (marker, len, rest[len..].trim()))
+}
+
+/// Deserializes JSON from model output, using the contents of the trailing json
+/// markdown code block if there is one, or otherwise the whole text.
+///
+/// ```
+/// use mini_prompt::parse::json;
+/// let v: Vec<u32> = json("Sure!\n```json\n[1, 2, 3,]\n```").unwrap();
+/// assert_eq!(v, vec![1, 2, 3]);
+/// ```
+///
+/// If the JSON is malformed, parsing is retried after [repair_json]. If that fails
+/// too, the last JSON value found by [bare_json] is used.
+pub fn json<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+    let body = markdown_codeblock(text, &MarkdownOptions::json())
+        .unwrap_or_else(|| text.trim().to_string());
+    let parse = |s: &str| {
+        serde_json_lenient::from_str(s)
+            .or_else(|e| serde_json_lenient::from_str(&repair_json(s)).map_err(|_| e.to_string()))
+    };
+    parse(&body).or_else(|e| match bare_json(&body) {
+        Some(region) if region != body => parse(region).map_err(|_| e),
+        _ => Err(e),
+    })
+}
+
+/// Returns the last JSON object or array embedded in some text, for models which
+/// ignore instructions to use a code block.
+///
+/// ```
+/// use mini_prompt::parse::bare_json;
+/// assert_eq!(
+///     bare_json(r#"The result is {"ok": true}, as [requested]."#),
+///     Some(r#"{"ok": true}"#),
+/// );
+/// ```
+pub fn bare_json(text: &str) -> Option<&str> {
+    bare_json_regions(text).last()
+}
+
+/// Returns each JSON object or array embedded in some text, in order of appearance.
+///
+/// Regions are found by scanning for balanced braces or brackets, and are only
+/// returned if they parse as JSON. Values nested within a returned region are not
+/// returned separately.
+pub fn bare_json_regions(text: &str) -> impl Iterator<Item = &str> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        while let Some(start) = text[pos..].find(['{', '[']).map(|i| pos + i) {
+            if let Some(end) = balanced_end(&text[start..]).map(|e| start + e) {
+                let region = &text[start..end];
+                if serde_json_lenient::from_str::<serde_json::Value>(region).is_ok() {
+                    pos = end;
+                    return Some(region);
+                }
+            }
+            pos = start + 1;
+        }
+        None
+    })
+}
+
+/// Returns the length of the balanced region at the start of the text, which begins
+/// with an opening brace or bracket.
+fn balanced_end(text: &str) -> Option<usize> {
+    let mut stack = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => stack.push('}'),
+            '[' => stack.push(']'),
+            '}' | ']' => {
+                if stack.pop() != Some(c) {
+                    return None;
+                }
+                if stack.is_empty() {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Fixes common defects in JSON written by LLMs, such as:
+///
+///  - Trailing commas
+///  - Single-quoted strings
+///  - Unquoted keys
+///  - Unescaped newlines within strings
+///  - Python literals (`True`, `False`, `None`)
+///  - Output truncated before closing strings, arrays, or objects
+///
+/// The result is not guaranteed to be valid JSON.
+///
+/// ```
+/// use mini_prompt::parse::repair_json;
+/// assert_eq!(
+///     repair_json("{name: 'Bob', tags: ['a', 'b',], ok: True, note: 'unfinis"),
+///     r#"{"name": "Bob", "tags": ["a", "b"], "ok": true, "note": "unfinis"}"#,
+/// );
+/// ```
+pub fn repair_json(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 8);
+    // The closing delimiters of the open arrays and objects.
+    let mut stack: Vec<char> = Vec::new();
+    // Whether the last token was a string in key position, with no value yet.
+    let mut dangling_key = false;
+
+    let mut chars = text.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            out.push(c);
+            continue;
+        }
+        let key_position = stack.last() == Some(&'}')
+            && matches!(out.trim_end().chars().last(), Some('{') | Some(','));
+        dangling_key = false;
+
+        match c {
+            '"' | '\'' => {
+                out.push('"');
+                while let Some(s) = chars.next() {
+                    match s {
+                        '\\' => match chars.next() {
+                            Some('\'') => out.push('\''),
+                            Some(e) => {
+                                out.push('\\');
+                                out.push(e);
+                            }
+                            None => {}
+                        },
+                        _ if s == c => break,
+                        '"' => out.push_str("\\\""),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\t' => out.push_str("\\t"),
+                        _ => out.push(s),
+                    }
+                }
+                out.push('"');
+                dangling_key = key_position;
+            }
+            '{' => {
+                stack.push('}');
+                out.push(c);
+            }
+            '[' => {
+                stack.push(']');
+                out.push(c);
+            }
+            '}' | ']' => {
+                strip_trailing_comma(&mut out);
+                if stack.last() == Some(&c) {
+                    stack.pop();
+                }
+                out.push(c);
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let mut word = String::from(c);
+                while let Some(&n) = chars.peek() {
+                    if !(n.is_alphanumeric() || n == '_') {
+                        break;
+                    }
+                    word.push(n);
+                    chars.next();
+                }
+
+                if key_position {
+                    out.push('"');
+                    out.push_str(&word);
+                    out.push('"');
+                } else {
+                    out.push_str(match word.as_str() {
+                        "True" => "true",
+                        "False" => "false",
+                        "None" => "null",
+                        w => w,
+                    });
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    // Close anything left open by truncated output.
+    out.truncate(out.trim_end().len());
+    if dangling_key {
+        out.push_str(": null");
+    } else if out.ends_with(':') {
+        out.push_str(" null");
+    }
+    strip_trailing_comma(&mut out);
+    while let Some(c) = stack.pop() {
+        out.push(c);
+    }
+    out
+}
+
+fn strip_trailing_comma(out: &mut String) {
+    let end = out.trim_end().len();
+    if out[..end].ends_with(',') {
+        out.remove(end - 1);
+    }
+}
+
+/// Deserializes YAML from model output, using the contents of the trailing yaml
+/// markdown code block if there is one, or otherwise the whole text.
+///
+/// ```
+/// use mini_prompt::parse::yaml_codeblock;
+/// let v: Vec<String> = yaml_codeblock("Ideas:\n```yaml\n- one\n- 'two: parts'\n```").unwrap();
+/// assert_eq!(v, vec!["one", "two: parts"]);
+/// ```
+#[cfg(feature = "yaml")]
+pub fn yaml_codeblock<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+    let body = markdown_codeblock(text, &MarkdownOptions::yaml())
+        .unwrap_or_else(|| text.trim().to_string());
+    serde_yaml::from_str(&body).map_err(|e| e.to_string())
+}
+
+/// Deserializes TOML from model output, using the contents of the trailing toml
+/// markdown code block if there is one, or otherwise the whole text.
+///
+/// ```
+/// use mini_prompt::parse::toml_codeblock;
+/// let v: toml::Table = toml_codeblock("```toml\n[server]\nport = 8080\n```").unwrap();
+/// assert_eq!(v["server"]["port"].as_integer(), Some(8080));
+/// ```
+#[cfg(feature = "toml")]
+pub fn toml_codeblock<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+    let body = markdown_codeblock(text, &MarkdownOptions::toml())
+        .unwrap_or_else(|| text.trim().to_string());
+    toml::from_str(&body).map_err(|e| e.to_string())
+}
+
+/// Extracts the first GFM table, returning the text of the header cells and the text
+/// of the cells in each subsequent row. Rows are padded or truncated to the number of
+/// headers, and formatting such as emphasis is stripped.
+///
+/// ```
+/// use mini_prompt::parse::markdown_table;
+/// let (headers, rows) = markdown_table(
+///     "| Lang | Typed |\n|---|---|\n| Rust | **yes** |\n| Python | no |",
+/// )
+/// .unwrap();
+/// assert_eq!(headers, vec!["Lang", "Typed"]);
+/// assert_eq!(rows[0], vec!["Rust", "yes"]);
+/// ```
+#[cfg(feature = "markdown")]
+pub fn markdown_table(text: &str) -> Option<(Vec<String>, Vec<Vec<String>>)> {
+    let mut candidates = vec![to_mdast(text, &ParseOptions::gfm()).unwrap()];
+    while let Some(mut node) = candidates.pop() {
+        if let Node::Table(Table { children, .. }) = node {
+            let mut rows = children.into_iter().map(|row| match row {
+                Node::TableRow(TableRow { children, .. }) => children
+                    .iter()
+                    .map(|cell| cell.to_string().trim().to_string())
+                    .collect::<Vec<_>>(),
+                _ => vec![],
+            });
+            let headers = rows.next()?;
+            let rows = rows
+                .map(|mut r| {
+                    r.resize(headers.len(), String::new());
+                    r
+                })
+                .collect();
+            return Some((headers, rows));
+        }
+
+        if let Some(children) = node.children_mut() {
+            candidates.extend(std::mem::take(children).into_iter().rev());
+        }
+    }
+
+    None
+}
+
+/// An entry in a markdown list, see [list_items].
+#[cfg(feature = "markdown")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListItem {
+    /// The text of the entry, excluding any nested lists.
+    pub text: String,
+    /// How deeply the list containing the entry is nested, starting from zero.
+    pub depth: usize,
+    /// Whether the entry is part of a numbered list.
+    pub ordered: bool,
+}
+
+/// Extracts the entries of every markdown list in order of appearance, with nested
+/// entries following their parent. Formatting such as emphasis is stripped.
+///
+/// ```
+/// use mini_prompt::parse::list_items;
+/// let items = list_items("Some ideas:\n1. **Bake** a cake\n   - with icing\n2. Go outside");
+/// assert_eq!(
+///     items.iter().map(|i| (i.text.as_str(), i.depth)).collect::<Vec<_>>(),
+///     vec![("Bake a cake", 0), ("with icing", 1), ("Go outside", 0)],
+/// );
+/// ```
+#[cfg(feature = "markdown")]
+pub fn list_items(text: &str) -> Vec<ListItem> {
+    fn collect(node: &Node, depth: usize, out: &mut Vec<ListItem>) {
+        match node {
+            Node::List(List {
+                children, ordered, ..
+            }) => {
+                for item in children.iter().filter_map(|c| c.children()) {
+                    out.push(ListItem {
+                        text: item
+                            .iter()
+                            .filter(|c| !matches!(c, Node::List(_)))
+                            .map(|c| c.to_string().trim().to_string())
+                            .filter(|t| !t.is_empty())
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                        depth,
+                        ordered: *ordered,
+                    });
+                    item.iter().for_each(|c| collect(c, depth + 1, out));
+                }
+            }
+            node => {
+                if let Some(children) = node.children() {
+                    children.iter().for_each(|c| collect(c, depth, out));
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    collect(&to_mdast(text, &ParseOptions::gfm()).unwrap(), 0, &mut out);
+    out
+}
+
+/// A change to a single file, see [unified_diff].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    /// The path of the file before the change, or None if the file is being created.
+    /// Any `a/` prefix is removed.
+    pub old_path: Option<String>,
+    /// The path of the file after the change, or None if the file is being deleted.
+    /// Any `b/` prefix is removed.
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+/// A contiguous region of changes within a [FileDiff].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// The line in the original file the hunk claims to start at, counting from one.
+    pub old_start: Option<usize>,
+    pub lines: Vec<DiffLine>,
+}
+
+/// A line of a [Hunk], without its leading marker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Context(String),
+    Add(String),
+    Remove(String),
+}
+
+impl FileDiff {
+    /// Applies the hunks to the contents of the original file, returning the new contents.
+    ///
+    /// Models often get line numbers wrong, so each hunk is located by searching for its
+    /// context and removed lines, preferring the match closest to where the hunk claims
+    /// to start. Trailing whitespace is ignored when matching.
+    pub fn apply(&self, original: &str) -> Result<String, String> {
+        let mut lines: Vec<&str> = original.lines().collect();
+        let (mut pos, mut offset) = (0, 0isize);
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            let (mut before, mut after) = (Vec::new(), Vec::new());
+            for line in hunk.lines.iter() {
+                match line {
+                    DiffLine::Context(l) => {
+                        before.push(l.as_str());
+                        after.push(l.as_str());
+                    }
+                    DiffLine::Remove(l) => before.push(l.as_str()),
+                    DiffLine::Add(l) => after.push(l.as_str()),
+                }
+            }
+
+            let hint = hunk.old_start.map_or(pos, |s| {
+                (s.saturating_sub(1) as isize + offset).max(pos as isize) as usize
+            });
+            let start = (pos..=lines.len())
+                .filter(|s| {
+                    s + before.len() <= lines.len()
+                        && before
+                            .iter()
+                            .zip(&lines[*s..])
+                            .all(|(a, b)| a.trim_end() == b.trim_end())
+                })
+                .min_by_key(|s| s.abs_diff(hint))
+                .ok_or_else(|| format!("hunk {} does not match the file", i + 1))?;
+
+            lines.splice(start..start + before.len(), after.iter().copied());
+            pos = start + after.len();
+            offset += after.len() as isize - before.len() as isize;
+        }
+
+        let mut out = lines.join("\n");
+        if !out.is_empty() && (original.is_empty() || original.ends_with('\n')) {
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+/// Extracts unified diffs from model output, using the contents of every diff or patch
+/// markdown code block if there are any, or otherwise the whole text.
+///
+/// Line counts in hunk headers are ignored, as models rarely get them right. Hunks
+/// which aren't preceded by `---`/`+++` file headers are returned in a [FileDiff]
+/// without paths.
+///
+/// ```
+/// use mini_prompt::parse::unified_diff;
+/// let text = "Fixed:\n```diff\n--- a/greet.py\n+++ b/greet.py\n@@ -1,2 +1,2 @@\n def greet():\n-    print('helo')\n+    print('hello')\n```";
+/// let diffs = unified_diff(text);
+/// assert_eq!(diffs[0].new_path.as_deref(), Some("greet.py"));
+/// assert_eq!(
+///     diffs[0].apply("def greet():\n    print('helo')\n").unwrap(),
+///     "def greet():\n    print('hello')\n",
+/// );
+/// ```
+pub fn unified_diff(text: &str) -> Vec<FileDiff> {
+    let blocks: Vec<String> =
+        markdown_codeblocks(text, &MarkdownOptions::any().leading().keep_indent())
+            .filter(|(lang, _)| matches!(lang.as_deref(), Some("diff" | "patch")))
+            .map(|(_, code)| code)
+            .collect();
+    match blocks.is_empty() {
+        true => parse_diff(text),
+        false => blocks.iter().flat_map(|b| parse_diff(b)).collect(),
+    }
+}
+
+fn parse_diff(text: &str) -> Vec<FileDiff> {
+    fn path(header: &str, prefix: &str) -> Option<String> {
+        let path = header.split('\t').next().unwrap_or_default().trim();
+        match path {
+            "/dev/null" => None,
+            path => Some(path.strip_prefix(prefix).unwrap_or(path).to_string()),
+        }
+    }
+
+    let mut files: Vec<FileDiff> = Vec::new();
+    let mut in_hunk = false;
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        let new_header = lines.peek().and_then(|l| l.strip_prefix("+++ "));
+        if let (Some(old), Some(new)) = (line.strip_prefix("--- "), new_header) {
+            files.push(FileDiff {
+                old_path: path(old, "a/"),
+                new_path: path(new, "b/"),
+                hunks: vec![],
+            });
+            lines.next();
+            in_hunk = false;
+        } else if let Some(header) = line.strip_prefix("@@") {
+            let old_start = header.trim_start().strip_prefix('-').and_then(|r| {
+                let end = r.find(|c: char| !c.is_ascii_digit()).unwrap_or(r.len());
+                r[..end].parse().ok()
+            });
+            if files.is_empty() {
+                files.push(FileDiff {
+                    old_path: None,
+                    new_path: None,
+                    hunks: vec![],
+                });
+            }
+            let hunks = &mut files.last_mut().unwrap().hunks;
+            hunks.push(Hunk {
+                old_start,
+                lines: vec![],
+            });
+            in_hunk = true;
+        } else if in_hunk {
+            let hunk = files.last_mut().unwrap().hunks.last_mut().unwrap();
+            match line.chars().next() {
+                // Models often drop the space from blank context lines.
+                None => hunk.lines.push(DiffLine::Context(String::new())),
+                Some(' ') => hunk.lines.push(DiffLine::Context(line[1..].to_string())),
+                Some('+') => hunk.lines.push(DiffLine::Add(line[1..].to_string())),
+                Some('-') => hunk.lines.push(DiffLine::Remove(line[1..].to_string())),
+                Some('\\') => {} // "\ No newline at end of file"
+                _ => in_hunk = false,
+            }
+        }
+    }
+
+    // Blank lines after a hunk are more likely to separate it from prose than to be context.
+    for hunk in files.iter_mut().flat_map(|f| f.hunks.iter_mut()) {
+        while hunk.lines.last() == Some(&DiffLine::Context(String::new())) {
+            hunk.lines.pop();
+        }
+    }
+    files
+}
+
+/// Applies each diff to the file at its path, in a map of paths to file contents.
+/// Files are created or removed if the diff creates or deletes them. If any diff fails
+/// to apply, an error is returned and no files are changed.
+pub fn apply_diffs(files: &mut HashMap<String, String>, diffs: &[FileDiff]) -> Result<(), String> {
+    let mut out = files.clone();
+    for diff in diffs {
+        let name = diff.old_path.as_ref().or(diff.new_path.as_ref());
+        let name = name.ok_or("diff does not name a file")?;
+        let original = match &diff.old_path {
+            Some(path) => out
+                .remove(path)
+                .ok_or_else(|| format!("{}: no such file", path))?,
+            None => String::new(),
+        };
+        let patched = diff
+            .apply(&original)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        if let Some(path) = &diff.new_path {
+            out.insert(path.clone(), patched);
+        }
+    }
+    *files = out;
+    Ok(())
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+/// Describes how to extract a multiclass/classification answer.
+///
+/// Excepts the LLM to output in the form `<key>: <chosen class>` towards
+/// the end of its output.
+pub struct EnumOptions<'a> {
+    key: &'a str,
+    classes: &'a [&'a str],
+    fuzzy: bool,
+    max_edit_distance: usize,
+    synonyms: &'a [(&'a str, &'a str)],
+}
+
+impl<'a> From<&'a [&'a str]> for EnumOptions<'a> {
+    fn from(classes: &'a [&'a str]) -> Self {
+        Self {
+            key: "answer",
+            classes,
+            fuzzy: false,
+            max_edit_distance: 0,
+            synonyms: &[],
+        }
+    }
+}
+
+impl<'a> EnumOptions<'a> {
+    /// Uses the given key instead of `answer`.
+    pub fn key(self, key: &'a str) -> Self {
+        Self { key, ..self }
+    }
+
+    /// Tolerates formatting around the answer: case, surrounding punctuation and
+    /// markdown, and trailing text after the class such as `query, because ...`.
+    pub fn fuzzy(self) -> Self {
+        Self {
+            fuzzy: true,
+            ..self
+        }
+    }
+
+    /// Accepts answers within the given edit distance of a class, if a single class is
+    /// closest. Implies [fuzzy](EnumOptions::fuzzy).
+    pub fn max_edit_distance(self, max_edit_distance: usize) -> Self {
+        Self {
+            fuzzy: true,
+            max_edit_distance,
+            ..self
+        }
+    }
+
+    /// Accepts alternative answers, given as (synonym, class) pairs. Implies
+    /// [fuzzy](EnumOptions::fuzzy).
+    pub fn synonyms(self, synonyms: &'a [(&'a str, &'a str)]) -> Self {
+        Self {
+            fuzzy: true,
+            synonyms,
+            ..self
+        }
+    }
+
+    /// Matches an answer against the classes, as configured.
+    fn fuzzy_match(&self, answer: &str) -> Option<&'a str> {
+        let answer = normalize(answer);
+        let candidates = self
+            .classes
+            .iter()
+            .map(|c| (normalize(c), *c))
+            .chain(self.synonyms.iter().map(|(s, c)| (normalize(s), *c)))
+            .collect::<Vec<_>>();
+
+        if let Some((_, class)) = candidates.iter().find(|(n, _)| *n == answer) {
+            return Some(class);
+        }
+
+        let prefixed = candidates
+            .iter()
+            .filter(|(n, _)| {
+                answer.starts_with(n.as_str())
+                    && !answer[n.len()..].starts_with(|c: char| c.is_alphanumeric())
+            })
+            .max_by_key(|(n, _)| n.len());
+        if let Some((_, class)) = prefixed {
+            return Some(class);
+        }
+
+        if self.max_edit_distance == 0 {
+            return None;
+        }
+        let mut distances = candidates
+            .iter()
+            .map(|(n, class)| (edit_distance(n, &answer), *class))
+            .filter(|(d, _)| *d <= self.max_edit_distance)
+            .collect::<Vec<_>>();
+        distances.sort_by_key(|(d, _)| *d);
+        match distances.as_slice() {
+            [(d, class), (d2, class2), ..] if d == d2 && class != class2 => None,
+            [(_, class), ..] => Some(class),
+            [] => None,
+        }
+    }
+}
+
+/// Lowercases text, trimming any surrounding whitespace, punctuation, or markdown.
+fn normalize(s: &str) -> String {
+    s.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// The Levenshtein distance between two strings, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = (prev + (ca != *cb) as usize).min(row[j] + 1).min(cur + 1);
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/// Extracts a trailing multiclass answer using the given opts as configuration.
+///
+/// ```
+/// use mini_prompt::parse::{multiclass, EnumOptions};
+/// multiclass("uwu\nanswer: query", &["query", "action"][..].into());
+///
+/// let opts = EnumOptions::from(&["query", "action"][..])
+///     .max_edit_distance(1)
+///     .synonyms(&[("search", "query")]);
+/// assert_eq!(multiclass("**Answer:** Query.", &opts), Some("query"));
+/// assert_eq!(multiclass("answer: actoin", &opts), None);
+/// assert_eq!(multiclass("answer: acton", &opts), Some("action"));
+/// assert_eq!(multiclass("answer: Search, since ...", &opts), Some("query"));
+/// ```
+pub fn multiclass<'a>(text: &str, opts: &'a EnumOptions) -> Option<&'a str> {
+    let mut lines: Vec<String> = text.split("\n").map(|s| s.trim().to_lowercase()).collect();
+    lines.reverse();
+
+    for line in lines.into_iter() {
+        if opts.fuzzy {
+            if let Some(class) = line_value(&line, opts.key).and_then(|a| opts.fuzzy_match(a)) {
+                return Some(class);
+            }
+            continue;
+        }
+
+        if line.starts_with(&opts.key.to_lowercase()) {
+            if let Some(":") = line.get(opts.key.len()..opts.key.len() + 1) {
+                if let Some(answer) = line.get(opts.key.len() + 1..).map(|a| a.trim()) {
+                    for class in opts.classes.iter() {
+                        if class == &answer {
+                            return Some(class);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns the value of a `key: value` line, ignoring the case of the key and tolerating
+/// markdown around it, such as `**key:**` or a list bullet.
+fn line_value<'t>(line: &'t str, key: &str) -> Option<&'t str> {
+    let rest = line.trim_start_matches(|c: char| !c.is_alphanumeric());
+    rest.get(..key.len())
+        .filter(|k| k.eq_ignore_ascii_case(key))
+        .and_then(|_| {
+            rest[key.len()..]
+                .trim_start_matches(['*', '_', '`'])
+                .strip_prefix(':')
+        })
+        .map(|v| v.trim_start_matches(['*', '_']).trim())
+}
+
+/// Extracts the value of each of the given keys from lines of the form `key: value`.
+/// If a key appears several times, the last value is used. Keys are matched ignoring
+/// case, and markdown formatting around keys is tolerated.
+///
+/// ```
+/// use mini_prompt::parse::keyed_values;
+/// let text = "Title: Login fails\n**Severity:** high\nSeverity: critical\n";
+/// let values = keyed_values(text, &["title", "severity", "summary"]);
+/// assert_eq!(values["title"], "Login fails");
+/// assert_eq!(values["severity"], "critical");
+/// assert!(!values.contains_key("summary"));
+/// ```
+pub fn keyed_values<'t, 'k>(text: &'t str, keys: &[&'k str]) -> HashMap<&'k str, &'t str> {
+    let mut out = HashMap::new();
+    for line in text.lines() {
+        for key in keys {
+            if let Some(value) = line_value(line, key) {
+                out.insert(*key, value);
+            }
+        }
+    }
+    out
+}
+
+/// Extracts a yes/no answer, returning it along with the word it was read from.
+///
+/// Lines are considered from the end of the text, and the first answer-like word in a
+/// line is used, so both `Yes, because ...` and `... so the answer is no.` work. Words
+/// preceded by `not` are negated.
+///
+/// ```
+/// use mini_prompt::parse::boolean;
+/// assert_eq!(boolean("Let me think.\nNo, that would be unsafe.").unwrap().0, false);
+/// assert_eq!(boolean("The claim is not correct.").unwrap(), (false, "correct"));
+/// ```
+pub fn boolean(text: &str) -> Option<(bool, &str)> {
+    for line in text.lines().rev() {
+        let mut negated = false;
+        for word in line
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .filter(|w| !w.is_empty())
+        {
+            let answer = match word.to_lowercase().as_str() {
+                "yes" | "true" | "correct" | "affirmative" | "yep" | "yeah" => true,
+                "no" | "false" | "incorrect" | "negative" | "nope" => false,
+                "not" | "isn't" | "wasn't" => {
+                    negated = true;
+                    continue;
+                }
+                _ => {
+                    negated = false;
+                    continue;
+                }
+            };
+            return Some((answer != negated, word));
+        }
+    }
+    None
+}
+
+/// Extracts the last number in the text, returning it along with the span it was read from.
+///
+/// Thousands separators (`1,234`), signs, decimals, and scientific notation (`1.5e-3`)
+/// are understood. Surrounding text such as units or currency symbols is ignored.
+///
+/// ```
+/// use mini_prompt::parse::number;
+/// assert_eq!(
+///     number("Step 1: add them.\nSo it's approximately $1,234.5 million."),
+///     Some((1234.5, "1,234.5")),
+/// );
+/// ```
+pub fn number(text: &str) -> Option<(f64, &str)> {
+    let bytes = text.as_bytes();
+    let digit_at = |i: usize| bytes.get(i).is_some_and(|b| b.is_ascii_digit());
+    let digits_from = |mut i: usize| {
+        while digit_at(i) {
+            i += 1;
+        }
+        i
+    };
+
+    let mut last = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let prev_alnum = i > 0 && bytes[i - 1].is_ascii_alphanumeric();
+        let start = i;
+        let mut end = match bytes[i] {
+            b'-' | b'+'
+                if !prev_alnum
+                    && (digit_at(i + 1)
+                        || (bytes.get(i + 1) == Some(&b'.') && digit_at(i + 2))) =>
+            {
+                i + 1
+            }
+            b'.' if !prev_alnum && digit_at(i + 1) => i,
+            b'0'..=b'9' if !prev_alnum => i,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        end = digits_from(end);
+        // Thousands separators must be followed by exactly three digits.
+        while bytes.get(end) == Some(&b',') && digits_from(end + 1) == end + 4 {
+            end += 4;
+        }
+        if bytes.get(end) == Some(&b'.') && digit_at(end + 1) {
+            end = digits_from(end + 1);
+        }
+        if matches!(bytes.get(end), Some(b'e') | Some(b'E')) {
+            let exp = match bytes.get(end + 1) {
+                Some(b'-') | Some(b'+') => end + 2,
+                _ => end + 1,
+            };
+            if digit_at(exp) {
+                end = digits_from(exp);
+            }
+        }
+
+        let span = &text[start..end];
+        if let Ok(n) = span.replace(',', "").parse::<f64>() {
+            last = Some((n, span));
+        }
+        i = end.max(start + 1);
+    }
+    last
+}
+
+/// Extracts the last date in the text, returning it along with the span it was read from.
+///
+/// ISO-8601 dates (`2024-03-05`, including within date-times) and dates with the month
+/// written out (`March 5th, 2024`, `5 Mar 2024`, `5th of March 2024`) are understood.
+///
+/// ```
+/// use mini_prompt::parse::date;
+/// let (d, span) = date("Remind me on the 5th of March, 2024 please.").unwrap();
+/// assert_eq!(d.to_string(), "2024-03-05");
+/// assert_eq!(span, "5th of March, 2024");
+/// ```
+#[cfg(feature = "chrono")]
+pub fn date(text: &str) -> Option<(NaiveDate, &str)> {
+    let iso = iso_datetimes(text)
+        .into_iter()
+        .map(|(s, e, dt, _)| (s, e, dt.date()));
+    iso.chain(natural_dates(text))
+        .max_by_key(|(start, _, _)| *start)
+        .map(|(start, end, d)| (d, &text[start..end]))
+}
+
+/// Extracts the last ISO-8601 date and time in the text (such as `2024-03-05T14:30`
+/// or `2024-03-05 14:30:00+02:00`), returning it along with the span it was read from.
+///
+/// Times with an offset or `Z` suffix are converted to UTC, and times without are
+/// returned as written.
+#[cfg(feature = "chrono")]
+pub fn datetime(text: &str) -> Option<(NaiveDateTime, &str)> {
+    iso_datetimes(text)
+        .into_iter()
+        .rev()
+        .find(|(_, _, _, has_time)| *has_time)
+        .map(|(start, end, dt, _)| (dt, &text[start..end]))
+}
+
+/// Finds ISO-8601 dates and date-times, as (start, end, value, has_time).
+#[cfg(feature = "chrono")]
+fn iso_datetimes(text: &str) -> Vec<(usize, usize, NaiveDateTime, bool)> {
+    let b = text.as_bytes();
+    let digit_at = |i: usize| b.get(i).is_some_and(|c| c.is_ascii_digit());
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i + 10 <= b.len() {
+        let is_date = (i == 0 || !b[i - 1].is_ascii_alphanumeric())
+            && (0..10).all(|j| match j {
+                4 | 7 => b[i + j] == b'-',
+                _ => digit_at(i + j),
+            });
+        let date = match is_date {
+            true => NaiveDate::parse_from_str(&text[i..i + 10], "%Y-%m-%d").ok(),
+            false => None,
+        };
+        let Some(date) = date else {
+            i += 1;
+            continue;
+        };
+
+        let mut end = i + 10;
+        let mut dt = date.and_time(NaiveTime::MIN);
+        let mut has_time = false;
+        if matches!(b.get(end), Some(b'T') | Some(b' '))
+            && digit_at(end + 1)
+            && digit_at(end + 2)
+            && b.get(end + 3) == Some(&b':')
+        {
+            let mut t_end = end + 1;
+            while b
+                .get(t_end)
+                .is_some_and(|c| c.is_ascii_digit() || *c == b':' || *c == b'.')
+            {
+                t_end += 1;
+            }
+            while text[..t_end].ends_with(['.', ':']) {
+                t_end -= 1;
+            }
+            let t = &text[end + 1..t_end];
+            if let Ok(time) = NaiveTime::parse_from_str(t, "%H:%M:%S%.f")
+                .or_else(|_| NaiveTime::parse_from_str(t, "%H:%M"))
+            {
+                dt = date.and_time(time);
+                has_time = true;
+                end = t_end;
+                if b.get(end) == Some(&b'Z') {
+                    end += 1;
+                } else if let Some((offset, len)) = utc_offset(&text[end..]) {
+                    dt -= chrono::TimeDelta::seconds(offset);
+                    end += len;
+                }
+            }
         }
+
+        out.push((i, end, dt, has_time));
+        i = end;
     }
+    out
 }
 
-/// Extracts a leading or trailing markdown code block using the given opts as configuration.
-pub fn markdown_codeblock(text: &str, opts: &MarkdownOptions) -> Option<String> {
-    // TODO: Might want to deindent any global indentation if anything of the sort exists?
+/// Parses a UTC offset of the form `+HH:MM`, `+HHMM`, or `+HH`, returning the offset in
+/// seconds and its length.
+#[cfg(feature = "chrono")]
+fn utc_offset(s: &str) -> Option<(i64, usize)> {
+    let sign = match s.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let num = |from: usize| {
+        s.get(from..from + 2)
+            .filter(|d| d.bytes().all(|c| c.is_ascii_digit()))
+            .and_then(|d| d.parse::<i64>().ok())
+    };
+
+    let hours = num(1)?;
+    let (minutes, len) = match (s.as_bytes().get(3), num(3), num(4)) {
+        (Some(b':'), _, Some(m)) => (m, 6),
+        (_, Some(m), _) => (m, 5),
+        _ => (0, 3),
+    };
+    Some((sign * (hours * 3600 + minutes * 60), len))
+}
 
-    let mut candidates = vec![to_mdast(text, &ParseOptions::default()).unwrap()];
-    while let Some(node) = candidates.pop() {
-        // println!("NEXT: {:?}", node);
+/// Finds dates with the month written out, as (start, end, value).
+#[cfg(feature = "chrono")]
+fn natural_dates(text: &str) -> Vec<(usize, usize, NaiveDate)> {
+    const MONTHS: [&str; 12] = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    let month = |w: &str| {
+        let w = w.to_lowercase();
+        MONTHS
+            .iter()
+            .position(|m| w.len() >= 3 && m.starts_with(&w))
+            .map(|i| i as u32 + 1)
+    };
+    let day = |w: &str| {
+        ["st", "nd", "rd", "th"]
+            .iter()
+            .find_map(|s| w.strip_suffix(s))
+            .unwrap_or(w)
+            .parse::<u32>()
+            .ok()
+    };
+    let year = |w: &str| match w.len() {
+        4 => w.parse::<i32>().ok(),
+        _ => None,
+    };
 
-        // Enqueue any nested markdown objects for consideration.
-        match (node.children(), opts.from_back) {
-            (Some(c), false) => {
-                let mut c = c.iter().collect::<Vec<_>>();
-                c.reverse();
-                c.iter().for_each(|c| candidates.push((*c).clone()));
-            }
-            (Some(c), true) => {
-                c.iter().for_each(|c| candidates.push((*c).clone()));
+    let mut words: Vec<(usize, &str)> = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                words.push((s, &text[s..i]));
+                start = None;
             }
             _ => {}
         }
+    }
+    let word = |i: usize| words.get(i).map(|(_, w)| *w).unwrap_or("");
+    let end_of = |i: usize| words[i].0 + words[i].1.len();
 
-        if let Node::Code(Code { value, lang, .. }) = node {
-            match (lang, opts.lang, opts.require_lang) {
-                (Some(lang), Some(want_lang), _) => {
-                    if lang == want_lang {
-                        return Some(value.as_str().into());
-                    }
-                }
-                (None, _, false) => {
-                    return Some(value.as_str().into());
+    let mut out = Vec::new();
+    for k in 0..words.len() {
+        let Some(m) = month(word(k)) else {
+            continue;
+        };
+
+        // March 5th, 2024
+        if let (Some(d), Some(y)) = (day(word(k + 1)), year(word(k + 2))) {
+            if let Some(date) = NaiveDate::from_ymd_opt(y, m, d) {
+                out.push((words[k].0, end_of(k + 2), date));
+                continue;
+            }
+        }
+        // 5th (of) March 2024
+        let j = match k > 0 && word(k - 1).eq_ignore_ascii_case("of") {
+            true => k - 1,
+            false => k,
+        };
+        if j > 0 {
+            if let (Some(d), Some(y)) = (day(word(j - 1)), year(word(k + 1))) {
+                if let Some(date) = NaiveDate::from_ymd_opt(y, m, d) {
+                    out.push((words[j - 1].0, end_of(k + 1), date));
                 }
-                _ => {}
             }
         }
     }
-
-    None
+    out
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone)]
-/// Describes how to extract a multiclass/classification answer.
-///
-/// Excepts the LLM to output in the form `<key>: <chosen class>` towards
-/// the end of its output.
-pub struct EnumOptions<'a> {
-    key: &'a str,
-    classes: &'a [&'a str],
+/// Describes how to extract an answer matching a regular expression.
+pub struct RegexOptions {
+    regex: Regex,
+    from_back: bool,
 }
 
-impl<'a> From<&'a [&'a str]> for EnumOptions<'a> {
-    fn from(classes: &'a [&'a str]) -> Self {
+impl RegexOptions {
+    /// Compiles the given pattern, which by default matches the trailing occurrence.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(|regex| regex.into())
+    }
+
+    pub fn leading(self) -> Self {
+        RegexOptions {
+            from_back: false,
+            ..self
+        }
+    }
+}
+
+impl From<Regex> for RegexOptions {
+    fn from(regex: Regex) -> Self {
         Self {
-            key: &"answer",
-            classes,
+            regex,
+            from_back: true,
         }
     }
 }
 
-/// Extracts a trailing multiclass answer using the given opts as configuration.
+/// Extracts a capture group from the leading or trailing match of a regular expression,
+/// using the given opts as configuration. Group 0 is the entire match.
+///
+/// Returns [None] if there is no match, or the group did not participate in it.
 ///
 /// ```
-/// use mini_prompt::parse::multiclass;
-/// multiclass("uwu\nanswer: query", &["query", "action"][..].into());
+/// use mini_prompt::parse::{regex_capture, RegexOptions};
+/// let opts = RegexOptions::new(r"Severity: (\w+)").unwrap();
+/// let text = "Severity: low, on reflection...\nSeverity: high";
+/// assert_eq!(regex_capture(text, &opts, 1), Some("high"));
+/// assert_eq!(regex_capture(text, &opts.leading(), 1), Some("low"));
 /// ```
-pub fn multiclass<'a>(text: &str, opts: &'a EnumOptions) -> Option<&'a str> {
-    let mut lines: Vec<String> = text.split("\n").map(|s| s.trim().to_lowercase()).collect();
-    lines.reverse();
-
-    for line in lines.into_iter() {
-        if line.starts_with(&opts.key.to_lowercase()) {
-            if let Some(":") = line.get(opts.key.len()..opts.key.len() + 1) {
-                if let Some(answer) = line.get(opts.key.len() + 1..).map(|a| a.trim()) {
-                    for class in opts.classes.iter() {
-                        if class == &answer {
-                            return Some(class);
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    None
+pub fn regex_capture<'t>(text: &'t str, opts: &RegexOptions, group: usize) -> Option<&'t str> {
+    let caps = match opts.from_back {
+        true => opts.regex.captures_iter(text).last()?,
+        false => opts.regex.captures(text)?,
+    };
+    caps.get(group).map(|m| m.as_str())
 }
 
 #[allow(dead_code)]
@@ -134,7 +1474,7 @@ pub struct TagOptions<'a> {
 
 impl<'a> Default for TagOptions<'a> {
     fn default() -> Self {
-        Self { key: &"answer" }
+        Self { key: "answer" }
     }
 }
 
@@ -145,6 +1485,11 @@ impl From<&'static str> for TagOptions<'static> {
 }
 
 impl<'a> TagOptions<'a> {
+    /// Describes tags with the given name, i.e. `<key>answer</key>`.
+    pub fn new(key: &'a str) -> Self {
+        Self { key }
+    }
+
     /// Iterates over each usage of the tag in the given str, yielding the contents each time.
     pub fn iter<'s>(self, s: &'s str) -> TagIter<'a, 's> {
         TagIter {
@@ -177,47 +1522,141 @@ impl<'opts, 's> Iterator for TagIter<'opts, 's> {
 /// Extracts an answer between tags, using the given opts as configuration.
 ///
 /// If a tagged answer is present, the answer is returned as well as any remaining
-/// text after the answer.
-pub fn tagged<'a, 'b>(mut text: &'a str, opts: &'b TagOptions<'b>) -> Option<(&'a str, &'a str)> {
-    while text.len() > 0 {
-        let l_bracket = text.find('<');
-        let has_key = l_bracket
-            .map(|i| text[i + 1..].starts_with(opts.key))
-            .unwrap_or(false);
-        let has_close = l_bracket
-            .map(|i| text[i + 1 + opts.key.len()..].starts_with('>'))
-            .unwrap_or(false);
-
-        if has_key && has_close {
-            let body = &text[l_bracket.unwrap() + opts.key.len() + 2..];
-
-            let l_bracket = body.find("</");
-            let has_key = l_bracket
-                .map(|i| body[i + 2..].starts_with(opts.key))
-                .unwrap_or(false);
-            let has_close = l_bracket
-                .map(|i| body[i + 2 + opts.key.len()..].starts_with('>'))
-                .unwrap_or(false);
-
-            if has_key && has_close {
-                return Some((
-                    &body[..l_bracket.unwrap()],
-                    &body[l_bracket.unwrap() + 3 + opts.key.len()..],
-                ));
-            }
-            text = body;
-            continue;
+/// text after the answer. Attributes and whitespace within the tags are ignored, see
+/// [tagged_attrs].
+pub fn tagged<'a, 'b>(text: &'a str, opts: &'b TagOptions<'b>) -> Option<(&'a str, &'a str)> {
+    tagged_attrs(text, opts).map(|(_, answer, rest)| (answer, rest))
+}
+
+/// Extracts an answer between tags like [tagged], also returning the attributes of the
+/// opening tag. Attribute values may be quoted or unquoted, and attributes without a
+/// value map to an empty string. A self-closing tag has an empty answer.
+///
+/// ```
+/// use mini_prompt::parse::{tagged_attrs, TagOptions};
+/// let (attrs, answer, _) = tagged_attrs(
+///     "< answer confidence=\"high\" source='docs'>42</answer >",
+///     &TagOptions::default(),
+/// )
+/// .unwrap();
+/// assert_eq!(answer, "42");
+/// assert_eq!(attrs["confidence"], "high");
+/// assert_eq!(attrs["source"], "docs");
+/// ```
+pub fn tagged_attrs<'a, 'b>(
+    text: &'a str,
+    opts: &'b TagOptions<'b>,
+) -> Option<(HashMap<&'a str, &'a str>, &'a str, &'a str)> {
+    const ATTR: &str = r#"([^\s=>/"']+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>"']+)))?"#;
+    static ATTRS: OnceLock<Regex> = OnceLock::new();
+    static OPEN_REST: OnceLock<Regex> = OnceLock::new();
+    let attr_re = ATTRS.get_or_init(|| Regex::new(ATTR).unwrap());
+    let open_rest = OPEN_REST.get_or_init(|| {
+        Regex::new(&format!(r"^(?P<attrs>(?:\s+{})*)\s*(?P<slash>/?)>", ATTR)).unwrap()
+    });
+
+    // The tags are found by hand rather than with a regex built for the key, so
+    // nothing is compiled per call.
+    let (caps, rest) = text.match_indices('<').find_map(|(i, _)| {
+        let rest = text[i + 1..].trim_start().strip_prefix(opts.key)?;
+        Some((open_rest.captures(rest)?, rest))
+    })?;
+    let attrs = attr_re
+        .captures_iter(caps.name("attrs").unwrap().as_str())
+        .map(|a| {
+            let value = (2..=4).find_map(|i| a.get(i)).map_or("", |v| v.as_str());
+            (a.get(1).unwrap().as_str(), value)
+        })
+        .collect();
+
+    let body = &rest[caps.get(0).unwrap().end()..];
+    if !caps["slash"].is_empty() {
+        return Some((attrs, "", body));
+    }
+    let (start, end) = body.match_indices('<').find_map(|(i, _)| {
+        let after = body[i + 1..]
+            .trim_start()
+            .strip_prefix('/')?
+            .trim_start()
+            .strip_prefix(opts.key)?
+            .trim_start()
+            .strip_prefix('>')?;
+        Some((i, body.len() - after.len()))
+    })?;
+    Some((attrs, &body[..start], &body[end..]))
+}
+
+/// The tags reasoning models use to delimit their chain of thought.
+const REASONING_TAGS: [&str; 3] = ["think", "thinking", "reasoning"];
+
+/// Separates the chain of thought emitted by reasoning models from the rest of the
+/// output, returning the remaining text (trimmed) and the contents of each
+/// `<think>`, `<thinking>`, or `<reasoning>` section.
+///
+/// A section left unclosed by truncated output extends to the end of the text, and a
+/// closing tag with no opening tag, as emitted by some providers, ends a section
+/// starting at the beginning of the text.
+///
+/// ```
+/// use mini_prompt::parse::split_reasoning;
+/// let (answer, reasoning) = split_reasoning("<think>2+2 is 4</think>\nanswer: 4");
+/// assert_eq!(answer, "answer: 4");
+/// assert_eq!(reasoning, vec!["2+2 is 4"]);
+/// ```
+pub fn split_reasoning(text: &str) -> (String, Vec<&str>) {
+    let mut answer = String::new();
+    let mut reasoning = Vec::new();
+    let mut rest = text;
+
+    let first_open = REASONING_TAGS
+        .iter()
+        .filter_map(|t| rest.find(&format!("<{}>", t)))
+        .min();
+    if let Some((end, tag)) = REASONING_TAGS
+        .iter()
+        .filter_map(|t| rest.find(&format!("</{}>", t)).map(|i| (i, t)))
+        .min()
+        .filter(|(end, _)| first_open.map_or(true, |open| *end < open))
+    {
+        reasoning.push(rest[..end].trim());
+        rest = &rest[end + tag.len() + 3..];
+    }
+
+    while let Some((start, tag)) = REASONING_TAGS
+        .iter()
+        .filter_map(|t| rest.find(&format!("<{}>", t)).map(|i| (i, t)))
+        .min()
+    {
+        answer += &rest[..start];
+        let body = &rest[start + tag.len() + 2..];
+        let close = format!("</{}>", tag);
+        match body.find(&close) {
+            Some(end) => {
+                reasoning.push(body[..end].trim());
+                rest = &body[end + close.len()..];
+            }
+            None => {
+                reasoning.push(body.trim());
+                rest = "";
+            }
         }
-        break;
     }
+    answer += rest;
 
-    None
+    (answer.trim().to_string(), reasoning)
+}
+
+/// Removes the chain of thought emitted by reasoning models, see [split_reasoning].
+pub fn strip_reasoning(text: &str) -> String {
+    split_reasoning(text).0
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{markdown_codeblock, MarkdownOptions, TagOptions};
-    use super::{multiclass, tagged};
+    use super::{
+        markdown_codeblock, markdown_codeblocks, repair_json, MarkdownOptions, TagOptions,
+    };
+    use super::{multiclass, tagged, EnumOptions};
     use indoc::indoc;
 
     #[test]
@@ -283,6 +1722,379 @@ Some random earlier text.
         );
     }
 
+    #[test]
+    fn stream_markdown_codeblocks() {
+        use super::{MarkdownStream, StreamEvent};
+        let text = indoc! {"
+            First:
+            ````markdown
+            ```rust
+            fn main() {}
+            ```
+            ````
+              ~~~
+            `not a fence`
+            ~~~~
+            Done.
+            ```python
+            print(1)"};
+
+        // The events are the same however the text is chunked, once merged.
+        let merged = |events: Vec<StreamEvent>| {
+            let mut out: Vec<StreamEvent> = Vec::new();
+            for e in events {
+                match (out.last_mut(), e) {
+                    (Some(StreamEvent::Text(t)), StreamEvent::Text(s))
+                    | (Some(StreamEvent::Code(t)), StreamEvent::Code(s)) => t.push_str(&s),
+                    (_, e) => out.push(e),
+                }
+            }
+            out
+        };
+        let mut stream = MarkdownStream::default();
+        let mut whole = stream.push(text);
+        whole.extend(stream.finish());
+        for size in [1, 2, 5] {
+            let mut stream = MarkdownStream::default();
+            let mut events = Vec::new();
+            for chunk in text.as_bytes().chunks(size) {
+                events.extend(stream.push(std::str::from_utf8(chunk).unwrap()));
+            }
+            events.extend(stream.finish());
+            assert_eq!(merged(events), whole);
+        }
+
+        assert_eq!(
+            whole,
+            vec![
+                StreamEvent::Text("First:\n".into()),
+                StreamEvent::CodeOpen(Some("markdown".into())),
+                StreamEvent::Code("```rust\nfn main() {}\n```\n".into()),
+                StreamEvent::CodeClose {
+                    lang: Some("markdown".into()),
+                    code: "```rust\nfn main() {}\n```".into()
+                },
+                StreamEvent::CodeOpen(None),
+                StreamEvent::Code("`not a fence`\n".into()),
+                StreamEvent::CodeClose {
+                    lang: None,
+                    code: "`not a fence`".into()
+                },
+                StreamEvent::Text("Done.\n".into()),
+                StreamEvent::CodeOpen(Some("python".into())),
+                StreamEvent::Code("print(1)".into()),
+                StreamEvent::CodeClose {
+                    lang: Some("python".into()),
+                    code: "print(1)".into()
+                },
+            ]
+        );
+
+        // Text is emitted as soon as it can't be a fence.
+        let mut stream = MarkdownStream::default();
+        assert_eq!(stream.push("``"), vec![]);
+        assert_eq!(stream.push("x"), vec![StreamEvent::Text("``x".into())]);
+    }
+
+    #[test]
+    fn find_all_markdown_codeblocks() {
+        let text = indoc! {"
+            First:
+            ```python
+            print(1)
+            ```
+            - A list item with a block:
+              ```
+              plain
+              ```
+            ```json
+            {}
+            ```
+        "};
+        assert_eq!(
+            markdown_codeblocks(text, &MarkdownOptions::any().leading()).collect::<Vec<_>>(),
+            vec![
+                (Some("python".into()), "print(1)".into()),
+                (None, "plain".into()),
+                (Some("json".into()), "{}".into()),
+            ]
+        );
+        assert_eq!(
+            markdown_codeblocks(text, &MarkdownOptions::json())
+                .map(|(_, code)| code)
+                .collect::<Vec<_>>(),
+            vec!["{}", "plain"]
+        );
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn decode_yaml_codeblock() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Plan {
+            name: String,
+            steps: Vec<String>,
+        }
+
+        let want = Plan {
+            name: "deploy".into(),
+            steps: vec!["build".into(), "ship it: now".into()],
+        };
+        let text = indoc! {r#"
+            Here's the plan:
+            ```yaml
+            name: deploy
+            steps:
+              - build
+              - "ship it: now"
+            ```
+        "#};
+        assert_eq!(super::yaml_codeblock::<Plan>(text).unwrap(), want);
+        assert_eq!(
+            super::yaml_codeblock::<Plan>("name: deploy\nsteps: [build, 'ship it: now']").unwrap(),
+            want
+        );
+        assert!(super::yaml_codeblock::<Plan>("```yaml\nname: [\n```").is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn decode_toml_codeblock() {
+        #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+        struct Config {
+            name: String,
+            workers: u32,
+        }
+
+        let text = indoc! {r#"
+            ```toml
+            name = "api"
+            workers = 4
+            ```
+        "#};
+        let config: Config = super::toml_codeblock(text).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                name: "api".into(),
+                workers: 4,
+            }
+        );
+        assert_eq!(
+            super::toml_codeblock::<Config>(&toml::to_string(&config).unwrap()).unwrap(),
+            config
+        );
+        assert!(super::toml_codeblock::<Config>("name = ").is_err());
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn parse_markdown_table() {
+        let text = indoc! {"
+            Sure, here's a comparison:
+
+            | Name | Stars | Notes |
+            |:-----|------:|-------|
+            | `serde` | 9k | *fast* |
+            | toml | 1k |
+            | yaml | 2k | a | extra |
+
+            Let me know if you need more.
+        "};
+        let (headers, rows) = super::markdown_table(text).unwrap();
+        assert_eq!(headers, vec!["Name", "Stars", "Notes"]);
+        assert_eq!(
+            rows,
+            vec![
+                vec!["serde", "9k", "fast"],
+                vec!["toml", "1k", ""],
+                vec!["yaml", "2k", "a"],
+            ]
+        );
+        assert_eq!(super::markdown_table("No | table | here"), None);
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn parse_list_items() {
+        let text = indoc! {"
+            Here are some ideas:
+
+            - Build a `CLI`
+              1. Parse args
+              2. Print help
+
+                 Including examples.
+            - *Write* docs
+
+            And one more:
+
+            3. Ship it
+        "};
+        let items: Vec<_> = super::list_items(text)
+            .into_iter()
+            .map(|i| (i.text, i.depth, i.ordered))
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                ("Build a CLI".into(), 0, false),
+                ("Parse args".into(), 1, true),
+                ("Print help\nIncluding examples.".into(), 1, true),
+                ("Write docs".into(), 0, false),
+                ("Ship it".into(), 0, true),
+            ]
+        );
+        assert!(super::list_items("No lists here.").is_empty());
+    }
+
+    #[test]
+    fn split_reasoning_sections() {
+        use super::{split_reasoning, strip_reasoning};
+
+        assert_eq!(
+            split_reasoning("<think>\nhmm\n</think>\n\nIt's <b>4</b>."),
+            ("It's <b>4</b>.".into(), vec!["hmm"])
+        );
+        assert_eq!(
+            split_reasoning("A <reasoning>one</reasoning>B<thinking>two</thinking> C"),
+            ("A B C".into(), vec!["one", "two"])
+        );
+        assert_eq!(
+            split_reasoning("no opening tag</think>answer: 4"),
+            ("answer: 4".into(), vec!["no opening tag"])
+        );
+        assert_eq!(
+            split_reasoning("answer: 4 <think>truncated"),
+            ("answer: 4".into(), vec!["truncated"])
+        );
+        assert_eq!(strip_reasoning(" plain "), "plain");
+    }
+
+    #[test]
+    fn find_bare_json() {
+        use super::{bare_json, bare_json_regions};
+
+        let text = r#"I think [this] is it: {"a": [1, {"b": "}"}]} or maybe ["x"]. {broken"#;
+        assert_eq!(
+            bare_json_regions(text).collect::<Vec<_>>(),
+            vec![r#"{"a": [1, {"b": "}"}]}"#, r#"["x"]"#]
+        );
+        assert_eq!(bare_json(text), Some(r#"["x"]"#));
+        assert_eq!(bare_json("[unclosed {\"a\": 1}"), Some(r#"{"a": 1}"#));
+        assert_eq!(bare_json("no json here {}"), Some("{}"));
+        assert_eq!(bare_json("none at all"), None);
+
+        let v: serde_json::Value = super::json(r#"Sure! {"answer": 4} Hope that helps."#).unwrap();
+        assert_eq!(v, serde_json::json!({"answer": 4}));
+    }
+
+    #[test]
+    fn dedent_codeblocks() {
+        let text = indoc! {"
+            1. Define the function:
+               ```python
+                   def f():
+                   \treturn 1
+
+                   f()
+               ```
+        "};
+        assert_eq!(
+            markdown_codeblock(text, &MarkdownOptions::python()).unwrap(),
+            "def f():\n\treturn 1\n\nf()"
+        );
+        assert_eq!(
+            markdown_codeblock(text, &MarkdownOptions::python().expand_tabs(4)).unwrap(),
+            "def f():\n    return 1\n\nf()"
+        );
+        // Without the parser, the indentation of the list item is kept too.
+        #[cfg(feature = "markdown")]
+        assert_eq!(
+            markdown_codeblock(text, &MarkdownOptions::python().keep_indent()).unwrap(),
+            "    def f():\n    \treturn 1\n\n    f()"
+        );
+        assert_eq!(super::dedent("  a\n\n   b\n"), "a\n\n b\n");
+        assert_eq!(super::dedent("  a\n\t b"), "  a\n\t b");
+    }
+
+    #[test]
+    fn parse_and_apply_unified_diff() {
+        let text = indoc! {"
+            Two changes:
+
+            ```diff
+            --- a/src/lib.rs
+            +++ b/src/lib.rs
+            @@ -1,3 +1,3 @@
+             fn add(a: u32, b: u32) -> u32 {
+            -    a - b
+            +    a + b
+             }
+            @@ -40,2 +40,3 @@
+             fn one() -> u32 {
+            +    // The loneliest number.
+                 1
+
+            --- /dev/null
+            +++ b/README.md
+            @@ -0,0 +1 @@
+            +# Adder
+            ```
+
+            Let me know if you need anything else.
+        "};
+        let diffs = super::unified_diff(text);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].old_path.as_deref(), Some("src/lib.rs"));
+        assert_eq!(diffs[0].hunks.len(), 2);
+        assert_eq!(diffs[0].hunks[1].old_start, Some(40));
+        assert_eq!(diffs[0].hunks[1].lines.len(), 3);
+        assert_eq!(diffs[1].old_path, None);
+
+        let mut files = std::collections::HashMap::from([(
+            "src/lib.rs".to_string(),
+            "fn add(a: u32, b: u32) -> u32 {\n    a - b\n}\n\nfn one() -> u32 {\n    1\n}\n"
+                .to_string(),
+        )]);
+        super::apply_diffs(&mut files, &diffs).unwrap();
+        assert_eq!(
+            files["src/lib.rs"],
+            "fn add(a: u32, b: u32) -> u32 {\n    a + b\n}\n\nfn one() -> u32 {\n    // The loneliest number.\n    1\n}\n"
+        );
+        assert_eq!(files["README.md"], "# Adder\n");
+
+        // Hunks without headers can still be applied directly, but not by path.
+        let diffs = super::unified_diff(
+            "@@ @@
+-a
++b
+ c
+",
+        );
+        assert_eq!(
+            diffs[0]
+                .apply(
+                    "x
+a
+c"
+                )
+                .unwrap(),
+            "x
+b
+c"
+        );
+        assert!(diffs[0]
+            .apply(
+                "x
+a
+d"
+            )
+            .is_err());
+        assert!(super::apply_diffs(&mut files, &diffs).is_err());
+        assert_eq!(files.len(), 2);
+    }
+
     #[test]
     fn parse_multiclass_simple() {
         assert_eq!(
@@ -328,6 +2140,173 @@ Some random earlier text.
         );
     }
 
+    #[test]
+    fn parse_multiclass_fuzzy() {
+        let classes = ["Query", "Action", "Query Plan"];
+        let opts = EnumOptions::from(&classes[..]).fuzzy();
+        assert_eq!(multiclass("answer: query.", &opts), Some("Query"));
+        assert_eq!(multiclass("*Answer*: `ACTION`", &opts), Some("Action"));
+        assert_eq!(multiclass("answer: query plan!", &opts), Some("Query Plan"));
+        assert_eq!(
+            multiclass("answer: action - it's a request", &opts),
+            Some("Action")
+        );
+        assert_eq!(multiclass("answer: actions", &opts), None);
+        assert_eq!(multiclass("answer: query.", &classes[..].into()), None);
+
+        let opts = opts.max_edit_distance(2).key("class");
+        assert_eq!(multiclass("class: actons", &opts), Some("Action"));
+        assert_eq!(multiclass("answer: actons", &opts), None);
+        assert_eq!(super::edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn parse_boolean() {
+        use super::boolean;
+
+        let cases = [
+            ("Yes.", Some(true)),
+            ("TRUE", Some(true)),
+            ("Affirmative, captain", Some(true)),
+            ("Considering it all...\n\nthe answer is: no", Some(false)),
+            ("No, it's true that it's raining but...", Some(false)),
+            ("This isn't true.", Some(false)),
+            ("That is not incorrect", Some(true)),
+            ("answer: yes\n", Some(true)),
+            ("I can't say.", None),
+            ("", None),
+        ];
+        for (text, want) in cases {
+            assert_eq!(boolean(text).map(|(b, _)| b), want, "parsing {:?}", text);
+        }
+        assert_eq!(boolean("Well... Yeah!"), Some((true, "Yeah")));
+    }
+
+    #[test]
+    fn parse_number() {
+        use super::number;
+
+        let cases = [
+            ("The answer is 42.", Some(42.0)),
+            ("answer: -3.5", Some(-3.5)),
+            ("It weighs about 12kg", Some(12.0)),
+            ("roughly 6.02e23 molecules", Some(6.02e23)),
+            ("1.5E-3 seconds", Some(1.5e-3)),
+            ("There are 1,000,000 of them, or 12,34 ish", Some(34.0)),
+            ("Total: 1,234,567", Some(1234567.0)),
+            ("+.5%", Some(0.5)),
+            ("Between 3 and 5, so 4.", Some(4.0)),
+            ("Using gpt4 and x86", None),
+            ("no numbers here", None),
+        ];
+        for (text, want) in cases {
+            assert_eq!(number(text).map(|(n, _)| n), want, "parsing {:?}", text);
+        }
+        assert_eq!(number("x = 3e, y = 2-1"), Some((1.0, "1")));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn parse_dates() {
+        use super::{date, datetime};
+
+        let cases = [
+            ("Due 2024-02-29.", Some("2024-02-29")),
+            (
+                "Not a date: 2023-02-29, but 2023-02-28 is",
+                Some("2023-02-28"),
+            ),
+            (
+                "from 2024-01-01T09:00:00Z until March 5, 2024",
+                Some("2024-03-05"),
+            ),
+            ("on Sept 9th 2025", Some("2025-09-09")),
+            (
+                "on 1 Jan 2020 and the 2nd of February, 2021",
+                Some("2021-02-02"),
+            ),
+            ("in may 2024", None),
+            ("build 12024-01-01x", None),
+        ];
+        for (text, want) in cases {
+            assert_eq!(
+                date(text).map(|(d, _)| d.to_string()).as_deref(),
+                want,
+                "parsing {:?}",
+                text
+            );
+        }
+
+        let cases = [
+            ("at 2024-03-05T14:30.", Some("2024-03-05 14:30:00")),
+            (
+                "at 2024-03-05 14:30:15.250",
+                Some("2024-03-05 14:30:15.250"),
+            ),
+            ("2024-03-05T23:30:00-02:00", Some("2024-03-06 01:30:00")),
+            ("2024-03-05T01:00+0530", Some("2024-03-04 19:30:00")),
+            (
+                "2024-03-05T10:00:00Z then 2024-03-06",
+                Some("2024-03-05 10:00:00"),
+            ),
+            ("2024-03-05", None),
+        ];
+        for (text, want) in cases {
+            assert_eq!(
+                datetime(text).map(|(d, _)| d.to_string()).as_deref(),
+                want,
+                "parsing {:?}",
+                text
+            );
+        }
+        assert_eq!(
+            datetime("When: 2024-03-05T10:00:00+01:00.").unwrap().1,
+            "2024-03-05T10:00:00+01:00"
+        );
+    }
+
+    #[test]
+    fn parse_regex_capture() {
+        use super::{regex_capture, RegexOptions};
+
+        let opts = RegexOptions::new(r"(?m)^Score: (\d+)/10( \(.*\))?$").unwrap();
+        let text = indoc! {"
+            Score: 3/10 (harsh)
+            Reconsidering.
+            Score: 7/10
+        "};
+        assert_eq!(regex_capture(text, &opts, 1), Some("7"));
+        assert_eq!(regex_capture(text, &opts, 2), None);
+        assert_eq!(
+            regex_capture(text, &opts.clone().leading(), 2),
+            Some(" (harsh)")
+        );
+        assert_eq!(regex_capture(text, &opts, 0), Some("Score: 7/10"));
+        assert_eq!(regex_capture(text, &opts, 3), None);
+        assert_eq!(regex_capture("nothing", &opts, 0), None);
+        assert!(RegexOptions::new("(").is_err());
+    }
+
+    #[test]
+    fn parse_keyed_values() {
+        let text = indoc! {"
+            Here's the triage:
+
+            - **Title:** Crash on startup
+            - __Severity__: medium
+            Summary: The app crashes: every time.
+            Severity: `high`
+            severityx: low
+            Notes:
+        "};
+        let values = super::keyed_values(text, &["title", "Severity", "summary", "notes"]);
+        assert_eq!(values["title"], "Crash on startup");
+        assert_eq!(values["Severity"], "`high`");
+        assert_eq!(values["summary"], "The app crashes: every time.");
+        assert_eq!(values["notes"], "");
+        assert_eq!(values.len(), 4);
+    }
+
     #[test]
     fn parse_tagged_simple() {
         assert_eq!(
@@ -360,6 +2339,45 @@ Some random earlier text.
         );
     }
 
+    #[test]
+    fn parse_tagged_after_other_tags() {
+        assert_eq!(
+            tagged(
+                "<thought>hmm</thought> <b>bold</b> <answer>query</answer>",
+                &TagOptions::default()
+            ),
+            Some(("query", ""))
+        );
+        assert_eq!(tagged("<a", &TagOptions::default()), None);
+    }
+
+    #[test]
+    fn parse_tagged_attributes() {
+        use super::tagged_attrs;
+        let opts = TagOptions::default();
+        let (attrs, answer, rest) = tagged_attrs(
+            "<answers>no</answers><answer\n  id=3 note=\"a > b\" final>\nyes\n</ answer> ok",
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(answer, "\nyes\n");
+        assert_eq!(rest, " ok");
+        assert_eq!(attrs.len(), 3);
+        assert_eq!(
+            (attrs["id"], attrs["note"], attrs["final"]),
+            ("3", "a > b", "")
+        );
+
+        let (attrs, answer, rest) = tagged_attrs("<answer kind='none' />rest", &opts).unwrap();
+        assert_eq!((attrs["kind"], answer, rest), ("none", "", "rest"));
+
+        assert_eq!(tagged("<answer x=1>unclosed", &opts), None);
+        assert_eq!(
+            tagged("<a.b>1</a.b>", &TagOptions::new("a.b")),
+            Some(("1", ""))
+        );
+    }
+
     #[test]
     fn parse_tagged_missing() {
         assert_eq!(
@@ -383,4 +2401,27 @@ Some random earlier text.
             None
         );
     }
+
+    #[test]
+    fn repair_malformed_json() {
+        let cases = [
+            (r#"{"a": 1,}"#, r#"{"a": 1}"#),
+            ("[1, 2,\n]", "[1, 2\n]"),
+            ("{'it\\'s': 'say \"hi\"'}", r#"{"it's": "say \"hi\""}"#),
+            ("{a: 1, b_2: [x]}", r#"{"a": 1, "b_2": [x]}"#),
+            ("{\"a\": \"line\none\"}", r#"{"a": "line\none"}"#),
+            ("[None, True, 'None']", r#"[null, true, "None"]"#),
+            (r#"{"a": [1, {"b": 2"#, r#"{"a": [1, {"b": 2}]}"#),
+            (r#"{"a": 1, "b":"#, r#"{"a": 1, "b": null}"#),
+            (r#"{"a": 1, "b"#, r#"{"a": 1, "b": null}"#),
+            (r#"{"a": 1,"#, r#"{"a": 1}"#),
+            (r#"["x", "y"#, r#"["x", "y"]"#),
+        ];
+        for (input, want) in cases {
+            assert_eq!(repair_json(input), want, "repairing {}", input);
+        }
+
+        let v: serde_json::Value = super::json("{a: 'b', c: [1, 2").unwrap();
+        assert_eq!(v, serde_json::json!({"a": "b", "c": [1, 2]}));
+    }
 }
@@ -0,0 +1,308 @@
+//! Redacting personal information from prompts before they are sent to a provider.
+//!
+//! Each email address, phone number, credit card number, or custom pattern is replaced
+//! with a placeholder such as `[EMAIL_1]`. The original values are kept locally, so
+//! placeholders in the model's response can be swapped back.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "openrouter")] {
+//! # use mini_prompt::*;
+//! use mini_prompt::pii::{Redacted, Redactor};
+//!
+//! let redactor = Redactor::new()
+//!     .with_pattern("ACCOUNT", regex::Regex::new(r"\bACC-\d{6}\b").unwrap());
+//! let backend = Redacted::new(callers::Openrouter::<models::Gemma27B3>::default(), redactor);
+//! # tokio::task::spawn(async move {
+//! // The provider sees "Write a short email to [EMAIL_1] about [ACCOUNT_1] being closed.",
+//! // and placeholders in the response are replaced with the original values.
+//! let resp = backend
+//!     .simple_call("Write a short email to jo@example.com about ACC-123456 being closed.")
+//!     .await;
+//! # });
+//! # }
+//! ```
+
+use crate::models::Model;
+use crate::{CallBase, CallErr, CallResp, Message, ModelCaller, Turn};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Finds personal information in text, replacing it with placeholders.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    /// The kind of information each pattern matches, and the pattern.
+    patterns: Vec<(String, Regex)>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Redactor {
+    /// Constructs a redactor for email addresses, credit card numbers, and phone numbers.
+    pub fn new() -> Self {
+        let builtin = [
+            ("EMAIL", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+            ("CARD", r"\b\d(?:[ -]?\d){12,18}\b"),
+            (
+                "PHONE",
+                r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{2,4}\)|\b\d{2,4})[\s.-]?\d{3,4}[\s.-]?\d{3,4}\b",
+            ),
+        ];
+        Self {
+            patterns: builtin
+                .into_iter()
+                .map(|(kind, p)| (kind.to_string(), Regex::new(p).unwrap()))
+                .collect(),
+        }
+    }
+
+    /// Constructs a redactor with no patterns.
+    pub fn empty() -> Self {
+        Self { patterns: vec![] }
+    }
+
+    /// Also redacts text matching the pattern, using placeholders named after the kind,
+    /// which should be uppercase, such as `EMPLOYEE_ID`.
+    pub fn with_pattern<S: Into<String>>(mut self, kind: S, pattern: Regex) -> Self {
+        self.patterns.push((kind.into(), pattern));
+        self
+    }
+
+    /// Replaces personal information in the text with placeholders, recording the
+    /// original values. A value seen before is given the same placeholder.
+    pub fn redact(&self, text: &str, placeholders: &mut Placeholders) -> String {
+        let mut out = text.to_string();
+        for (kind, pattern) in self.patterns.iter() {
+            out = pattern
+                .replace_all(&out, |caps: &regex::Captures| {
+                    let value = &caps[0];
+                    // Digit runs which fail the Luhn check aren't card numbers.
+                    if kind == "CARD" && !luhn(value) {
+                        return value.to_string();
+                    }
+                    placeholders.insert(kind, value)
+                })
+                .into_owned();
+        }
+        out
+    }
+}
+
+/// Returns true if the digits in the text pass the Luhn checksum used by card numbers.
+fn luhn(text: &str) -> bool {
+    let sum: u32 = text
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| match (i % 2, d * 2) {
+            (0, _) => d,
+            (_, d) if d > 9 => d - 9,
+            (_, d) => d,
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+/// The original values of placeholders inserted by a [Redactor].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Placeholders {
+    /// Original values, keyed by placeholder.
+    originals: HashMap<String, String>,
+    /// Placeholders, keyed by original value.
+    placeholders: HashMap<String, String>,
+    /// The number of placeholders of each kind.
+    counts: HashMap<String, usize>,
+}
+
+impl Placeholders {
+    /// Returns the placeholder for the value, creating one if the value is new.
+    fn insert(&mut self, kind: &str, value: &str) -> String {
+        if let Some(p) = self.placeholders.get(value) {
+            return p.clone();
+        }
+        let n = self.counts.entry(kind.to_string()).or_default();
+        *n += 1;
+        let p = format!("[{}_{}]", kind, n);
+        self.originals.insert(p.clone(), value.to_string());
+        self.placeholders.insert(value.to_string(), p.clone());
+        p
+    }
+
+    /// Returns the original value of the placeholder, such as `[EMAIL_1]`.
+    pub fn original(&self, placeholder: &str) -> Option<&str> {
+        self.originals.get(placeholder).map(|s| s.as_str())
+    }
+
+    /// Replaces each placeholder in the text with its original value.
+    pub fn restore(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for (p, original) in self.originals.iter() {
+            if out.contains(p.as_str()) {
+                out = out.replace(p.as_str(), original);
+            }
+        }
+        out
+    }
+
+    /// Returns the number of distinct values which have been redacted.
+    pub fn len(&self) -> usize {
+        self.originals.len()
+    }
+
+    /// Returns true if nothing has been redacted.
+    pub fn is_empty(&self) -> bool {
+        self.originals.is_empty()
+    }
+}
+
+/// Wraps a [ModelCaller], redacting personal information from everything sent to the
+/// provider other than the system prompt: the instructions, along with the text, tool
+/// calls, and tool results of every turn.
+///
+/// Placeholders in the response, including in tool call arguments, are replaced with
+/// their original values, so tools run locally see the real values. Placeholders are
+/// kept for the life of the wrapper, so a value keeps its placeholder across calls.
+pub struct Redacted<B: ModelCaller> {
+    backend: B,
+    redactor: Redactor,
+    placeholders: Mutex<Placeholders>,
+}
+
+impl<B: ModelCaller> Redacted<B> {
+    /// Constructs a caller which redacts requests to the given backend.
+    pub fn new(backend: B, redactor: Redactor) -> Self {
+        Self {
+            backend,
+            redactor,
+            placeholders: Mutex::default(),
+        }
+    }
+
+    /// Returns the placeholders inserted so far.
+    pub fn placeholders(&self) -> Placeholders {
+        self.placeholders.lock().unwrap().clone()
+    }
+
+    /// Consumes the wrapper, returning the underlying backend.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+}
+
+impl<B: ModelCaller> ModelCaller for Redacted<B> {
+    fn get_model(&self) -> impl Model {
+        self.backend.get_model()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.backend.supports_response_schema()
+    }
+
+    fn endpoint(&self) -> String {
+        self.backend.endpoint()
+    }
+
+    async fn call(&self, params: CallBase, mut turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        let params = {
+            let redactor = &self.redactor;
+            let placeholders = &mut *self.placeholders.lock().unwrap();
+            let params = CallBase {
+                instructions: redactor.redact(&params.instructions, placeholders),
+                ..params
+            };
+            for m in turns.iter_mut().flat_map(|t| t.content.iter_mut()) {
+                match m {
+                    Message::Text { text } => *text = redactor.redact(text, placeholders).into(),
+                    Message::ToolCall { arguments, .. } => {
+                        *arguments = redactor.redact(arguments, placeholders)
+                    }
+                    Message::ToolResult { result, .. } => {
+                        *result = redactor.redact(result, placeholders).into()
+                    }
+                    // Thinking is signed by the provider, so must be sent back unchanged.
+                    Message::Thinking { .. } | Message::RedactedThinking { .. } => {}
+                }
+            }
+            params
+        };
+
+        let mut resp = self.backend.call(params, turns).await?;
+        let placeholders = self.placeholders.lock().unwrap();
+        for m in resp.content.content.iter_mut() {
+            match m {
+                Message::Text { text } => *text = placeholders.restore(text).into(),
+                Message::ToolCall { arguments, .. } => *arguments = placeholders.restore(arguments),
+                Message::ToolResult { result, .. } => *result = placeholders.restore(result).into(),
+                Message::Thinking { .. } | Message::RedactedThinking { .. } => {}
+            }
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scripted;
+    use crate::Role;
+
+    #[test]
+    fn redact_and_restore() {
+        let redactor = Redactor::new().with_pattern("ID", Regex::new(r"\bE\d{4}\b").unwrap());
+        let mut placeholders = Placeholders::default();
+        let text = "Mail jo@example.com or call +61 (02) 9876 5432 or 555-123-4567 about \
+            card 4111 1111 1111 1111. Ask jo@example.com for E1234.";
+        let redacted = redactor.redact(text, &mut placeholders);
+        assert_eq!(
+            redacted,
+            "Mail [EMAIL_1] or call [PHONE_1] or [PHONE_2] about card [CARD_1]. \
+            Ask [EMAIL_1] for [ID_1]."
+        );
+        assert_eq!(placeholders.len(), 5);
+        assert_eq!(placeholders.original("[PHONE_2]"), Some("555-123-4567"));
+        assert_eq!(placeholders.restore(&redacted), text);
+
+        assert!(luhn("4111-1111-1111-1111"));
+        assert!(!luhn("1234 5678 9012 3456"));
+    }
+
+    #[tokio::test]
+    async fn redacted_caller() {
+        let backend = Scripted::new(vec![Scripted::tool_calls(&[(
+            "send",
+            r#"{"to": "[EMAIL_1]"}"#,
+        )])]);
+        let backend = Redacted::new(backend, Redactor::new());
+        let turns = vec![Turn {
+            role: Role::User,
+            content: vec![Message::text("My email is jo@example.com.")],
+        }];
+        let resp = backend
+            .call(
+                CallBase {
+                    instructions: "Reply to jo@example.com.".into(),
+                    ..Default::default()
+                },
+                turns,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.content.content[0],
+            Message::ToolCall {
+                id: "call_0".into(),
+                name: "send".into(),
+                arguments: r#"{"to": "jo@example.com"}"#.into(),
+            }
+        );
+
+        let (params, turns) = &backend.into_inner().seen()[0];
+        assert_eq!(params.instructions, "Reply to [EMAIL_1].");
+        assert_eq!(turns[0].text(), "My email is [EMAIL_1].");
+    }
+}
@@ -0,0 +1,304 @@
+//! Composing prompts, calls, and parsing into reusable chains of steps.
+//!
+//! Each step takes the output of the previous step as its input. Steps are combined
+//! with [StepExt::then], and the resulting chain can be run any number of times. The
+//! output of every step is recorded as it runs, which is useful for logging.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "openrouter")] {
+//! # use mini_prompt::*;
+//! use mini_prompt::pipeline::{call, parse, render, Step, StepExt};
+//! use mini_prompt::prompt::Template;
+//!
+//! let chain = render(Template::new("List three facts about {{topic}}."), "topic")
+//!     .then(call(CallBase::default()))
+//!     .then(render(
+//!         Template::new("How many of these facts are about animals?\n\n{{facts}}"),
+//!         "facts",
+//!     ))
+//!     .then(call(CallBase::default()))
+//!     .then(parse(|text| parse::number(text).map(|(n, _)| n).ok_or("no number")));
+//!
+//! let backend = callers::Openrouter::<models::Gemma27B3>::default();
+//! # tokio::task::spawn(async move {
+//! let mut trace = vec![];
+//! let n = chain.run(&backend, "the ocean", &mut trace).await;
+//! for record in trace {
+//!     println!("{}: {}", record.step, record.value);
+//! }
+//! # });
+//! # }
+//! ```
+
+use crate::prompt::Template;
+use crate::{CallBase, CallErr, ModelCaller, ParseAttempt};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// The output of a step of a chain, recorded as the chain runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The kind of step, such as `call`.
+    pub step: &'static str,
+    /// The output of the step. Parsed and mapped values are formatted with [Debug].
+    pub value: String,
+}
+
+/// A step which transforms its input into an output, possibly by calling a model.
+///
+/// Every step also implements [StepExt], so it can be chained.
+pub trait Step<B: ModelCaller, I: Send>: StepExt + Send + Sync {
+    type Output: Send;
+
+    /// Runs the step, appending a [Record] of each output to `trace`.
+    fn run(
+        &self,
+        backend: &B,
+        input: I,
+        trace: &mut Vec<Record>,
+    ) -> impl std::future::Future<Output = Result<Self::Output, CallErr>> + Send;
+}
+
+/// Chains steps together, see [Then].
+///
+/// This is implemented for each step rather than for every type, so it doesn't clash
+/// with other `then` methods, such as that of `FutureExt`. Custom steps implement it
+/// with `impl StepExt for MyStep {}`.
+pub trait StepExt: Sized {
+    /// Returns a step which runs this step, then the given step on its output.
+    fn then<S>(self, next: S) -> Then<Self, S> {
+        Then(self, next)
+    }
+}
+
+/// Runs one step, then another on its output. Constructed with [StepExt::then].
+pub struct Then<A, S>(A, S);
+
+impl<A, S> StepExt for Then<A, S> {}
+
+impl<B, I, A, S> Step<B, I> for Then<A, S>
+where
+    B: ModelCaller,
+    I: Send,
+    A: Step<B, I>,
+    S: Step<B, A::Output>,
+{
+    type Output = S::Output;
+
+    async fn run(
+        &self,
+        backend: &B,
+        input: I,
+        trace: &mut Vec<Record>,
+    ) -> Result<S::Output, CallErr> {
+        let mid = self.0.run(backend, input, trace).await?;
+        self.1.run(backend, mid, trace).await
+    }
+}
+
+/// Renders a template with the input as the value of a variable, see [render].
+pub struct Render {
+    template: Template,
+    var: String,
+}
+
+/// Returns a step which renders the template, substituting the input for the given
+/// variable.
+pub fn render<S: Into<String>>(template: Template, var: S) -> Render {
+    Render {
+        template,
+        var: var.into(),
+    }
+}
+
+impl StepExt for Render {}
+
+impl<B: ModelCaller, I: AsRef<str> + Send> Step<B, I> for Render {
+    type Output = String;
+
+    async fn run(&self, _: &B, input: I, trace: &mut Vec<Record>) -> Result<String, CallErr> {
+        let text = self.template.render(&[(&self.var, input.as_ref())])?;
+        trace.push(Record {
+            step: "render",
+            value: text.clone(),
+        });
+        Ok(text)
+    }
+}
+
+/// Calls the model with the input as instructions, see [call].
+pub struct Call {
+    params: CallBase,
+}
+
+/// Returns a step which calls the model, outputting the text of its response.
+///
+/// The input is used as the instructions of the call, following any instructions
+/// already set in the given params.
+pub fn call(params: CallBase) -> Call {
+    Call { params }
+}
+
+impl StepExt for Call {}
+
+impl<B: ModelCaller, I: Into<String> + Send> Step<B, I> for Call {
+    type Output = String;
+
+    async fn run(&self, backend: &B, input: I, trace: &mut Vec<Record>) -> Result<String, CallErr> {
+        let input = input.into();
+        let mut params = self.params.clone();
+        params.instructions = match params.instructions.is_empty() {
+            true => input,
+            false => params.instructions + "\n\n" + &input,
+        };
+
+        let text = backend.call(params, vec![]).await?.content.text();
+        trace.push(Record {
+            step: "call",
+            value: text.clone(),
+        });
+        Ok(text)
+    }
+}
+
+/// Parses the input, see [parse].
+pub struct Parse<F, T> {
+    parse: F,
+    _output: PhantomData<fn() -> T>,
+}
+
+/// Returns a step which parses the input with the given function, such as one from
+/// [crate::parse]. Parse errors are reported as [CallErr::ParseFailed].
+pub fn parse<F, T, E>(parse: F) -> Parse<F, T>
+where
+    F: Fn(&str) -> Result<T, E>,
+    E: ToString,
+{
+    Parse {
+        parse,
+        _output: PhantomData,
+    }
+}
+
+impl<F, T> StepExt for Parse<F, T> {}
+
+impl<B, I, F, T, E> Step<B, I> for Parse<F, T>
+where
+    B: ModelCaller,
+    I: AsRef<str> + Send,
+    F: Fn(&str) -> Result<T, E> + Send + Sync,
+    T: Debug + Send,
+    E: ToString,
+{
+    type Output = T;
+
+    async fn run(&self, _: &B, input: I, trace: &mut Vec<Record>) -> Result<T, CallErr> {
+        let input = input.as_ref();
+        let value = (self.parse)(input).map_err(|e| {
+            CallErr::ParseFailed(vec![ParseAttempt {
+                output: input.to_string(),
+                err: e.to_string(),
+            }])
+        })?;
+        trace.push(Record {
+            step: "parse",
+            value: format!("{:?}", value),
+        });
+        Ok(value)
+    }
+}
+
+/// Transforms the input with a function, see [map].
+pub struct Map<F, T> {
+    map: F,
+    _output: PhantomData<fn() -> T>,
+}
+
+/// Returns a step which transforms the input with the given function.
+pub fn map<F, T>(map: F) -> Map<F, T> {
+    Map {
+        map,
+        _output: PhantomData,
+    }
+}
+
+impl<F, T> StepExt for Map<F, T> {}
+
+impl<B, I, F, T> Step<B, I> for Map<F, T>
+where
+    B: ModelCaller,
+    I: Send,
+    F: Fn(I) -> T + Send + Sync,
+    T: Debug + Send,
+{
+    type Output = T;
+
+    async fn run(&self, _: &B, input: I, trace: &mut Vec<Record>) -> Result<T, CallErr> {
+        let value = (self.map)(input);
+        trace.push(Record {
+            step: "map",
+            value: format!("{:?}", value),
+        });
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scripted;
+    // Chaining steps must not clash with the combinators of futures.
+    use futures::FutureExt;
+
+    #[tokio::test]
+    async fn run_chain() {
+        let chain = render(Template::new("Name a {{thing}}."), "thing")
+            .then(call(CallBase {
+                instructions: "Be brief.".into(),
+                ..Default::default()
+            }))
+            .then(map(|s: String| s.trim().to_uppercase()))
+            .then(render(
+                Template::new("How many letters in {{word}}?"),
+                "word",
+            ))
+            .then(call(CallBase::default()))
+            .then(parse(|text: &str| text.trim().parse::<u32>()));
+
+        let backend = Scripted::new(vec![Scripted::text(" Red "), Scripted::text("3")]);
+        let mut trace = vec![];
+        let n = chain
+            .run(&backend, "colour", &mut trace)
+            .then(|r| async move { r.unwrap() })
+            .await;
+        assert_eq!(n, 3);
+        assert_eq!(
+            backend.seen()[0].0.instructions,
+            "Be brief.\n\nName a colour."
+        );
+        assert_eq!(backend.seen()[1].0.instructions, "How many letters in RED?");
+        assert_eq!(
+            trace
+                .iter()
+                .map(|r| (r.step, r.value.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("render", "Name a colour."),
+                ("call", " Red "),
+                ("map", "\"RED\""),
+                ("render", "How many letters in RED?"),
+                ("call", "3"),
+                ("parse", "3"),
+            ]
+        );
+
+        // Chains can be run again, and the trace shows how far a failed run got.
+        let backend = Scripted::new(vec![Scripted::text("Blue"), Scripted::text("four")]);
+        let mut trace = vec![];
+        match chain.run(&backend, "colour", &mut trace).await {
+            Err(CallErr::ParseFailed(attempts)) => assert_eq!(attempts[0].output, "four"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert_eq!(trace.len(), 5);
+    }
+}
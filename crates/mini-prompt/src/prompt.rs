@@ -0,0 +1,286 @@
+//! Utilities for building prompts.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A prompt with `{{variable}}` placeholders, which are substituted when rendered.
+///
+/// Single braces are left untouched, so templates can contain JSON or code examples
+/// without escaping. A literal `{{` is written as `\{{`. Substituted values are
+/// never themselves expanded, so user-provided data cannot inject placeholders.
+///
+/// ```
+/// # use mini_prompt::prompt::Template;
+/// let t = Template::new("Summarize {{doc}} in {{ lang }}, as {\"summary\": ...}");
+/// assert_eq!(
+///     t.render(&[("doc", "the {{lang}} report"), ("lang", "French")]).unwrap(),
+///     "Summarize the {{lang}} report in French, as {\"summary\": ...}",
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Literal(&'a str),
+    Var(&'a str),
+}
+
+impl Template {
+    /// Constructs a template from the given source text.
+    pub fn new<S: Into<String>>(source: S) -> Self {
+        Self {
+            source: source.into(),
+        }
+    }
+
+    /// Returns the source text of the template.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn segments(&self) -> Result<Vec<Segment<'_>>, String> {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while let Some(i) = self.source[pos..].find("{{").map(|i| pos + i) {
+            if self.source[..i].ends_with('\\') {
+                out.push(Segment::Literal(&self.source[pos..i - 1]));
+                out.push(Segment::Literal("{{"));
+                pos = i + 2;
+                continue;
+            }
+
+            out.push(Segment::Literal(&self.source[pos..i]));
+            let end = self.source[i + 2..]
+                .find("}}")
+                .map(|e| i + 2 + e)
+                .ok_or_else(|| format!("unclosed '{{{{' at position {}", i))?;
+            let name = self.source[i + 2..end].trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(format!(
+                    "invalid variable name '{}' at position {}",
+                    name, i
+                ));
+            }
+            out.push(Segment::Var(name));
+            pos = end + 2;
+        }
+        out.push(Segment::Literal(&self.source[pos..]));
+        Ok(out)
+    }
+
+    /// Returns the names of the variables used by the template, in order of first use.
+    pub fn variables(&self) -> Result<Vec<&str>, String> {
+        let mut out: Vec<&str> = Vec::new();
+        for s in self.segments()? {
+            if let Segment::Var(name) = s {
+                if !out.contains(&name) {
+                    out.push(name);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Renders the template, substituting each placeholder with the value of the
+    /// variable of the same name.
+    ///
+    /// Returns an error if the template is malformed or a variable has no value.
+    /// Values for variables not used by the template are ignored.
+    pub fn render(&self, vars: &[(&str, &str)]) -> Result<String, String> {
+        let mut out = String::with_capacity(self.source.len());
+        for s in self.segments()? {
+            match s {
+                Segment::Literal(text) => out += text,
+                Segment::Var(name) => {
+                    out += vars
+                        .iter()
+                        .find(|(k, _)| *k == name)
+                        .map(|(_, v)| *v)
+                        .ok_or_else(|| format!("no value for variable '{}'", name))?
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Identifies a specific version of a prompt registered with a [PromptStore].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PromptRef {
+    pub name: String,
+    pub version: u32,
+    /// A hash of the prompt's source text, see [PromptVersion::hash].
+    pub hash: String,
+}
+
+/// A version of a prompt registered with a [PromptStore].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptVersion {
+    name: String,
+    version: u32,
+    hash: String,
+    template: Template,
+}
+
+impl PromptVersion {
+    /// Returns the template of this version of the prompt.
+    pub fn template(&self) -> &Template {
+        &self.template
+    }
+
+    /// Returns the hash of the source text of the template, which is stable across
+    /// builds and platforms, so it can be used to detect when a prompt has changed.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Renders the template, see [Template::render].
+    pub fn render(&self, vars: &[(&str, &str)]) -> Result<String, String> {
+        self.template.render(vars)
+    }
+
+    /// Returns a reference to this version of the prompt, which can be set on
+    /// [CallBase::prompt](crate::CallBase::prompt) to record which prompt produced a response.
+    pub fn reference(&self) -> PromptRef {
+        PromptRef {
+            name: self.name.clone(),
+            version: self.version,
+            hash: self.hash.clone(),
+        }
+    }
+}
+
+/// Hashes text using 64-bit FNV-1a, as a hex string.
+fn hash(text: &str) -> String {
+    let h = text.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", h)
+}
+
+/// A registry of named, versioned prompts.
+///
+/// Responses to calls made with a registered prompt record its name, version, and
+/// hash, so changes in output can be traced back to changes in prompts.
+///
+/// ```
+/// # use mini_prompt::*;
+/// # use mini_prompt::prompt::PromptStore;
+/// let mut store = PromptStore::new();
+/// store.register("summarize", 1, "Summarize {{doc}}").unwrap();
+/// store.register("summarize", 2, "Summarize {{doc}} in one sentence").unwrap();
+///
+/// let prompt = store.latest("summarize").unwrap();
+/// let params = CallBase {
+///     instructions: prompt.render(&[("doc", "...")]).unwrap(),
+///     prompt: Some(prompt.reference()),
+///     ..Default::default()
+/// };
+/// assert_eq!(params.prompt.unwrap().version, 2);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PromptStore {
+    prompts: BTreeMap<String, BTreeMap<u32, PromptVersion>>,
+}
+
+impl PromptStore {
+    /// Constructs an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a version of the named prompt.
+    ///
+    /// Versions are immutable: registering an existing version again succeeds only if
+    /// the source text is unchanged.
+    pub fn register<S: Into<String>>(
+        &mut self,
+        name: &str,
+        version: u32,
+        source: S,
+    ) -> Result<&PromptVersion, String> {
+        let template = Template::new(source);
+        template.variables()?;
+        let p = PromptVersion {
+            name: name.to_string(),
+            version,
+            hash: hash(template.source()),
+            template,
+        };
+
+        let versions = self.prompts.entry(name.to_string()).or_default();
+        match versions.get(&version) {
+            Some(existing) if existing.hash != p.hash => Err(format!(
+                "version {} of prompt '{}' is already registered with different content",
+                version, name
+            )),
+            _ => Ok(versions.entry(version).or_insert(p)),
+        }
+    }
+
+    /// Returns the given version of the named prompt.
+    pub fn get(&self, name: &str, version: u32) -> Option<&PromptVersion> {
+        self.prompts.get(name)?.get(&version)
+    }
+
+    /// Returns the highest version of the named prompt.
+    pub fn latest(&self, name: &str) -> Option<&PromptVersion> {
+        self.prompts.get(name)?.values().next_back()
+    }
+
+    /// Returns the names of all registered prompts.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.prompts.keys().map(|k| k.as_str())
+    }
+
+    /// Returns the version of a prompt matching a reference, if its content is unchanged.
+    pub fn resolve(&self, r: &PromptRef) -> Option<&PromptVersion> {
+        self.get(&r.name, r.version).filter(|p| p.hash == r.hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let t = Template::new("{{a}}+{{ b }}={{a}}{{b}}");
+        assert_eq!(t.render(&[("a", "1"), ("b", "2")]).unwrap(), "1+2=12");
+        assert_eq!(t.variables().unwrap(), vec!["a", "b"]);
+        assert!(t.render(&[("a", "1")]).is_err());
+
+        let t = Template::new(r"literal \{{a}} and {{a}}");
+        assert_eq!(t.render(&[("a", "x")]).unwrap(), "literal {{a}} and x");
+    }
+
+    #[test]
+    fn prompt_store() {
+        let mut store = PromptStore::new();
+        let v1 = store.register("p", 1, "one {{x}}").unwrap().reference();
+        store.register("p", 2, "two {{x}}").unwrap();
+        assert!(store.register("p", 1, "one {{x}}").is_ok());
+        assert!(store.register("p", 1, "changed {{x}}").is_err());
+        assert!(store.register("q", 1, "{{").is_err());
+
+        assert_eq!(v1.hash, hash("one {{x}}"));
+        assert_eq!(hash("a"), "af63dc4c8601ec8c");
+        assert_eq!(
+            store.latest("p").unwrap().render(&[("x", "!")]).unwrap(),
+            "two !"
+        );
+        assert_eq!(store.resolve(&v1).unwrap().version, 1);
+        assert_eq!(store.names().collect::<Vec<_>>(), vec!["p"]);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(Template::new("{{a").render(&[("a", "")]).is_err());
+        assert!(Template::new("{{}}").render(&[]).is_err());
+        assert!(Template::new("{{a b}}").render(&[]).is_err());
+        assert_eq!(Template::new("a } {b}").render(&[]).unwrap(), "a } {b}");
+    }
+}
@@ -0,0 +1,219 @@
+//! Answering questions from retrieved documents, with citations.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "openrouter")] {
+//! # use mini_prompt::*;
+//! use mini_prompt::embed::VectorStore;
+//!
+//! let backend = callers::Openrouter::<models::Gemma27B3>::default();
+//! let embedder = embed::Openai::<models::TextEmbedding3Small>::default();
+//! let store = VectorStore::load("store.json").unwrap();
+//! # tokio::task::spawn(async move {
+//! let answer = rag::answer(&backend, &embedder, &store, "How do I reset my password?")
+//!     .await
+//!     .unwrap();
+//! println!("{}", answer.text);
+//! for chunk in answer.cited() {
+//!     println!("[{}] {:?}", chunk.number, chunk.metadata.get("source"));
+//! }
+//! # });
+//! # }
+//! ```
+
+use crate::embed::{Embedder, Filter, Metadata, VectorStore};
+use crate::{CallBase, CallErr, Message, ModelCaller, Role, Turn};
+use regex::Regex;
+
+/// Controls retrieval for [answer_with].
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The maximum number of chunks to retrieve. Defaults to 4.
+    pub k: usize,
+    /// Restricts retrieval to chunks with matching metadata.
+    pub filter: Filter,
+    /// Chunks less similar than this to the question are not used.
+    pub min_score: Option<f32>,
+    /// The parameters of the call, to which the sources and instructions for citing
+    /// them are appended.
+    pub params: CallBase,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            k: 4,
+            filter: Filter::new(),
+            min_score: None,
+            params: CallBase::default(),
+        }
+    }
+}
+
+/// A retrieved document given to the model as a source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// The number the model cites the chunk by, starting at 1.
+    pub number: usize,
+    pub text: String,
+    pub metadata: Metadata,
+    /// The cosine similarity of the chunk to the question.
+    pub score: f32,
+}
+
+/// An answer to a question, along with the chunks it was based on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Answer {
+    pub text: String,
+    /// The chunks given to the model, most similar first.
+    pub chunks: Vec<Chunk>,
+}
+
+impl Answer {
+    /// Returns the chunks cited in the answer, such as with `[2]`.
+    pub fn cited(&self) -> Vec<&Chunk> {
+        let re = Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").unwrap();
+        let numbers: Vec<usize> = re
+            .captures_iter(&self.text)
+            .flat_map(|c| {
+                c[1].split(',')
+                    .filter_map(|n| n.trim().parse().ok())
+                    .collect::<Vec<usize>>()
+            })
+            .collect();
+        self.chunks
+            .iter()
+            .filter(|c| numbers.contains(&c.number))
+            .collect()
+    }
+}
+
+/// Answers the question using the chunks of the store most similar to it, as
+/// described in [answer_with], with the default [Options].
+pub async fn answer<B: ModelCaller, E: Embedder>(
+    backend: &B,
+    embedder: &E,
+    store: &VectorStore,
+    question: &str,
+) -> Result<Answer, CallErr> {
+    answer_with(backend, embedder, store, question, &Options::default()).await
+}
+
+/// Embeds the question and retrieves the most similar chunks from the store, then asks
+/// the model to answer using only those chunks, citing them by number.
+///
+/// The model is told to say so if the chunks don't contain the answer.
+pub async fn answer_with<B: ModelCaller, E: Embedder>(
+    backend: &B,
+    embedder: &E,
+    store: &VectorStore,
+    question: &str,
+    opts: &Options,
+) -> Result<Answer, CallErr> {
+    let embedding = embedder
+        .embed(vec![question.to_string()])
+        .await?
+        .pop()
+        .ok_or("no embedding for the question")?;
+    let chunks: Vec<Chunk> = store
+        .query(&embedding, opts.k, &opts.filter)
+        .into_iter()
+        .filter(|h| opts.min_score.map_or(true, |min| h.score >= min))
+        .enumerate()
+        .map(|(i, h)| Chunk {
+            number: i + 1,
+            text: h.document.text.clone(),
+            metadata: h.document.metadata.clone(),
+            score: h.score,
+        })
+        .collect();
+
+    let resp = backend
+        .call(
+            grounded_params(opts.params.clone(), &chunks),
+            vec![Turn {
+                role: Role::User,
+                content: vec![Message::text(question)],
+            }],
+        )
+        .await?;
+    Ok(Answer {
+        text: resp.content.text(),
+        chunks,
+    })
+}
+
+/// Appends the chunks to the instructions, along with instructions to cite them.
+fn grounded_params(params: CallBase, chunks: &[Chunk]) -> CallBase {
+    let mut sources = String::new();
+    for c in chunks {
+        sources += &format!(
+            "<source id=\"{}\">\n{}\n</source>\n",
+            c.number,
+            c.text.trim()
+        );
+    }
+    let grounding = format!(
+        "Answer the user's question using only the sources below. Cite the sources \
+        supporting each claim by their id in square brackets, such as [1]. If the sources \
+        don't contain the answer, say that you don't know.\n\n<sources>\n{}</sources>",
+        sources
+    );
+    CallBase {
+        instructions: if params.instructions.is_empty() {
+            grounding
+        } else {
+            params.instructions + "\n\n" + &grounding
+        },
+        ..params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Scripted, Words};
+
+    #[tokio::test]
+    async fn answer_with_citations() {
+        let mut store = VectorStore::new();
+        let source = |s: &str| Metadata::from([("source".to_string(), s.to_string())]);
+        store
+            .add_texts(
+                &Words,
+                vec![
+                    (
+                        "Reset your password from the login page.".into(),
+                        source("faq"),
+                    ),
+                    ("Resets expire after an hour.".into(), source("faq")),
+                    ("The office is in Sydney.".into(), source("about")),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let backend = Scripted::new(vec![Scripted::text(
+            "Use the login page [1], and be quick [1, 2].",
+        )]);
+        let opts = Options {
+            k: 2,
+            min_score: Some(0.1),
+            ..Default::default()
+        };
+        let answer = answer_with(&backend, &Words, &store, "reset password login?", &opts)
+            .await
+            .unwrap();
+        assert_eq!(answer.chunks.len(), 1);
+        assert_eq!(
+            answer.chunks[0].text,
+            "Reset your password from the login page."
+        );
+        assert_eq!(answer.cited(), vec![&answer.chunks[0]]);
+
+        let (params, turns) = &backend.seen()[0];
+        assert!(params.instructions.contains(
+            "<sources>\n<source id=\"1\">\nReset your password from the login page.\n</source>\n</sources>"
+        ));
+        assert_eq!(turns[0].text(), "reset password login?");
+    }
+}
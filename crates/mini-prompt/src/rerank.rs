@@ -0,0 +1,198 @@
+//! Reordering retrieved documents by their relevance to a query.
+//!
+//! Rerankers read the query and each document together, so they judge relevance more
+//! accurately than comparing embeddings, at the cost of a request per query. They are
+//! typically applied to the top results of a [crate::embed::VectorStore] query before
+//! the best few are put in the prompt.
+//!
+//! ```rust,no_run
+//! use mini_prompt::rerank::{reorder, Cohere, Reranker};
+//! use mini_prompt::models::RerankV35;
+//!
+//! let reranker = Cohere::<RerankV35>::default();
+//! # tokio::task::spawn(async move {
+//! let docs = vec![
+//!     "Our office is in Sydney.".to_string(),
+//!     "Resets are done from the login page.".to_string(),
+//! ];
+//! let ranked = reranker
+//!     .rerank("How do I reset my password?", docs.clone(), Some(1))
+//!     .await
+//!     .unwrap();
+//! assert_eq!(reorder(docs, &ranked), vec!["Resets are done from the login page."]);
+//! # });
+//! ```
+
+use crate::credentials::{self, CredentialProvider};
+use crate::data_model::{CohereRerankRequest, RerankResponse, VoyageRerankRequest};
+use crate::embed::post;
+use crate::models::{CohereRerankModel, VoyageRerankModel};
+use crate::CallErr;
+use std::sync::Arc;
+
+/// The relevance of a document to a query, see [Reranker::rerank].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ranked {
+    /// The position of the document in the documents given to the reranker.
+    pub index: usize,
+    /// The relevance of the document, from 0 to 1.
+    pub score: f32,
+}
+
+/// A type which is able to order documents by relevance to a query.
+pub trait Reranker: Send + Sync {
+    /// Scores each document's relevance to the query, returning the most relevant
+    /// first. If `top_n` is given, only that many are returned.
+    fn rerank(
+        &self,
+        query: &str,
+        documents: Vec<String>,
+        top_n: Option<usize>,
+    ) -> impl std::future::Future<Output = Result<Vec<Ranked>, CallErr>> + Send;
+}
+
+/// Returns the items ranked by a reranker, in order of relevance. Items which weren't
+/// ranked are dropped.
+pub fn reorder<T>(items: Vec<T>, ranked: &[Ranked]) -> Vec<T> {
+    let mut items: Vec<Option<T>> = items.into_iter().map(Some).collect();
+    ranked
+        .iter()
+        .filter_map(|r| items.get_mut(r.index).and_then(Option::take))
+        .collect()
+}
+
+/// Returns the results of the response, most relevant first.
+fn ranked(res: RerankResponse) -> Vec<Ranked> {
+    let mut out: Vec<Ranked> = res
+        .results
+        .into_iter()
+        .map(|r| Ranked {
+            index: r.index,
+            score: r.relevance_score,
+        })
+        .collect();
+    out.sort_by(|a, b| b.score.total_cmp(&a.score));
+    out
+}
+
+/// A [Reranker] that uses a model accessible via the Cohere rerank API.
+///
+/// If an API key is not provided, it will be read from the environment variable
+/// `COHERE_API_KEY` or `CO_API_KEY`, or the [config file](crate::config).
+#[derive(Debug, Clone, Default)]
+pub struct Cohere<M: CohereRerankModel> {
+    pub model: M,
+    pub api_key: Option<String>,
+    /// Where the key comes from if `api_key` isn't set, see [crate::credentials].
+    pub credentials: Option<Arc<dyn CredentialProvider>>,
+}
+
+impl<M: CohereRerankModel> Reranker for Cohere<M> {
+    async fn rerank(
+        &self,
+        query: &str,
+        documents: Vec<String>,
+        top_n: Option<usize>,
+    ) -> Result<Vec<Ranked>, CallErr> {
+        let resp = post(
+            "https://api.cohere.com/v2/rerank".into(),
+            Some(
+                credentials::api_key(
+                    &self.api_key,
+                    &self.credentials,
+                    &["COHERE_API_KEY", "CO_API_KEY"],
+                    |c| &c.cohere,
+                )
+                .await?,
+            ),
+            &CohereRerankRequest {
+                model: M::MODEL_STR.into(),
+                query: query.into(),
+                documents,
+                top_n,
+            },
+        )
+        .await?;
+        Ok(ranked(resp.json().await?))
+    }
+}
+
+/// A [Reranker] that uses a model accessible via the Voyage AI rerank API.
+///
+/// If an API key is not provided, it will be read from the environment variable
+/// `VOYAGE_API_KEY`, or the [config file](crate::config).
+#[derive(Debug, Clone, Default)]
+pub struct Voyage<M: VoyageRerankModel> {
+    pub model: M,
+    pub api_key: Option<String>,
+    /// Where the key comes from if `api_key` isn't set, see [crate::credentials].
+    pub credentials: Option<Arc<dyn CredentialProvider>>,
+}
+
+impl<M: VoyageRerankModel> Reranker for Voyage<M> {
+    async fn rerank(
+        &self,
+        query: &str,
+        documents: Vec<String>,
+        top_n: Option<usize>,
+    ) -> Result<Vec<Ranked>, CallErr> {
+        let resp = post(
+            "https://api.voyageai.com/v1/rerank".into(),
+            Some(
+                credentials::api_key(&self.api_key, &self.credentials, &["VOYAGE_API_KEY"], |c| {
+                    &c.voyage
+                })
+                .await?,
+            ),
+            &VoyageRerankRequest {
+                model: M::MODEL_STR.into(),
+                query: query.into(),
+                documents,
+                top_k: top_n,
+            },
+        )
+        .await?;
+        Ok(ranked(resp.json().await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_reorder() {
+        let cohere: RerankResponse = serde_json::from_str(
+            r#"{
+                "id": "1",
+                "results": [
+                    {"index": 2, "relevance_score": 0.1},
+                    {"index": 0, "relevance_score": 0.9}
+                ],
+                "meta": {}
+            }"#,
+        )
+        .unwrap();
+        let ranked = ranked(cohere);
+        assert_eq!(
+            ranked,
+            vec![
+                Ranked {
+                    index: 0,
+                    score: 0.9
+                },
+                Ranked {
+                    index: 2,
+                    score: 0.1
+                },
+            ]
+        );
+        assert_eq!(reorder(vec!["a", "b", "c"], &ranked), vec!["a", "c"]);
+
+        let voyage: RerankResponse = serde_json::from_str(
+            r#"{"object": "list", "data": [{"index": 1, "relevance_score": 0.5}], "model": "rerank-2"}"#,
+        )
+        .unwrap();
+        assert_eq!(reorder(vec!["a", "b"], &super::ranked(voyage)), vec!["b"]);
+    }
+}
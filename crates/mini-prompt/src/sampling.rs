@@ -0,0 +1,285 @@
+//! Making the same call several times, to measure how consistent a prompt is.
+
+use crate::{CallBase, CallErr, CallResp, ModelCaller, ParseAttempt, Turn};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The results of making a call several times, see [sample_n].
+#[derive(Debug)]
+pub struct Samples {
+    /// The result of each call, in the order the calls were specified.
+    pub results: Vec<Result<CallResp, CallErr>>,
+}
+
+impl Samples {
+    /// Returns the successful responses.
+    pub fn responses(&self) -> impl Iterator<Item = &CallResp> {
+        self.results.iter().filter_map(|r| r.as_ref().ok())
+    }
+
+    /// Reports how often the responses agree, comparing the text of each
+    /// response after trimming whitespace.
+    pub fn agreement(&self) -> Agreement<String> {
+        self.agreement_by(|resp| Some(resp.content.text().trim().to_string()))
+    }
+
+    /// Reports how often the responses agree, comparing the key extracted from
+    /// each response, such as a parsed answer. Responses for which no key is
+    /// extracted are counted as failures.
+    pub fn agreement_by<K, F>(&self, key: F) -> Agreement<K>
+    where
+        K: Eq + Hash + Clone,
+        F: Fn(&CallResp) -> Option<K>,
+    {
+        let mut counts: Vec<(K, usize)> = Vec::new();
+        let mut index: HashMap<K, usize> = HashMap::new();
+        let mut failures = 0;
+        for r in self.results.iter() {
+            match r.as_ref().ok().and_then(&key) {
+                Some(k) => match index.get(&k) {
+                    Some(&i) => counts[i].1 += 1,
+                    None => {
+                        index.insert(k.clone(), counts.len());
+                        counts.push((k, 1));
+                    }
+                },
+                None => failures += 1,
+            }
+        }
+        // Stable, so ties are ordered by first appearance.
+        counts.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+
+        Agreement { counts, failures }
+    }
+}
+
+/// Statistics describing how often a set of responses agree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Agreement<K> {
+    /// Each distinct answer with the number of responses giving it, most common first.
+    pub counts: Vec<(K, usize)>,
+    /// The number of calls which failed, or whose answer could not be determined.
+    pub failures: usize,
+}
+
+impl<K> Agreement<K> {
+    /// Returns the most common answer.
+    pub fn majority(&self) -> Option<&K> {
+        self.counts.first().map(|(k, _)| k)
+    }
+
+    /// Returns the number of distinct answers.
+    pub fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns the fraction of all calls which gave the most common answer, from 0 to 1.
+    pub fn ratio(&self) -> f32 {
+        let total = self.counts.iter().map(|(_, n)| n).sum::<usize>() + self.failures;
+        match self.counts.first() {
+            Some((_, n)) => *n as f32 / total as f32,
+            None => 0.0,
+        }
+    }
+}
+
+/// Makes the same call `n` times concurrently, sharing the backend between them.
+///
+/// Unless the temperature is zero, responses will typically differ. Use
+/// [Samples::agreement] to see how much.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "openrouter")] {
+/// # use mini_prompt::*;
+/// # use mini_prompt::sampling::sample_n;
+/// let backend = callers::Openrouter::<models::Gemma27B3>::default();
+/// # tokio::task::spawn(async move {
+/// let samples = sample_n(
+///     &backend,
+///     &CallBase {
+///         instructions: "Name a primary color. Respond with just the color.".into(),
+///         temperature: Some(1.0),
+///         ..Default::default()
+///     },
+///     &[],
+///     5,
+/// )
+/// .await;
+///
+/// let agreement = samples.agreement();
+/// println!("{:?} ({:.0}%)", agreement.majority(), agreement.ratio() * 100.0);
+/// # });
+/// # }
+/// ```
+pub async fn sample_n<B: ModelCaller>(
+    backend: &B,
+    params: &CallBase,
+    turns: &[Turn],
+    n: usize,
+) -> Samples {
+    sample_each(backend, vec![params.clone(); n], turns).await
+}
+
+/// Makes a call for each of the given parameters concurrently, sharing the backend
+/// between them. This is useful for comparing responses across temperatures.
+pub async fn sample_each<B: ModelCaller>(
+    backend: &B,
+    params: Vec<CallBase>,
+    turns: &[Turn],
+) -> Samples {
+    let calls = params.into_iter().map(|p| {
+        let turns = turns.to_vec();
+        async move { backend.call(p, turns).await }
+    });
+
+    Samples {
+        results: futures::future::join_all(calls).await,
+    }
+}
+
+/// Samples `n` responses concurrently and returns the answer extracted most often,
+/// along with how often each answer was given. This is known as self-consistency, and
+/// tends to be more accurate than a single response when the model reasons before
+/// answering.
+///
+/// Answers are extracted from the text of each response by `parse`. If no answer can be
+/// extracted from any response, the error of the first failed call is returned, or
+/// [CallErr::ParseFailed] if every call succeeded.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "openrouter")] {
+/// # use mini_prompt::*;
+/// # use mini_prompt::sampling::self_consistency;
+/// let backend = callers::Openrouter::<models::Gemma27B3>::default();
+/// # tokio::task::spawn(async move {
+/// let params = CallBase {
+///     instructions: "A bat and a ball cost $1.10. The bat costs $1 more than the ball. \
+///         How many cents does the ball cost? Think step by step.".into(),
+///     temperature: Some(0.8),
+///     ..Default::default()
+/// };
+/// let (answer, agreement) = self_consistency(&backend, &params, &[], 5, |text| {
+///     parse::number(text).map(|(n, _)| n as u32)
+/// })
+/// .await
+/// .unwrap();
+/// println!("{} cents, {:.0}% agreement", answer, agreement.ratio() * 100.0);
+/// # });
+/// # }
+/// ```
+pub async fn self_consistency<B, K, F>(
+    backend: &B,
+    params: &CallBase,
+    turns: &[Turn],
+    n: usize,
+    parse: F,
+) -> Result<(K, Agreement<K>), CallErr>
+where
+    B: ModelCaller,
+    K: Eq + Hash + Clone,
+    F: Fn(&str) -> Option<K>,
+{
+    let samples = sample_n(backend, params, turns, n).await;
+    let agreement = samples.agreement_by(|resp| parse(&resp.content.text()));
+    if let Some(answer) = agreement.majority() {
+        return Ok((answer.clone(), agreement));
+    }
+
+    let mut attempts = Vec::with_capacity(n);
+    for r in samples.results {
+        match r {
+            Ok(resp) => attempts.push(ParseAttempt {
+                output: resp.content.text(),
+                err: "no answer could be extracted".into(),
+            }),
+            Err(e) => return Err(e),
+        }
+    }
+    Err(CallErr::ParseFailed(attempts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Gemma27B3, Model};
+    use crate::testing::{Scripted, Shared};
+
+    /// Answers with the temperature it was called with.
+    struct ByTemperature;
+
+    impl ModelCaller for ByTemperature {
+        fn get_model(&self) -> impl Model {
+            Gemma27B3
+        }
+
+        async fn call(&self, params: CallBase, _: Vec<Turn>) -> Result<CallResp, CallErr> {
+            match params.temperature {
+                Some(t) => Ok(Scripted::text(&format!(" {} ", t))),
+                None => Err(CallErr::NoCompletions),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn agreement() {
+        let temps = [Some(0.0), Some(1.0), None, Some(1.0), Some(0.0), Some(1.0)];
+        let samples = sample_each(
+            &ByTemperature,
+            temps
+                .iter()
+                .map(|&temperature| CallBase {
+                    temperature,
+                    ..Default::default()
+                })
+                .collect(),
+            &[],
+        )
+        .await;
+
+        assert_eq!(samples.responses().count(), 5);
+        let a = samples.agreement();
+        assert_eq!(a.counts, vec![("1".to_string(), 3), ("0".to_string(), 2)]);
+        assert_eq!(a.failures, 1);
+        assert_eq!(a.majority().unwrap(), "1");
+        assert_eq!(a.distinct(), 2);
+        assert_eq!(a.ratio(), 0.5);
+
+        let samples = sample_n(
+            &Scripted::new(vec![Scripted::text("x"); 3]),
+            &CallBase::default(),
+            &[],
+            3,
+        )
+        .await;
+        assert_eq!(samples.agreement().ratio(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn majority_vote() {
+        let parse = |text: &str| text.strip_prefix("answer: ").map(String::from);
+        let backend = Shared::new(&["answer: 3", "answer: 4", "I don't know", "answer: 4"]);
+        let (answer, agreement) = self_consistency(&backend, &CallBase::default(), &[], 4, parse)
+            .await
+            .unwrap();
+        assert_eq!(answer, "4");
+        assert_eq!(
+            agreement.counts,
+            vec![("4".to_string(), 2), ("3".to_string(), 1)]
+        );
+        assert_eq!(agreement.failures, 1);
+
+        let backend = Shared::new(&["a", "b"]);
+        match self_consistency(&backend, &CallBase::default(), &[], 2, parse).await {
+            Err(CallErr::ParseFailed(attempts)) => {
+                assert_eq!(attempts.len(), 2);
+                assert_eq!(attempts[1].output, "b");
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        assert!(matches!(
+            self_consistency(&ByTemperature, &CallBase::default(), &[], 2, |_| Some(())).await,
+            Err(CallErr::NoCompletions)
+        ));
+    }
+}
@@ -0,0 +1,261 @@
+//! Splitting long text into chunks which fit within a model's context.
+//!
+//! Chunks end at the most natural boundary available, preferring paragraph breaks to
+//! line breaks, line breaks to the ends of sentences, and sentences to words. With
+//! [SplitOptions::markdown], chunks also prefer to end before headings, and avoid
+//! ending within code blocks.
+//!
+//! ```
+//! use mini_prompt::split::{split, SplitOptions};
+//! let text = "The first paragraph.\n\nThe second paragraph. It has two sentences.";
+//! assert_eq!(
+//!     split(text, &SplitOptions::chars(50)),
+//!     vec!["The first paragraph.", "The second paragraph. It has two sentences."],
+//! );
+//! ```
+
+use crate::tokens::TokenCounter;
+
+/// Boundary levels, from most to least preferred.
+const HEADING: u8 = 0;
+const PARAGRAPH: u8 = 1;
+const LINE: u8 = 2;
+const SENTENCE: u8 = 3;
+const WORD: u8 = 4;
+
+/// Describes how to split text into chunks, see [split].
+#[derive(Clone)]
+pub struct SplitOptions {
+    max: usize,
+    overlap: usize,
+    counter: Option<TokenCounter>,
+    markdown: bool,
+}
+
+impl SplitOptions {
+    /// Splits text into chunks of at most `max` characters.
+    pub fn chars(max: usize) -> Self {
+        Self {
+            max,
+            overlap: 0,
+            counter: None,
+            markdown: false,
+        }
+    }
+
+    /// Splits text into chunks of at most `max` tokens, as counted by the given counter,
+    /// such as [tokens::estimate](crate::tokens::estimate).
+    pub fn tokens(max: usize, counter: TokenCounter) -> Self {
+        Self {
+            counter: Some(counter),
+            ..Self::chars(max)
+        }
+    }
+
+    /// Repeats up to the given number of characters or tokens from the end of each
+    /// chunk at the start of the next, so context isn't lost at chunk boundaries.
+    /// The overlap always starts at the beginning of a word.
+    pub fn overlap(self, overlap: usize) -> Self {
+        Self { overlap, ..self }
+    }
+
+    /// Treats the text as markdown, preferring to end chunks before headings, and
+    /// avoiding ending chunks within code blocks.
+    pub fn markdown(self) -> Self {
+        Self {
+            markdown: true,
+            ..self
+        }
+    }
+
+    fn size(&self, text: &str) -> usize {
+        match &self.counter {
+            Some(count) => count(text),
+            None => text.chars().count(),
+        }
+    }
+}
+
+/// Splits text into chunks no larger than the maximum size set in the given opts.
+/// Whitespace is trimmed from the start and end of each chunk, and chunks which would
+/// be empty are left out.
+///
+/// A chunk only ends within a word if the word is larger than the maximum size.
+pub fn split<'t>(text: &'t str, opts: &SplitOptions) -> Vec<&'t str> {
+    let bounds = boundaries(text, opts.markdown);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let limit = fit(text, start, opts);
+        let end = match limit == text.len() {
+            true => limit,
+            // Skip boundaries in the first third of the chunk, so a good boundary
+            // near the start doesn't produce a tiny chunk.
+            false => bounds
+                .iter()
+                .filter(|(p, _)| *p > start + (limit - start) / 3 && *p <= limit)
+                .min_by_key(|(p, level)| (*level, std::cmp::Reverse(*p)))
+                .map_or(limit, |(p, _)| *p),
+        };
+
+        let chunk = text[start..end].trim();
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        if end == text.len() {
+            break;
+        }
+
+        start = match opts.overlap {
+            0 => end,
+            overlap => bounds
+                .iter()
+                .find(|(p, level)| {
+                    *p > start && *p < end && *level <= WORD && opts.size(&text[*p..end]) <= overlap
+                })
+                .map_or(end, |(p, _)| *p),
+        };
+    }
+    chunks
+}
+
+/// Returns the furthest char boundary such that the text from `start` fits in the
+/// maximum size, which is always at least one character past `start`.
+fn fit(text: &str, start: usize, opts: &SplitOptions) -> usize {
+    let rest = &text[start..];
+    let end = match &opts.counter {
+        None => rest
+            .char_indices()
+            .nth(opts.max)
+            .map_or(rest.len(), |(i, _)| i),
+        Some(_) => {
+            let mut ends: Vec<usize> = rest.char_indices().skip(1).map(|(i, _)| i).collect();
+            ends.push(rest.len());
+            let fits = ends.partition_point(|e| opts.size(&rest[..*e]) <= opts.max);
+            fits.checked_sub(1).map_or(0, |i| ends[i])
+        }
+    };
+    match end {
+        0 => start + rest.chars().next().map_or(0, |c| c.len_utf8()),
+        end => start + end,
+    }
+}
+
+/// Returns the positions at which a chunk could end, with the level of each boundary.
+fn boundaries(text: &str, markdown: bool) -> Vec<(usize, u8)> {
+    let mut out = Vec::new();
+    let (mut pos, mut in_code, mut prev_blank) = (0, false, false);
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let fence = markdown && (trimmed.starts_with("```") || trimmed.starts_with("~~~"));
+        let level = match (in_code, fence) {
+            // Lines within a code block are a last resort.
+            (true, false) => WORD,
+            (false, _) if markdown && trimmed.starts_with('#') => HEADING,
+            (false, true) => PARAGRAPH,
+            _ if prev_blank => PARAGRAPH,
+            _ => LINE,
+        };
+        if pos > 0 {
+            out.push((pos, level));
+        }
+        if fence {
+            in_code = !in_code;
+        }
+
+        if !in_code {
+            // Words start after whitespace, other than indentation.
+            let (mut seen_text, mut after_space) = (false, false);
+            for (i, c) in line.char_indices() {
+                if c.is_whitespace() {
+                    after_space = seen_text;
+                    continue;
+                }
+                if after_space {
+                    let level = match line[..i].trim_end().ends_with(['.', '!', '?']) {
+                        true => SENTENCE,
+                        false => WORD,
+                    };
+                    out.push((pos + i, level));
+                }
+                (seen_text, after_space) = (true, false);
+            }
+        }
+        prev_blank = line.trim().is_empty();
+        pos += line.len();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use std::sync::Arc;
+
+    #[test]
+    fn split_at_boundaries() {
+        let text = "One two three. Four five six! Seven eight nine.";
+        assert_eq!(
+            split(text, &SplitOptions::chars(35)),
+            vec!["One two three. Four five six!", "Seven eight nine."]
+        );
+        assert_eq!(
+            split(text, &SplitOptions::chars(12)),
+            vec![
+                "One two",
+                "three.",
+                "Four five",
+                "six!",
+                "Seven eight",
+                "nine."
+            ]
+        );
+        assert_eq!(
+            split("abcdefghij", &SplitOptions::chars(4)),
+            vec!["abcd", "efgh", "ij"]
+        );
+        assert_eq!(split("  \n ", &SplitOptions::chars(4)), Vec::<&str>::new());
+        assert_eq!(split("héllo wörld", &SplitOptions::chars(3)).len(), 4);
+    }
+
+    #[test]
+    fn split_with_overlap() {
+        let text = "one two three four five six seven";
+        let chunks = split(text, &SplitOptions::chars(15).overlap(6));
+        assert_eq!(
+            chunks,
+            vec!["one two three", "three four", "four five six", "six seven"]
+        );
+
+        let words = Arc::new(|s: &str| s.split_whitespace().count());
+        let chunks = split(text, &SplitOptions::tokens(3, words).overlap(1));
+        assert_eq!(
+            chunks,
+            vec!["one two three", "three four five", "five six seven"]
+        );
+    }
+
+    #[test]
+    fn split_markdown() {
+        let text = indoc! {"
+            # Intro
+            Some words here.
+            # Usage
+            Run it with cargo:
+            ```sh
+            cargo build
+            cargo run
+            ```
+            Done.
+        "};
+        assert_eq!(
+            split(text, &SplitOptions::chars(40).markdown()),
+            vec![
+                "# Intro\nSome words here.",
+                "# Usage\nRun it with cargo:",
+                "```sh\ncargo build\ncargo run\n```\nDone."
+            ]
+        );
+    }
+}
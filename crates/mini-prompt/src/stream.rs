@@ -0,0 +1,406 @@
+//! Reading responses which providers stream as server-sent events.
+
+#[cfg(feature = "anthropic")]
+use crate::data_model::{AnthropicDelta, AnthropicMsgResponse, AnthropicStreamEvent};
+#[cfg(any(feature = "openai", feature = "openrouter"))]
+use crate::data_model::{
+    ChatChoice, FunctionCall, OAIChatMessage, OAICompletionsChunk, OAICompletionsResponse,
+    OAILogprobs, OAITokenLogprob, OAIToolCall, ToolCallType,
+};
+use crate::CallErr;
+#[cfg(any(feature = "openai", feature = "openrouter"))]
+use crate::{FinishReason, Role};
+
+/// Splits a `text/event-stream` body into the data of each event, as the body arrives.
+#[derive(Debug, Default)]
+pub(crate) struct SseEvents {
+    buf: Vec<u8>,
+}
+
+impl SseEvents {
+    /// Adds the next chunk of the body, returning the data of each event it completes.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<String>, CallErr> {
+        self.buf.extend(chunk.iter().filter(|b| **b != b'\r'));
+        let mut events = vec![];
+        while let Some(end) = self.buf.windows(2).position(|w| w == b"\n\n") {
+            let event: Vec<u8> = self.buf.drain(..end + 2).collect();
+            events.extend(event_data(&event)?);
+        }
+        Ok(events)
+    }
+
+    /// Returns the data of the last event, if the body didn't end with a blank line.
+    pub fn finish(self) -> Result<Option<String>, CallErr> {
+        event_data(&self.buf)
+    }
+}
+
+/// Returns the data lines of an event joined by newlines, or [None] for events
+/// without data, such as comments.
+fn event_data(event: &[u8]) -> Result<Option<String>, CallErr> {
+    let event = std::str::from_utf8(event).map_err(|e| format!("invalid event: {}", e))?;
+    let mut data: Option<String> = None;
+    for line in event.lines() {
+        if let Some(d) = line.strip_prefix("data:") {
+            let d = d.strip_prefix(' ').unwrap_or(d);
+            match data.as_mut() {
+                Some(existing) => {
+                    existing.push('\n');
+                    existing.push_str(d);
+                }
+                None => data = Some(d.to_string()),
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// Reads a streamed response, passing the data of each event to `on_event`.
+pub(crate) async fn read_events(
+    mut resp: reqwest::Response,
+    mut on_event: impl FnMut(&str) -> Result<(), CallErr>,
+) -> Result<(), CallErr> {
+    let mut events = SseEvents::default();
+    while let Some(chunk) = resp.chunk().await? {
+        for data in events.push(&chunk)? {
+            on_event(&data)?;
+        }
+    }
+    match events.finish()? {
+        Some(data) => on_event(&data),
+        None => Ok(()),
+    }
+}
+
+#[cfg(any(feature = "openai", feature = "openrouter"))]
+/// Builds a chat completions response from the events of a streamed one.
+#[derive(Debug, Default)]
+pub(crate) struct OAIStream {
+    id: String,
+    model: String,
+    system_fingerprint: Option<String>,
+    content: Option<String>,
+    reasoning: Option<String>,
+    tool_calls: Vec<OAIToolCall>,
+    logprobs: Option<Vec<OAITokenLogprob>>,
+    finish_reason: Option<FinishReason>,
+}
+
+#[cfg(any(feature = "openai", feature = "openrouter"))]
+impl OAIStream {
+    /// Adds an event to the response, passing any text it adds to `on_text`.
+    pub fn push(&mut self, data: &str, on_text: &mut impl FnMut(&str)) -> Result<(), CallErr> {
+        if data == "[DONE]" {
+            return Ok(());
+        }
+        let chunk: OAICompletionsChunk =
+            serde_json::from_str(data).map_err(|e| format!("invalid event: {}", e))?;
+        if let Some(err) = chunk.error {
+            return Err(format!("the response failed: {}", err).into());
+        }
+        if self.id.is_empty() {
+            self.id = chunk.id;
+        }
+        if self.model.is_empty() {
+            self.model = chunk.model;
+        }
+        if chunk.system_fingerprint.is_some() {
+            self.system_fingerprint = chunk.system_fingerprint;
+        }
+
+        // Only one choice is ever requested.
+        let Some(choice) = chunk.choices.into_iter().next() else {
+            return Ok(());
+        };
+        if let Some(text) = choice.delta.content.filter(|t| !t.is_empty()) {
+            on_text(&text);
+            self.content.get_or_insert_with(String::new).push_str(&text);
+        }
+        if let Some(reasoning) = choice.delta.reasoning {
+            let r = self.reasoning.get_or_insert_with(String::new);
+            r.push_str(&reasoning);
+        }
+        for delta in choice.delta.tool_calls {
+            if self.tool_calls.len() <= delta.index {
+                self.tool_calls
+                    .resize_with(delta.index + 1, || OAIToolCall {
+                        id: String::new(),
+                        r#type: ToolCallType::Function,
+                        function: FunctionCall {
+                            name: String::new(),
+                            arguments: String::new(),
+                        },
+                    });
+            }
+            let call = &mut self.tool_calls[delta.index];
+            if let Some(id) = delta.id {
+                call.id = id;
+            }
+            if let Some(f) = delta.function {
+                call.function.name += f.name.as_deref().unwrap_or_default();
+                call.function.arguments += f.arguments.as_deref().unwrap_or_default();
+            }
+        }
+        if let Some(logprobs) = choice.logprobs.and_then(|l| l.content) {
+            self.logprobs.get_or_insert_with(Vec::new).extend(logprobs);
+        }
+        if choice.finish_reason.is_some() {
+            self.finish_reason = choice.finish_reason;
+        }
+        Ok(())
+    }
+
+    /// Returns the response, failing if the stream ended before it finished.
+    pub fn finish(self) -> Result<OAICompletionsResponse, CallErr> {
+        let finish_reason = self
+            .finish_reason
+            .ok_or("the stream ended before the response finished")?;
+        Ok(OAICompletionsResponse {
+            id: self.id,
+            object: None,
+            created: None,
+            model: self.model,
+            choices: vec![ChatChoice {
+                index: 0,
+                message: OAIChatMessage {
+                    role: Role::Assistant,
+                    content: self.content,
+                    tool_calls: self.tool_calls,
+                    tool_call_id: None,
+                    name: None,
+                    reasoning: self.reasoning,
+                },
+                finish_reason,
+                logprobs: self.logprobs.map(|content| OAILogprobs {
+                    content: Some(content),
+                }),
+            }],
+            system_fingerprint: self.system_fingerprint,
+        })
+    }
+}
+
+#[cfg(feature = "anthropic")]
+/// Builds a messages API response from the events of a streamed one.
+#[derive(Debug, Default)]
+pub(crate) struct AnthropicStream {
+    id: String,
+    model: String,
+    /// Each content block, along with the JSON input streamed for tool uses.
+    blocks: Vec<(serde_json::Value, String)>,
+    stop_reason: Option<crate::FinishReason>,
+}
+
+#[cfg(feature = "anthropic")]
+impl AnthropicStream {
+    /// Adds an event to the response, passing any text it adds to `on_text`.
+    pub fn push(&mut self, data: &str, on_text: &mut impl FnMut(&str)) -> Result<(), CallErr> {
+        let event: AnthropicStreamEvent =
+            serde_json::from_str(data).map_err(|e| format!("invalid event: {}", e))?;
+        match event {
+            AnthropicStreamEvent::MessageStart { message } => {
+                self.id = message.id;
+                self.model = message.model;
+            }
+            AnthropicStreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
+                if self.blocks.len() <= index {
+                    self.blocks.resize(index + 1, Default::default());
+                }
+                self.blocks[index].0 = content_block;
+            }
+            AnthropicStreamEvent::ContentBlockDelta { index, delta } => {
+                let Some((block, input)) = self.blocks.get_mut(index) else {
+                    return Err(format!("a delta for missing content block {}", index).into());
+                };
+                match delta {
+                    AnthropicDelta::TextDelta { text } => {
+                        on_text(&text);
+                        append(block, "text", &text);
+                    }
+                    AnthropicDelta::InputJsonDelta { partial_json } => {
+                        input.push_str(&partial_json)
+                    }
+                    AnthropicDelta::ThinkingDelta { thinking } => {
+                        append(block, "thinking", &thinking)
+                    }
+                    AnthropicDelta::SignatureDelta { signature } => {
+                        append(block, "signature", &signature)
+                    }
+                    AnthropicDelta::Other => {}
+                }
+            }
+            AnthropicStreamEvent::MessageDelta { delta } => {
+                if delta.stop_reason.is_some() {
+                    self.stop_reason = delta.stop_reason;
+                }
+            }
+            AnthropicStreamEvent::Error { error } => {
+                return Err(format!("the response failed: {}", error).into());
+            }
+            AnthropicStreamEvent::Other => {}
+        }
+        Ok(())
+    }
+
+    /// Returns the response, failing if the stream ended before it finished.
+    pub fn finish(self) -> Result<AnthropicMsgResponse, CallErr> {
+        let stop_reason = self
+            .stop_reason
+            .ok_or("the stream ended before the response finished")?;
+        let content = self
+            .blocks
+            .into_iter()
+            .map(|(mut block, input)| {
+                if !input.is_empty() {
+                    block["input"] = serde_json::from_str(&input)
+                        .map_err(|e| format!("invalid tool input: {}", e))?;
+                }
+                serde_json::from_value(block)
+                    .map_err(|e| format!("invalid content block: {}", e).into())
+            })
+            .collect::<Result<_, CallErr>>()?;
+        Ok(AnthropicMsgResponse {
+            id: self.id,
+            object: None,
+            role: None,
+            model: self.model,
+            content,
+            stop_reason,
+        })
+    }
+}
+
+#[cfg(feature = "anthropic")]
+/// Appends text to a string field of a content block.
+fn append(block: &mut serde_json::Value, field: &str, text: &str) {
+    match block.get_mut(field) {
+        Some(serde_json::Value::String(s)) => s.push_str(text),
+        _ => block[field] = text.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_events() {
+        let body = "data: one\r\n\r\n: keep-alive\n\nevent: x\ndata: two\ndata:three\n\ndata: fo";
+        let mut events = SseEvents::default();
+        let mut out = vec![];
+        // Chunks can end anywhere, including within a character.
+        for chunk in body.as_bytes().chunks(3) {
+            out.extend(events.push(chunk).unwrap());
+        }
+        out.extend(events.finish().unwrap());
+        assert_eq!(out, vec!["one", "two\nthree", "fo"]);
+
+        let mut events = SseEvents::default();
+        let bytes = "data: é\n\n".as_bytes();
+        assert!(events.push(&bytes[..7]).unwrap().is_empty());
+        assert_eq!(events.push(&bytes[7..]).unwrap(), vec!["é"]);
+    }
+
+    #[cfg(any(feature = "openai", feature = "openrouter"))]
+    #[test]
+    fn oai_stream() {
+        let events = [
+            r#"{"id":"c1","model":"m","choices":[{"delta":{"role":"assistant","content":""}}]}"#,
+            r#"{"id":"c1","choices":[{"delta":{"reasoning":"Hmm."}}]}"#,
+            r#"{"id":"c1","choices":[{"delta":{"content":"Hello"}}]}"#,
+            r#"{"id":"c1","choices":[{"delta":{"content":" there"}}]}"#,
+            r#"{"id":"c1","choices":[{"delta":{"tool_calls":[{"index":0,"id":"t1","type":"function","function":{"name":"add","arguments":""}}]}}]}"#,
+            r#"{"id":"c1","choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"a\":"}}]}}]}"#,
+            r#"{"id":"c1","choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"1}"}}]}}]}"#,
+            r#"{"id":"c1","choices":[{"delta":{},"finish_reason":"tool_calls"}]}"#,
+            "[DONE]",
+        ];
+        let mut text = vec![];
+        let mut res = OAIStream::default();
+        for e in events {
+            res.push(e, &mut |t: &str| text.push(t.to_string()))
+                .unwrap();
+        }
+        assert_eq!(text, vec!["Hello", " there"]);
+
+        let res = res.finish().unwrap();
+        assert_eq!((res.id.as_str(), res.model.as_str()), ("c1", "m"));
+        let choice = &res.choices[0];
+        assert_eq!(choice.finish_reason, FinishReason::ToolCalls);
+        assert_eq!(choice.message.content.as_deref(), Some("Hello there"));
+        assert_eq!(choice.message.reasoning.as_deref(), Some("Hmm."));
+        assert_eq!(choice.message.tool_calls[0].id, "t1");
+        assert_eq!(choice.message.tool_calls[0].function.name, "add");
+        assert_eq!(
+            choice.message.tool_calls[0].function.arguments,
+            r#"{"a":1}"#
+        );
+
+        let mut res = OAIStream::default();
+        res.push(r#"{"choices":[{"delta":{"content":"Hi"}}]}"#, &mut |_| {})
+            .unwrap();
+        assert!(res.finish().is_err());
+        let mut res = OAIStream::default();
+        let err = r#"{"error":{"message":"overloaded"},"choices":[]}"#;
+        assert!(res.push(err, &mut |_| {}).is_err());
+    }
+
+    #[cfg(feature = "anthropic")]
+    #[test]
+    fn anthropic_stream() {
+        use crate::data_model::AnthropicCompletion;
+
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1","type":"message","role":"assistant","model":"claude","content":[],"stop_reason":null}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Hmm."}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"ping"}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"Adding"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":" now."}}"#,
+            r#"{"type":"content_block_start","index":2,"content_block":{"type":"tool_use","id":"t1","name":"add","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":"{\"a\":"}}"#,
+            r#"{"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":"1}"}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":9}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+        let mut text = vec![];
+        let mut res = AnthropicStream::default();
+        for e in events {
+            res.push(e, &mut |t: &str| text.push(t.to_string()))
+                .unwrap();
+        }
+        assert_eq!(text, vec!["Adding", " now."]);
+
+        let res = res.finish().unwrap();
+        assert_eq!((res.id.as_str(), res.model.as_str()), ("msg_1", "claude"));
+        assert_eq!(res.stop_reason, crate::FinishReason::ToolCalls);
+        assert_eq!(
+            res.content,
+            vec![
+                AnthropicCompletion::Thinking {
+                    thinking: "Hmm.".into(),
+                    signature: "sig".into(),
+                },
+                AnthropicCompletion::Text {
+                    text: "Adding now.".into(),
+                },
+                AnthropicCompletion::ToolUse {
+                    id: "t1".into(),
+                    name: "add".into(),
+                    input: serde_json::json!({"a": 1}),
+                },
+            ]
+        );
+
+        let mut res = AnthropicStream::default();
+        let err = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert!(res.push(err, &mut |_| {}).is_err());
+        assert!(res.finish().is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Test doubles for exercising model-calling logic without a network.
+
+use crate::embed::Embedder;
+use crate::models::{Gemma27B3, Model};
+use crate::{CallBase, CallErr, CallResp, FinishReason, Message, ModelCaller, Role, Turn};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// A [ModelCaller] which replays a fixed script of responses, recording each request.
+pub(crate) struct Scripted {
+    responses: Mutex<VecDeque<CallResp>>,
+    seen: Mutex<Vec<(CallBase, Vec<Turn>)>>,
+}
+
+impl Clone for Scripted {
+    fn clone(&self) -> Self {
+        Self {
+            responses: Mutex::new(self.responses.lock().unwrap().clone()),
+            seen: Mutex::new(self.seen()),
+        }
+    }
+}
+
+impl Scripted {
+    pub fn new(responses: Vec<CallResp>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into()),
+            seen: Mutex::new(vec![]),
+        }
+    }
+
+    /// Returns the requests made so far.
+    pub fn seen(&self) -> Vec<(CallBase, Vec<Turn>)> {
+        self.seen.lock().unwrap().clone()
+    }
+
+    /// A response consisting of a single text message.
+    pub fn text(text: &str) -> CallResp {
+        CallResp {
+            id: "resp".into(),
+            model: "scripted".into(),
+            finish_reason: FinishReason::Stop,
+            content: Turn {
+                role: Role::Assistant,
+                content: vec![Message::text(text)],
+            },
+            prompt: None,
+            logprobs: None,
+            system_fingerprint: None,
+            raw: None,
+        }
+    }
+
+    /// A response invoking each of the given (name, arguments) tools.
+    pub fn tool_calls(calls: &[(&str, &str)]) -> CallResp {
+        CallResp {
+            id: "resp".into(),
+            model: "scripted".into(),
+            finish_reason: FinishReason::ToolCalls,
+            content: Turn {
+                role: Role::Assistant,
+                content: calls
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, arguments))| Message::ToolCall {
+                        id: format!("call_{}", i),
+                        name: name.to_string(),
+                        arguments: arguments.to_string(),
+                    })
+                    .collect(),
+            },
+            prompt: None,
+            logprobs: None,
+            system_fingerprint: None,
+            raw: None,
+        }
+    }
+}
+
+impl ModelCaller for Scripted {
+    fn get_model(&self) -> impl Model {
+        Gemma27B3
+    }
+
+    async fn call(&self, params: CallBase, turns: Vec<Turn>) -> Result<CallResp, CallErr> {
+        self.seen.lock().unwrap().push((params, turns));
+        let resp = self.responses.lock().unwrap().pop_front();
+        resp.ok_or(CallErr::NoCompletions)
+    }
+}
+
+/// A [ModelCaller] which answers with the next of a fixed list of texts, shared between
+/// clones so concurrent calls get different responses.
+#[derive(Clone)]
+pub(crate) struct Shared(Arc<Mutex<VecDeque<String>>>);
+
+impl Shared {
+    pub fn new(texts: &[&str]) -> Self {
+        Self(Arc::new(Mutex::new(
+            texts.iter().map(|t| t.to_string()).collect(),
+        )))
+    }
+}
+
+impl ModelCaller for Shared {
+    fn get_model(&self) -> impl Model {
+        Gemma27B3
+    }
+
+    async fn call(&self, _: CallBase, _: Vec<Turn>) -> Result<CallResp, CallErr> {
+        let text = self.0.lock().unwrap().pop_front();
+        text.map(|t| Scripted::text(&t))
+            .ok_or(CallErr::NoCompletions)
+    }
+}
+
+/// An [Embedder] which counts the words of each text into a fixed number of buckets,
+/// so texts sharing words are similar. Common short words are ignored.
+#[derive(Clone, Default)]
+pub(crate) struct Words;
+
+impl Embedder for Words {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, CallErr> {
+        Ok(texts
+            .iter()
+            .map(|t| {
+                let mut v = vec![0.0; 64];
+                for w in t
+                    .split(|c: char| !c.is_alphanumeric())
+                    .filter(|w| w.len() > 2)
+                {
+                    let h = w
+                        .to_lowercase()
+                        .bytes()
+                        .fold(7usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize));
+                    v[h % 64] += 1.0;
+                }
+                v
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,140 @@
+//! Utilities for counting tokens.
+//!
+//! Counts are made using a [TokenCounter], which counts the tokens in a piece of text.
+//! Any model can use the [estimate] heuristic, while exact counts for OpenAI models are
+//! available from [openai] with the `tiktoken` feature. For Anthropic models, use
+//! [Anthropic::count_tokens](crate::callers::Anthropic::count_tokens).
+//!
+//! ```
+//! # use mini_prompt::*;
+//! let params = CallBase {
+//!     system: "You are a helpful assistant".into(),
+//!     ..Default::default()
+//! };
+//! let turns = vec![Turn {
+//!     role: Role::User,
+//!     content: vec![Message::text("Hi!")],
+//! }];
+//! if params.count_prompt_tokens(&turns, tokens::estimate) > 128_000 {
+//!     panic!("prompt exceeds the context window");
+//! }
+//! ```
+
+use crate::{CallBase, Message, Turn};
+use std::sync::Arc;
+
+/// Counts the tokens in a piece of text.
+pub type TokenCounter = Arc<dyn Fn(&str) -> usize + Send + Sync>;
+
+/// The approximate number of tokens used to frame each message.
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Estimates the number of tokens in some text, at roughly four characters per token.
+///
+/// This is a rough heuristic which works for any model, but can be significantly
+/// off for code or non-English text.
+pub fn estimate(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Counts the tokens in a turn, using the given function to count the tokens
+/// in each piece of text.
+pub fn count_turn<F: Fn(&str) -> usize>(turn: &Turn, count: F) -> usize {
+    turn.content
+        .iter()
+        .map(|m| {
+            MESSAGE_OVERHEAD
+                + match m {
+                    Message::Text { text } => count(text),
+                    Message::ToolCall {
+                        name, arguments, ..
+                    } => count(name) + count(arguments),
+                    Message::ToolResult { result, .. } => count(result),
+                    Message::Thinking { text, .. } => count(text),
+                    Message::RedactedThinking { data } => count(data),
+                }
+        })
+        .sum()
+}
+
+/// Returns a counter which exactly counts tokens for the given OpenAI model, such
+/// as `gpt-4o-mini`. Unrecognized models use the `o200k_base` encoding.
+///
+/// Openrouter-style names such as `openai/gpt-4o-mini` are also accepted.
+#[cfg(feature = "tiktoken")]
+pub fn openai(model: &str) -> TokenCounter {
+    use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+
+    let model = model.strip_prefix("openai/").unwrap_or(model);
+    let bpe = match get_tokenizer(model) {
+        Some(Tokenizer::Cl100kBase) => tiktoken_rs::cl100k_base_singleton(),
+        Some(Tokenizer::P50kBase) => tiktoken_rs::p50k_base_singleton(),
+        Some(Tokenizer::P50kEdit) => tiktoken_rs::p50k_edit_singleton(),
+        Some(Tokenizer::R50kBase) | Some(Tokenizer::Gpt2) => tiktoken_rs::r50k_base_singleton(),
+        Some(Tokenizer::O200kBase) | None => tiktoken_rs::o200k_base_singleton(),
+    };
+    Arc::new(move |text| bpe.encode_with_special_tokens(text).len())
+}
+
+impl CallBase {
+    /// Counts the tokens which would be sent as input for a call with these parameters
+    /// and the given turns, including the system prompt, instructions, and tool definitions.
+    ///
+    /// This is approximate, as each provider frames the input slightly differently.
+    pub fn count_prompt_tokens<F: Fn(&str) -> usize>(&self, turns: &[Turn], count: F) -> usize {
+        let tools: usize = self
+            .tools
+            .iter()
+            .map(|t| {
+                MESSAGE_OVERHEAD
+                    + count(&t.name)
+                    + count(&t.description)
+                    + count(&t.parameters.to_string())
+            })
+            .sum();
+        let turns: usize = turns.iter().map(|t| count_turn(t, &count)).sum();
+
+        let segments: usize = self
+            .system_segments
+            .iter()
+            .map(|s| MESSAGE_OVERHEAD + count(s))
+            .sum();
+
+        MESSAGE_OVERHEAD * 2
+            + count(&self.system)
+            + segments
+            + count(&self.instructions)
+            + tools
+            + turns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Role;
+
+    #[test]
+    fn estimates() {
+        assert_eq!(estimate(""), 0);
+        assert_eq!(estimate("abcde"), 2);
+        assert_eq!(
+            count_turn(
+                &Turn {
+                    role: Role::User,
+                    content: vec![Message::text("abcd"), Message::text("efgh")],
+                },
+                estimate
+            ),
+            10
+        );
+    }
+
+    #[cfg(feature = "tiktoken")]
+    #[test]
+    fn openai_counts() {
+        let count = openai("gpt-4o-mini");
+        assert_eq!(count("hello world"), 2);
+        assert_eq!(openai("openai/gpt-4")("hello world"), 2);
+    }
+}
@@ -0,0 +1,26 @@
+//! Ready-made tools for use in a [ToolsSession](crate::ToolsSession).
+//!
+//! Each constructor returns a `(ToolInfo, RawToolFunc)` pair, which can be passed
+//! straight into [ToolsSession::new](crate::ToolsSession::new) or
+//! [ToolsSession::add_tool](crate::ToolsSession::add_tool).
+
+mod calculator;
+pub use calculator::{calculator, evaluate};
+
+mod fs;
+pub use fs::{fs_tools, list_dir, read_file, write_file, FsOptions};
+
+#[cfg(feature = "builtin-http")]
+mod http;
+#[cfg(feature = "builtin-http")]
+pub use http::{http_fetch, HttpFetchOptions};
+
+#[cfg(feature = "builtin-shell")]
+mod shell;
+#[cfg(feature = "builtin-shell")]
+pub use shell::{shell, ShellOptions};
+
+/// Formats the result handed to the model when a built-in tool fails.
+fn error(err: String) -> String {
+    serde_json::json!({"status": "error", "error": err}).to_string()
+}
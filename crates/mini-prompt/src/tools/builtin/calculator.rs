@@ -0,0 +1,292 @@
+use super::error;
+use crate::tools::RawToolFunc;
+use crate::ToolInfo;
+use serde::Deserialize;
+
+/// A tool which evaluates arithmetic expressions.
+///
+/// See [evaluate] for the supported syntax.
+pub fn calculator() -> (ToolInfo, RawToolFunc) {
+    let info = ToolInfo::new(
+        "calculator",
+        "Evaluates an arithmetic expression, returning the numeric result. \
+        Supports + - * / % ^, parentheses, the constants pi and e, and the functions \
+        sqrt, abs, exp, ln, log10, log2, sin, cos, tan, asin, acos, atan, floor, ceil, round, min and max.",
+        Some(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "expression": {"type": "string", "description": "The expression to evaluate, such as `2 * (3 + 4)`."},
+            },
+            "required": ["expression"],
+        })),
+    );
+
+    #[derive(Deserialize)]
+    struct Args {
+        expression: String,
+    }
+
+    let f: RawToolFunc = Box::new(move |args| {
+        let args: Args = match serde_json_lenient::from_str(&args) {
+            Ok(a) => a,
+            Err(e) => return error(format!("invalid arguments: {}", e)),
+        };
+        match evaluate(&args.expression) {
+            Ok(result) => serde_json::json!({"result": result}).to_string(),
+            Err(e) => error(e),
+        }
+    });
+
+    (info, f)
+}
+
+/// Evaluates an arithmetic expression.
+///
+/// Supports numbers (including scientific notation), the binary operators `+ - * / % ^`,
+/// unary minus, parentheses, the constants `pi` and `e`, and the functions `sqrt`, `abs`,
+/// `exp`, `ln`, `log10`, `log2`, `sin`, `cos`, `tan`, `asin`, `acos`, `atan`, `floor`,
+/// `ceil`, `round`, `min` and `max`.
+///
+/// ```
+/// # use mini_prompt::tools::builtin::evaluate;
+/// assert_eq!(evaluate("2 * (3 + 4) ^ 2"), Ok(98.0));
+/// ```
+pub fn evaluate(expr: &str) -> Result<f64, String> {
+    let mut p = Parser {
+        chars: expr.chars().collect(),
+        pos: 0,
+        depth: 0,
+    };
+    let v = p.expr()?;
+    p.skip_ws();
+    if p.pos < p.chars.len() {
+        return Err(format!(
+            "unexpected '{}' at position {}",
+            p.chars[p.pos], p.pos
+        ));
+    }
+    if !v.is_finite() {
+        return Err("result is not a finite number".into());
+    }
+    Ok(v)
+}
+
+/// Bounds recursion, so deeply nested input cannot overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn skip_ws(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_ws();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<f64, String> {
+        let mut v = self.term()?;
+        loop {
+            if self.eat('+') {
+                v += self.term()?;
+            } else if self.eat('-') {
+                v -= self.term()?;
+            } else {
+                return Ok(v);
+            }
+        }
+    }
+
+    // term := unary (('*' | '/' | '%') unary)*
+    fn term(&mut self) -> Result<f64, String> {
+        let mut v = self.unary()?;
+        loop {
+            if self.eat('*') {
+                v *= self.unary()?;
+            } else if self.eat('/') {
+                let d = self.unary()?;
+                if d == 0.0 {
+                    return Err("division by zero".into());
+                }
+                v /= d;
+            } else if self.eat('%') {
+                let d = self.unary()?;
+                if d == 0.0 {
+                    return Err("division by zero".into());
+                }
+                v %= d;
+            } else {
+                return Ok(v);
+            }
+        }
+    }
+
+    // unary := ('-' | '+')* power
+    fn unary(&mut self) -> Result<f64, String> {
+        // All recursion passes through here, so this is where nesting is bounded.
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("expression is nested too deeply".into());
+        }
+
+        let mut negate = false;
+        loop {
+            if self.eat('-') {
+                negate = !negate;
+            } else if !self.eat('+') {
+                break;
+            }
+        }
+        let v = self.power()?;
+
+        self.depth -= 1;
+        Ok(if negate { -v } else { v })
+    }
+
+    // power := atom ('^' unary)?, which is right-associative.
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            Ok(base.powf(self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    // atom := number | '(' expr ')' | ident | ident '(' expr (',' expr)* ')'
+    fn atom(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let v = self.expr()?;
+                if !self.eat(')') {
+                    return Err(format!("expected ')' at position {}", self.pos));
+                }
+                Ok(v)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let start = self.pos;
+                while self.pos < self.chars.len() && self.chars[self.pos].is_ascii_alphanumeric() {
+                    self.pos += 1;
+                }
+                let ident: String = self.chars[start..self.pos].iter().collect();
+
+                if !self.eat('(') {
+                    return match ident.as_str() {
+                        "pi" => Ok(std::f64::consts::PI),
+                        "e" => Ok(std::f64::consts::E),
+                        _ => Err(format!("unknown constant '{}'", ident)),
+                    };
+                }
+                let mut args = vec![self.expr()?];
+                while self.eat(',') {
+                    args.push(self.expr()?);
+                }
+                if !self.eat(')') {
+                    return Err(format!("expected ')' at position {}", self.pos));
+                }
+                Self::call(&ident, &args)
+            }
+            Some(c) => Err(format!("unexpected '{}' at position {}", c, self.pos)),
+            None => Err("unexpected end of expression".into()),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        let start = self.pos;
+        while self.pos < self.chars.len() {
+            let c = self.chars[self.pos];
+            let exponent_sign = (c == '-' || c == '+')
+                && matches!(self.chars.get(self.pos - 1), Some('e') | Some('E'));
+            if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        let s: String = self.chars[start..self.pos].iter().collect();
+        s.parse().map_err(|_| format!("invalid number '{}'", s))
+    }
+
+    fn call(name: &str, args: &[f64]) -> Result<f64, String> {
+        let unary = |f: fn(f64) -> f64| match args {
+            [x] => Ok(f(*x)),
+            _ => Err(format!("{}() takes exactly one argument", name)),
+        };
+        match name {
+            "sqrt" => unary(f64::sqrt),
+            "abs" => unary(f64::abs),
+            "exp" => unary(f64::exp),
+            "ln" => unary(f64::ln),
+            "log10" => unary(f64::log10),
+            "log2" => unary(f64::log2),
+            "sin" => unary(f64::sin),
+            "cos" => unary(f64::cos),
+            "tan" => unary(f64::tan),
+            "asin" => unary(f64::asin),
+            "acos" => unary(f64::acos),
+            "atan" => unary(f64::atan),
+            "floor" => unary(f64::floor),
+            "ceil" => unary(f64::ceil),
+            "round" => unary(f64::round),
+            "min" => Ok(args.iter().copied().fold(f64::INFINITY, f64::min)),
+            "max" => Ok(args.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+            _ => Err(format!("unknown function '{}'", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_expressions() {
+        assert_eq!(evaluate("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(evaluate("2 ^ 3 ^ 2"), Ok(512.0));
+        assert_eq!(evaluate("-2 ^ 2"), Ok(-4.0));
+        assert_eq!(evaluate("10 % 4 - -1"), Ok(3.0));
+        assert_eq!(evaluate("1.5e3 + 2E-1"), Ok(1500.2));
+        assert_eq!(evaluate("max(1, sqrt(16), 3)"), Ok(4.0));
+        assert_eq!(evaluate("round(pi * 100)"), Ok(314.0));
+    }
+
+    #[test]
+    fn evaluate_errors() {
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("1 +").is_err());
+        assert!(evaluate("(1").is_err());
+        assert!(evaluate("2 3").is_err());
+        assert!(evaluate("system(1)").is_err());
+        assert!(evaluate("sqrt(1, 2)").is_err());
+        assert!(evaluate(&"(".repeat(1000)).is_err());
+        assert!(evaluate(&"2^".repeat(1000)).is_err());
+    }
+
+    #[test]
+    fn calculator_tool() {
+        let (_, mut f) = calculator();
+        assert_eq!(f(r#"{"expression": "6 * 7"}"#.into()), r#"{"result":42.0}"#);
+        assert!(f(r#"{"expression": "6 *"}"#.into()).contains("error"));
+    }
+}
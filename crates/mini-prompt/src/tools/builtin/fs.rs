@@ -0,0 +1,283 @@
+use super::error;
+use crate::tools::RawToolFunc;
+use crate::ToolInfo;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Configuration for the filesystem tools, see [fs_tools].
+#[derive(Debug, Clone)]
+pub struct FsOptions {
+    /// The directory the tools are confined to. Paths provided by the model are
+    /// interpreted relative to this directory.
+    pub root: PathBuf,
+    /// Whether the `write_file` tool is provided.
+    pub writable: bool,
+    /// The maximum number of bytes returned when reading a file.
+    pub max_read_bytes: usize,
+}
+
+impl FsOptions {
+    /// Constructs options for read-only access to the given directory.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            writable: false,
+            max_read_bytes: 256 * 1024,
+        }
+    }
+
+    /// Allows files to be written.
+    pub fn writable(self) -> Self {
+        Self {
+            writable: true,
+            ..self
+        }
+    }
+}
+
+/// Resolves a model-provided path against the root, ensuring the result (after
+/// following any symlinks) lies within the root.
+fn resolve(root: &Path, rel: &str) -> Result<PathBuf, String> {
+    let root = root
+        .canonicalize()
+        .map_err(|e| format!("invalid root: {}", e))?;
+
+    let mut out = root.clone();
+    for c in Path::new(rel).components() {
+        match c {
+            Component::Normal(p) => out.push(p),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() || !out.starts_with(&root) {
+                    return Err(format!("{} is outside the permitted directory", rel));
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err("absolute paths are not permitted".into());
+            }
+        }
+    }
+
+    // Symlinks could point anywhere, so resolve the deepest part of the path
+    // which exists and check that instead.
+    let mut existing = out.as_path();
+    while existing.symlink_metadata().is_err() {
+        existing = existing.parent().ok_or("invalid path")?;
+    }
+    let real = existing.canonicalize().map_err(|e| e.to_string())?;
+    if !real.starts_with(&root) {
+        return Err(format!("{} is outside the permitted directory", rel));
+    }
+    match out.strip_prefix(existing).unwrap() {
+        rest if rest.as_os_str().is_empty() => Ok(real),
+        rest => Ok(real.join(rest)),
+    }
+}
+
+#[derive(Deserialize)]
+struct PathArgs {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct WriteArgs {
+    path: String,
+    content: String,
+}
+
+fn path_schema(desc: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "path": {"type": "string", "description": desc},
+        },
+        "required": ["path"],
+    })
+}
+
+/// A tool which reads a text file within the root directory.
+pub fn read_file(opts: FsOptions) -> (ToolInfo, RawToolFunc) {
+    let info = ToolInfo::new(
+        "read_file",
+        "Reads the contents of a text file.",
+        Some(path_schema("The relative path of the file to read.")),
+    );
+    let f: RawToolFunc = Box::new(move |args| {
+        let r = serde_json_lenient::from_str::<PathArgs>(&args)
+            .map_err(|e| format!("invalid arguments: {}", e))
+            .and_then(|a| resolve(&opts.root, &a.path))
+            .and_then(|p| {
+                // Reads one byte past the limit, to tell whether the file was truncated.
+                let mut data = Vec::new();
+                File::open(p)
+                    .and_then(|f| {
+                        f.take(opts.max_read_bytes as u64 + 1)
+                            .read_to_end(&mut data)
+                    })
+                    .map(|_| data)
+                    .map_err(|e| e.to_string())
+            });
+        match r {
+            Ok(mut data) => {
+                let truncated = data.len() > opts.max_read_bytes;
+                data.truncate(opts.max_read_bytes);
+                serde_json::json!({
+                    "truncated": truncated,
+                    "content": String::from_utf8_lossy(&data),
+                })
+                .to_string()
+            }
+            Err(e) => error(e),
+        }
+    });
+    (info, f)
+}
+
+/// A tool which writes a text file within the root directory, creating any
+/// missing parent directories.
+pub fn write_file(opts: FsOptions) -> (ToolInfo, RawToolFunc) {
+    let info = ToolInfo::new(
+        "write_file",
+        "Writes the given content to a file, replacing it if it exists.",
+        Some(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string", "description": "The relative path of the file to write."},
+                "content": {"type": "string", "description": "The new contents of the file."},
+            },
+            "required": ["path", "content"],
+        })),
+    );
+    let f: RawToolFunc = Box::new(move |args| {
+        let r = serde_json_lenient::from_str::<WriteArgs>(&args)
+            .map_err(|e| format!("invalid arguments: {}", e))
+            .and_then(|a| Ok((resolve(&opts.root, &a.path)?, a.content)))
+            .and_then(|(p, content)| {
+                if let Some(parent) = p.parent() {
+                    std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                }
+                std::fs::write(p, content).map_err(|e| e.to_string())
+            });
+        match r {
+            Ok(()) => serde_json::json!({"status": "success"}).to_string(),
+            Err(e) => error(e),
+        }
+    });
+    (info, f)
+}
+
+/// A tool which lists the entries of a directory within the root directory.
+pub fn list_dir(opts: FsOptions) -> (ToolInfo, RawToolFunc) {
+    let info = ToolInfo::new(
+        "list_dir",
+        "Lists the files and directories within a directory.",
+        Some(path_schema(
+            "The relative path of the directory to list. Use \".\" for the top-level directory.",
+        )),
+    );
+    let f: RawToolFunc = Box::new(move |args| {
+        let r = serde_json_lenient::from_str::<PathArgs>(&args)
+            .map_err(|e| format!("invalid arguments: {}", e))
+            .and_then(|a| resolve(&opts.root, &a.path))
+            .and_then(|p| {
+                let mut entries = std::fs::read_dir(p)
+                    .and_then(|rd| {
+                        rd.map(|e| {
+                            let e = e?;
+                            let mut name = e.file_name().to_string_lossy().to_string();
+                            if e.file_type()?.is_dir() {
+                                name.push('/');
+                            }
+                            Ok(name)
+                        })
+                        .collect::<Result<Vec<_>, std::io::Error>>()
+                    })
+                    .map_err(|e| e.to_string())?;
+                entries.sort();
+                Ok(entries)
+            });
+        match r {
+            Ok(entries) => serde_json::json!({ "entries": entries }).to_string(),
+            Err(e) => error(e),
+        }
+    });
+    (info, f)
+}
+
+/// Returns the `read_file` and `list_dir` tools, as well as `write_file` if the
+/// options allow writes, all confined to the root directory.
+///
+/// ```
+/// # use mini_prompt::tools::builtin::{fs_tools, FsOptions};
+/// let tools = fs_tools(FsOptions::new("/tmp/workspace").writable());
+/// ```
+pub fn fs_tools(opts: FsOptions) -> Vec<(ToolInfo, RawToolFunc)> {
+    let mut out = vec![read_file(opts.clone()), list_dir(opts.clone())];
+    if opts.writable {
+        out.push(write_file(opts));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tempdir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("mini-prompt-fs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn read_write_list() {
+        let dir = tempdir("rwl");
+        let (_, mut write) = write_file(FsOptions::new(&dir));
+        let (_, mut read) = read_file(FsOptions::new(&dir));
+        let (_, mut list) = list_dir(FsOptions::new(&dir));
+
+        assert_eq!(
+            write(r#"{"path": "a/b.txt", "content": "hi"}"#.into()),
+            r#"{"status":"success"}"#
+        );
+        assert_eq!(
+            read(r#"{"path": "./a/../a/b.txt"}"#.into()),
+            r#"{"truncated":false,"content":"hi"}"#
+        );
+        let (_, mut read_short) = read_file(FsOptions {
+            max_read_bytes: 1,
+            ..FsOptions::new(&dir)
+        });
+        assert_eq!(
+            read_short(r#"{"path": "a/b.txt"}"#.into()),
+            r#"{"truncated":true,"content":"h"}"#
+        );
+        assert_eq!(list(r#"{"path": "."}"#.into()), r#"{"entries":["a/"]}"#);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_escapes() {
+        let dir = tempdir("escape");
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(dir.join("secret"), "shh").unwrap();
+
+        assert!(resolve(&root, "../secret").is_err());
+        assert!(resolve(&root, "a/../../secret").is_err());
+        assert!(resolve(&root, "/etc/passwd").is_err());
+        assert!(resolve(&root, "new/file.txt").is_ok());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&dir, root.join("link")).unwrap();
+            assert!(resolve(&root, "link/secret").is_err());
+            assert!(resolve(&root, "link/new/file.txt").is_err());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
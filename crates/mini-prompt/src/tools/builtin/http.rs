@@ -0,0 +1,171 @@
+use super::error;
+use crate::tools::RawToolFunc;
+use crate::ToolInfo;
+use reqwest::Url;
+use serde::Deserialize;
+use std::io::Read;
+use std::time::Duration;
+
+/// Configuration for the [http_fetch] tool.
+///
+/// No URLs may be fetched until they are allowed with [HttpFetchOptions::allow].
+#[derive(Debug, Clone)]
+pub struct HttpFetchOptions {
+    allowed: Vec<Url>,
+    /// The maximum number of bytes of the response body returned to the model.
+    pub max_bytes: usize,
+    /// The maximum duration of a fetch, including reading the body.
+    pub timeout: Duration,
+}
+
+impl Default for HttpFetchOptions {
+    fn default() -> Self {
+        Self {
+            allowed: vec![],
+            max_bytes: 256 * 1024,
+            timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+impl HttpFetchOptions {
+    /// Allows fetching URLs beginning with the given prefix, such as `https://docs.rs/`.
+    ///
+    /// The scheme, host and port of a fetched URL must exactly match the prefix,
+    /// and its path must begin with the path of the prefix.
+    pub fn allow(mut self, prefix: &str) -> Result<Self, String> {
+        let url = Url::parse(prefix).map_err(|e| format!("invalid prefix {}: {}", prefix, e))?;
+        self.allowed.push(url);
+        Ok(self)
+    }
+
+    /// Returns true if the given URL may be fetched.
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        matches!(url.scheme(), "http" | "https")
+            && self.allowed.iter().any(|a| {
+                a.scheme() == url.scheme()
+                    && a.host_str() == url.host_str()
+                    && a.port_or_known_default() == url.port_or_known_default()
+                    && url.path().starts_with(a.path())
+            })
+    }
+}
+
+#[derive(Deserialize)]
+struct Args {
+    url: String,
+}
+
+/// A tool which fetches the contents of a URL via a HTTP GET request.
+///
+/// Only URLs allowed by the given options may be fetched, including as the target of a redirect.
+///
+/// ```
+/// # use mini_prompt::tools::builtin::{http_fetch, HttpFetchOptions};
+/// let tool = http_fetch(HttpFetchOptions::default().allow("https://docs.rs/").unwrap());
+/// ```
+pub fn http_fetch(opts: HttpFetchOptions) -> (ToolInfo, RawToolFunc) {
+    let info = ToolInfo::new(
+        "http_fetch",
+        "Fetches the contents of a URL using a HTTP GET request.",
+        Some(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "url": {"type": "string", "description": "The URL to fetch."},
+            },
+            "required": ["url"],
+        })),
+    );
+
+    let f: RawToolFunc = Box::new(move |args| {
+        let url = match serde_json_lenient::from_str::<Args>(&args)
+            .map_err(|e| e.to_string())
+            .and_then(|a| Url::parse(&a.url).map_err(|e| e.to_string()))
+        {
+            Ok(url) => url,
+            Err(e) => return error(format!("invalid arguments: {}", e)),
+        };
+        if !opts.is_allowed(&url) {
+            return error(format!("fetching {} is not permitted", url));
+        }
+
+        // The blocking client cannot be used from within an async runtime, so
+        // the fetch is made from a thread of its own.
+        std::thread::scope(|s| {
+            s.spawn(|| fetch(&opts, url))
+                .join()
+                .expect("http_fetch thread panicked")
+        })
+        .unwrap_or_else(error)
+    });
+
+    (info, f)
+}
+
+fn fetch(opts: &HttpFetchOptions, url: Url) -> Result<String, String> {
+    let redirect_opts = opts.clone();
+    let client = reqwest::blocking::Client::builder()
+        .timeout(opts.timeout)
+        .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > 5 {
+                attempt.error("too many redirects")
+            } else if redirect_opts.is_allowed(attempt.url()) {
+                attempt.follow()
+            } else {
+                attempt.error("redirected to a URL which is not permitted")
+            }
+        }))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let resp = client.get(url).send().map_err(|e| e.to_string())?;
+    let status = resp.status().as_u16();
+    let content_type = resp
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let mut body = Vec::new();
+    resp.take(opts.max_bytes as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| e.to_string())?;
+    let truncated = body.len() > opts.max_bytes;
+    body.truncate(opts.max_bytes);
+
+    Ok(serde_json::json!({
+        "status": status,
+        "content_type": content_type,
+        "truncated": truncated,
+        "body": String::from_utf8_lossy(&body),
+    })
+    .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allow_list() {
+        let opts = HttpFetchOptions::default()
+            .allow("https://docs.rs/serde/")
+            .unwrap();
+        let allowed = |u: &str| opts.is_allowed(&Url::parse(u).unwrap());
+
+        assert!(allowed("https://docs.rs/serde/latest"));
+        assert!(allowed("https://docs.rs:443/serde/"));
+        assert!(!allowed("https://docs.rs/tokio/"));
+        assert!(!allowed("http://docs.rs/serde/"));
+        assert!(!allowed("https://docs.rs.evil.com/serde/"));
+        assert!(!allowed("https://docs.rs:8443/serde/"));
+    }
+
+    #[test]
+    fn rejects_disallowed() {
+        let (_, mut f) = http_fetch(HttpFetchOptions::default());
+        assert!(f(r#"{"url": "https://example.com/"}"#.into()).contains("not permitted"));
+        assert!(f(r#"{"url": 5}"#.into()).contains("invalid arguments"));
+    }
+}
@@ -0,0 +1,232 @@
+use super::error;
+use crate::tools::RawToolFunc;
+use crate::ToolInfo;
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Configuration for the [shell] tool.
+#[derive(Debug, Clone)]
+pub struct ShellOptions {
+    /// The directory commands are run in.
+    pub working_dir: PathBuf,
+    /// Names of environment variables passed through to commands. All other
+    /// variables are cleared.
+    pub env_allow: Vec<String>,
+    /// How long a command may run before it is killed.
+    pub timeout: Duration,
+    /// The maximum number of bytes captured from each of stdout and stderr.
+    pub max_output_bytes: usize,
+    /// The shell used to interpret commands, which is passed `-c <command>`.
+    pub shell: String,
+}
+
+impl ShellOptions {
+    /// Constructs options which run commands in the given directory, with an
+    /// empty environment except for `PATH`.
+    pub fn new<P: Into<PathBuf>>(working_dir: P) -> Self {
+        Self {
+            working_dir: working_dir.into(),
+            env_allow: vec!["PATH".into()],
+            timeout: Duration::from_secs(30),
+            max_output_bytes: 64 * 1024,
+            shell: "sh".into(),
+        }
+    }
+
+    /// Passes the named environment variable through to commands.
+    pub fn allow_env<S: Into<String>>(mut self, name: S) -> Self {
+        self.env_allow.push(name.into());
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct Args {
+    command: String,
+}
+
+/// A tool which runs shell commands, for coding-agent experiments.
+///
+/// Commands run with a cleared environment (except for allowed variables), in the
+/// configured directory, and are killed if they exceed the timeout. This is **not**
+/// a security boundary: commands can still access anything the current user can,
+/// and background processes they start are not terminated on timeout. Run agents
+/// using this tool inside a container or VM.
+///
+/// ```no_run
+/// # use mini_prompt::tools::builtin::{shell, ShellOptions};
+/// let tool = shell(ShellOptions::new("/tmp/workspace").allow_env("HOME"));
+/// ```
+pub fn shell(opts: ShellOptions) -> (ToolInfo, RawToolFunc) {
+    let info = ToolInfo::new(
+        "shell",
+        "Runs a shell command, returning its exit code and output.",
+        Some(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "command": {"type": "string", "description": "The command to run."},
+            },
+            "required": ["command"],
+        })),
+    );
+
+    let f: RawToolFunc = Box::new(move |args| {
+        let args: Args = match serde_json_lenient::from_str(&args) {
+            Ok(a) => a,
+            Err(e) => return error(format!("invalid arguments: {}", e)),
+        };
+        run(&opts, &args.command, &|name| std::env::var_os(name)).unwrap_or_else(error)
+    });
+
+    (info, f)
+}
+
+/// Spawns a thread which reads up to `max` bytes from `r` into the returned buffer,
+/// discarding anything beyond that. The channel is signalled when `r` is exhausted.
+fn capture<R: Read + Send + 'static>(
+    mut r: R,
+    max: usize,
+    done: mpsc::Sender<()>,
+) -> Arc<Mutex<(Vec<u8>, bool)>> {
+    let out = Arc::new(Mutex::new((Vec::new(), false)));
+    let buf = out.clone();
+    std::thread::spawn(move || {
+        let mut chunk = [0u8; 8192];
+        while let Ok(n) = r.read(&mut chunk) {
+            if n == 0 {
+                break;
+            }
+            let (data, truncated) = &mut *buf.lock().unwrap();
+            let take = n.min(max - data.len());
+            data.extend_from_slice(&chunk[..take]);
+            *truncated |= take < n;
+        }
+        let _ = done.send(());
+    });
+    out
+}
+
+/// Runs the command, looking up the allowed environment variables with `env`.
+fn run(
+    opts: &ShellOptions,
+    command: &str,
+    env: &dyn Fn(&str) -> Option<OsString>,
+) -> Result<String, String> {
+    let mut cmd = Command::new(&opts.shell);
+    cmd.arg("-c")
+        .arg(command)
+        .current_dir(&opts.working_dir)
+        .env_clear()
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    for name in opts.env_allow.iter() {
+        if let Some(v) = env(name) {
+            cmd.env(name, v);
+        }
+    }
+
+    let start = Instant::now();
+    let mut child = cmd.spawn().map_err(|e| e.to_string())?;
+    let (done_tx, done_rx) = mpsc::channel();
+    let stdout = capture(
+        child.stdout.take().unwrap(),
+        opts.max_output_bytes,
+        done_tx.clone(),
+    );
+    let stderr = capture(child.stderr.take().unwrap(), opts.max_output_bytes, done_tx);
+
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if start.elapsed() >= opts.timeout {
+            timed_out = true;
+            let _ = child.kill();
+            break child.wait().map_err(|e| e.to_string())?;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    // Give the readers a moment to drain the pipes. They may be held open
+    // by processes the command left running, in which case we move on.
+    for _ in 0..2 {
+        let _ = done_rx.recv_timeout(Duration::from_millis(250));
+    }
+
+    let (stdout, stdout_truncated) = stdout.lock().unwrap().clone();
+    let (stderr, stderr_truncated) = stderr.lock().unwrap().clone();
+    Ok(serde_json::json!({
+        "exit_code": status.code(),
+        "timed_out": timed_out,
+        "truncated": stdout_truncated || stderr_truncated,
+        "stdout": String::from_utf8_lossy(&stdout),
+        "stderr": String::from_utf8_lossy(&stderr),
+    })
+    .to_string())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn run_tool(opts: ShellOptions, command: &str) -> Value {
+        let (_, mut f) = shell(opts);
+        serde_json::from_str(&f(serde_json::json!({ "command": command }).to_string())).unwrap()
+    }
+
+    #[test]
+    fn runs_commands() {
+        let out = run_tool(ShellOptions::new("/"), "pwd; echo oops >&2; exit 3");
+        assert_eq!(out["exit_code"], 3);
+        assert_eq!(out["stdout"], "/\n");
+        assert_eq!(out["stderr"], "oops\n");
+        assert_eq!(out["timed_out"], false);
+    }
+
+    #[test]
+    fn clears_environment() {
+        let env = |name: &str| match name {
+            "HOME" | "MINI_PROMPT_SHELL_TEST" => Some(OsString::from("leaked")),
+            _ => None,
+        };
+        let stdout = |opts: ShellOptions| {
+            let out = run(&opts, "echo \"[$HOME][$MINI_PROMPT_SHELL_TEST]\"", &env).unwrap();
+            serde_json::from_str::<Value>(&out).unwrap()["stdout"].clone()
+        };
+        assert_eq!(stdout(ShellOptions::new("/")), "[][]\n");
+        assert_eq!(
+            stdout(ShellOptions::new("/").allow_env("MINI_PROMPT_SHELL_TEST")),
+            "[][leaked]\n"
+        );
+    }
+
+    #[test]
+    fn enforces_limits() {
+        let out = run_tool(
+            ShellOptions {
+                timeout: Duration::from_millis(100),
+                ..ShellOptions::new("/")
+            },
+            "sleep 5",
+        );
+        assert_eq!(out["timed_out"], true);
+
+        let out = run_tool(
+            ShellOptions {
+                max_output_bytes: 4,
+                ..ShellOptions::new("/")
+            },
+            "echo hello world",
+        );
+        assert_eq!(out["stdout"], "hell");
+        assert_eq!(out["truncated"], true);
+    }
+}
@@ -1,4 +1,5 @@
 use indoc::indoc;
+use mini_prompt::assertions::{assert_contains_ci, assert_eq_ci};
 use mini_prompt::parse::TagOptions;
 use mini_prompt::{callers, ModelCaller};
 use serde::Deserialize;
@@ -115,14 +116,10 @@ async fn memory_add() {
     }
 
     assert_eq!(new_mems.len(), 1);
-    assert!(new_mems[0]
-        .text
-        .trim()
-        .to_lowercase()
-        .contains("sausage party"));
-    assert_eq!(
-        new_mems[0].date.clone().map(|s| s.trim().to_lowercase()),
-        Some("2025-02-11".to_string())
+    assert_contains_ci(&new_mems[0].text, "sausage party");
+    assert_eq_ci(
+        new_mems[0].date.as_deref().unwrap_or_default(),
+        "2025-02-11",
     );
 }
 
@@ -155,9 +152,9 @@ async fn memory_edit() {
 
     assert_eq!(edit_mems.len(), 1);
     assert_eq!(edit_mems[0].id, 412);
-    assert_eq!(
-        edit_mems[0].date.clone().map(|s| s.trim().to_lowercase()),
-        Some("2025-07-23".to_string())
+    assert_eq_ci(
+        edit_mems[0].date.as_deref().unwrap_or_default(),
+        "2025-07-23",
     );
     assert!(edit_mems[0].text.is_none() || edit_mems[0].text.as_ref().unwrap() == "Moving day");
 }